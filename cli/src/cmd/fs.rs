@@ -1,10 +1,13 @@
 use std::collections::VecDeque;
+use std::sync::Arc;
 
 use agentfs_sdk::{AgentFSOptions, EncryptionConfig};
 use anyhow::{Context, Result as AnyhowResult};
 use turso::Value;
 
 use crate::cmd::init::open_agentfs;
+use crate::levfs::{load_hooks, HookedFileSystem, PathFileSystem};
+use crate::opts::HookOptions;
 
 const ROOT_INO: i64 = 1;
 const S_IFMT: u32 = 0o170000;
@@ -126,11 +129,13 @@ pub async fn cat_filesystem(
     }
 }
 
+/// Write a file, running it through the LevFS hooks a mount would run.
 pub async fn write_filesystem(
     id_or_path: String,
     path: &str,
     content: &str,
     encryption: Option<&(String, String)>,
+    hooks: &HookOptions,
 ) -> AnyhowResult<()> {
     let mut options = AgentFSOptions::resolve(&id_or_path)?;
     if let Some((key, cipher)) = encryption {
@@ -139,7 +144,13 @@ pub async fn write_filesystem(
             cipher: cipher.clone(),
         });
     }
+    let hooks = load_hooks(hooks)?;
     let agentfs = open_agentfs(options).await?;
+    let hooks = hooks
+        .with_log(agentfs.hook_log.clone())
+        .with_queue(agentfs.hook_queue.clone())
+        .with_read_log(agentfs.read_log.clone());
+    let fs = PathFileSystem::new(HookedFileSystem::wrap(Arc::new(agentfs.fs.clone()), hooks));
 
     let mut components = path.split("/").collect::<Vec<_>>();
     if !path.starts_with("/") {
@@ -149,16 +160,39 @@ pub async fn write_filesystem(
     // we must start with /a (first TWO entries)
     for i in 2..components.len() {
         let dir_path = components[0..i].join("/");
-        if agentfs.fs.stat(&dir_path).await?.is_none() {
-            agentfs.fs.mkdir(&dir_path, 0, 0).await?;
+        if fs.stat(&dir_path).await?.is_none() {
+            fs.mkdir(&dir_path, 0, 0).await?;
         }
     }
-    // Remove file if it exists (overwrite behavior)
-    if agentfs.fs.stat(path).await?.is_some() {
-        agentfs.fs.remove(path).await?;
+    // Replaces the file if it exists (overwrite behavior)
+    fs.write_file(path, content.as_bytes()).await?;
+    Ok(())
+}
+
+/// Enable content-addressed chunk deduplication, migrating existing chunks,
+/// and print how much space it saves.
+pub async fn dedup_filesystem(
+    stdout: &mut impl std::io::Write,
+    id_or_path: String,
+    encryption: Option<&(String, String)>,
+) -> AnyhowResult<()> {
+    let mut options = AgentFSOptions::resolve(&id_or_path)?;
+    if let Some((key, cipher)) = encryption {
+        options = options.with_encryption(EncryptionConfig {
+            hex_key: key.clone(),
+            cipher: cipher.clone(),
+        });
     }
-    let (_, file) = agentfs.fs.create_file(path, S_IFREG | 0o644, 0, 0).await?;
-    file.pwrite(0, content.as_bytes()).await?;
+    let agentfs = open_agentfs(options).await?;
+
+    agentfs
+        .fs
+        .enable_dedup()
+        .await
+        .context("Failed to enable deduplication")?;
+    let stats = agentfs.fs.statfs().await?;
+    writeln!(stdout, "Logical bytes:  {}", stats.bytes_used)?;
+    writeln!(stdout, "Physical bytes: {}", stats.physical_bytes)?;
     Ok(())
 }
 
@@ -274,7 +308,8 @@ mod tests {
     use agentfs_sdk::{AgentFS, AgentFSOptions, EncryptionConfig};
     use tempfile::NamedTempFile;
 
-    use crate::cmd::fs::{cat_filesystem, ls_filesystem, write_filesystem};
+    use crate::cmd::fs::{cat_filesystem, dedup_filesystem, ls_filesystem, write_filesystem};
+    use crate::opts::HookOptions;
 
     const TEST_KEY: &str = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
     const TEST_CIPHER: &str = "aes256gcm";
@@ -402,6 +437,31 @@ f d/e/3.md
         );
     }
 
+    #[tokio::test]
+    pub async fn dedup_shares_identical_files() {
+        let (agentfs, path, _file) = agentfs().await;
+        let content = vec![7u8; 64 * 1024];
+        write_file(&agentfs.fs, "1.bin", &content, 0, 0)
+            .await
+            .unwrap();
+        write_file(&agentfs.fs, "2.bin", &content, 0, 0)
+            .await
+            .unwrap();
+        drop(agentfs);
+
+        let mut buf = Vec::new();
+        dedup_filesystem(&mut buf, path.clone(), None)
+            .await
+            .unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("Logical bytes:  131072"));
+        assert!(output.contains("Physical bytes: 4096"));
+
+        let mut buf = Vec::new();
+        cat_filesystem(&mut buf, path, "2.bin", None).await.unwrap();
+        assert_eq!(buf, content);
+    }
+
     // Encryption tests
 
     #[tokio::test]
@@ -450,6 +510,7 @@ f d/e/3.md
             "/new_file.txt",
             "new content",
             encryption.as_ref(),
+            &HookOptions::default(),
         )
         .await
         .unwrap();
@@ -473,6 +534,7 @@ f d/e/3.md
         }
         let (_, file) = fs.create_file(path, S_IFREG | 0o644, uid, gid).await?;
         file.pwrite(0, data).await?;
+        file.flush().await?;
         Ok(())
     }
 }
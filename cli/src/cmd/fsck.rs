@@ -0,0 +1,104 @@
+use agentfs_sdk::{AgentFSOptions, FsckReport};
+use anyhow::{Context, Result as AnyhowResult};
+use std::io::Write;
+
+use crate::cmd::init::open_agentfs;
+
+/// Check the filesystem for inconsistencies and corrupted chunks, optionally
+/// repairing them.
+///
+/// Returns the report so the caller can exit with a failure status when
+/// issues remain.
+pub async fn check_filesystem(
+    stdout: &mut impl Write,
+    id_or_path: &str,
+    repair: bool,
+    format: &str,
+) -> AnyhowResult<FsckReport> {
+    let options = AgentFSOptions::resolve(id_or_path)?;
+    let agentfs = open_agentfs(options).await?;
+
+    let report = agentfs
+        .fsck(repair)
+        .await
+        .context("Failed to check filesystem")?;
+
+    match format {
+        "json" => {
+            let json = serde_json::to_string_pretty(&report)
+                .context("Failed to serialize report to JSON")?;
+            writeln!(stdout, "{}", json)?;
+        }
+        _ => format_report(stdout, &report)?,
+    }
+    Ok(report)
+}
+
+fn format_report(stdout: &mut impl Write, report: &FsckReport) -> AnyhowResult<()> {
+    for issue in &report.issues {
+        let status = if issue.repaired { "repaired" } else { "error" };
+        writeln!(stdout, "{:<9} {:<19} {}", status, issue.kind, issue.message)?;
+    }
+
+    let repaired = report.issues.iter().filter(|i| i.repaired).count();
+    writeln!(
+        stdout,
+        "{} inodes, {} chunks verified, {} chunks without checksum",
+        report.inodes, report.chunks_verified, report.chunks_unverified
+    )?;
+    if report.issues.is_empty() {
+        writeln!(stdout, "No issues found")?;
+    } else {
+        writeln!(
+            stdout,
+            "{} issues found, {} repaired",
+            report.issues.len(),
+            repaired
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use agentfs_sdk::{AgentFS, AgentFSOptions};
+    use tempfile::NamedTempFile;
+
+    #[tokio::test]
+    async fn test_fsck_repair() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap().to_string();
+        {
+            let agentfs = AgentFS::open(AgentFSOptions::with_path(path.clone()))
+                .await
+                .unwrap();
+            agentfs.fs.mkdir("/dir", 0, 0).await.unwrap();
+            let conn = agentfs.get_connection().await.unwrap();
+            conn.execute("UPDATE fs_inode SET nlink = 1 WHERE ino = 1", ())
+                .await
+                .unwrap();
+        }
+
+        let mut buf = Vec::new();
+        let report = check_filesystem(&mut buf, &path, false, "table")
+            .await
+            .unwrap();
+        assert!(report.has_errors());
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("link-count"));
+
+        let mut buf = Vec::new();
+        let report = check_filesystem(&mut buf, &path, true, "table")
+            .await
+            .unwrap();
+        assert!(!report.has_errors());
+        assert!(String::from_utf8(buf).unwrap().contains("1 repaired"));
+
+        let mut buf = Vec::new();
+        check_filesystem(&mut buf, &path, false, "table")
+            .await
+            .unwrap();
+        assert!(String::from_utf8(buf).unwrap().contains("No issues found"));
+    }
+}
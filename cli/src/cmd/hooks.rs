@@ -0,0 +1,304 @@
+use agentfs_sdk::{
+    AgentFSOptions, HookLogDecision, HookLogEntry, HookLogQuery, ReadLogEntry, ReadLogQuery,
+};
+use anyhow::{Context, Result as AnyhowResult};
+use chrono::TimeZone;
+use std::io::Write;
+
+use crate::cmd::init::open_agentfs;
+use crate::cmd::timeline::OutputFormat;
+
+/// Options for the hooks log command
+#[derive(Debug, Clone)]
+pub struct HookLogOptions {
+    pub limit: i64,
+    pub since: Option<i64>,
+    pub path: Option<String>,
+    pub hook: Option<String>,
+    pub event: Option<String>,
+    pub decision: Option<String>,
+    pub format: String,
+}
+
+/// Display the hook decision log
+///
+/// Without `--since`, shows the most recent entries.
+pub async fn show_hook_log(
+    stdout: &mut impl Write,
+    id_or_path: &str,
+    options: &HookLogOptions,
+) -> AnyhowResult<()> {
+    let agent_options = AgentFSOptions::resolve(id_or_path)?;
+    let agentfs = open_agentfs(agent_options).await?;
+    let output_format: OutputFormat = options.format.parse()?;
+
+    let decision = match options.decision.as_deref() {
+        Some(decision) => Some(
+            HookLogDecision::parse(decision)
+                .with_context(|| format!("Invalid decision: {}", decision))?,
+        ),
+        None => None,
+    };
+    let query = HookLogQuery {
+        after: options.since,
+        path: options.path.clone(),
+        event_type: options.event.clone(),
+        hook: options.hook.clone(),
+        decision,
+        limit: Some(options.limit),
+    };
+    let entries = if options.since.is_some() {
+        agentfs.hook_log.query(&query).await
+    } else {
+        agentfs.hook_log.tail(&query).await
+    }
+    .context("Failed to query hook log")?;
+
+    match output_format {
+        OutputFormat::Table => format_table(stdout, &entries)?,
+        OutputFormat::Json => format_json(stdout, &entries)?,
+    }
+    Ok(())
+}
+
+/// Options for the hooks reads command
+#[derive(Debug, Clone)]
+pub struct ReadLogOptions {
+    pub limit: i64,
+    pub since: Option<i64>,
+    pub path: Option<String>,
+    pub format: String,
+}
+
+/// Display the read log
+///
+/// Without `--since`, shows the most recent entries.
+pub async fn show_read_log(
+    stdout: &mut impl Write,
+    id_or_path: &str,
+    options: &ReadLogOptions,
+) -> AnyhowResult<()> {
+    let agent_options = AgentFSOptions::resolve(id_or_path)?;
+    let agentfs = open_agentfs(agent_options).await?;
+    let output_format: OutputFormat = options.format.parse()?;
+
+    let query = ReadLogQuery {
+        after: options.since,
+        path: options.path.clone(),
+        limit: Some(options.limit),
+    };
+    let entries = if options.since.is_some() {
+        agentfs.read_log.query(&query).await
+    } else {
+        agentfs.read_log.tail(&query).await
+    }
+    .context("Failed to query read log")?;
+
+    match output_format {
+        OutputFormat::Table => format_reads_table(stdout, &entries)?,
+        OutputFormat::Json => {
+            let json = serde_json::to_string_pretty(&entries)
+                .context("Failed to serialize read log to JSON")?;
+            writeln!(stdout, "{}", json)?;
+        }
+    }
+    Ok(())
+}
+
+/// Format timestamp as YYYY-MM-DD HH:MM:SS
+fn format_timestamp(timestamp: i64) -> String {
+    chrono::Utc
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| format!("Invalid timestamp: {}", timestamp))
+}
+
+/// Format a latency in microseconds for display
+fn format_latency(latency_us: Option<i64>) -> String {
+    match latency_us {
+        Some(us) if us >= 1_000_000 => format!("{:.1}s", us as f64 / 1_000_000.0),
+        Some(us) if us >= 1_000 => format!("{:.1}ms", us as f64 / 1_000.0),
+        Some(us) => format!("{}us", us),
+        None => "-".to_string(),
+    }
+}
+
+/// Format hook log entries in table format
+fn format_table(stdout: &mut impl Write, entries: &[HookLogEntry]) -> AnyhowResult<()> {
+    if entries.is_empty() {
+        writeln!(stdout, "No hook decisions found")?;
+        return Ok(());
+    }
+
+    writeln!(
+        stdout,
+        "{:<6} {:<20} {:<16} {:<20} {:<10} {:>8} {:<30} {}",
+        "ID", "TIME", "EVENT", "HOOK", "DECISION", "LATENCY", "PATH", "MESSAGE"
+    )?;
+    for entry in entries {
+        writeln!(
+            stdout,
+            "{:<6} {:<20} {:<16} {:<20} {:<10} {:>8} {:<30} {}",
+            entry.id,
+            format_timestamp(entry.timestamp),
+            entry.event_type,
+            entry.hook.as_deref().unwrap_or("-"),
+            entry.decision,
+            format_latency(entry.latency_us),
+            entry.path.as_deref().unwrap_or("-"),
+            entry.message.as_deref().unwrap_or("")
+        )?;
+    }
+    Ok(())
+}
+
+/// Format read log entries in table format
+fn format_reads_table(stdout: &mut impl Write, entries: &[ReadLogEntry]) -> AnyhowResult<()> {
+    if entries.is_empty() {
+        writeln!(stdout, "No reads found")?;
+        return Ok(());
+    }
+
+    writeln!(
+        stdout,
+        "{:<6} {:<20} {:<20} {:>6} {:>8} {:>12} {}",
+        "ID", "OPENED", "CLOSED", "OPENS", "READS", "BYTES", "PATH"
+    )?;
+    for entry in entries {
+        writeln!(
+            stdout,
+            "{:<6} {:<20} {:<20} {:>6} {:>8} {:>12} {}",
+            entry.id,
+            format_timestamp(entry.opened_at),
+            format_timestamp(entry.closed_at),
+            entry.opens,
+            entry.reads,
+            entry.bytes,
+            entry.path
+        )?;
+    }
+    Ok(())
+}
+
+/// Format hook log entries as JSON
+fn format_json(stdout: &mut impl Write, entries: &[HookLogEntry]) -> AnyhowResult<()> {
+    let json =
+        serde_json::to_string_pretty(entries).context("Failed to serialize hook log to JSON")?;
+    writeln!(stdout, "{}", json)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use agentfs_sdk::{AgentFS, HookLogRecord, ReadLogRecord};
+    use tempfile::NamedTempFile;
+
+    fn options(format: &str) -> HookLogOptions {
+        HookLogOptions {
+            limit: 100,
+            since: None,
+            path: None,
+            hook: None,
+            event: None,
+            decision: None,
+            format: format.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hook_log_shows_decisions() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap().to_string();
+        {
+            let agentfs = AgentFS::open(AgentFSOptions::with_path(path.clone()))
+                .await
+                .unwrap();
+            for (decision, message) in [
+                (HookLogDecision::Allow, None),
+                (HookLogDecision::Deny, Some("missing frontmatter")),
+            ] {
+                agentfs
+                    .hook_log
+                    .record(&HookLogRecord {
+                        event_type: "file:close".to_string(),
+                        path: Some("/docs/a.md".to_string()),
+                        hook: Some("levfs-validator".to_string()),
+                        decision,
+                        message: message.map(str::to_string),
+                        latency_us: Some(1500),
+                    })
+                    .await
+                    .unwrap();
+            }
+        }
+
+        let mut buf = Vec::new();
+        show_hook_log(&mut buf, &path, &options("table"))
+            .await
+            .unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("levfs-validator"));
+        assert!(output.contains("1.5ms"));
+        assert!(output.contains("missing frontmatter"));
+
+        let mut buf = Vec::new();
+        let mut opts = options("json");
+        opts.decision = Some("deny".to_string());
+        show_hook_log(&mut buf, &path, &opts).await.unwrap();
+        let entries: Vec<serde_json::Value> = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["decision"], "deny");
+        assert_eq!(entries[0]["latency_us"], 1500);
+
+        opts.decision = Some("maybe".to_string());
+        assert!(show_hook_log(&mut Vec::new(), &path, &opts).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_read_log_shows_reads() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap().to_string();
+        {
+            let agentfs = AgentFS::open(AgentFSOptions::with_path(path.clone()))
+                .await
+                .unwrap();
+            for read_path in ["/docs/a.md", "/src/main.rs"] {
+                agentfs
+                    .read_log
+                    .record(&ReadLogRecord {
+                        path: read_path.to_string(),
+                        opened_at: 1_700_000_000,
+                        closed_at: 1_700_000_002,
+                        opens: 3,
+                        reads: 12,
+                        bytes: 40960,
+                    })
+                    .await
+                    .unwrap();
+            }
+        }
+
+        let mut buf = Vec::new();
+        let mut opts = ReadLogOptions {
+            limit: 100,
+            since: None,
+            path: None,
+            format: "table".to_string(),
+        };
+        show_read_log(&mut buf, &path, &opts).await.unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("/src/main.rs"));
+        assert!(output.contains("40960"));
+
+        let mut buf = Vec::new();
+        opts.path = Some("/docs".to_string());
+        opts.format = "json".to_string();
+        show_read_log(&mut buf, &path, &opts).await.unwrap();
+        let entries: Vec<serde_json::Value> = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["path"], "/docs/a.md");
+        assert_eq!(entries[0]["opens"], 3);
+    }
+}
@@ -0,0 +1,227 @@
+use agentfs_sdk::{AgentFSOptions, JournalEntry, JournalOp, JournalQuery};
+use anyhow::{Context, Result as AnyhowResult};
+use chrono::TimeZone;
+use std::io::Write;
+use std::time::Duration;
+
+use crate::cmd::init::open_agentfs;
+use crate::cmd::timeline::OutputFormat;
+
+/// How often `--follow` polls the journal for new entries
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Options for the log command
+#[derive(Debug, Clone)]
+pub struct LogOptions {
+    pub limit: i64,
+    pub since: Option<i64>,
+    pub path: Option<String>,
+    pub follow: bool,
+    pub format: String,
+}
+
+/// Display the filesystem change journal
+///
+/// Without `--since`, shows the most recent entries. With `--follow`, keeps
+/// printing new entries as they are recorded until interrupted.
+pub async fn show_log(
+    stdout: &mut impl Write,
+    id_or_path: &str,
+    options: &LogOptions,
+) -> AnyhowResult<()> {
+    let agent_options = AgentFSOptions::resolve(id_or_path)?;
+    let agentfs = open_agentfs(agent_options).await?;
+    let output_format: OutputFormat = options.format.parse()?;
+
+    let mut query = JournalQuery {
+        after: options.since,
+        path: options.path.clone(),
+        limit: Some(options.limit),
+        ..Default::default()
+    };
+    let entries = if options.since.is_some() {
+        agentfs.journal.query(&query).await
+    } else {
+        agentfs.journal.tail(&query).await
+    }
+    .context("Failed to query journal")?;
+
+    if !options.follow {
+        match output_format {
+            OutputFormat::Table => format_table(stdout, &entries)?,
+            OutputFormat::Json => format_json(stdout, &entries)?,
+        }
+        return Ok(());
+    }
+
+    // Following prints entries as they arrive: a table without a trailing
+    // summary, or one JSON object per line
+    if output_format == OutputFormat::Table {
+        write_header(stdout)?;
+    }
+    let mut entries = entries;
+    query.limit = Some(i64::MAX);
+    loop {
+        for entry in &entries {
+            match output_format {
+                OutputFormat::Table => write_row(stdout, entry)?,
+                OutputFormat::Json => writeln!(stdout, "{}", serde_json::to_string(entry)?)?,
+            }
+        }
+        stdout.flush()?;
+        if let Some(last) = entries.last() {
+            query.after = Some(last.id);
+        }
+        tokio::time::sleep(FOLLOW_INTERVAL).await;
+        entries = agentfs
+            .journal
+            .query(&query)
+            .await
+            .context("Failed to query journal")?;
+    }
+}
+
+/// Format timestamp as YYYY-MM-DD HH:MM:SS
+fn format_timestamp(timestamp: i64) -> String {
+    chrono::Utc
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| format!("Invalid timestamp: {}", timestamp))
+}
+
+/// Describe the target of an entry: its path, and where it moved or links to
+fn format_target(entry: &JournalEntry) -> String {
+    let path = entry
+        .path
+        .clone()
+        .or_else(|| entry.ino.map(|ino| format!("<inode {}>", ino)))
+        .unwrap_or_default();
+    match &entry.new_path {
+        Some(new_path) => format!("{} -> {}", path, new_path),
+        None => path,
+    }
+}
+
+/// Describe operation-specific details of an entry
+fn format_details(entry: &JournalEntry) -> String {
+    match entry.op {
+        JournalOp::Write => format!(
+            "{}+{} size={}",
+            entry.offset.unwrap_or(0),
+            entry.length.unwrap_or(0),
+            entry.size.unwrap_or(0)
+        ),
+        JournalOp::Truncate => format!("size={}", entry.size.unwrap_or(0)),
+        JournalOp::Symlink => format!("-> {}", entry.detail.as_deref().unwrap_or("")),
+        _ => entry.detail.clone().unwrap_or_default(),
+    }
+}
+
+fn write_header(stdout: &mut impl Write) -> AnyhowResult<()> {
+    writeln!(
+        stdout,
+        "{:<6} {:<20} {:<11} {:<40} {}",
+        "ID", "TIME", "OP", "PATH", "DETAILS"
+    )?;
+    Ok(())
+}
+
+fn write_row(stdout: &mut impl Write, entry: &JournalEntry) -> AnyhowResult<()> {
+    writeln!(
+        stdout,
+        "{:<6} {:<20} {:<11} {:<40} {}",
+        entry.id,
+        format_timestamp(entry.timestamp),
+        entry.op,
+        format_target(entry),
+        format_details(entry)
+    )?;
+    Ok(())
+}
+
+/// Format journal entries in table format
+fn format_table(stdout: &mut impl Write, entries: &[JournalEntry]) -> AnyhowResult<()> {
+    if entries.is_empty() {
+        writeln!(stdout, "No changes found")?;
+        return Ok(());
+    }
+
+    write_header(stdout)?;
+    for entry in entries {
+        write_row(stdout, entry)?;
+    }
+    Ok(())
+}
+
+/// Format journal entries as JSON
+fn format_json(stdout: &mut impl Write, entries: &[JournalEntry]) -> AnyhowResult<()> {
+    let json =
+        serde_json::to_string_pretty(entries).context("Failed to serialize journal to JSON")?;
+    writeln!(stdout, "{}", json)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use agentfs_sdk::{AgentFS, AgentFSOptions};
+    use tempfile::NamedTempFile;
+
+    fn options(format: &str) -> LogOptions {
+        LogOptions {
+            limit: 100,
+            since: None,
+            path: None,
+            follow: false,
+            format: format.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_log_shows_changes() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap().to_string();
+        {
+            let agentfs = AgentFS::open(AgentFSOptions::with_path(path.clone()))
+                .await
+                .unwrap();
+            agentfs.fs.mkdir("/docs", 0, 0).await.unwrap();
+            agentfs.fs.pwrite("/docs/a.txt", 0, b"hello").await.unwrap();
+            agentfs
+                .fs
+                .rename("/docs/a.txt", "/docs/b.txt")
+                .await
+                .unwrap();
+        }
+
+        let mut buf = Vec::new();
+        show_log(&mut buf, &path, &options("table")).await.unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("mkdir"));
+        assert!(output.contains("0+5 size=5"));
+        assert!(output.contains("/docs/a.txt -> /docs/b.txt"));
+
+        let mut buf = Vec::new();
+        let mut opts = options("json");
+        opts.since = Some(2);
+        show_log(&mut buf, &path, &opts).await.unwrap();
+        let entries: Vec<serde_json::Value> = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["op"], "write");
+        assert_eq!(entries[1]["op"], "rename");
+    }
+
+    #[tokio::test]
+    async fn test_log_empty() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap().to_string();
+        AgentFS::open(AgentFSOptions::with_path(path.clone()))
+            .await
+            .unwrap();
+
+        let mut buf = Vec::new();
+        show_log(&mut buf, &path, &options("table")).await.unwrap();
+        assert!(String::from_utf8(buf).unwrap().contains("No changes found"));
+    }
+}
@@ -1,4 +1,4 @@
-use agentfs_sdk::{AgentFS, AgentFSOptions, Stats};
+use agentfs_sdk::{AgentFS, AgentFSOptions, FileSystem, Stats};
 
 const S_IFREG: u32 = 0o100000;
 use anyhow::{Context, Result};
@@ -11,11 +11,15 @@ use std::sync::Arc;
 use tokio::sync::Mutex;
 
 use crate::cmd::init::open_agentfs;
+use crate::levfs::{load_hooks, HookedFileSystem, PathFileSystem};
+use crate::opts::HookOptions;
+use crate::tool_call;
 
 /// Main entry point for MCP server command
 pub async fn handle_mcp_server_command(
     id_or_path: String,
     tools_filter: Option<Vec<String>>,
+    hooks: HookOptions,
 ) -> Result<()> {
     // Resolve and open AgentFS
     let options = AgentFSOptions::resolve(&id_or_path).context(format!(
@@ -25,10 +29,18 @@ pub async fn handle_mcp_server_command(
 
     eprintln!("Using agent: {}", id_or_path);
 
+    let tool_call_control = tool_call::control_file(&options.db_path()?);
+    let hooks = load_hooks(&hooks)?;
     let agentfs = open_agentfs(options).await?;
+    tool_call::attach(&agentfs.fs, tool_call_control);
+    let hooks = hooks
+        .with_log(agentfs.hook_log.clone())
+        .with_queue(agentfs.hook_queue.clone())
+        .with_read_log(agentfs.read_log.clone());
+    let fs = HookedFileSystem::wrap(Arc::new(agentfs.fs.clone()), hooks);
 
     // Create MCP server with tool filtering
-    let server = McpServer::new(agentfs, tools_filter);
+    let server = McpServer::new(agentfs, fs, tools_filter);
 
     // Run server with stdio transport
     eprintln!("Starting MCP server on stdio...");
@@ -41,11 +53,14 @@ pub async fn handle_mcp_server_command(
 /// MCP Server implementation
 struct McpServer {
     agentfs: Arc<AgentFS>,
+    /// The agent's filesystem with the hooks of a mount, used for every file
+    /// tool
+    fs: PathFileSystem,
     enabled_tools: Option<HashSet<String>>,
 }
 
 impl McpServer {
-    fn new(agentfs: AgentFS, tools_filter: Option<Vec<String>>) -> Self {
+    fn new(agentfs: AgentFS, fs: Arc<dyn FileSystem>, tools_filter: Option<Vec<String>>) -> Self {
         let enabled_tools = tools_filter.map(|tools| {
             let set: HashSet<String> = tools.into_iter().collect();
             eprintln!("Tool filter enabled. Exposing tools: {:?}", set);
@@ -58,6 +73,7 @@ impl McpServer {
 
         Self {
             agentfs: Arc::new(agentfs),
+            fs: PathFileSystem::new(fs),
             enabled_tools,
         }
     }
@@ -645,7 +661,6 @@ impl McpServer {
         let path = normalize_path(&params.path)?;
 
         let data = self
-            .agentfs
             .fs
             .read_file(&path)
             .await
@@ -676,11 +691,10 @@ impl McpServer {
         }
 
         // Remove file if it exists (overwrite behavior)
-        if self.agentfs.fs.stat(&path).await?.is_some() {
-            self.agentfs.fs.remove(&path).await?;
+        if self.fs.stat(&path).await?.is_some() {
+            self.fs.remove(&path).await?;
         }
         let (_, file) = self
-            .agentfs
             .fs
             .create_file(&path, S_IFREG | 0o644, 0, 0)
             .await
@@ -688,6 +702,7 @@ impl McpServer {
         file.pwrite(0, &data)
             .await
             .context("Failed to write file")?;
+        file.flush().await.context("Failed to write file")?;
 
         Ok(format!("Wrote {} bytes to {}", data.len(), path))
     }
@@ -708,7 +723,7 @@ impl McpServer {
             let parent_path = normalize_path(&parent_str)?;
 
             // Check if parent exists
-            if self.agentfs.fs.stat(&parent_path).await?.is_some() {
+            if self.fs.stat(&parent_path).await?.is_some() {
                 return Ok(());
             }
 
@@ -716,8 +731,7 @@ impl McpServer {
             self.ensure_parent_dirs(&parent_path).await?;
 
             // Create parent
-            self.agentfs
-                .fs
+            self.fs
                 .mkdir(&parent_path, 0, 0)
                 .await
                 .context(format!("Failed to create directory: {}", parent_path))?;
@@ -730,18 +744,9 @@ impl McpServer {
     async fn handle_readdir(&self, params: ReaddirParams) -> Result<String> {
         let path = normalize_path(&params.path)?;
 
-        let stats = self
-            .agentfs
-            .fs
-            .stat(&path)
-            .await
-            .context("Failed to stat directory")?
-            .ok_or_else(|| anyhow::anyhow!("Directory not found: {}", path))?;
-
         let entries = self
-            .agentfs
             .fs
-            .readdir(stats.ino)
+            .readdir(&path)
             .await
             .context("Failed to read directory")?
             .ok_or_else(|| anyhow::anyhow!("Directory not found: {}", path))?;
@@ -753,8 +758,7 @@ impl McpServer {
     async fn handle_mkdir(&self, params: MkdirParams) -> Result<String> {
         let path = normalize_path(&params.path)?;
 
-        self.agentfs
-            .fs
+        self.fs
             .mkdir(&path, 0, 0)
             .await
             .context("Failed to create directory")?;
@@ -766,8 +770,7 @@ impl McpServer {
     async fn handle_remove(&self, params: RemoveParams) -> Result<String> {
         let path = normalize_path(&params.path)?;
 
-        self.agentfs
-            .fs
+        self.fs
             .remove(&path)
             .await
             .context("Failed to remove directory")?;
@@ -780,8 +783,7 @@ impl McpServer {
         let from = normalize_path(&params.from)?;
         let to = normalize_path(&params.to)?;
 
-        self.agentfs
-            .fs
+        self.fs
             .rename(&from, &to)
             .await
             .context("Failed to rename")?;
@@ -794,7 +796,6 @@ impl McpServer {
         let path = normalize_path(&params.path)?;
 
         let stats = self
-            .agentfs
             .fs
             .stat(&path)
             .await
@@ -808,7 +809,7 @@ impl McpServer {
     async fn handle_access(&self, params: AccessParams) -> Result<String> {
         let path = normalize_path(&params.path)?;
 
-        let exists = self.agentfs.fs.stat(&path).await?.is_some();
+        let exists = self.fs.stat(&path).await?.is_some();
 
         Ok(serde_json::to_string(&json!({ "exists": exists }))?)
     }
@@ -861,12 +862,7 @@ impl McpServer {
         resources: &'a mut Vec<JsonValue>,
     ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<()>> + Send + 'a>> {
         Box::pin(async move {
-            let dir_stats = match self.agentfs.fs.stat(path).await? {
-                Some(s) => s,
-                None => return Ok(()),
-            };
-
-            let entries = match self.agentfs.fs.readdir(dir_stats.ino).await? {
+            let entries = match self.fs.readdir(path).await? {
                 Some(entries) => entries,
                 None => return Ok(()),
             };
@@ -878,7 +874,7 @@ impl McpServer {
                     format!("{}/{}", path, entry)
                 };
 
-                let stats = match self.agentfs.fs.stat(&full_path).await? {
+                let stats = match self.fs.stat(&full_path).await? {
                     Some(s) => s,
                     None => continue,
                 };
@@ -905,7 +901,6 @@ impl McpServer {
         let normalized = normalize_path(path)?;
 
         let data = self
-            .agentfs
             .fs
             .read_file(&normalized)
             .await
@@ -954,3 +949,53 @@ impl From<Stats> for StatsResponse {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::levfs::FsHooks;
+    use lev_reactive::{HookContext, HookDecision, HookRegistry, SyncHook};
+
+    /// Denies creating files
+    struct DenyCreate;
+
+    impl SyncHook for DenyCreate {
+        fn name(&self) -> &str {
+            "deny-create"
+        }
+
+        fn execute(&self, context: &HookContext) -> lev_reactive::Result<HookDecision> {
+            Ok(match context.event_type.as_str() {
+                "file:create" => HookDecision::Deny,
+                _ => HookDecision::Allow,
+            })
+        }
+
+        fn priority(&self) -> i32 {
+            0
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hooks_deny_write_file() {
+        let agentfs = AgentFS::open(AgentFSOptions::ephemeral()).await.unwrap();
+        let mut registry = HookRegistry::new();
+        registry.register_sync(Box::new(DenyCreate));
+        let hooks = FsHooks::new(Some(registry), None, Default::default());
+        let fs = HookedFileSystem::wrap(Arc::new(agentfs.fs.clone()), hooks);
+        let server = McpServer::new(agentfs, fs, None);
+
+        let write = json!({
+            "name": "write_file",
+            "arguments": { "path": "/a.md", "content": "hello" }
+        });
+        let err = server.handle_tools_call(write).await.unwrap_err();
+        assert!(format!("{:#}", err).contains("Operation not permitted"));
+        assert!(server.agentfs.fs.stat("/a.md").await.unwrap().is_none());
+
+        // Operations the hook allows go through
+        let mkdir = json!({ "name": "mkdir", "arguments": { "path": "/docs" } });
+        server.handle_tools_call(mkdir).await.unwrap();
+        assert!(server.agentfs.fs.stat("/docs").await.unwrap().is_some());
+    }
+}
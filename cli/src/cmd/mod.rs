@@ -1,8 +1,12 @@
 pub mod completions;
 pub mod fs;
+pub mod fsck;
+pub mod hooks;
 pub mod init;
+pub mod log;
 pub mod mcp_server;
 pub mod ps;
+pub mod snapshot;
 pub mod sync;
 pub mod timeline;
 
@@ -8,9 +8,11 @@ use std::{
 use tokio::sync::Mutex;
 use turso::value::Value;
 
+use crate::levfs::{load_hooks, HookedFileSystem};
 use crate::mount::{mount_fs, MountOpts};
 use crate::nfs::AgentNFS;
 use crate::nfsserve::tcp::NFSTcp;
+use crate::opts::HookOptions;
 
 #[cfg(target_os = "linux")]
 use agentfs_sdk::{get_mounts, Mount};
@@ -51,6 +53,8 @@ pub struct MountArgs {
     pub gid: Option<u32>,
     /// The mount backend to use (fuse or nfs).
     pub backend: MountBackend,
+    /// LevFS hook options.
+    pub hooks: HookOptions,
 }
 
 /// Mount the agent filesystem (Linux).
@@ -86,6 +90,9 @@ pub fn mount(args: MountArgs) -> Result<()> {
 #[cfg(target_os = "linux")]
 fn mount_fuse(args: MountArgs) -> Result<()> {
     let opts = AgentFSOptions::resolve(&args.id_or_path)?;
+    let tool_call_control = crate::tool_call::control_file(&opts.db_path()?);
+    // Load hooks before daemonizing so config errors reach the terminal
+    let hooks = load_hooks(&args.hooks)?;
 
     let fsname = format!(
         "agentfs:{}",
@@ -119,6 +126,15 @@ fn mount_fuse(args: MountArgs) -> Result<()> {
     let mount = move || {
         let rt = crate::get_runtime();
         let agentfs = rt.block_on(open_agentfs(opts))?;
+        crate::tool_call::attach(&agentfs.fs, tool_call_control);
+        let hooks = {
+            // Replaying queued workflow events spawns on the runtime
+            let _runtime = rt.enter();
+            hooks
+                .with_log(agentfs.hook_log.clone())
+                .with_queue(agentfs.hook_queue.clone())
+                .with_read_log(agentfs.read_log.clone())
+        };
 
         // Check for overlay configuration
         let fs: Arc<dyn FileSystem> = rt.block_on(async {
@@ -158,7 +174,7 @@ fn mount_fuse(args: MountArgs) -> Result<()> {
             }
         })?;
 
-        crate::fuse::mount(fs, fuse_opts, rt)
+        crate::fuse::mount(HookedFileSystem::wrap(fs, hooks), fuse_opts, rt)
     };
 
     if args.foreground {
@@ -177,6 +193,8 @@ async fn mount_nfs_backend(args: MountArgs) -> Result<()> {
     use crate::cmd::init::open_agentfs;
 
     let opts = AgentFSOptions::resolve(&args.id_or_path)?;
+    let tool_call_control = crate::tool_call::control_file(&opts.db_path()?);
+    let hooks = load_hooks(&args.hooks)?;
 
     if !args.mountpoint.exists() {
         anyhow::bail!("Mountpoint does not exist: {}", args.mountpoint.display());
@@ -193,6 +211,11 @@ async fn mount_nfs_backend(args: MountArgs) -> Result<()> {
 
     // Open AgentFS
     let agentfs = open_agentfs(opts).await?;
+    crate::tool_call::attach(&agentfs.fs, tool_call_control);
+    let hooks = hooks
+        .with_log(agentfs.hook_log.clone())
+        .with_queue(agentfs.hook_queue.clone())
+        .with_read_log(agentfs.read_log.clone());
 
     // Check for overlay configuration
     // Query base_path in a separate scope so connection is released before load_whiteouts
@@ -223,10 +246,10 @@ async fn mount_nfs_backend(args: MountArgs) -> Result<()> {
         let hostfs = HostFS::new(&base_path)?;
         let overlay = OverlayFS::new(Arc::new(hostfs), agentfs.fs);
         overlay.load().await?; // Load persisted whiteouts and origin mappings
-        Arc::new(Mutex::new(overlay)) as Arc<Mutex<dyn FileSystem + Send>>
+        HookedFileSystem::wrap_locked(overlay, hooks)
     } else {
         // Plain AgentFS
-        Arc::new(Mutex::new(agentfs.fs)) as Arc<Mutex<dyn FileSystem + Send>>
+        HookedFileSystem::wrap_locked(agentfs.fs, hooks)
     };
 
     if args.foreground {
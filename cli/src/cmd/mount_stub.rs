@@ -1,6 +1,8 @@
 use anyhow::Result;
 use std::{io::Write, path::PathBuf};
 
+use crate::opts::HookOptions;
+
 pub use crate::opts::MountBackend;
 
 /// Arguments for the mount command.
@@ -25,6 +27,8 @@ pub struct MountArgs {
     pub gid: Option<u32>,
     /// The mount backend to use (fuse or nfs).
     pub backend: MountBackend,
+    /// LevFS hook options.
+    pub hooks: HookOptions,
 }
 
 /// List all currently mounted agentfs filesystems
@@ -12,10 +12,19 @@ use tokio::signal;
 use tokio::sync::Mutex;
 
 use crate::cmd::init::open_agentfs;
+use crate::levfs::{load_hooks, HookedFileSystem};
 use crate::nfs::AgentNFS;
+use crate::opts::HookOptions;
 
 /// Handle the `nfs` command - start a standalone NFS server.
-pub async fn handle_nfs_command(id_or_path: String, bind: String, port: u32) -> Result<()> {
+pub async fn handle_nfs_command(
+    id_or_path: String,
+    bind: String,
+    port: u32,
+    hooks: HookOptions,
+) -> Result<()> {
+    let hooks = load_hooks(&hooks)?;
+
     // Resolve database path
     let db_path = resolve_db_path(&id_or_path)?;
 
@@ -26,6 +35,11 @@ pub async fn handle_nfs_command(id_or_path: String, bind: String, port: u32) ->
 
     let options = AgentFSOptions::with_path(db_path_str);
     let agentfs = open_agentfs(options).await?;
+    crate::tool_call::attach(&agentfs.fs, crate::tool_call::control_file(db_path_str));
+    let hooks = hooks
+        .with_log(agentfs.hook_log.clone())
+        .with_queue(agentfs.hook_queue.clone())
+        .with_read_log(agentfs.read_log.clone());
 
     // Check if overlay is configured in the database
     let base_path = agentfs
@@ -34,16 +48,16 @@ pub async fn handle_nfs_command(id_or_path: String, bind: String, port: u32) ->
         .context("Failed to check overlay config")?;
 
     // Create filesystem - either direct AgentFS or overlay with base
-    let fs: Arc<Mutex<dyn FileSystem>> = if let Some(base_str) = base_path {
+    let fs: Arc<Mutex<dyn FileSystem + Send>> = if let Some(base_str) = base_path {
         let hostfs = HostFS::new(&base_str).context("Failed to create HostFS")?;
         let overlay = OverlayFS::new(Arc::new(hostfs), agentfs.fs);
         overlay.load().await?; // Load persisted whiteouts and origin mappings
 
         eprintln!("Mode: overlay (base: {})", base_str);
-        Arc::new(Mutex::new(overlay))
+        HookedFileSystem::wrap_locked(overlay, hooks)
     } else {
         eprintln!("Mode: direct AgentFS");
-        Arc::new(Mutex::new(agentfs.fs))
+        HookedFileSystem::wrap_locked(agentfs.fs, hooks)
     };
 
     // Create NFS adapter
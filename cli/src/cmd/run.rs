@@ -7,6 +7,8 @@
 use anyhow::Result;
 use std::path::PathBuf;
 
+use crate::opts::HookOptions;
+
 #[cfg_attr(all(target_os = "linux", feature = "sandbox"), path = "run_linux.rs")]
 #[cfg_attr(all(target_os = "macos", feature = "sandbox"), path = "run_darwin.rs")]
 #[cfg_attr(
@@ -26,6 +28,7 @@ pub async fn handle_run_command(
     session: Option<String>,
     system: bool,
     encryption: Option<(String, String)>,
+    hooks: HookOptions,
     command: PathBuf,
     args: Vec<String>,
 ) -> Result<()> {
@@ -37,6 +40,7 @@ pub async fn handle_run_command(
         session,
         system,
         encryption,
+        hooks,
         command,
         args,
     )
@@ -16,8 +16,10 @@ use std::process::Command;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
+use crate::levfs::{load_hooks, HookedFileSystem};
 use crate::nfs::AgentNFS;
 use crate::nfsserve::tcp::NFSTcp;
+use crate::opts::HookOptions;
 
 #[cfg(target_os = "macos")]
 use crate::sandbox::darwin::{generate_sandbox_profile, SandboxConfig};
@@ -35,10 +37,12 @@ pub async fn run(
     session_id: Option<String>,
     _system: bool,
     encryption: Option<(String, String)>,
+    hooks: HookOptions,
     command: PathBuf,
     args: Vec<String>,
 ) -> Result<()> {
     let cwd = std::env::current_dir().context("Failed to get current directory")?;
+    let hooks = load_hooks(&hooks)?;
     let home = dirs::home_dir().context("Failed to get home directory")?;
 
     let session = setup_run_directory(session_id, allow, no_default_allows, &cwd, &home)?;
@@ -75,6 +79,10 @@ pub async fn run(
     let agentfs = AgentFS::open(options)
         .await
         .context("Failed to create AgentFS")?;
+    let hooks = hooks
+        .with_log(agentfs.hook_log.clone())
+        .with_queue(agentfs.hook_queue.clone())
+        .with_read_log(agentfs.read_log.clone());
 
     // Create overlay filesystem with CWD as base
     let base_str = cwd.to_string_lossy().to_string();
@@ -87,7 +95,7 @@ pub async fn run(
         .await
         .context("Failed to initialize overlay")?;
 
-    let fs: Arc<Mutex<dyn FileSystem>> = Arc::new(Mutex::new(overlay));
+    let fs: Arc<Mutex<dyn FileSystem + Send>> = HookedFileSystem::wrap_locked(overlay, hooks);
 
     // Create NFS adapter
     let nfs = AgentNFS::new(fs);
@@ -6,6 +6,8 @@
 use anyhow::Result;
 use std::path::PathBuf;
 
+use crate::opts::HookOptions;
+
 /// Run the command in a Linux sandbox.
 #[allow(clippy::too_many_arguments)]
 pub async fn run(
@@ -16,6 +18,7 @@ pub async fn run(
     session: Option<String>,
     system: bool,
     encryption: Option<(String, String)>,
+    hooks: HookOptions,
     command: PathBuf,
     args: Vec<String>,
 ) -> Result<()> {
@@ -29,6 +32,9 @@ pub async fn run(
         if encryption.is_some() {
             eprintln!("Warning: --key is not supported with --experimental-sandbox, ignoring");
         }
+        if hooks.config.is_some() || hooks.audit {
+            eprintln!("Warning: --hooks and --hooks-audit are not supported with --experimental-sandbox, ignoring");
+        }
         crate::sandbox::linux_ptrace::run_cmd(strace, command, args).await;
     } else {
         if strace {
@@ -40,6 +46,7 @@ pub async fn run(
             session,
             system,
             encryption,
+            hooks,
             command,
             args,
         )
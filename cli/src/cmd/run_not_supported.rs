@@ -5,6 +5,8 @@
 use anyhow::{bail, Result};
 use std::path::PathBuf;
 
+use crate::opts::HookOptions;
+
 /// Run the command in a Windows sandbox.
 pub async fn run(
     _allow: Vec<PathBuf>,
@@ -14,6 +16,7 @@ pub async fn run(
     _session: Option<String>,
     _system: bool,
     _encryption: Option<(String, String)>,
+    _hooks: HookOptions,
     _command: PathBuf,
     _args: Vec<String>,
 ) -> Result<()> {
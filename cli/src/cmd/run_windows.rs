@@ -5,6 +5,8 @@
 use anyhow::{bail, Result};
 use std::path::PathBuf;
 
+use crate::opts::HookOptions;
+
 /// Run the command in a Windows sandbox.
 #[allow(clippy::too_many_arguments)]
 pub async fn run(
@@ -15,6 +17,7 @@ pub async fn run(
     _session: Option<String>,
     _system: bool,
     _encryption: Option<(String, String)>,
+    _hooks: HookOptions,
     _command: PathBuf,
     _args: Vec<String>,
 ) -> Result<()> {
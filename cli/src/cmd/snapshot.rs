@@ -0,0 +1,173 @@
+use agentfs_sdk::{AgentFSOptions, Snapshot};
+use anyhow::{Context, Result as AnyhowResult};
+use chrono::TimeZone;
+use std::io::Write;
+
+use crate::cmd::init::open_agentfs;
+
+/// Take a named snapshot
+pub async fn create_snapshot(
+    stdout: &mut impl Write,
+    id_or_path: &str,
+    name: &str,
+) -> AnyhowResult<()> {
+    let options = AgentFSOptions::resolve(id_or_path)?;
+    let agentfs = open_agentfs(options).await?;
+
+    let snapshot = agentfs
+        .snapshot(name)
+        .await
+        .context("Failed to create snapshot")?;
+    writeln!(stdout, "Created snapshot '{}'", snapshot.name)?;
+    Ok(())
+}
+
+/// List snapshots, oldest first
+pub async fn list_snapshots(
+    stdout: &mut impl Write,
+    id_or_path: &str,
+    format: &str,
+) -> AnyhowResult<()> {
+    let options = AgentFSOptions::resolve(id_or_path)?;
+    let agentfs = open_agentfs(options).await?;
+
+    let snapshots = agentfs
+        .list_snapshots()
+        .await
+        .context("Failed to list snapshots")?;
+
+    match format {
+        "json" => {
+            let json = serde_json::to_string_pretty(&snapshots)
+                .context("Failed to serialize snapshots to JSON")?;
+            writeln!(stdout, "{}", json)?;
+        }
+        _ => format_table(stdout, &snapshots)?,
+    }
+    Ok(())
+}
+
+/// Restore the filesystem and key-value store to a snapshot
+pub async fn restore_snapshot(
+    stdout: &mut impl Write,
+    id_or_path: &str,
+    name: &str,
+) -> AnyhowResult<()> {
+    let options = AgentFSOptions::resolve(id_or_path)?;
+    let agentfs = open_agentfs(options).await?;
+
+    agentfs
+        .restore_snapshot(name)
+        .await
+        .context("Failed to restore snapshot")?;
+    writeln!(stdout, "Restored snapshot '{}'", name)?;
+    Ok(())
+}
+
+/// Delete a snapshot
+pub async fn remove_snapshot(
+    stdout: &mut impl Write,
+    id_or_path: &str,
+    name: &str,
+) -> AnyhowResult<()> {
+    let options = AgentFSOptions::resolve(id_or_path)?;
+    let agentfs = open_agentfs(options).await?;
+
+    agentfs
+        .delete_snapshot(name)
+        .await
+        .context("Failed to delete snapshot")?;
+    writeln!(stdout, "Deleted snapshot '{}'", name)?;
+    Ok(())
+}
+
+/// Format timestamp as YYYY-MM-DD HH:MM:SS
+fn format_timestamp(timestamp: i64) -> String {
+    chrono::Utc
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| format!("Invalid timestamp: {}", timestamp))
+}
+
+fn format_table(stdout: &mut impl Write, snapshots: &[Snapshot]) -> AnyhowResult<()> {
+    if snapshots.is_empty() {
+        writeln!(stdout, "No snapshots found")?;
+        return Ok(());
+    }
+
+    writeln!(stdout, "{:<4} {:<30} {:<20}", "ID", "NAME", "CREATED")?;
+    for snapshot in snapshots {
+        writeln!(
+            stdout,
+            "{:<4} {:<30} {:<20}",
+            snapshot.id,
+            snapshot.name,
+            format_timestamp(snapshot.created_at)
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use agentfs_sdk::{AgentFS, AgentFSOptions};
+    use tempfile::NamedTempFile;
+
+    #[tokio::test]
+    async fn test_snapshot_commands() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap().to_string();
+        {
+            let agentfs = AgentFS::open(AgentFSOptions::with_path(path.clone()))
+                .await
+                .unwrap();
+            agentfs.kv.set("key", &"before").await.unwrap();
+        }
+
+        let mut buf = Vec::new();
+        list_snapshots(&mut buf, &path, "table").await.unwrap();
+        assert!(String::from_utf8(buf)
+            .unwrap()
+            .contains("No snapshots found"));
+
+        let mut buf = Vec::new();
+        create_snapshot(&mut buf, &path, "checkpoint")
+            .await
+            .unwrap();
+
+        {
+            let agentfs = AgentFS::open(AgentFSOptions::with_path(path.clone()))
+                .await
+                .unwrap();
+            agentfs.kv.set("key", &"after").await.unwrap();
+        }
+
+        let mut buf = Vec::new();
+        list_snapshots(&mut buf, &path, "table").await.unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("NAME"));
+        assert!(output.contains("checkpoint"));
+
+        let mut buf = Vec::new();
+        restore_snapshot(&mut buf, &path, "checkpoint")
+            .await
+            .unwrap();
+        {
+            let agentfs = AgentFS::open(AgentFSOptions::with_path(path.clone()))
+                .await
+                .unwrap();
+            let value: Option<String> = agentfs.kv.get("key").await.unwrap();
+            assert_eq!(value.as_deref(), Some("before"));
+        }
+
+        let mut buf = Vec::new();
+        remove_snapshot(&mut buf, &path, "checkpoint")
+            .await
+            .unwrap();
+        let mut buf = Vec::new();
+        list_snapshots(&mut buf, &path, "json").await.unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap().trim(), "[]");
+    }
+}
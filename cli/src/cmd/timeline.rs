@@ -1,6 +1,7 @@
 use agentfs_sdk::{toolcalls::ToolCall, AgentFSOptions, ToolCalls};
 use anyhow::{Context, Result as AnyhowResult};
 use chrono::TimeZone;
+use serde::Serialize;
 use std::io::Write;
 use std::str::FromStr;
 
@@ -32,6 +33,16 @@ pub struct TimelineOptions {
     pub filter: Option<String>,
     pub status: Option<String>,
     pub format: String,
+    pub files: bool,
+}
+
+/// A tool call with the files it changed, when requested
+#[derive(Debug, Serialize)]
+struct TimelineEntry {
+    #[serde(flatten)]
+    call: ToolCall,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    files: Option<Vec<String>>,
 }
 
 /// Display agent action timeline from tool call audit log
@@ -63,11 +74,28 @@ pub async fn show_timeline(
         calls.retain(|call| call.status.to_string() == *status_filter);
     }
 
+    // Attach the files each call changed from the change journal
+    let mut entries = Vec::with_capacity(calls.len());
+    for call in calls {
+        let files = if options.files {
+            Some(
+                agentfs
+                    .journal
+                    .files_for_tool_call(call.id)
+                    .await
+                    .context("Failed to query journal")?,
+            )
+        } else {
+            None
+        };
+        entries.push(TimelineEntry { call, files });
+    }
+
     // Format and display
     let output_format: OutputFormat = options.format.parse()?;
     match output_format {
-        OutputFormat::Table => format_table(stdout, &calls)?,
-        OutputFormat::Json => format_json(stdout, &calls)?,
+        OutputFormat::Table => format_table(stdout, &entries)?,
+        OutputFormat::Json => format_json(stdout, &entries)?,
     }
 
     Ok(())
@@ -92,8 +120,8 @@ fn format_timestamp(timestamp: i64) -> String {
 }
 
 /// Format tool calls in table format
-fn format_table(stdout: &mut impl Write, calls: &[ToolCall]) -> AnyhowResult<()> {
-    if calls.is_empty() {
+fn format_table(stdout: &mut impl Write, entries: &[TimelineEntry]) -> AnyhowResult<()> {
+    if entries.is_empty() {
         writeln!(stdout, "No tool calls found")?;
         return Ok(());
     }
@@ -106,7 +134,7 @@ fn format_table(stdout: &mut impl Write, calls: &[ToolCall]) -> AnyhowResult<()>
     )?;
 
     // Print rows
-    for call in calls {
+    for TimelineEntry { call, files } in entries {
         let tool_name = truncate_with_ellipsis(&call.name, 20);
         let status = call.status.to_string();
         let duration = call
@@ -120,15 +148,19 @@ fn format_table(stdout: &mut impl Write, calls: &[ToolCall]) -> AnyhowResult<()>
             "{:<4} {:<20} {:<10} {:>10} {:<20}",
             call.id, tool_name, status, duration, timestamp
         )?;
+
+        for file in files.iter().flatten() {
+            writeln!(stdout, "     {}", file)?;
+        }
     }
 
     Ok(())
 }
 
 /// Format tool calls as JSON
-fn format_json(stdout: &mut impl Write, calls: &[ToolCall]) -> AnyhowResult<()> {
+fn format_json(stdout: &mut impl Write, entries: &[TimelineEntry]) -> AnyhowResult<()> {
     let json =
-        serde_json::to_string_pretty(calls).context("Failed to serialize tool calls to JSON")?;
+        serde_json::to_string_pretty(entries).context("Failed to serialize tool calls to JSON")?;
     writeln!(stdout, "{}", json)?;
     Ok(())
 }
@@ -155,6 +187,7 @@ mod tests {
             filter: None,
             status: None,
             format: "table".to_string(),
+            files: false,
         }
     }
 
@@ -328,4 +361,44 @@ mod tests {
         assert!(output.contains("very_long_tool_na..."));
         assert!(!output.contains("very_long_tool_name_that_exceeds_twenty_characters"));
     }
+
+    #[tokio::test]
+    async fn test_timeline_files() {
+        let (agentfs, path, _file) = create_test_agentfs().await;
+
+        let id = agentfs.tools.start("write_report", None).await.unwrap();
+        agentfs.set_tool_call(Some(id));
+        agentfs
+            .fs
+            .pwrite("/report.md", 0, b"# Report")
+            .await
+            .unwrap();
+        agentfs.set_tool_call(None);
+        agentfs.fs.pwrite("/other.md", 0, b"other").await.unwrap();
+        agentfs.tools.start("idle_tool", None).await.unwrap();
+        drop(agentfs);
+
+        let mut buf = Vec::new();
+        let options = TimelineOptions {
+            files: true,
+            ..default_options()
+        };
+        show_timeline(&mut buf, &path, &options).await.unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("     /report.md"));
+        assert!(!output.contains("/other.md"));
+
+        let mut buf = Vec::new();
+        let options = TimelineOptions {
+            files: true,
+            format: "json".to_string(),
+            ..default_options()
+        };
+        show_timeline(&mut buf, &path, &options).await.unwrap();
+        let calls: Vec<serde_json::Value> = serde_json::from_slice(&buf).unwrap();
+        let report = calls.iter().find(|c| c["name"] == "write_report").unwrap();
+        assert_eq!(report["files"], serde_json::json!(["/report.md"]));
+        let idle = calls.iter().find(|c| c["name"] == "idle_tool").unwrap();
+        assert_eq!(idle["files"], serde_json::json!([]));
+    }
 }
@@ -1,18 +1,19 @@
 use crate::fuser::{
     consts::{
-        FUSE_ASYNC_READ, FUSE_CACHE_SYMLINKS, FUSE_NO_OPENDIR_SUPPORT, FUSE_PARALLEL_DIROPS,
-        FUSE_WRITEBACK_CACHE,
+        FUSE_ASYNC_READ, FUSE_CACHE_SYMLINKS, FUSE_FLOCK_LOCKS, FUSE_NO_OPENDIR_SUPPORT,
+        FUSE_PARALLEL_DIROPS, FUSE_POSIX_LOCKS, FUSE_WRITEBACK_CACHE,
     },
     fuse_forget_one, FileAttr, FileType, Filesystem, KernelConfig, MountOption, ReplyAttr,
-    ReplyCreate, ReplyData, ReplyDirectory, ReplyDirectoryPlus, ReplyEmpty, ReplyEntry, ReplyOpen,
-    ReplyStatfs, ReplyWrite, Request,
+    ReplyCreate, ReplyData, ReplyDirectory, ReplyDirectoryPlus, ReplyEmpty, ReplyEntry, ReplyLock,
+    ReplyOpen, ReplyStatfs, ReplyWrite, ReplyXattr, Request,
 };
+use crate::locks::{LockManager, LockWait};
 use agentfs_sdk::error::Error as SdkError;
-use agentfs_sdk::filesystem::{S_IFBLK, S_IFCHR, S_IFDIR, S_IFIFO, S_IFLNK, S_IFMT, S_IFSOCK};
+use agentfs_sdk::filesystem::{
+    FsError, S_IFBLK, S_IFCHR, S_IFDIR, S_IFIFO, S_IFLNK, S_IFMT, S_IFSOCK,
+};
 use agentfs_sdk::{BoxedFile, FileSystem, Stats, TimeChange};
-use lev_reactive::{HookContext, HookDecision, HookRegistry};
 use parking_lot::Mutex;
-use serde_json;
 use std::{
     collections::HashMap,
     ffi::OsStr,
@@ -42,6 +43,20 @@ fn error_to_errno(e: &SdkError) -> i32 {
     }
 }
 
+/// Reply to a getxattr/listxattr request following the FUSE size protocol.
+///
+/// A `size` of 0 asks for the length of the data only; otherwise the data is
+/// returned if it fits, or `ERANGE` if the caller's buffer is too small.
+fn reply_xattr(reply: ReplyXattr, data: &[u8], size: u32) {
+    if size == 0 {
+        reply.size(data.len() as u32);
+    } else if data.len() > size as usize {
+        reply.error(libc::ERANGE);
+    } else {
+        reply.data(data);
+    }
+}
+
 /// Maximize the file descriptor limit by raising the soft limit to the hard limit.
 ///
 /// This helps avoid "too many open files" errors when passthrough filesystems
@@ -107,10 +122,8 @@ struct AgentFSFuse {
     open_files: Arc<Mutex<HashMap<u64, OpenFile>>>,
     /// Next file handle to allocate
     next_fh: AtomicU64,
-    /// Synchronous hooks executed before file operations
-    sync_hooks: Option<HookRegistry>,
-    /// Asynchronous hooks executed after file operations
-    async_hooks: Option<HookRegistry>,
+    /// Advisory byte-range locks (fcntl, OFD and flock)
+    locks: Arc<LockManager>,
 }
 
 impl Filesystem for AgentFSFuse {
@@ -126,6 +139,8 @@ impl Filesystem for AgentFSFuse {
     ///   for symlink resolution.
     /// - No opendir support: skips opendir/releasedir calls since we don't track
     ///   directory handles, reducing round-trips for directory operations.
+    /// - POSIX and flock locks: routes `fcntl`/`flock` locking through getlk/setlk
+    ///   so locks are tracked by the daemon rather than being no-ops.
     fn init(&mut self, _req: &Request, config: &mut KernelConfig) -> Result<(), libc::c_int> {
         tracing::debug!("FUSE::init");
         let _ = config.add_capabilities(
@@ -133,7 +148,9 @@ impl Filesystem for AgentFSFuse {
                 | FUSE_WRITEBACK_CACHE
                 | FUSE_PARALLEL_DIROPS
                 | FUSE_CACHE_SYMLINKS
-                | FUSE_NO_OPENDIR_SUPPORT,
+                | FUSE_NO_OPENDIR_SUPPORT
+                | FUSE_POSIX_LOCKS
+                | FUSE_FLOCK_LOCKS,
         );
         Ok(())
     }
@@ -207,10 +224,8 @@ impl Filesystem for AgentFSFuse {
         }
     }
 
-    /// Sets file attributes, handling truncate and chmod operations.
-    ///
-    /// Currently `size` changes (truncate) and `mode` changes (chmod) are supported.
-    /// Other attribute changes (uid, gid, timestamps) are accepted but ignored.
+    /// Sets file attributes: mode (chmod), ownership (chown), size (truncate)
+    /// and timestamps (utimensat).
     fn setattr(
         &mut self,
         _req: &Request,
@@ -937,53 +952,12 @@ impl Filesystem for AgentFSFuse {
             open_file.file.clone()
         };
 
-        // Execute synchronous hooks BEFORE write
-        if let Some(ref sync_hooks) = self.sync_hooks {
-            let ctx = HookContext {
-                event_type: "file:write".to_string(),
-                source: "levfs".to_string(),
-                data: serde_json::json!({
-                    "fh": fh,
-                    "offset": offset,
-                    "size": data.len(),
-                }),
-            };
-            match sync_hooks.execute_sync(&ctx) {
-                Ok(HookDecision::Deny) | Ok(HookDecision::AllowWithMessage(_)) => {
-                    reply.error(libc::EPERM);
-                    return;
-                }
-                Ok(HookDecision::Allow) | Ok(HookDecision::Transform(_)) => {}
-                Err(_) => {
-                    reply.error(libc::EIO);
-                    return;
-                }
-            }
-        }
-
         let data_len = data.len();
         let data_vec = data.to_vec();
         let result = self
             .runtime
             .block_on(async move { file.pwrite(offset as u64, &data_vec).await });
 
-        // Execute asynchronous hooks AFTER write (fire-and-forget)
-        if let Some(ref async_hooks) = self.async_hooks {
-            let ctx = HookContext {
-                event_type: "file:write".to_string(),
-                source: "levfs".to_string(),
-                data: serde_json::json!({
-                    "fh": fh,
-                    "offset": offset,
-                    "size": data_len,
-                }),
-            };
-            let hooks = async_hooks.clone();
-            self.runtime.spawn(async move {
-                let _ = hooks.execute_async(&ctx).await;
-            });
-        }
-
         match result {
             Ok(()) => reply.written(data_len as u32),
             Err(e) => reply.error(error_to_errno(&e)),
@@ -992,14 +966,28 @@ impl Filesystem for AgentFSFuse {
 
     /// Flushes data to the backend storage.
     ///
-    /// Since writes go directly to the database, this is a no-op.
-    fn flush(&mut self, _req: &Request, _ino: u64, fh: u64, _lock_owner: u64, reply: ReplyEmpty) {
-        tracing::debug!("FUSE::flush: fh={}", fh);
-        let open_files = self.open_files.lock();
-        if open_files.contains_key(&fh) {
-            reply.ok();
-        } else {
-            reply.error(libc::EBADF);
+    /// Closing any descriptor releases the owner's POSIX locks on the file.
+    /// The handle is then flushed, which lets hooked handles validate the
+    /// written file; a failure is reported to the `close()` caller.
+    fn flush(&mut self, _req: &Request, ino: u64, fh: u64, lock_owner: u64, reply: ReplyEmpty) {
+        tracing::debug!("FUSE::flush: fh={}, lock_owner={}", fh, lock_owner);
+        self.locks.release_owner(ino, lock_owner);
+        let file = {
+            let open_files = self.open_files.lock();
+            match open_files.get(&fh) {
+                Some(open_file) => open_file.file.clone(),
+                None => {
+                    reply.error(libc::EBADF);
+                    return;
+                }
+            }
+        };
+
+        let result = self.runtime.block_on(async move { file.flush().await });
+
+        match result {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(error_to_errno(&e)),
         }
     }
 
@@ -1030,23 +1018,131 @@ impl Filesystem for AgentFSFuse {
 
     /// Releases (closes) an open file handle.
     ///
-    /// Removes the file handle from the open files table.
-    /// Since writes go directly to the database, no flushing is needed.
+    /// Removes the file handle from the open files table and drops any flock
+    /// locks held through it, then flushes the handle one last time.
     fn release(
         &mut self,
         _req: &Request,
-        _ino: u64,
+        ino: u64,
         fh: u64,
         _flags: i32,
-        _lock_owner: Option<u64>,
+        lock_owner: Option<u64>,
         _flush: bool,
         reply: ReplyEmpty,
     ) {
         tracing::debug!("FUSE::release: fh={}", fh);
-        self.open_files.lock().remove(&fh);
+        if let Some(lock_owner) = lock_owner {
+            self.locks.release_owner(ino, lock_owner);
+        }
+        let file = self
+            .open_files
+            .lock()
+            .remove(&fh)
+            .map(|open_file| open_file.file);
+
+        // Validate changes made after the last flush (e.g. through a shared
+        // mapping). The kernel ignores release errors, but a blocked change
+        // is still rolled back.
+        if let Some(file) = file {
+            if let Err(e) = self.runtime.block_on(async move { file.flush().await }) {
+                tracing::debug!("FUSE::release: flush failed: {}", e);
+            }
+        }
         reply.ok();
     }
 
+    /// Tests for a conflicting byte-range lock (F_GETLK).
+    ///
+    /// Replies with the first conflicting lock, or with the requested range
+    /// and `F_UNLCK` if the lock could be placed.
+    fn getlk(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        fh: u64,
+        lock_owner: u64,
+        start: u64,
+        end: u64,
+        typ: i32,
+        pid: u32,
+        reply: ReplyLock,
+    ) {
+        tracing::debug!(
+            "FUSE::getlk: ino={}, fh={}, lock_owner={}, start={}, end={}, typ={}",
+            ino,
+            fh,
+            lock_owner,
+            start,
+            end,
+            typ
+        );
+
+        match self.locks.test(ino, lock_owner, start, end, typ) {
+            Some(lock) => reply.locked(lock.start, lock.end, lock.typ, lock.pid),
+            None => reply.locked(start, end, libc::F_UNLCK, pid),
+        }
+    }
+
+    /// Acquires, changes or releases a byte-range lock (F_SETLK/F_SETLKW).
+    ///
+    /// Non-blocking requests fail with `EAGAIN` on conflict. Blocking requests
+    /// wait on the runtime so the session loop keeps serving the requests that
+    /// will eventually release the conflicting lock; the wait is cancelled with
+    /// `EINTR` if the owner closes the file first, and fails with `EDEADLK` if
+    /// the owners holding the conflicting locks are waiting on this one.
+    fn setlk(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        fh: u64,
+        lock_owner: u64,
+        start: u64,
+        end: u64,
+        typ: i32,
+        pid: u32,
+        sleep: bool,
+        reply: ReplyEmpty,
+    ) {
+        tracing::debug!(
+            "FUSE::setlk: ino={}, fh={}, lock_owner={}, start={}, end={}, typ={}, sleep={}",
+            ino,
+            fh,
+            lock_owner,
+            start,
+            end,
+            typ,
+            sleep
+        );
+
+        match typ {
+            libc::F_UNLCK => {
+                self.locks.unlock(ino, lock_owner, start, end);
+                reply.ok();
+            }
+            libc::F_RDLCK | libc::F_WRLCK => {
+                if self
+                    .locks
+                    .try_lock(ino, lock_owner, pid, start, end, typ)
+                    .is_ok()
+                {
+                    reply.ok();
+                } else if !sleep {
+                    reply.error(libc::EAGAIN);
+                } else {
+                    let locks = self.locks.clone();
+                    self.runtime.spawn(async move {
+                        match locks.lock(ino, lock_owner, pid, start, end, typ).await {
+                            LockWait::Acquired => reply.ok(),
+                            LockWait::Cancelled => reply.error(libc::EINTR),
+                            LockWait::Deadlock => reply.error(libc::EDEADLK),
+                        }
+                    });
+                }
+            }
+            _ => reply.error(libc::EINVAL),
+        }
+    }
+
     /// Returns filesystem statistics.
     ///
     /// Queries actual usage from the SDK and reports it to tools like `df`.
@@ -1061,7 +1157,7 @@ impl Filesystem for AgentFSFuse {
 
         let (used_blocks, used_inodes) = match result {
             Ok(stats) => {
-                let used_blocks = stats.bytes_used.div_ceil(BLOCK_SIZE);
+                let used_blocks = stats.physical_bytes.div_ceil(BLOCK_SIZE);
                 (used_blocks, stats.inodes)
             }
             Err(_) => (0, 1), // Fallback: just root inode
@@ -1084,6 +1180,126 @@ impl Filesystem for AgentFSFuse {
         );
     }
 
+    // ─────────────────────────────────────────────────────────────
+    // Extended Attributes
+    // ─────────────────────────────────────────────────────────────
+
+    /// Sets an extended attribute.
+    ///
+    /// `flags` carries `XATTR_CREATE`/`XATTR_REPLACE`, which are passed through
+    /// to the SDK unchanged.
+    fn setxattr(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        name: &OsStr,
+        value: &[u8],
+        flags: i32,
+        _position: u32,
+        reply: ReplyEmpty,
+    ) {
+        tracing::debug!(
+            "FUSE::setxattr: ino={}, name={:?}, len={}, flags={:#x}",
+            ino,
+            name,
+            value.len(),
+            flags
+        );
+
+        let Some(name_str) = name.to_str() else {
+            reply.error(libc::EINVAL);
+            return;
+        };
+
+        let fs = self.fs.clone();
+        let name_owned = name_str.to_string();
+        let value_owned = value.to_vec();
+        let result = self.runtime.block_on(async move {
+            fs.setxattr(ino as i64, &name_owned, &value_owned, flags)
+                .await
+        });
+
+        match result {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(error_to_errno(&e)),
+        }
+    }
+
+    /// Gets an extended attribute.
+    ///
+    /// When `size` is 0 the kernel is asking for the value length only.
+    /// Returns `ERANGE` if the value does not fit in `size` bytes.
+    fn getxattr(&mut self, _req: &Request, ino: u64, name: &OsStr, size: u32, reply: ReplyXattr) {
+        tracing::debug!(
+            "FUSE::getxattr: ino={}, name={:?}, size={}",
+            ino,
+            name,
+            size
+        );
+
+        let Some(name_str) = name.to_str() else {
+            reply.error(libc::EINVAL);
+            return;
+        };
+
+        let fs = self.fs.clone();
+        let name_owned = name_str.to_string();
+        let result = self
+            .runtime
+            .block_on(async move { fs.getxattr(ino as i64, &name_owned).await });
+
+        match result {
+            Ok(Some(value)) => reply_xattr(reply, &value, size),
+            Ok(None) => reply.error(FsError::NoAttribute.to_errno()),
+            Err(e) => reply.error(error_to_errno(&e)),
+        }
+    }
+
+    /// Lists extended attribute names.
+    ///
+    /// Names are returned as a sequence of NUL-terminated strings.
+    fn listxattr(&mut self, _req: &Request, ino: u64, size: u32, reply: ReplyXattr) {
+        tracing::debug!("FUSE::listxattr: ino={}, size={}", ino, size);
+
+        let fs = self.fs.clone();
+        let result = self
+            .runtime
+            .block_on(async move { fs.listxattr(ino as i64).await });
+
+        match result {
+            Ok(names) => {
+                let mut buf = Vec::new();
+                for name in names {
+                    buf.extend_from_slice(name.as_bytes());
+                    buf.push(0);
+                }
+                reply_xattr(reply, &buf, size);
+            }
+            Err(e) => reply.error(error_to_errno(&e)),
+        }
+    }
+
+    /// Removes an extended attribute.
+    fn removexattr(&mut self, _req: &Request, ino: u64, name: &OsStr, reply: ReplyEmpty) {
+        tracing::debug!("FUSE::removexattr: ino={}, name={:?}", ino, name);
+
+        let Some(name_str) = name.to_str() else {
+            reply.error(libc::EINVAL);
+            return;
+        };
+
+        let fs = self.fs.clone();
+        let name_owned = name_str.to_string();
+        let result = self
+            .runtime
+            .block_on(async move { fs.removexattr(ino as i64, &name_owned).await });
+
+        match result {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(error_to_errno(&e)),
+        }
+    }
+
     // ─────────────────────────────────────────────────────────────
     // Inode Lifecycle
     // ─────────────────────────────────────────────────────────────
@@ -1128,8 +1344,7 @@ impl AgentFSFuse {
             runtime,
             open_files: Arc::new(Mutex::new(HashMap::new())),
             next_fh: AtomicU64::new(1),
-            sync_hooks: None,
-            async_hooks: None,
+            locks: Arc::new(LockManager::new()),
         }
     }
 
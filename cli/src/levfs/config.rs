@@ -0,0 +1,971 @@
+//! Hook configuration loaded from `hooks.yaml`.
+//!
+//! Mounts read `~/.config/lev/reactive/hooks.yaml` (or the file given with
+//! `--hooks`) and build the sync and async pipelines from it:
+//!
+//! ```yaml
+//! hooks:
+//!   - name: levfs-validator
+//!     type: sync
+//!     priority: 100
+//!     config:
+//!       max_size: 10485760
+//!       schema_dir: ~/.config/lev/schemas/
+//!       schemas:
+//!         - path: "/docs/**/*.md"
+//!           schema: document
+//!   - name: levfs-policy
+//!     type: sync
+//!     config:
+//!       rules:
+//!         - paths: ["/.git/**"]
+//!           action: deny
+//!   - name: levfs-workflow
+//!     type: async
+//!     config:
+//!       workflow: default-workflow
+//!       executor:
+//!         type: http
+//!         url: http://127.0.0.1:8080/hooks
+//!       debounce_ms: 500
+//!   - name: my-policy
+//!     type: sync
+//!     library: /usr/local/lib/libmy_policy.so
+//!   - name: secrets
+//!     type: sync
+//!     wasm:
+//!       module: /usr/local/lib/lev/secrets.wasm
+//!       fuel: 10000000
+//!       memory_mb: 64
+//!       content: true
+//! read_audit:
+//!   window_ms: 5000
+//! validate_max_bytes: 67108864
+//! ```
+//!
+//! `levfs-validator`, `levfs-policy` (see [`super::policy`]) and
+//! `levfs-workflow` are built in; any other hook is loaded from its
+//! `library`, or run from its WebAssembly module (see `wasm.rs`). Hooks run
+//! in order of priority, highest first; `priority` defaults to the hook's
+//! own.
+//!
+//! Sync hooks also take `timeout_ms`, `on_failure` (`open` or `closed`),
+//! `errno`, `max_workers` and `breaker: { failures, cooldown_ms, on_open }`,
+//! which control how the mount survives a hook that hangs or fails, and
+//! `audit: true`, which records
+//! what the hook would deny instead of enforcing it (see [`GuardedHook`]).
+//! With `reads: true`, a sync hook also runs before lookups, directory
+//! listings and opens, where it can deny or redact reads (see `hooked.rs`).
+//!
+//! `read_audit` records which files were opened and how much was read from
+//! them in the agent's read log, folding the handles of a path closed within
+//! `window_ms` (default 5000) into one entry (see [`ReadAudit`]).
+//!
+//! `validate_max_bytes` (default 64 MiB) bounds what validating a file at
+//! close reads: larger files reach sync hooks without their content, and
+//! changes needing more original content saved for a rollback fail.
+//!
+//! `levfs-workflow` runs its workflow with `flowmind run <workflow>` unless
+//! given an `executor`: a `command` with `args`, a unix `socket` or an `http`
+//! webhook (see `workflow.rs`).
+
+use super::guard::{
+    parse_errno, FailurePolicy, GuardSettings, GuardedHook, ReasonedHook, Unreasoned,
+};
+use super::hooks::{HookRecorder, RecordedHook};
+use super::policy::PolicyConfig;
+#[cfg(feature = "wasm")]
+use super::wasm::{WasmAsyncHook, WasmHook, WasmHookKind, WasmSettings};
+use super::workflow::{Executor, WorkflowOptions};
+use super::{FsHooks, LevFSPolicy, LevFSValidator, LevFSWorkflow, ReadAudit};
+use crate::opts::HookOptions;
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use lev_reactive::{AsyncHook, HookContext, HookDecision, HookRegistry, Plugin, SyncHook};
+use serde::Deserialize;
+use std::ffi::{c_char, CString};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Name of the built-in validator hook
+const VALIDATOR: &str = "levfs-validator";
+
+/// Name of the built-in policy hook
+const POLICY: &str = "levfs-policy";
+
+/// Name of the built-in workflow hook
+const WORKFLOW: &str = "levfs-workflow";
+
+/// Workflow run by `levfs-workflow` when its config names none
+const DEFAULT_WORKFLOW: &str = "default-workflow";
+
+/// Milliseconds the read audit folds the handles of a path by default
+const DEFAULT_READ_WINDOW_MS: u64 = 5000;
+
+/// Get the default hooks file, `~/.config/lev/reactive/hooks.yaml`
+pub fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("lev").join("reactive").join("hooks.yaml"))
+}
+
+/// Load the hooks for a mount
+///
+/// Reads the `--hooks` file if given, which must exist, or else the default
+/// hooks file if there is one. Without either, no hooks are configured. With
+/// `--hooks-audit`, every sync hook runs in audit mode.
+pub fn load_hooks(options: &HookOptions) -> Result<FsHooks> {
+    let path = match options.config.as_deref() {
+        Some(path) => path.to_path_buf(),
+        None => match default_path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(FsHooks::default()),
+        },
+    };
+    let config = HooksConfig::load(&path)?;
+    config
+        .build(options.audit)
+        .with_context(|| format!("Invalid hooks config {}", path.display()))
+}
+
+/// Whether a hook runs before (sync) or after (async) operations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HookType {
+    Sync,
+    Async,
+}
+
+/// Contents of a `hooks.yaml` file
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HooksConfig {
+    #[serde(default)]
+    pub hooks: Vec<HookConfig>,
+    pub read_audit: Option<ReadAuditConfig>,
+    /// Most bytes of a file read to validate it at close
+    pub validate_max_bytes: Option<u64>,
+}
+
+/// Settings of the read audit
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReadAuditConfig {
+    /// Milliseconds within which the closed handles of a path are folded
+    /// into one read log entry
+    pub window_ms: Option<u64>,
+}
+
+/// A configured hook
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HookConfig {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub hook_type: HookType,
+    /// Overrides the hook's own priority
+    pub priority: Option<i32>,
+    /// Shared library to load the hook from
+    pub library: Option<PathBuf>,
+    /// WebAssembly module to run the hook in, instead of a library
+    pub wasm: Option<WasmConfig>,
+    /// Hook-specific settings
+    #[serde(default)]
+    pub config: serde_yaml::Value,
+    /// Milliseconds to wait for a sync hook; 0 waits without a limit
+    pub timeout_ms: Option<u64>,
+    /// Whether an operation proceeds when a sync hook fails or times out
+    pub on_failure: Option<OnFailure>,
+    /// Errno of a fail-closed sync hook, by name or number
+    pub errno: Option<Errno>,
+    /// Most threads running a sync hook, including ones stuck in calls that
+    /// timed out
+    pub max_workers: Option<usize>,
+    pub breaker: Option<BreakerConfig>,
+    /// Record what a sync hook would deny instead of enforcing it
+    pub audit: Option<bool>,
+    /// Also run a sync hook before lookups, listings and opens
+    pub reads: Option<bool>,
+}
+
+/// Failure policy of a sync hook
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnFailure {
+    Open,
+    Closed,
+}
+
+/// An errno given by name (`EACCES`) or number
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Errno {
+    Number(i32),
+    Name(String),
+}
+
+/// A hook run in a WebAssembly module
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WasmConfig {
+    pub module: PathBuf,
+    /// Fuel each call may consume
+    pub fuel: Option<u64>,
+    /// Largest memory the module may use, in MiB
+    pub memory_mb: Option<usize>,
+    /// Pass the content of `file:close` events to the module
+    #[serde(default)]
+    pub content: bool,
+}
+
+/// Circuit breaker of a sync hook
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BreakerConfig {
+    /// Consecutive failures that disable the hook
+    pub failures: Option<u32>,
+    /// Milliseconds the hook stays disabled
+    pub cooldown_ms: Option<u64>,
+    /// Whether operations proceed while the hook is disabled; defaults to
+    /// `on_failure`
+    pub on_open: Option<OnFailure>,
+}
+
+/// Settings of `levfs-validator`
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ValidatorConfig {
+    max_size: Option<usize>,
+    schema_dir: Option<String>,
+    #[serde(default)]
+    schemas: Vec<SchemaRoute>,
+}
+
+/// Schema applied to files whose path matches a glob
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SchemaRoute {
+    path: String,
+    schema: String,
+}
+
+/// Settings of `levfs-workflow`
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WorkflowConfig {
+    workflow: Option<String>,
+    executor: Option<ExecutorConfig>,
+    debounce_ms: Option<u64>,
+    concurrency: Option<usize>,
+    retries: Option<u32>,
+    backoff_ms: Option<u64>,
+    timeout_ms: Option<u64>,
+    persist: Option<bool>,
+}
+
+/// How `levfs-workflow` runs its workflow
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum ExecutorConfig {
+    Command {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+    Socket {
+        path: PathBuf,
+    },
+    Http {
+        url: String,
+    },
+}
+
+impl WorkflowConfig {
+    fn options(&self) -> Result<WorkflowOptions> {
+        let defaults = WorkflowOptions::default();
+        let executor = match &self.executor {
+            Some(ExecutorConfig::Command { command, args }) => Executor::Command {
+                program: command.clone(),
+                args: args.clone(),
+            },
+            Some(ExecutorConfig::Socket { path }) => Executor::Socket { path: path.clone() },
+            Some(ExecutorConfig::Http { url }) if url.starts_with("http://") => {
+                Executor::Http { url: url.clone() }
+            }
+            Some(ExecutorConfig::Http { url }) => {
+                bail!("Unsupported webhook URL {}, only http:// is supported", url)
+            }
+            None => defaults.executor,
+        };
+        if self.concurrency == Some(0) {
+            bail!("concurrency must be at least 1");
+        }
+        Ok(WorkflowOptions {
+            executor,
+            debounce: self
+                .debounce_ms
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.debounce),
+            concurrency: self.concurrency.unwrap_or(defaults.concurrency),
+            retries: self.retries.unwrap_or(defaults.retries),
+            backoff: self
+                .backoff_ms
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.backoff),
+            timeout: self
+                .timeout_ms
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.timeout),
+            persist: self.persist.unwrap_or(defaults.persist),
+        })
+    }
+}
+
+impl HooksConfig {
+    /// Parse a hooks file
+    pub fn parse(yaml: &str) -> Result<Self> {
+        // An empty file configures no hooks
+        if yaml.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        Ok(serde_yaml::from_str(yaml)?)
+    }
+
+    /// Read and parse a hooks file
+    pub fn load(path: &Path) -> Result<Self> {
+        let yaml = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read hooks config {}", path.display()))?;
+        Self::parse(&yaml).with_context(|| format!("Invalid hooks config {}", path.display()))
+    }
+
+    /// Construct the configured hooks, ordered by priority
+    ///
+    /// `audit` puts every sync hook in audit mode, whatever its config says.
+    pub fn build(&self, audit: bool) -> Result<FsHooks> {
+        let recorder = HookRecorder::default();
+        let mut sync_hooks: Vec<(Arc<Prioritized<dyn SyncHook>>, bool)> = Vec::new();
+        let mut async_hooks: Vec<Prioritized<dyn AsyncHook>> = Vec::new();
+
+        for hook in &self.hooks {
+            match hook.hook_type {
+                HookType::Sync => {
+                    let mut settings = hook
+                        .guard_settings()
+                        .with_context(|| format!("Invalid sync hook '{}'", hook.name))?;
+                    settings.audit |= audit;
+                    if settings.audit {
+                        tracing::info!("LevFS: sync hook {} runs in audit mode", hook.name);
+                    }
+                    let built = hook
+                        .build_sync()
+                        .with_context(|| format!("Failed to load sync hook '{}'", hook.name))?;
+                    let priority = hook.priority.unwrap_or_else(|| built.priority());
+                    let guarded: Box<dyn SyncHook> =
+                        Box::new(GuardedHook::new(built, settings, recorder.clone()));
+                    let reads = hook.reads.unwrap_or(false);
+                    sync_hooks.push((Arc::new(Prioritized::new(guarded, priority)), reads));
+                }
+                HookType::Async => {
+                    if hook.has_guard_settings() {
+                        bail!(
+                            "Invalid async hook '{}': timeout_ms, on_failure, errno, max_workers, breaker, audit and reads only apply to sync hooks",
+                            hook.name
+                        );
+                    }
+                    let built = hook
+                        .build_async(&recorder)
+                        .with_context(|| format!("Failed to load async hook '{}'", hook.name))?;
+                    for built in built {
+                        let priority = hook.priority.unwrap_or_else(|| built.priority());
+                        async_hooks.push(Prioritized::new(built, priority));
+                    }
+                }
+            }
+        }
+
+        // Highest priority first; hooks of equal priority keep file order
+        sync_hooks.sort_by_key(|(hook, _)| std::cmp::Reverse(hook.priority));
+        async_hooks.sort_by_key(|hook| std::cmp::Reverse(hook.priority));
+
+        let sync_registry = (!sync_hooks.is_empty()).then(|| {
+            let mut registry = HookRegistry::new();
+            for (hook, _) in &sync_hooks {
+                tracing::info!(
+                    "LevFS: sync hook {} (priority {})",
+                    hook.name(),
+                    hook.priority
+                );
+                registry.register_sync(Box::new(Shared(hook.clone())));
+            }
+            registry
+        });
+        // A hook run before reads is the same hook, sharing its breaker
+        let read_registry = sync_hooks.iter().any(|(_, reads)| *reads).then(|| {
+            let mut registry = HookRegistry::new();
+            for (hook, _) in sync_hooks.iter().filter(|(_, reads)| *reads) {
+                tracing::info!("LevFS: sync hook {} runs before reads", hook.name());
+                registry.register_sync(Box::new(Shared(hook.clone())));
+            }
+            registry
+        });
+        let async_registry = (!async_hooks.is_empty()).then(|| {
+            let mut registry = HookRegistry::new();
+            for hook in async_hooks {
+                tracing::info!(
+                    "LevFS: async hook {} (priority {})",
+                    hook.name(),
+                    hook.priority
+                );
+                registry.register_async(Box::new(hook));
+            }
+            registry
+        });
+        let read_audit = self.read_audit.as_ref().map(|config| {
+            let window = config.window_ms.unwrap_or(DEFAULT_READ_WINDOW_MS);
+            ReadAudit::new(Duration::from_millis(window))
+        });
+        let mut hooks = FsHooks::new(sync_registry, async_registry, recorder)
+            .with_read_hooks(read_registry)
+            .with_read_audit(read_audit);
+        if let Some(limit) = self.validate_max_bytes {
+            hooks = hooks.with_validate_limit(limit);
+        }
+        Ok(hooks)
+    }
+}
+
+impl HookConfig {
+    fn has_guard_settings(&self) -> bool {
+        self.timeout_ms.is_some()
+            || self.on_failure.is_some()
+            || self.errno.is_some()
+            || self.max_workers.is_some()
+            || self.breaker.is_some()
+            || self.audit.is_some()
+            || self.reads.is_some()
+    }
+
+    /// Timeout, failure policy and breaker of a sync hook, defaulting to a
+    /// one second timeout that fails closed with `EIO`
+    fn guard_settings(&self) -> Result<GuardSettings> {
+        let defaults = GuardSettings::default();
+        let timeout = match self.timeout_ms {
+            Some(0) => None,
+            Some(ms) => Some(Duration::from_millis(ms)),
+            None => defaults.timeout,
+        };
+        let errno = match &self.errno {
+            Some(Errno::Number(errno)) if *errno > 0 => *errno,
+            Some(Errno::Name(name)) => match parse_errno(name) {
+                Some(errno) => errno,
+                None => bail!("Unknown errno '{}'", name),
+            },
+            Some(Errno::Number(errno)) => bail!("Invalid errno {}", errno),
+            None => libc::EIO,
+        };
+        let policy = |on_failure: Option<OnFailure>| match on_failure {
+            Some(OnFailure::Open) => FailurePolicy::Open,
+            Some(OnFailure::Closed) | None => FailurePolicy::Closed(errno),
+        };
+        let breaker = self.breaker.as_ref();
+        let on_failure = policy(self.on_failure);
+        let on_open = policy(breaker.and_then(|b| b.on_open).or(self.on_failure));
+        if self.errno.is_some()
+            && on_failure == FailurePolicy::Open
+            && on_open == FailurePolicy::Open
+        {
+            bail!("errno only applies to `on_failure: closed` or `breaker.on_open: closed`");
+        }
+        let max_workers = self.max_workers.unwrap_or(defaults.max_workers);
+        if max_workers == 0 {
+            bail!("max_workers must be at least 1");
+        }
+        let breaker_failures = breaker
+            .and_then(|b| b.failures)
+            .unwrap_or(defaults.breaker_failures);
+        if breaker_failures == 0 {
+            bail!("breaker.failures must be at least 1");
+        }
+        let breaker_cooldown = breaker
+            .and_then(|b| b.cooldown_ms)
+            .map(Duration::from_millis)
+            .unwrap_or(defaults.breaker_cooldown);
+        Ok(GuardSettings {
+            timeout,
+            on_failure,
+            breaker_failures,
+            breaker_cooldown,
+            on_open,
+            max_workers,
+            audit: self.audit.unwrap_or(false),
+        })
+    }
+
+    fn build_sync(&self) -> Result<Box<dyn ReasonedHook>> {
+        match self.name.as_str() {
+            VALIDATOR => Ok(Box::new(self.build_validator()?)),
+            POLICY => {
+                let config: PolicyConfig = self.settings()?;
+                let policy = LevFSPolicy::new(&config).map_err(|e| anyhow::anyhow!("{}", e))?;
+                Ok(Box::new(policy))
+            }
+            WORKFLOW => bail!("'{}' is an async hook", WORKFLOW),
+            _ if self.wasm.is_some() => self.load_wasm_sync(),
+            _ => Ok(Box::new(Unreasoned(self.load_sync_library()?))),
+        }
+    }
+
+    /// Build the async hooks of this entry, recording their outcomes with
+    /// `recorder`
+    fn build_async(&self, recorder: &HookRecorder) -> Result<Vec<Box<dyn AsyncHook>>> {
+        match self.name.as_str() {
+            WORKFLOW => {
+                let config: WorkflowConfig = self.settings()?;
+                let workflow = config.workflow.as_deref().unwrap_or(DEFAULT_WORKFLOW);
+                // The workflow hook records its runs itself, once they finish
+                let plugin = LevFSWorkflow::new(workflow)
+                    .with_options(config.options()?)
+                    .with_recorder(recorder.clone());
+                Ok(plugin.async_hooks())
+            }
+            VALIDATOR | POLICY => bail!("'{}' is a sync hook", self.name),
+            _ if self.wasm.is_some() => Ok(vec![Box::new(RecordedHook::new(
+                self.load_wasm_async()?,
+                recorder.clone(),
+            ))]),
+            _ => Ok(self
+                .load_async_library()?
+                .into_iter()
+                .map(|hook| {
+                    Box::new(RecordedHook::new(hook, recorder.clone())) as Box<dyn AsyncHook>
+                })
+                .collect()),
+        }
+    }
+
+    fn build_validator(&self) -> Result<LevFSValidator> {
+        let config: ValidatorConfig = self.settings()?;
+        let mut validator = LevFSValidator::new();
+        if let Some(max_size) = config.max_size {
+            validator = validator.with_max_size(max_size);
+        }
+        if let Some(schema_dir) = &config.schema_dir {
+            validator = validator.with_schema_dir(expand_home(schema_dir));
+        }
+        for route in &config.schemas {
+            validator = validator
+                .with_schema_route(&route.path, &route.schema)
+                .map_err(|e| anyhow::anyhow!("{}", e))
+                .with_context(|| format!("Invalid schema route '{}'", route.path))?;
+        }
+        Ok(validator)
+    }
+
+    /// Deserialize the `config` block, treating a missing block as empty
+    fn settings<T: Default + serde::de::DeserializeOwned>(&self) -> Result<T> {
+        if self.config.is_null() {
+            return Ok(T::default());
+        }
+        serde_yaml::from_value(self.config.clone()).context("Invalid config")
+    }
+
+    /// The `config` block as JSON, or `None` without one
+    fn config_json(&self) -> Result<Option<String>> {
+        if self.config.is_null() {
+            return Ok(None);
+        }
+        Ok(Some(
+            serde_json::to_string(&self.config).context("Invalid config")?,
+        ))
+    }
+
+    /// The `config` block as JSON for a library, or `None` without one
+    fn config_c_string(&self) -> Result<Option<CString>> {
+        Ok(match self.config_json()? {
+            Some(json) => Some(CString::new(json)?),
+            None => None,
+        })
+    }
+
+    /// Load the hook's WebAssembly module, calling its pre-op or post-op
+    /// function
+    #[cfg(feature = "wasm")]
+    fn load_wasm(&self, kind: WasmHookKind) -> Result<WasmHook> {
+        let Some(wasm) = &self.wasm else {
+            bail!("no `wasm` module given");
+        };
+        if self.library.is_some() {
+            bail!("`library` and `wasm` cannot both be given");
+        }
+        let defaults = WasmSettings::default();
+        let settings = WasmSettings {
+            fuel: wasm.fuel.unwrap_or(defaults.fuel),
+            memory: wasm
+                .memory_mb
+                .map(|mb| mb * 1024 * 1024)
+                .unwrap_or(defaults.memory),
+            content: wasm.content,
+        };
+        WasmHook::load(
+            &self.name,
+            &wasm.module,
+            kind,
+            settings,
+            self.config_json()?,
+        )
+    }
+
+    #[cfg(feature = "wasm")]
+    fn load_wasm_sync(&self) -> Result<Box<dyn ReasonedHook>> {
+        Ok(Box::new(self.load_wasm(WasmHookKind::Pre)?))
+    }
+
+    #[cfg(feature = "wasm")]
+    fn load_wasm_async(&self) -> Result<Box<dyn AsyncHook>> {
+        let hook = self.load_wasm(WasmHookKind::Post)?;
+        Ok(Box::new(WasmAsyncHook::new(hook)))
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    fn load_wasm_sync(&self) -> Result<Box<dyn ReasonedHook>> {
+        bail!("agentfs was built without WebAssembly hooks (feature `wasm`)")
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    fn load_wasm_async(&self) -> Result<Box<dyn AsyncHook>> {
+        bail!("agentfs was built without WebAssembly hooks (feature `wasm`)")
+    }
+
+    fn library(&self) -> Result<&Path> {
+        match &self.library {
+            Some(library) => Ok(library),
+            None => bail!("not a built-in hook, and no `library` given"),
+        }
+    }
+
+    /// Load a sync hook from a library exporting `create_plugin()`, or
+    /// `create_plugin_with_config()` when the hook has a `config` block
+    fn load_sync_library(&self) -> Result<Box<dyn SyncHook>> {
+        let library = open_library(self.library()?)?;
+        let ptr = match self.config_c_string()? {
+            // SAFETY: the symbols are declared with these signatures by the
+            // plugin ABI (see `validator.rs`)
+            Some(config) => unsafe {
+                let create = library
+                    .get::<CreateSyncHookWithConfig>(b"create_plugin_with_config\0")
+                    .context("Library takes no config (no `create_plugin_with_config`)")?;
+                create(config.as_ptr())
+            },
+            None => unsafe {
+                let create = library
+                    .get::<CreateSyncHook>(b"create_plugin\0")
+                    .context("Library exports no `create_plugin`")?;
+                create()
+            },
+        };
+        if ptr.is_null() {
+            bail!("Plugin failed to initialize");
+        }
+        // The hook's code must outlive it, which is the rest of the process
+        std::mem::forget(library);
+        // SAFETY: the plugin returned a pointer from `Box::into_raw`
+        Ok(unsafe { Box::from_raw(ptr) })
+    }
+
+    /// Load the async hooks of a plugin from a library exporting
+    /// `_plugin_create()`, or `_plugin_create_with_config()` when the hook has
+    /// a `config` block
+    fn load_async_library(&self) -> Result<Vec<Box<dyn AsyncHook>>> {
+        let library = open_library(self.library()?)?;
+        let ptr = match self.config_c_string()? {
+            // SAFETY: the symbols are declared with these signatures by the
+            // plugin ABI (see `workflow.rs`)
+            Some(config) => unsafe {
+                let create = library
+                    .get::<CreatePluginWithConfig>(b"_plugin_create_with_config\0")
+                    .context("Library takes no config (no `_plugin_create_with_config`)")?;
+                create(config.as_ptr())
+            },
+            None => unsafe {
+                let create = library
+                    .get::<CreatePlugin>(b"_plugin_create\0")
+                    .context("Library exports no `_plugin_create`")?;
+                create()
+            },
+        };
+        if ptr.is_null() {
+            bail!("Plugin failed to initialize");
+        }
+        std::mem::forget(library);
+        // SAFETY: the plugin returned a pointer from `Box::into_raw`
+        let plugin = unsafe { Box::from_raw(ptr) };
+        let hooks = plugin.async_hooks();
+        if hooks.is_empty() {
+            bail!(
+                "Plugin '{}' provides no async hooks",
+                plugin.metadata().name
+            );
+        }
+        Ok(hooks)
+    }
+}
+
+#[allow(improper_ctypes_definitions)]
+type CreateSyncHook = unsafe extern "C" fn() -> *mut dyn SyncHook;
+#[allow(improper_ctypes_definitions)]
+type CreateSyncHookWithConfig = unsafe extern "C" fn(*const c_char) -> *mut dyn SyncHook;
+#[allow(improper_ctypes_definitions)]
+type CreatePlugin = unsafe extern "C" fn() -> *mut dyn Plugin;
+#[allow(improper_ctypes_definitions)]
+type CreatePluginWithConfig = unsafe extern "C" fn(*const c_char) -> *mut dyn Plugin;
+
+fn open_library(path: &Path) -> Result<libloading::Library> {
+    // SAFETY: loading a library runs its initializers; hook libraries are
+    // trusted like any other code the user configures
+    unsafe { libloading::Library::new(path) }
+        .with_context(|| format!("Failed to load library {}", path.display()))
+}
+
+/// Expand a leading `~/` to the home directory
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// A hook whose priority is set by the config
+struct Prioritized<H: ?Sized> {
+    hook: Box<H>,
+    priority: i32,
+}
+
+impl<H: ?Sized> Prioritized<H> {
+    fn new(hook: Box<H>, priority: i32) -> Self {
+        Self { hook, priority }
+    }
+}
+
+impl SyncHook for Prioritized<dyn SyncHook> {
+    fn name(&self) -> &str {
+        self.hook.name()
+    }
+
+    fn execute(&self, context: &HookContext) -> lev_reactive::Result<HookDecision> {
+        self.hook.execute(context)
+    }
+
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+}
+
+/// A sync hook registered in more than one registry
+struct Shared(Arc<Prioritized<dyn SyncHook>>);
+
+impl SyncHook for Shared {
+    fn name(&self) -> &str {
+        self.0.name()
+    }
+
+    fn execute(&self, context: &HookContext) -> lev_reactive::Result<HookDecision> {
+        self.0.execute(context)
+    }
+
+    fn priority(&self) -> i32 {
+        self.0.priority()
+    }
+}
+
+#[async_trait]
+impl AsyncHook for Prioritized<dyn AsyncHook> {
+    fn name(&self) -> &str {
+        self.hook.name()
+    }
+
+    async fn execute(&self, context: &HookContext) -> lev_reactive::Result<HookDecision> {
+        self.hook.execute(context).await
+    }
+
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hooks_config() {
+        let config = HooksConfig::parse(
+            r#"
+hooks:
+  - name: levfs-validator
+    type: sync
+    priority: 100
+    config:
+      max_size: 1024
+  - name: my-policy
+    type: async
+    library: /tmp/libmy_policy.so
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.hooks.len(), 2);
+        assert_eq!(config.hooks[0].hook_type, HookType::Sync);
+        assert_eq!(config.hooks[0].priority, Some(100));
+        assert_eq!(
+            config.hooks[1].library.as_deref(),
+            Some(Path::new("/tmp/libmy_policy.so"))
+        );
+        assert!(config.hooks[1].config.is_null());
+
+        let config = HooksConfig::parse(
+            "hooks:\n  - name: x\n    type: sync\n    wasm:\n      module: /tmp/x.wasm\n      memory_mb: 16\n",
+        )
+        .unwrap();
+        let wasm = config.hooks[0].wasm.as_ref().unwrap();
+        assert_eq!(wasm.module, Path::new("/tmp/x.wasm"));
+        assert_eq!(wasm.memory_mb, Some(16));
+        assert!(!wasm.content);
+
+        assert!(HooksConfig::parse("").unwrap().hooks.is_empty());
+        assert!(HooksConfig::parse("hooks:\n  - name: x\n    type: later\n").is_err());
+    }
+
+    #[test]
+    fn test_guard_settings() {
+        let config = HooksConfig::parse(
+            r#"
+hooks:
+  - name: levfs-validator
+    type: sync
+    timeout_ms: 250
+    errno: EACCES
+    breaker:
+      failures: 3
+      cooldown_ms: 1000
+      on_open: open
+  - name: levfs-validator
+    type: sync
+    timeout_ms: 0
+    on_failure: open
+    max_workers: 2
+    audit: true
+"#,
+        )
+        .unwrap();
+        let settings = config.hooks[0].guard_settings().unwrap();
+        assert_eq!(settings.timeout, Some(Duration::from_millis(250)));
+        assert_eq!(settings.on_failure, FailurePolicy::Closed(libc::EACCES));
+        assert!(!settings.audit);
+        assert_eq!(settings.breaker_failures, 3);
+        assert_eq!(settings.breaker_cooldown, Duration::from_secs(1));
+        assert_eq!(settings.on_open, FailurePolicy::Open);
+        assert_eq!(settings.max_workers, 16);
+        let settings = config.hooks[1].guard_settings().unwrap();
+        assert_eq!(settings.timeout, None);
+        assert_eq!(settings.on_failure, FailurePolicy::Open);
+        assert_eq!(settings.on_open, FailurePolicy::Open);
+        assert_eq!(settings.max_workers, 2);
+        assert!(settings.audit);
+    }
+
+    #[test]
+    fn test_workflow_options() {
+        let config = HooksConfig::parse(
+            r#"
+hooks:
+  - name: levfs-workflow
+    type: async
+    config:
+      workflow: lint
+      executor:
+        type: command
+        command: /usr/local/bin/runner
+        args: ["{workflow}", "--path", "{path}"]
+      debounce_ms: 0
+      retries: 5
+      timeout_ms: 2000
+  - name: levfs-workflow
+    type: async
+    config:
+      executor:
+        type: socket
+        path: /run/lev/workflows.sock
+"#,
+        )
+        .unwrap();
+        let settings: WorkflowConfig = config.hooks[0].settings().unwrap();
+        let options = settings.options().unwrap();
+        assert_eq!(
+            options.executor,
+            Executor::Command {
+                program: "/usr/local/bin/runner".to_string(),
+                args: vec!["{workflow}".into(), "--path".into(), "{path}".into()],
+            }
+        );
+        assert_eq!(options.debounce, Duration::ZERO);
+        assert_eq!(options.retries, 5);
+        assert_eq!(options.timeout, Duration::from_secs(2));
+        assert_eq!(options.concurrency, 4);
+        assert!(options.persist);
+
+        let settings: WorkflowConfig = config.hooks[1].settings().unwrap();
+        assert_eq!(
+            settings.options().unwrap().executor,
+            Executor::Socket {
+                path: PathBuf::from("/run/lev/workflows.sock")
+            }
+        );
+        assert!(HooksConfig::parse(
+            "hooks:\n  - name: levfs-workflow\n    type: async\n    config:\n      executor:\n        type: pipe\n",
+        )
+        .unwrap()
+        .hooks[0]
+            .settings::<WorkflowConfig>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_build_hooks_config() {
+        let config = HooksConfig::parse(
+            "hooks:\n  - name: levfs-validator\n    type: sync\n    config:\n      max_size: 1024\n  - name: levfs-policy\n    type: sync\n",
+        )
+        .unwrap();
+        let hooks = config.build(false).unwrap();
+        assert!(hooks.has_sync_hooks());
+        assert!(!hooks.has_read_hooks());
+        assert!(hooks.read_audit().is_none());
+
+        let config = HooksConfig::parse(
+            "hooks:\n  - name: levfs-policy\n    type: sync\n    reads: true\n  - name: levfs-validator\n    type: sync\nread_audit:\n  window_ms: 100\n",
+        )
+        .unwrap();
+        let hooks = config.build(false).unwrap();
+        assert!(hooks.has_sync_hooks());
+        assert!(hooks.has_read_hooks());
+        assert!(hooks.read_audit().is_some());
+
+        let errors = [
+            "hooks:\n  - name: levfs-validator\n    type: async\n",
+            "hooks:\n  - name: levfs-validator\n    type: sync\n    config:\n      max: 1\n",
+            "hooks:\n  - name: my-policy\n    type: sync\n",
+            "hooks:\n  - name: levfs-validator\n    type: sync\n    errno: ENOPE\n",
+            "hooks:\n  - name: levfs-validator\n    type: sync\n    on_failure: open\n    errno: EIO\n",
+            "hooks:\n  - name: levfs-workflow\n    type: async\n    timeout_ms: 100\n",
+            "hooks:\n  - name: levfs-workflow\n    type: async\n    reads: true\n",
+            "hooks:\n  - name: levfs-policy\n    type: async\n",
+            "hooks:\n  - name: levfs-workflow\n    type: async\n    config:\n      concurrency: 0\n",
+            "hooks:\n  - name: levfs-workflow\n    type: async\n    config:\n      executor:\n        type: http\n        url: https://example.com/\n",
+            "hooks:\n  - name: x\n    type: sync\n    wasm:\n      module: /nonexistent/x.wasm\n",
+            "hooks:\n  - name: levfs-policy\n    type: sync\n    config:\n      rules:\n        - ops: [read]\n          action: deny\n",
+        ];
+        for yaml in errors {
+            assert!(
+                HooksConfig::parse(yaml).unwrap().build(false).is_err(),
+                "{yaml}"
+            );
+        }
+    }
+}
@@ -0,0 +1,844 @@
+//! Timeouts, failure policy and circuit breaking for sync hooks.
+//!
+//! Sync hooks run inline with the filesystem operation they gate, so a hook
+//! that hangs would hang every process using the mount. [`GuardedHook`] wraps
+//! a configured sync hook and:
+//!
+//! - runs it on a pool of worker threads and stops waiting for the reply
+//!   after a timeout,
+//! - turns a timeout, error or panic into the hook's [`FailurePolicy`],
+//! - stops calling the hook for a cooldown after repeated failures, during
+//!   which operations get the breaker's own policy, and
+//! - in audit mode, records what the hook would have denied and lets the
+//!   operation proceed.
+//!
+//! Every outcome is recorded in the hook log with how long the hook took,
+//! except reads the hook allowed.
+//!
+//! Built-in hooks implement [`ReasonedHook`] to give the reason for a denial
+//! with their decision, since `Deny` cannot carry one.
+//!
+//! A hook call that timed out keeps its worker busy until it returns, so a
+//! new worker takes its place. A hook has at most `max_workers` workers,
+//! stuck ones included; a call finding all of them busy is rejected as a
+//! failure instead of starting another.
+
+use super::hooks::{decision_errno, display_path, log_decision, HookOp, HookRecorder};
+use agentfs_sdk::HookLogDecision;
+use lev_reactive::{HookContext, HookDecision, LevError, SyncHook};
+use parking_lot::Mutex;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+/// What a sync hook failure means for the operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailurePolicy {
+    /// Let the operation proceed
+    Open,
+    /// Fail the operation with an errno
+    Closed(i32),
+}
+
+impl fmt::Display for FailurePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FailurePolicy::Open => f.write_str("fail-open"),
+            FailurePolicy::Closed(errno) => write!(f, "fail-closed, {}", errno_name(*errno)),
+        }
+    }
+}
+
+/// Settings of a [`GuardedHook`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GuardSettings {
+    /// How long to wait for the hook; `None` runs it inline without a limit
+    pub timeout: Option<Duration>,
+    pub on_failure: FailurePolicy,
+    /// Consecutive failures that open the breaker
+    pub breaker_failures: u32,
+    /// How long an open breaker skips the hook
+    pub breaker_cooldown: Duration,
+    /// What operations get while an open breaker skips the hook
+    pub on_open: FailurePolicy,
+    /// Most workers running the hook at once, including ones stuck in calls
+    /// that timed out
+    pub max_workers: usize,
+    /// Record denials instead of enforcing them
+    pub audit: bool,
+}
+
+impl Default for GuardSettings {
+    fn default() -> Self {
+        Self {
+            timeout: Some(Duration::from_secs(1)),
+            on_failure: FailurePolicy::Closed(libc::EIO),
+            breaker_failures: 5,
+            breaker_cooldown: Duration::from_secs(30),
+            on_open: FailurePolicy::Closed(libc::EIO),
+            max_workers: 16,
+            audit: false,
+        }
+    }
+}
+
+/// Counters of a guarded hook
+#[derive(Debug, Default)]
+pub struct HookMetrics {
+    pub calls: AtomicU64,
+    pub errors: AtomicU64,
+    pub timeouts: AtomicU64,
+    /// Calls rejected because every worker was busy
+    pub rejected: AtomicU64,
+    /// Calls skipped while the breaker was open
+    pub skipped: AtomicU64,
+    /// Times the breaker opened
+    pub trips: AtomicU64,
+    /// Denials recorded but not enforced in audit mode
+    pub audited: AtomicU64,
+}
+
+impl fmt::Display for HookMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} calls, {} errors, {} timeouts, {} rejected, {} skipped, breaker opened {} times, {} audited denials",
+            self.calls.load(Ordering::Relaxed),
+            self.errors.load(Ordering::Relaxed),
+            self.timeouts.load(Ordering::Relaxed),
+            self.rejected.load(Ordering::Relaxed),
+            self.skipped.load(Ordering::Relaxed),
+            self.trips.load(Ordering::Relaxed),
+            self.audited.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// Outcome of a guarded hook call before audit mode is applied
+struct Evaluation {
+    outcome: lev_reactive::Result<HookDecision>,
+    /// Reason for a denial, audit or failure, if known
+    reason: Option<String>,
+    /// Policy the outcome comes from, if the call failed or was skipped
+    failed: Option<FailurePolicy>,
+}
+
+/// Why a hook call failed
+enum Failure {
+    Error(LevError),
+    Timeout,
+    /// Every worker was busy, most likely stuck in calls that timed out
+    Saturated,
+    Panic,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Error(e) => write!(f, "failed: {}", e),
+            Failure::Timeout => f.write_str("timed out"),
+            Failure::Saturated => f.write_str("rejected, all workers busy"),
+            Failure::Panic => f.write_str("panicked"),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Breaker {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+/// A sync hook's decision with the reason the hook gave for it
+#[derive(Debug)]
+pub struct Ruling {
+    pub decision: HookDecision,
+    /// Why the hook denies, or why an `Allow` is recorded as an audit
+    pub reason: Option<String>,
+}
+
+impl Ruling {
+    /// Deny the operation for `reason`
+    pub fn deny(reason: impl Into<String>) -> Self {
+        Self {
+            decision: HookDecision::Deny,
+            reason: Some(reason.into()),
+        }
+    }
+
+    /// Let the operation proceed, giving the hook log an `audit` entry with
+    /// `reason` as a denial in audit mode would
+    pub fn audit(reason: impl Into<String>) -> Self {
+        Self {
+            decision: HookDecision::Allow,
+            reason: Some(reason.into()),
+        }
+    }
+}
+
+impl From<HookDecision> for Ruling {
+    fn from(decision: HookDecision) -> Self {
+        Self {
+            decision,
+            reason: None,
+        }
+    }
+}
+
+/// A sync hook that gives reasons for its decisions
+///
+/// Its `SyncHook::execute` returns the decision of [`ReasonedHook::judge`]
+/// without the reason.
+pub trait ReasonedHook: SyncHook {
+    fn judge(&self, context: &HookContext) -> lev_reactive::Result<Ruling>;
+}
+
+/// A sync hook that gives no reasons, like one loaded from a library
+pub struct Unreasoned(pub Box<dyn SyncHook>);
+
+impl SyncHook for Unreasoned {
+    fn name(&self) -> &str {
+        self.0.name()
+    }
+
+    fn execute(&self, context: &HookContext) -> lev_reactive::Result<HookDecision> {
+        self.0.execute(context)
+    }
+
+    fn priority(&self) -> i32 {
+        self.0.priority()
+    }
+}
+
+impl ReasonedHook for Unreasoned {
+    fn judge(&self, context: &HookContext) -> lev_reactive::Result<Ruling> {
+        self.0.execute(context).map(Ruling::from)
+    }
+}
+
+/// Workers a hook keeps once the calls that timed out have returned
+const WORKERS: usize = 4;
+
+/// A hook call for a worker, with where to send the outcome
+struct Job {
+    context: HookContext,
+    reply: mpsc::SyncSender<Result<Ruling, Failure>>,
+}
+
+/// State the workers of a hook share
+struct Pool {
+    hook: Arc<dyn ReasonedHook>,
+    jobs: Mutex<mpsc::Receiver<Job>>,
+    /// Workers waiting for a job
+    idle: AtomicUsize,
+    /// Running workers, including ones stuck in a call that timed out
+    workers: AtomicUsize,
+}
+
+impl Pool {
+    /// Run jobs until the hook is dropped, or until this worker is one more
+    /// than the pool keeps
+    fn work(&self) {
+        loop {
+            let job = self.jobs.lock().recv();
+            let Ok(job) = job else {
+                return;
+            };
+            self.idle.fetch_sub(1, Ordering::SeqCst);
+            let outcome =
+                match panic::catch_unwind(AssertUnwindSafe(|| self.hook.judge(&job.context))) {
+                    Ok(outcome) => outcome.map_err(Failure::Error),
+                    Err(_) => Err(Failure::Panic),
+                };
+            let retire = self
+                .workers
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                    (n > WORKERS).then(|| n - 1)
+                })
+                .is_ok();
+            // Idle before replying, so the caller's next call finds this worker
+            if !retire {
+                self.idle.fetch_add(1, Ordering::SeqCst);
+            }
+            // The caller is gone if the call timed out
+            let _ = job.reply.send(outcome);
+            if retire {
+                return;
+            }
+        }
+    }
+}
+
+/// A sync hook with a timeout, failure policy and circuit breaker
+pub struct GuardedHook {
+    hook: Arc<dyn ReasonedHook>,
+    settings: GuardSettings,
+    breaker: Mutex<Breaker>,
+    metrics: Arc<HookMetrics>,
+    recorder: HookRecorder,
+    pool: Arc<Pool>,
+    /// Queue of the pool's workers, closed when the hook is dropped
+    jobs: mpsc::Sender<Job>,
+}
+
+impl GuardedHook {
+    /// Guard a hook, recording its decisions with `recorder`
+    pub fn new(
+        hook: Box<dyn ReasonedHook>,
+        settings: GuardSettings,
+        recorder: HookRecorder,
+    ) -> Self {
+        let hook: Arc<dyn ReasonedHook> = Arc::from(hook);
+        let (jobs, queue) = mpsc::channel();
+        Self {
+            pool: Arc::new(Pool {
+                hook: hook.clone(),
+                jobs: Mutex::new(queue),
+                idle: AtomicUsize::new(0),
+                workers: AtomicUsize::new(0),
+            }),
+            jobs,
+            hook,
+            settings,
+            breaker: Mutex::new(Breaker::default()),
+            metrics: Arc::new(HookMetrics::default()),
+            recorder,
+        }
+    }
+
+    /// Counters of this hook, updated as it runs
+    pub fn metrics(&self) -> Arc<HookMetrics> {
+        self.metrics.clone()
+    }
+
+    /// Start another worker; `Ok(false)` if the hook has `max_workers`
+    fn spawn_worker(&self) -> std::io::Result<bool> {
+        let max_workers = self.settings.max_workers;
+        let reserved = self
+            .pool
+            .workers
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < max_workers).then(|| n + 1)
+            });
+        if reserved.is_err() {
+            return Ok(false);
+        }
+        self.pool.idle.fetch_add(1, Ordering::SeqCst);
+        let pool = self.pool.clone();
+        let spawned = std::thread::Builder::new()
+            .name(format!("hook-{}", self.hook.name()))
+            .spawn(move || pool.work());
+        if let Err(e) = spawned {
+            self.pool.workers.fetch_sub(1, Ordering::SeqCst);
+            self.pool.idle.fetch_sub(1, Ordering::SeqCst);
+            return Err(e);
+        }
+        Ok(true)
+    }
+
+    fn run(&self, context: &HookContext) -> Result<Ruling, Failure> {
+        let Some(timeout) = self.settings.timeout else {
+            return self.hook.judge(context).map_err(Failure::Error);
+        };
+
+        if self.pool.idle.load(Ordering::SeqCst) == 0 {
+            let workers = self.pool.workers.load(Ordering::SeqCst);
+            if workers >= self.settings.max_workers {
+                return Err(Failure::Saturated);
+            }
+            if workers < WORKERS {
+                self.spawn_worker()
+                    .map_err(|e| Failure::Error(LevError::Io(e)))?;
+            }
+        }
+        let (reply, outcome) = mpsc::sync_channel(1);
+        let job = Job {
+            context: HookContext {
+                event_type: context.event_type.clone(),
+                source: context.source.clone(),
+                data: context.data.clone(),
+            },
+            reply,
+        };
+        if self.jobs.send(job).is_err() {
+            return Err(Failure::Panic);
+        }
+        match outcome.recv_timeout(timeout) {
+            Ok(outcome) => outcome,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                // The call keeps its worker busy until it returns; past
+                // `max_workers`, later calls are rejected until it does
+                if let Err(e) = self.spawn_worker() {
+                    tracing::warn!(
+                        "LevFS: hook {}: failed to start a worker: {}",
+                        self.hook.name(),
+                        e
+                    );
+                }
+                Err(Failure::Timeout)
+            }
+            // Workers reply even when the hook panics
+            Err(mpsc::RecvTimeoutError::Disconnected) => Err(Failure::Panic),
+        }
+    }
+
+    /// The outcome of a call failed or skipped under `policy`
+    fn fail(&self, policy: FailurePolicy) -> lev_reactive::Result<HookDecision> {
+        match policy {
+            FailurePolicy::Open => Ok(HookDecision::Allow),
+            FailurePolicy::Closed(errno) => {
+                Err(LevError::Io(std::io::Error::from_raw_os_error(errno)))
+            }
+        }
+    }
+
+    fn record_success(&self) {
+        let mut breaker = self.breaker.lock();
+        if breaker.open_until.take().is_some() {
+            tracing::info!("LevFS: hook {} recovered, breaker closed", self.hook.name());
+        }
+        breaker.consecutive_failures = 0;
+    }
+
+    fn record_failure(&self, context: &HookContext, failure: &Failure) {
+        match failure {
+            Failure::Timeout => self.metrics.timeouts.fetch_add(1, Ordering::Relaxed),
+            Failure::Saturated => self.metrics.rejected.fetch_add(1, Ordering::Relaxed),
+            Failure::Error(_) | Failure::Panic => {
+                self.metrics.errors.fetch_add(1, Ordering::Relaxed)
+            }
+        };
+        tracing::warn!(
+            "LevFS: hook {} {} on {} {} ({})",
+            self.hook.name(),
+            failure,
+            context.event_type,
+            display_path(&context.data),
+            self.settings.on_failure
+        );
+
+        let mut breaker = self.breaker.lock();
+        breaker.consecutive_failures += 1;
+        if breaker.consecutive_failures >= self.settings.breaker_failures {
+            breaker.open_until = Some(Instant::now() + self.settings.breaker_cooldown);
+            self.metrics.trips.fetch_add(1, Ordering::Relaxed);
+            tracing::warn!(
+                "LevFS: hook {} failed {} times in a row, skipping it for {:?} ({})",
+                self.hook.name(),
+                breaker.consecutive_failures,
+                self.settings.breaker_cooldown,
+                self.settings.on_open
+            );
+        }
+    }
+
+    /// Run the hook unless the breaker is open, applying the failure policy
+    fn evaluate(&self, context: &HookContext) -> Evaluation {
+        if self.is_open() {
+            self.metrics.skipped.fetch_add(1, Ordering::Relaxed);
+            return Evaluation {
+                outcome: self.fail(self.settings.on_open),
+                reason: Some("hook disabled after repeated failures".to_string()),
+                failed: Some(self.settings.on_open),
+            };
+        }
+        self.metrics.calls.fetch_add(1, Ordering::Relaxed);
+        match self.run(context) {
+            Ok(Ruling { decision, reason }) => {
+                self.record_success();
+                Evaluation {
+                    outcome: Ok(decision),
+                    reason,
+                    failed: None,
+                }
+            }
+            Err(failure) => {
+                self.record_failure(context, &failure);
+                Evaluation {
+                    outcome: self.fail(self.settings.on_failure),
+                    reason: Some(format!("hook {}", failure)),
+                    failed: Some(self.settings.on_failure),
+                }
+            }
+        }
+    }
+
+    /// Whether the breaker is open; once the cooldown has passed, one call
+    /// is let through to probe the hook
+    fn is_open(&self) -> bool {
+        let mut breaker = self.breaker.lock();
+        match breaker.open_until {
+            Some(until) if Instant::now() < until => true,
+            Some(_) => {
+                // Keep the breaker open for other calls while this one probes
+                breaker.open_until = Some(Instant::now() + self.settings.breaker_cooldown);
+                false
+            }
+            None => false,
+        }
+    }
+}
+
+impl SyncHook for GuardedHook {
+    fn name(&self) -> &str {
+        self.hook.name()
+    }
+
+    fn execute(&self, context: &HookContext) -> lev_reactive::Result<HookDecision> {
+        let start = Instant::now();
+        let Evaluation {
+            outcome,
+            reason,
+            failed,
+        } = self.evaluate(context);
+        let latency = start.elapsed();
+
+        if self.settings.audit && decision_errno(&outcome).is_some() {
+            self.metrics.audited.fetch_add(1, Ordering::Relaxed);
+            tracing::info!(
+                "LevFS: audit: hook {} would deny {} {}: {}",
+                self.hook.name(),
+                context.event_type,
+                display_path(&context.data),
+                reason.as_deref().unwrap_or("no reason given")
+            );
+            self.recorder.record(
+                context,
+                self.hook.name(),
+                HookLogDecision::Audit,
+                reason,
+                latency,
+            );
+            return Ok(HookDecision::Allow);
+        }
+
+        let (decision, message) = match (&outcome, reason, failed) {
+            (_, Some(reason), Some(policy)) => (
+                HookLogDecision::Error,
+                Some(format!("{} ({})", reason, policy)),
+            ),
+            (Ok(HookDecision::Deny), reason, _) => (HookLogDecision::Deny, reason),
+            // Only an `Allow` the hook asked to audit comes with a reason
+            (Ok(HookDecision::Allow), Some(reason), _) => (HookLogDecision::Audit, Some(reason)),
+            _ => log_decision(&outcome),
+        };
+        // Reads are too frequent to log; only what hooks did about them is
+        let routine_read = decision == HookLogDecision::Allow
+            && HookOp::parse(&context.event_type).is_some_and(|op| op.is_read());
+        if !routine_read {
+            self.recorder
+                .record(context, self.hook.name(), decision, message, latency);
+        }
+        outcome
+    }
+
+    fn priority(&self) -> i32 {
+        self.hook.priority()
+    }
+}
+
+impl Drop for GuardedHook {
+    fn drop(&mut self) {
+        tracing::info!("LevFS: hook {}: {}", self.hook.name(), self.metrics);
+    }
+}
+
+/// Errnos a hook config can name
+const ERRNOS: &[(&str, i32)] = &[
+    ("EPERM", libc::EPERM),
+    ("EIO", libc::EIO),
+    ("EAGAIN", libc::EAGAIN),
+    ("EACCES", libc::EACCES),
+    ("EBUSY", libc::EBUSY),
+    ("EINVAL", libc::EINVAL),
+    ("EFBIG", libc::EFBIG),
+    ("ENOSPC", libc::ENOSPC),
+    ("EROFS", libc::EROFS),
+    ("ETIMEDOUT", libc::ETIMEDOUT),
+];
+
+/// Parse an errno given by name (`EACCES`) or number
+pub fn parse_errno(s: &str) -> Option<i32> {
+    ERRNOS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(s))
+        .map(|(_, errno)| *errno)
+        .or_else(|| s.parse().ok().filter(|errno| *errno > 0))
+}
+
+/// Name of an errno for log messages
+fn errno_name(errno: i32) -> String {
+    ERRNOS
+        .iter()
+        .find(|(_, e)| *e == errno)
+        .map(|(name, _)| name.to_string())
+        .unwrap_or_else(|| format!("errno {}", errno))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+
+    struct TestHook {
+        hang: Arc<AtomicBool>,
+    }
+
+    impl SyncHook for TestHook {
+        fn name(&self) -> &str {
+            "test"
+        }
+
+        fn execute(&self, context: &HookContext) -> lev_reactive::Result<HookDecision> {
+            self.judge(context).map(|ruling| ruling.decision)
+        }
+
+        fn priority(&self) -> i32 {
+            0
+        }
+    }
+
+    impl ReasonedHook for TestHook {
+        fn judge(&self, _context: &HookContext) -> lev_reactive::Result<Ruling> {
+            if self.hang.load(Ordering::Relaxed) {
+                std::thread::sleep(Duration::from_millis(200));
+            }
+            Ok(Ruling::deny("test denial"))
+        }
+    }
+
+    fn context() -> HookContext {
+        HookContext {
+            event_type: "file:write".to_string(),
+            source: "levfs".to_string(),
+            data: serde_json::json!({ "path": "/a.md" }),
+        }
+    }
+
+    /// A guarded hook that hangs until the returned flag is cleared
+    fn guarded(on_failure: FailurePolicy) -> (GuardedHook, Arc<HookMetrics>, Arc<AtomicBool>) {
+        let hang = Arc::new(AtomicBool::new(true));
+        let hook = TestHook { hang: hang.clone() };
+        let settings = GuardSettings {
+            timeout: Some(Duration::from_millis(20)),
+            on_failure,
+            breaker_failures: 2,
+            breaker_cooldown: Duration::from_millis(300),
+            on_open: on_failure,
+            max_workers: 16,
+            audit: false,
+        };
+        let guarded = GuardedHook::new(Box::new(hook), settings, HookRecorder::default());
+        let metrics = guarded.metrics();
+        (guarded, metrics, hang)
+    }
+
+    #[test]
+    fn test_timeout_failure_policy() {
+        let (hook, _, _) = guarded(FailurePolicy::Open);
+        assert!(matches!(hook.execute(&context()), Ok(HookDecision::Allow)));
+
+        let (hook, metrics, _) = guarded(FailurePolicy::Closed(libc::EACCES));
+        assert_eq!(
+            decision_errno(&hook.execute(&context())),
+            Some(libc::EACCES)
+        );
+        assert_eq!(metrics.timeouts.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_breaker_skips_failing_hook() {
+        let (hook, metrics, hang) = guarded(FailurePolicy::Open);
+        for _ in 0..4 {
+            assert!(matches!(hook.execute(&context()), Ok(HookDecision::Allow)));
+        }
+        assert_eq!(metrics.calls.load(Ordering::Relaxed), 2);
+        assert_eq!(metrics.skipped.load(Ordering::Relaxed), 2);
+        assert_eq!(metrics.trips.load(Ordering::Relaxed), 1);
+
+        // After the cooldown a working hook closes the breaker again
+        std::thread::sleep(Duration::from_millis(350));
+        hang.store(false, Ordering::Relaxed);
+        assert!(matches!(hook.execute(&context()), Ok(HookDecision::Deny)));
+        assert!(matches!(hook.execute(&context()), Ok(HookDecision::Deny)));
+        assert_eq!(metrics.calls.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn test_breaker_open_policy() {
+        let hook = TestHook {
+            hang: Arc::new(AtomicBool::new(true)),
+        };
+        let settings = GuardSettings {
+            timeout: Some(Duration::from_millis(20)),
+            on_failure: FailurePolicy::Closed(libc::EACCES),
+            breaker_failures: 2,
+            on_open: FailurePolicy::Open,
+            ..GuardSettings::default()
+        };
+        let guarded = GuardedHook::new(Box::new(hook), settings, HookRecorder::default());
+        for _ in 0..2 {
+            assert_eq!(
+                decision_errno(&guarded.execute(&context())),
+                Some(libc::EACCES)
+            );
+        }
+        // Skipped calls fall back to the breaker's policy
+        assert!(matches!(
+            guarded.execute(&context()),
+            Ok(HookDecision::Allow)
+        ));
+        assert_eq!(guarded.metrics().skipped.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_worker_cap_rejects_calls() {
+        let hook = TestHook {
+            hang: Arc::new(AtomicBool::new(true)),
+        };
+        let settings = GuardSettings {
+            timeout: Some(Duration::from_millis(20)),
+            on_failure: FailurePolicy::Open,
+            breaker_failures: 100,
+            max_workers: 2,
+            ..GuardSettings::default()
+        };
+        let guarded = GuardedHook::new(Box::new(hook), settings, HookRecorder::default());
+        for _ in 0..4 {
+            assert!(matches!(
+                guarded.execute(&context()),
+                Ok(HookDecision::Allow)
+            ));
+        }
+        // Both workers are stuck, so no more are started
+        let metrics = guarded.metrics();
+        assert_eq!(metrics.timeouts.load(Ordering::Relaxed), 2);
+        assert_eq!(metrics.rejected.load(Ordering::Relaxed), 2);
+        assert_eq!(guarded.pool.workers.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_audit_allows_denials() {
+        let hook = TestHook {
+            hang: Arc::new(AtomicBool::new(false)),
+        };
+        let settings = GuardSettings {
+            timeout: None,
+            audit: true,
+            ..GuardSettings::default()
+        };
+        let guarded = GuardedHook::new(Box::new(hook), settings, HookRecorder::default());
+        assert!(matches!(
+            guarded.execute(&context()),
+            Ok(HookDecision::Allow)
+        ));
+        assert_eq!(guarded.metrics().audited.load(Ordering::Relaxed), 1);
+
+        let evaluation = guarded.evaluate(&context());
+        assert!(matches!(evaluation.outcome, Ok(HookDecision::Deny)));
+        assert_eq!(evaluation.reason.as_deref(), Some("test denial"));
+        assert!(evaluation.failed.is_none());
+    }
+
+    #[test]
+    fn test_workers_persist_across_calls() {
+        struct ThreadHook(Mutex<Vec<std::thread::ThreadId>>);
+
+        impl SyncHook for ThreadHook {
+            fn name(&self) -> &str {
+                "threads"
+            }
+
+            fn execute(&self, _context: &HookContext) -> lev_reactive::Result<HookDecision> {
+                self.0.lock().push(std::thread::current().id());
+                Ok(HookDecision::Allow)
+            }
+
+            fn priority(&self) -> i32 {
+                0
+            }
+        }
+
+        let hook = Arc::new(ThreadHook(Mutex::new(Vec::new())));
+        struct Calls(Arc<ThreadHook>);
+
+        impl SyncHook for Calls {
+            fn name(&self) -> &str {
+                self.0.name()
+            }
+
+            fn execute(&self, context: &HookContext) -> lev_reactive::Result<HookDecision> {
+                self.0.execute(context)
+            }
+
+            fn priority(&self) -> i32 {
+                0
+            }
+        }
+
+        let guarded = GuardedHook::new(
+            Box::new(Unreasoned(Box::new(Calls(hook.clone())))),
+            GuardSettings::default(),
+            HookRecorder::default(),
+        );
+        for _ in 0..8 {
+            assert!(matches!(
+                guarded.execute(&context()),
+                Ok(HookDecision::Allow)
+            ));
+        }
+        let threads = hook.0.lock();
+        assert_eq!(threads.len(), 8);
+        assert!(threads.iter().all(|id| *id == threads[0]));
+        assert_ne!(threads[0], std::thread::current().id());
+    }
+
+    #[test]
+    fn test_panicking_hook_keeps_its_worker() {
+        struct PanicHook;
+
+        impl SyncHook for PanicHook {
+            fn name(&self) -> &str {
+                "panic"
+            }
+
+            fn execute(&self, _context: &HookContext) -> lev_reactive::Result<HookDecision> {
+                panic!("hook bug")
+            }
+
+            fn priority(&self) -> i32 {
+                0
+            }
+        }
+
+        let settings = GuardSettings {
+            on_failure: FailurePolicy::Open,
+            ..GuardSettings::default()
+        };
+        let guarded = GuardedHook::new(
+            Box::new(Unreasoned(Box::new(PanicHook))),
+            settings,
+            HookRecorder::default(),
+        );
+        for _ in 0..2 {
+            assert!(matches!(
+                guarded.execute(&context()),
+                Ok(HookDecision::Allow)
+            ));
+        }
+        let metrics = guarded.metrics();
+        assert_eq!(metrics.errors.load(Ordering::Relaxed), 2);
+        assert_eq!(metrics.timeouts.load(Ordering::Relaxed), 0);
+        assert_eq!(guarded.pool.workers.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_parse_errno() {
+        assert_eq!(parse_errno("EACCES"), Some(libc::EACCES));
+        assert_eq!(parse_errno("erofs"), Some(libc::EROFS));
+        assert_eq!(parse_errno("5"), Some(5));
+        assert_eq!(parse_errno("ENOPE"), None);
+    }
+}
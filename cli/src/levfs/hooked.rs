@@ -0,0 +1,1126 @@
+//! `FileSystem` decorator that runs LevFS hooks around mutating operations.
+//!
+//! Wrapping any `Arc<dyn FileSystem>` in a [`HookedFileSystem`] gives every
+//! frontend built on it (FUSE, NFS, the sandbox) the same policy enforcement:
+//! sync hooks run before each mutating trait method and can deny it, async
+//! hooks run after it succeeds. Writes and truncates made through open file
+//! handles are hooked as well, and the whole file is validated again when the
+//! handle is flushed. The original content of the ranges they change is saved
+//! first, so a file denied at close can be rolled back.
+//!
+//! Read hooks run before opens, lookups and directory listings. They can deny
+//! them, or redact with a `Transform`: a redacted entry is hidden from lookups
+//! and listings, and a file redacted at open reads as the content the hook
+//! returned. With a read audit configured, the reads made through each handle
+//! are counted and recorded when it is closed.
+//!
+//! Events on existing entries carry an `entry` object describing the entry
+//! before the operation (`type`, `mode`, `uid`, `gid`, `size`, `mtime` and
+//! `created_in_session`), looked up only when sync hooks are configured.
+
+use super::hooks::{display_path, file_type_name, FsHooks, HookOp, InodePaths, Verdict};
+use super::reads;
+use agentfs_sdk::error::{Error as SdkError, Result};
+use agentfs_sdk::{BoxedFile, DirEntry, File, FileSystem, FilesystemStats, Stats, TimeChange};
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use serde_json::{json, Value as JsonValue};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::runtime::Handle;
+use tokio::sync::Mutex as AsyncMutex;
+
+/// Bytes of original content saved at a time before a handle changes them
+const BASELINE_CHUNK_SIZE: u64 = 64 * 1024;
+
+/// Error returned for an operation a sync hook denied
+fn denied(errno: i32) -> SdkError {
+    SdkError::Io(std::io::Error::from_raw_os_error(errno))
+}
+
+/// Describe a timestamp change for a hook context: `null` when unchanged,
+/// `"now"`, or seconds since the epoch.
+fn time_change_json(time: &TimeChange) -> JsonValue {
+    match time {
+        TimeChange::Set(secs, _) => json!(secs),
+        TimeChange::Now => json!("now"),
+        TimeChange::Omit => JsonValue::Null,
+    }
+}
+
+/// Hook state shared by the filesystem and the files opened through it
+struct HookState {
+    hooks: FsHooks,
+    /// The wrapped filesystem, asked for the paths of inodes not in `paths`
+    fs: Arc<dyn FileSystem>,
+    paths: InodePaths,
+    /// Inodes created through the wrapper since it was mounted
+    created: Mutex<HashSet<u64>>,
+}
+
+impl HookState {
+    /// Run sync hooks before an operation whose data cannot be transformed
+    fn before(&self, op: HookOp, data: &JsonValue) -> Result<()> {
+        if let Verdict::Transform(_) = self.hooks.before(op, data).map_err(denied)? {
+            tracing::warn!(
+                "LevFS: ignoring Transform for {} {}, only file:close content can be transformed",
+                op,
+                display_path(data)
+            );
+        }
+        Ok(())
+    }
+
+    fn after(&self, op: HookOp, data: JsonValue) {
+        self.hooks.after(&Handle::current(), op, data);
+    }
+
+    /// Get the path of an inode, resolving it through the wrapped filesystem
+    /// when the wrapper hasn't seen it: a handle kept across a restart, a
+    /// hard link whose known name was unlinked, or a forgotten inode
+    async fn path(&self, ino: i64) -> Option<String> {
+        if let Some(path) = self.paths.path(ino as u64) {
+            return Some(path);
+        }
+        let path = self.fs.inode_path(ino).await.ok().flatten()?;
+        self.paths.insert(ino as u64, path.clone());
+        Some(path)
+    }
+
+    /// Get the path of `name` in the directory `parent`
+    async fn child(&self, parent_ino: i64, name: &str) -> Option<String> {
+        self.path(parent_ino).await?;
+        self.paths.child(parent_ino as u64, name)
+    }
+
+    /// Describe the entry an operation acts on for the `entry` field of its
+    /// context
+    fn entry(&self, stats: Option<&Stats>) -> JsonValue {
+        let Some(stats) = stats else {
+            return JsonValue::Null;
+        };
+        json!({
+            "type": file_type_name(stats.mode),
+            "mode": stats.mode & 0o7777,
+            "uid": stats.uid,
+            "gid": stats.gid,
+            "size": stats.size,
+            "mtime": stats.mtime,
+            "created_in_session": self.created.lock().contains(&(stats.ino as u64)),
+        })
+    }
+
+    /// Record a successful creation of `name` in `parent`
+    fn record_creation(&self, parent_ino: i64, name: &str, ino: i64) {
+        self.paths.remember(parent_ino as u64, name, ino as u64);
+        if self.hooks.has_sync_hooks() {
+            self.created.lock().insert(ino as u64);
+        }
+    }
+
+    /// Context for a `file:setattr` event, with `null` for unchanged attributes
+    async fn setattr_data(&self, ino: i64, entry: JsonValue, changes: JsonValue) -> JsonValue {
+        let data = json!({
+            "ino": ino,
+            "path": self.path(ino).await,
+            "entry": entry,
+            "mode": null,
+            "uid": null,
+            "gid": null,
+            "size": null,
+            "atime": null,
+            "mtime": null,
+        });
+        with_fields(data, changes)
+    }
+
+    /// Context for an operation creating `name` in `parent`
+    async fn entry_data(&self, parent_ino: i64, name: &str) -> JsonValue {
+        json!({
+            "parent": parent_ino,
+            "name": name,
+            "path": self.child(parent_ino, name).await,
+        })
+    }
+
+    /// Run read hooks on a lookup of `name` in `parent`; `Ok(false)` when a
+    /// hook redacts the entry
+    async fn visible(&self, parent_ino: i64, name: &str, stats: Option<&Stats>) -> Result<bool> {
+        if !self.hooks.has_read_hooks() || name == "." || name == ".." {
+            return Ok(true);
+        }
+        let data = with_fields(
+            self.entry_data(parent_ino, name).await,
+            json!({ "entry": self.entry(stats) }),
+        );
+        let verdict = self.hooks.before(HookOp::Lookup, &data).map_err(denied)?;
+        Ok(verdict == Verdict::Allow)
+    }
+
+    /// Whether a directory listing shows `name`: entries a hook redacts are
+    /// left out, entries it denies are listed but cannot be looked up
+    async fn listed(&self, parent_ino: i64, name: &str, stats: Option<&Stats>) -> bool {
+        !matches!(self.visible(parent_ino, name, stats).await, Ok(false))
+    }
+}
+
+/// Get the replacement content from a `Transform` of a `file:close` event:
+/// either a string, or an object with a string `content` field.
+fn transformed_content(value: &JsonValue) -> Option<&str> {
+    value
+        .as_str()
+        .or_else(|| value.get("content").and_then(|c| c.as_str()))
+}
+
+/// Add operation-specific fields to a hook context
+fn with_fields(mut data: JsonValue, fields: JsonValue) -> JsonValue {
+    if let (Some(data), JsonValue::Object(fields)) = (data.as_object_mut(), fields) {
+        data.extend(fields);
+    }
+    data
+}
+
+/// A filesystem that runs hooks around the mutating operations of another
+///
+/// Denied operations fail with an `Io` error carrying the hook's errno
+/// (`EPERM` for `Deny`), which frontends report as they would any other
+/// errno. Paths in hook contexts are resolved from the lookups, directory
+/// listings and creations that pass through the wrapper, and asked of the
+/// wrapped filesystem for other inodes.
+pub struct HookedFileSystem {
+    inner: Arc<dyn FileSystem>,
+    state: Arc<HookState>,
+}
+
+impl HookedFileSystem {
+    /// Wrap a filesystem, running `hooks` around its mutating operations.
+    pub fn new(inner: Arc<dyn FileSystem>, hooks: FsHooks) -> Self {
+        Self {
+            inner: inner.clone(),
+            state: Arc::new(HookState {
+                hooks,
+                fs: inner,
+                paths: InodePaths::new(),
+                created: Mutex::new(HashSet::new()),
+            }),
+        }
+    }
+
+    /// Wrap a filesystem in a `HookedFileSystem`, or return it unchanged
+    /// when no hooks are configured.
+    pub fn wrap(inner: Arc<dyn FileSystem>, hooks: FsHooks) -> Arc<dyn FileSystem> {
+        if hooks.is_empty() {
+            inner
+        } else {
+            Arc::new(Self::new(inner, hooks))
+        }
+    }
+
+    /// Like [`HookedFileSystem::wrap`], for the lock-guarded filesystems
+    /// taken by the NFS adapter and `mount_fs`.
+    pub fn wrap_locked<F: FileSystem + 'static>(
+        inner: F,
+        hooks: FsHooks,
+    ) -> Arc<AsyncMutex<dyn FileSystem + Send>> {
+        if hooks.is_empty() {
+            Arc::new(AsyncMutex::new(inner))
+        } else {
+            Arc::new(AsyncMutex::new(Self::new(Arc::new(inner), hooks)))
+        }
+    }
+
+    /// Get the path an inode was last seen under, if any.
+    pub fn path(&self, ino: i64) -> Option<String> {
+        self.state.paths.path(ino as u64)
+    }
+
+    /// Attributes of an inode for the `entry` of a hook context, or `null`
+    /// when unknown or when no sync hook would see them
+    async fn entry(&self, ino: i64) -> JsonValue {
+        if !self.state.hooks.has_sync_hooks() {
+            return JsonValue::Null;
+        }
+        let stats = self.inner.getattr(ino).await.ok().flatten();
+        self.state.entry(stats.as_ref())
+    }
+
+    /// Like [`HookedFileSystem::entry`], for the entry `name` in `parent`
+    async fn child_entry(&self, parent_ino: i64, name: &str) -> JsonValue {
+        if !self.state.hooks.has_sync_hooks() {
+            return JsonValue::Null;
+        }
+        let stats = self.inner.lookup(parent_ino, name).await.ok().flatten();
+        self.state.entry(stats.as_ref())
+    }
+
+    /// Run read hooks before listing `ino`; `Ok(false)` when a hook redacts
+    /// the listing, which is then empty
+    async fn before_readdir(&self, ino: i64) -> Result<bool> {
+        if !self.state.hooks.has_read_hooks() {
+            return Ok(true);
+        }
+        let data = json!({
+            "ino": ino,
+            "path": self.state.path(ino).await,
+            "entry": self.entry(ino).await,
+        });
+        let verdict = self
+            .state
+            .hooks
+            .before(HookOp::Readdir, &data)
+            .map_err(denied)?;
+        Ok(verdict == Verdict::Allow)
+    }
+
+    /// Run read hooks before opening `ino`, returning the content the file
+    /// was redacted to, if a hook redacted it
+    async fn before_open(
+        &self,
+        ino: i64,
+        flags: i32,
+        stats: Option<&Stats>,
+    ) -> Result<Option<Vec<u8>>> {
+        if !self.state.hooks.has_read_hooks() {
+            return Ok(None);
+        }
+        let data = json!({
+            "ino": ino,
+            "path": self.state.path(ino).await,
+            "flags": flags,
+            "entry": self.state.entry(stats),
+        });
+        let verdict = self
+            .state
+            .hooks
+            .before(HookOp::Open, &data)
+            .map_err(denied)?;
+        match verdict {
+            Verdict::Allow => Ok(None),
+            Verdict::Transform(value) if flags & libc::O_ACCMODE == libc::O_RDONLY => {
+                let content = transformed_content(&value).unwrap_or_default();
+                Ok(Some(content.as_bytes().to_vec()))
+            }
+            // A redacted file can only be opened for reading
+            Verdict::Transform(_) => Err(denied(libc::EACCES)),
+        }
+    }
+
+    /// Wrap an open file so its writes and truncates run hooks.
+    ///
+    /// `stats` are the file's attributes when it was opened, reported as the
+    /// `entry` of its events.
+    fn wrap_file(&self, ino: i64, stats: Option<Stats>, file: BoxedFile) -> HookedFile {
+        HookedFile {
+            inner: file,
+            ino,
+            stats,
+            state: self.state.clone(),
+            dirty: AtomicBool::new(false),
+            baseline: AsyncMutex::new(None),
+            redacted: None,
+            opened_at: None,
+            reads: AtomicU64::new(0),
+            bytes_read: AtomicU64::new(0),
+        }
+    }
+}
+
+#[async_trait]
+impl FileSystem for HookedFileSystem {
+    async fn lookup(&self, parent_ino: i64, name: &str) -> Result<Option<Stats>> {
+        let stats = self.inner.lookup(parent_ino, name).await?;
+        if let Some(ref stats) = stats {
+            if !self.state.visible(parent_ino, name, Some(stats)).await? {
+                return Ok(None);
+            }
+            if name != "." && name != ".." {
+                self.state
+                    .paths
+                    .remember(parent_ino as u64, name, stats.ino as u64);
+            }
+        }
+        Ok(stats)
+    }
+
+    async fn getattr(&self, ino: i64) -> Result<Option<Stats>> {
+        self.inner.getattr(ino).await
+    }
+
+    async fn readlink(&self, ino: i64) -> Result<Option<String>> {
+        self.inner.readlink(ino).await
+    }
+
+    async fn readdir(&self, ino: i64) -> Result<Option<Vec<String>>> {
+        if !self.before_readdir(ino).await? {
+            return Ok(Some(Vec::new()));
+        }
+        let names = self.inner.readdir(ino).await?;
+        if !self.state.hooks.has_read_hooks() {
+            return Ok(names);
+        }
+        let Some(names) = names else {
+            return Ok(None);
+        };
+        let mut listed = Vec::with_capacity(names.len());
+        for name in names {
+            if self.state.listed(ino, &name, None).await {
+                listed.push(name);
+            }
+        }
+        Ok(Some(listed))
+    }
+
+    async fn readdir_plus(&self, ino: i64) -> Result<Option<Vec<DirEntry>>> {
+        if !self.before_readdir(ino).await? {
+            return Ok(Some(Vec::new()));
+        }
+        let mut entries = self.inner.readdir_plus(ino).await?;
+        if let Some(ref mut entries) = entries {
+            if self.state.hooks.has_read_hooks() {
+                let mut listed = Vec::with_capacity(entries.len());
+                for entry in entries.drain(..) {
+                    if self
+                        .state
+                        .listed(ino, &entry.name, Some(&entry.stats))
+                        .await
+                    {
+                        listed.push(entry);
+                    }
+                }
+                *entries = listed;
+            }
+            for entry in entries {
+                self.state
+                    .paths
+                    .remember(ino as u64, &entry.name, entry.stats.ino as u64);
+            }
+        }
+        Ok(entries)
+    }
+
+    async fn chmod(&self, ino: i64, mode: u32) -> Result<()> {
+        let entry = self.entry(ino).await;
+        let data = self
+            .state
+            .setattr_data(ino, entry, json!({ "mode": mode }))
+            .await;
+        self.state.before(HookOp::Setattr, &data)?;
+        self.inner.chmod(ino, mode).await?;
+        self.state.after(HookOp::Setattr, data);
+        Ok(())
+    }
+
+    async fn chown(&self, ino: i64, uid: Option<u32>, gid: Option<u32>) -> Result<()> {
+        let entry = self.entry(ino).await;
+        let data = self
+            .state
+            .setattr_data(ino, entry, json!({ "uid": uid, "gid": gid }))
+            .await;
+        self.state.before(HookOp::Setattr, &data)?;
+        self.inner.chown(ino, uid, gid).await?;
+        self.state.after(HookOp::Setattr, data);
+        Ok(())
+    }
+
+    async fn utimens(&self, ino: i64, atime: TimeChange, mtime: TimeChange) -> Result<()> {
+        let entry = self.entry(ino).await;
+        let data = self
+            .state
+            .setattr_data(
+                ino,
+                entry,
+                json!({
+                    "atime": time_change_json(&atime),
+                    "mtime": time_change_json(&mtime),
+                }),
+            )
+            .await;
+        self.state.before(HookOp::Setattr, &data)?;
+        self.inner.utimens(ino, atime, mtime).await?;
+        self.state.after(HookOp::Setattr, data);
+        Ok(())
+    }
+
+    async fn open(&self, ino: i64, flags: i32) -> Result<BoxedFile> {
+        let stats = if self.state.hooks.has_sync_hooks() {
+            self.inner.getattr(ino).await?
+        } else {
+            None
+        };
+        let redacted = self.before_open(ino, flags, stats.as_ref()).await?;
+        let file = self.inner.open(ino, flags).await?;
+        let mut file = self.wrap_file(ino, stats, file);
+        file.redacted = redacted;
+        if flags & libc::O_ACCMODE != libc::O_WRONLY {
+            file.opened_at = Some(reads::now());
+        }
+        Ok(Arc::new(file))
+    }
+
+    async fn mkdir(
+        &self,
+        parent_ino: i64,
+        name: &str,
+        mode: u32,
+        uid: u32,
+        gid: u32,
+    ) -> Result<Stats> {
+        let data = with_fields(
+            self.state.entry_data(parent_ino, name).await,
+            json!({ "mode": mode, "uid": uid, "gid": gid }),
+        );
+        self.state.before(HookOp::Mkdir, &data)?;
+        let stats = self.inner.mkdir(parent_ino, name, mode, uid, gid).await?;
+        self.state.record_creation(parent_ino, name, stats.ino);
+        self.state.after(HookOp::Mkdir, data);
+        Ok(stats)
+    }
+
+    async fn create_file(
+        &self,
+        parent_ino: i64,
+        name: &str,
+        mode: u32,
+        uid: u32,
+        gid: u32,
+    ) -> Result<(Stats, BoxedFile)> {
+        let data = with_fields(
+            self.state.entry_data(parent_ino, name).await,
+            json!({ "mode": mode, "uid": uid, "gid": gid }),
+        );
+        self.state.before(HookOp::Create, &data)?;
+        let (stats, file) = self
+            .inner
+            .create_file(parent_ino, name, mode, uid, gid)
+            .await?;
+        self.state.record_creation(parent_ino, name, stats.ino);
+        self.state.after(HookOp::Create, data);
+        let kept = self.state.hooks.has_sync_hooks().then(|| stats.clone());
+        let file = Arc::new(self.wrap_file(stats.ino, kept, file));
+        Ok((stats, file))
+    }
+
+    async fn mknod(
+        &self,
+        parent_ino: i64,
+        name: &str,
+        mode: u32,
+        rdev: u64,
+        uid: u32,
+        gid: u32,
+    ) -> Result<Stats> {
+        let data = with_fields(
+            self.state.entry_data(parent_ino, name).await,
+            json!({ "mode": mode, "rdev": rdev, "uid": uid, "gid": gid }),
+        );
+        self.state.before(HookOp::Mknod, &data)?;
+        let stats = self
+            .inner
+            .mknod(parent_ino, name, mode, rdev, uid, gid)
+            .await?;
+        self.state.record_creation(parent_ino, name, stats.ino);
+        self.state.after(HookOp::Mknod, data);
+        Ok(stats)
+    }
+
+    async fn symlink(
+        &self,
+        parent_ino: i64,
+        name: &str,
+        target: &str,
+        uid: u32,
+        gid: u32,
+    ) -> Result<Stats> {
+        let data = with_fields(
+            self.state.entry_data(parent_ino, name).await,
+            json!({ "target": target, "uid": uid, "gid": gid }),
+        );
+        self.state.before(HookOp::Symlink, &data)?;
+        let stats = self
+            .inner
+            .symlink(parent_ino, name, target, uid, gid)
+            .await?;
+        self.state.record_creation(parent_ino, name, stats.ino);
+        self.state.after(HookOp::Symlink, data);
+        Ok(stats)
+    }
+
+    async fn unlink(&self, parent_ino: i64, name: &str) -> Result<()> {
+        let data = with_fields(
+            self.state.entry_data(parent_ino, name).await,
+            json!({ "entry": self.child_entry(parent_ino, name).await }),
+        );
+        self.state.before(HookOp::Unlink, &data)?;
+        self.inner.unlink(parent_ino, name).await?;
+        if let Some(path) = data["path"].as_str() {
+            self.state.paths.remove(path);
+        }
+        self.state.after(HookOp::Unlink, data);
+        Ok(())
+    }
+
+    async fn rmdir(&self, parent_ino: i64, name: &str) -> Result<()> {
+        let data = with_fields(
+            self.state.entry_data(parent_ino, name).await,
+            json!({ "entry": self.child_entry(parent_ino, name).await }),
+        );
+        self.state.before(HookOp::Rmdir, &data)?;
+        self.inner.rmdir(parent_ino, name).await?;
+        if let Some(path) = data["path"].as_str() {
+            self.state.paths.remove(path);
+        }
+        self.state.after(HookOp::Rmdir, data);
+        Ok(())
+    }
+
+    async fn link(&self, ino: i64, newparent_ino: i64, newname: &str) -> Result<Stats> {
+        let data = json!({
+            "ino": ino,
+            "path": self.state.path(ino).await,
+            "newparent": newparent_ino,
+            "newname": newname,
+            "new_path": self.state.child(newparent_ino, newname).await,
+            "entry": self.entry(ino).await,
+        });
+        self.state.before(HookOp::Link, &data)?;
+        let stats = self.inner.link(ino, newparent_ino, newname).await?;
+        self.state
+            .paths
+            .remember(newparent_ino as u64, newname, ino as u64);
+        self.state.after(HookOp::Link, data);
+        Ok(stats)
+    }
+
+    async fn rename(
+        &self,
+        oldparent_ino: i64,
+        oldname: &str,
+        newparent_ino: i64,
+        newname: &str,
+    ) -> Result<()> {
+        let path = self.state.child(oldparent_ino, oldname).await;
+        let new_path = self.state.child(newparent_ino, newname).await;
+        let data = json!({
+            "parent": oldparent_ino,
+            "name": oldname,
+            "newparent": newparent_ino,
+            "newname": newname,
+            "path": path,
+            "new_path": new_path,
+            "entry": self.child_entry(oldparent_ino, oldname).await,
+        });
+        self.state.before(HookOp::Rename, &data)?;
+        self.inner
+            .rename(oldparent_ino, oldname, newparent_ino, newname)
+            .await?;
+        match (path, new_path) {
+            (Some(from), Some(to)) => self.state.paths.rename(&from, &to),
+            (Some(from), None) => self.state.paths.remove(&from),
+            _ => {}
+        }
+        self.state.after(HookOp::Rename, data);
+        Ok(())
+    }
+
+    async fn statfs(&self) -> Result<FilesystemStats> {
+        self.inner.statfs().await
+    }
+
+    async fn getxattr(&self, ino: i64, name: &str) -> Result<Option<Vec<u8>>> {
+        self.inner.getxattr(ino, name).await
+    }
+
+    async fn setxattr(&self, ino: i64, name: &str, value: &[u8], flags: i32) -> Result<()> {
+        let data = json!({
+            "ino": ino,
+            "path": self.state.path(ino).await,
+            "attr": name,
+            "size": value.len(),
+            "flags": flags,
+            "entry": self.entry(ino).await,
+        });
+        self.state.before(HookOp::Setxattr, &data)?;
+        self.inner.setxattr(ino, name, value, flags).await?;
+        self.state.after(HookOp::Setxattr, data);
+        Ok(())
+    }
+
+    async fn listxattr(&self, ino: i64) -> Result<Vec<String>> {
+        self.inner.listxattr(ino).await
+    }
+
+    async fn removexattr(&self, ino: i64, name: &str) -> Result<()> {
+        let data = json!({
+            "ino": ino,
+            "path": self.state.path(ino).await,
+            "attr": name,
+            "entry": self.entry(ino).await,
+        });
+        self.state.before(HookOp::Removexattr, &data)?;
+        self.inner.removexattr(ino, name).await?;
+        self.state.after(HookOp::Removexattr, data);
+        Ok(())
+    }
+
+    async fn inode_path(&self, ino: i64) -> Result<Option<String>> {
+        Ok(self.state.path(ino).await)
+    }
+
+    async fn forget(&self, ino: i64, nlookup: u64) {
+        self.state.paths.forget(ino as u64);
+        self.inner.forget(ino, nlookup).await;
+    }
+}
+
+/// Content of a file before its first unvalidated change
+///
+/// Only the chunks changed since are saved, so appending to a large file
+/// saves nothing and overwriting part of it saves that part.
+struct Baseline {
+    /// Size of the file before the change
+    size: u64,
+    /// Original content of the changed chunks, by chunk index
+    chunks: BTreeMap<u64, Vec<u8>>,
+}
+
+/// An open file whose writes and truncates run hooks
+///
+/// Changes made through the handle are validated as a whole when it is
+/// flushed (on close) or synced: sync hooks see a `file:close` event with the
+/// complete content. If they deny it, the file is restored to its content
+/// before the first unvalidated change and the flush fails with the hook's
+/// errno. Files over the validate limit are validated without their content,
+/// and a change that would save more than the limit for a rollback fails
+/// with `EFBIG`.
+struct HookedFile {
+    inner: BoxedFile,
+    ino: i64,
+    /// Attributes of the file when it was opened; only kept when sync hooks
+    /// are configured
+    stats: Option<Stats>,
+    state: Arc<HookState>,
+    /// Whether the file changed since it was last validated
+    dirty: AtomicBool,
+    /// Content before the first unvalidated change, restored when validation
+    /// fails. Only kept when sync hooks are configured.
+    baseline: AsyncMutex<Option<Baseline>>,
+    /// Content a read hook redacted the file to, served instead of its own
+    redacted: Option<Vec<u8>>,
+    /// When the file was opened for reading; unset for handles the read
+    /// audit ignores
+    opened_at: Option<i64>,
+    reads: AtomicU64,
+    bytes_read: AtomicU64,
+}
+
+impl HookedFile {
+    /// Save the original content of the bytes from `start` to `end` before
+    /// they are changed, failing with `EFBIG` past the validate limit.
+    async fn save_baseline(&self, start: u64, end: u64) -> Result<()> {
+        if !self.state.hooks.has_sync_hooks() {
+            return Ok(());
+        }
+        let mut baseline = self.baseline.lock().await;
+        let baseline = match baseline.as_mut() {
+            Some(baseline) => baseline,
+            None => baseline.insert(Baseline {
+                size: self.inner.fstat().await?.size as u64,
+                chunks: BTreeMap::new(),
+            }),
+        };
+        // Bytes past the original size need no saving: a rollback truncates
+        let end = end.min(baseline.size);
+        if start >= end {
+            return Ok(());
+        }
+        let limit = self.state.hooks.validate_limit();
+        for index in start / BASELINE_CHUNK_SIZE..=(end - 1) / BASELINE_CHUNK_SIZE {
+            if baseline.chunks.contains_key(&index) {
+                continue;
+            }
+            if (baseline.chunks.len() as u64 + 1) * BASELINE_CHUNK_SIZE > limit {
+                let path = self.state.path(self.ino).await;
+                tracing::warn!(
+                    "LevFS: refusing to change {}, a rollback would need more than {} bytes",
+                    path.as_deref().unwrap_or("<unknown>"),
+                    limit
+                );
+                return Err(denied(libc::EFBIG));
+            }
+            let offset = index * BASELINE_CHUNK_SIZE;
+            let length = BASELINE_CHUNK_SIZE.min(baseline.size - offset);
+            let data = self.inner.pread(offset, length).await?;
+            baseline.chunks.insert(index, data);
+        }
+        Ok(())
+    }
+
+    /// Restore the file to its content before the first unvalidated change
+    async fn roll_back(&self, baseline: Baseline) -> Result<()> {
+        self.inner.truncate(baseline.size).await?;
+        for (index, data) in baseline.chunks {
+            self.inner
+                .pwrite(index * BASELINE_CHUNK_SIZE, &data)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Run hooks on the whole file after unvalidated changes, rolling it back
+    /// if a sync hook denies the new content.
+    async fn validate(&self) -> Result<()> {
+        if !self.dirty.swap(false, Ordering::SeqCst) || self.state.hooks.is_empty() {
+            return Ok(());
+        }
+        let mut baseline = self.baseline.lock().await;
+        let original = baseline.take();
+
+        // Files over the limit are validated by size only, like binary files
+        let size = self.inner.fstat().await?.size as u64;
+        let content = if size <= self.state.hooks.validate_limit() {
+            Some(self.inner.pread(0, size).await?)
+        } else {
+            None
+        };
+        let mut data = json!({
+            "ino": self.ino,
+            "path": self.state.path(self.ino).await,
+            "size": size,
+            "content": content.as_deref().and_then(|c| std::str::from_utf8(c).ok()),
+            "entry": self.state.entry(self.stats.as_ref()),
+        });
+        let verdict = match self.state.hooks.before(HookOp::Close, &data) {
+            Ok(verdict) => verdict,
+            Err(errno) => {
+                if let Some(original) = original {
+                    tracing::warn!(
+                        "LevFS: rolling back {} after validation failed",
+                        display_path(&data)
+                    );
+                    self.roll_back(original).await?;
+                }
+                return Err(denied(errno));
+            }
+        };
+        if let Verdict::Transform(value) = verdict {
+            self.apply_transform(&mut data, &value).await?;
+        }
+        self.state.after(HookOp::Close, data);
+        Ok(())
+    }
+
+    /// Replace the content of the file with the content a sync hook returned
+    /// for a `file:close` event, updating the event data to match.
+    async fn apply_transform(&self, data: &mut JsonValue, value: &JsonValue) -> Result<()> {
+        let Some(content) = transformed_content(value) else {
+            tracing::warn!(
+                "LevFS: ignoring Transform without content for {}",
+                display_path(data)
+            );
+            return Ok(());
+        };
+        self.inner.truncate(content.len() as u64).await?;
+        self.inner.pwrite(0, content.as_bytes()).await?;
+        tracing::info!(
+            "LevFS: {} rewritten by sync hook ({} -> {} bytes)",
+            display_path(data),
+            data["size"],
+            content.len()
+        );
+        data["size"] = json!(content.len());
+        data["content"] = json!(content);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl File for HookedFile {
+    async fn pread(&self, offset: u64, size: u64) -> Result<Vec<u8>> {
+        let data = match &self.redacted {
+            Some(content) => {
+                let start = (offset as usize).min(content.len());
+                let end = start.saturating_add(size as usize).min(content.len());
+                content[start..end].to_vec()
+            }
+            None => self.inner.pread(offset, size).await?,
+        };
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        self.bytes_read
+            .fetch_add(data.len() as u64, Ordering::Relaxed);
+        Ok(data)
+    }
+
+    async fn pwrite(&self, offset: u64, data: &[u8]) -> Result<()> {
+        if self.redacted.is_some() {
+            return Err(denied(libc::EBADF));
+        }
+        let hook_data = json!({
+            "ino": self.ino,
+            "path": self.state.path(self.ino).await,
+            "offset": offset,
+            "size": data.len(),
+            "entry": self.state.entry(self.stats.as_ref()),
+        });
+        self.state.before(HookOp::Write, &hook_data)?;
+        self.save_baseline(offset, offset.saturating_add(data.len() as u64))
+            .await?;
+        self.inner.pwrite(offset, data).await?;
+        self.dirty.store(true, Ordering::SeqCst);
+        self.state.after(HookOp::Write, hook_data);
+        Ok(())
+    }
+
+    async fn truncate(&self, size: u64) -> Result<()> {
+        if self.redacted.is_some() {
+            return Err(denied(libc::EBADF));
+        }
+        let data = self
+            .state
+            .setattr_data(
+                self.ino,
+                self.state.entry(self.stats.as_ref()),
+                json!({ "size": size }),
+            )
+            .await;
+        self.state.before(HookOp::Setattr, &data)?;
+        self.save_baseline(size, u64::MAX).await?;
+        self.inner.truncate(size).await?;
+        self.dirty.store(true, Ordering::SeqCst);
+        self.state.after(HookOp::Setattr, data);
+        Ok(())
+    }
+
+    async fn fsync(&self) -> Result<()> {
+        self.validate().await?;
+        self.inner.fsync().await
+    }
+
+    async fn fstat(&self) -> Result<Stats> {
+        let mut stats = self.inner.fstat().await?;
+        if let Some(ref content) = self.redacted {
+            stats.size = content.len() as i64;
+        }
+        Ok(stats)
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.validate().await?;
+        self.inner.flush().await
+    }
+}
+
+impl Drop for HookedFile {
+    /// Account for the reads made through the handle in the read audit
+    fn drop(&mut self) {
+        let (Some(opened_at), Some(read_audit)) = (self.opened_at, self.state.hooks.read_audit())
+        else {
+            return;
+        };
+        if let Some(path) = self.state.paths.path(self.ino as u64) {
+            read_audit.closed(
+                path,
+                opened_at,
+                self.reads.load(Ordering::Relaxed),
+                self.bytes_read.load(Ordering::Relaxed),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::validator::LevFSValidator;
+    use super::*;
+    use agentfs_sdk::{AgentFS, AgentFSOptions};
+    use lev_reactive::{HookContext, HookDecision, HookRegistry, SyncHook};
+
+    async fn hooked() -> HookedFileSystem {
+        let agent = AgentFS::open(AgentFSOptions::ephemeral()).await.unwrap();
+        HookedFileSystem::new(Arc::new(agent.fs), FsHooks::default())
+    }
+
+    #[tokio::test]
+    async fn test_hooked_passes_operations_through() {
+        let fs = hooked().await;
+        let dir = fs.mkdir(1, "docs", 0o755, 0, 0).await.unwrap();
+        let (stats, file) = fs
+            .create_file(dir.ino, "a.md", 0o100644, 0, 0)
+            .await
+            .unwrap();
+        file.pwrite(0, b"hello").await.unwrap();
+        file.truncate(4).await.unwrap();
+
+        assert_eq!(file.pread(0, 10).await.unwrap(), b"hell");
+        assert_eq!(fs.path(stats.ino).as_deref(), Some("/docs/a.md"));
+    }
+
+    /// A hooked filesystem whose validator denies files over `max_size`
+    async fn validated(max_size: usize) -> HookedFileSystem {
+        let mut registry = HookRegistry::new();
+        registry.register_sync(Box::new(LevFSValidator::new().with_max_size(max_size)));
+        let agent = AgentFS::open(AgentFSOptions::ephemeral()).await.unwrap();
+        let hooks = FsHooks::new(Some(registry), None, Default::default());
+        HookedFileSystem::new(Arc::new(agent.fs), hooks)
+    }
+
+    #[tokio::test]
+    async fn test_denied_close_restores_file() {
+        let fs = validated(16).await;
+        let (stats, file) = fs.create_file(1, "a.md", 0o100644, 0, 0).await.unwrap();
+        file.pwrite(0, b"original").await.unwrap();
+        file.flush().await.unwrap();
+
+        let file = fs.open(stats.ino, libc::O_RDWR).await.unwrap();
+        file.pwrite(4, b" content longer than allowed")
+            .await
+            .unwrap();
+        assert!(file.flush().await.is_err());
+        assert_eq!(file.pread(0, 64).await.unwrap(), b"original");
+        let stats = fs.getattr(stats.ino).await.unwrap().unwrap();
+        assert_eq!(stats.size, 8);
+
+        // A truncate is rolled back with the writes after it
+        file.truncate(0).await.unwrap();
+        file.pwrite(0, b"a replacement too long").await.unwrap();
+        assert!(file.fsync().await.is_err());
+        assert_eq!(file.pread(0, 64).await.unwrap(), b"original");
+    }
+
+    #[tokio::test]
+    async fn test_denied_close_empties_new_file() {
+        let fs = validated(16).await;
+        let (stats, file) = fs.create_file(1, "a.md", 0o100644, 0, 0).await.unwrap();
+        file.pwrite(0, b"content longer than allowed")
+            .await
+            .unwrap();
+        assert!(file.flush().await.is_err());
+
+        let stats = fs.getattr(stats.ino).await.unwrap().unwrap();
+        assert_eq!(stats.size, 0);
+        assert!(file.pread(0, 64).await.unwrap().is_empty());
+
+        // Content within the limit is kept
+        file.pwrite(0, b"short").await.unwrap();
+        file.flush().await.unwrap();
+        assert_eq!(file.pread(0, 64).await.unwrap(), b"short");
+    }
+
+    #[tokio::test]
+    async fn test_large_file_saves_changed_chunks() {
+        const SIZE: u64 = 1024 * 1024;
+        let mut registry = HookRegistry::new();
+        registry.register_sync(Box::new(LevFSValidator::new().with_max_size(SIZE as usize)));
+        let agent = AgentFS::open(AgentFSOptions::ephemeral()).await.unwrap();
+        let inner: Arc<dyn FileSystem> = Arc::new(agent.fs);
+        let hooks = FsHooks::new(Some(registry), None, Default::default())
+            .with_validate_limit(4 * BASELINE_CHUNK_SIZE);
+        let fs = HookedFileSystem::new(inner.clone(), hooks);
+
+        let (stats, file) = inner
+            .create_file(1, "big.bin", 0o100644, 0, 0)
+            .await
+            .unwrap();
+        file.pwrite(0, &vec![b'a'; SIZE as usize]).await.unwrap();
+        file.flush().await.unwrap();
+
+        // An append saves nothing, and the file is validated by its size
+        let file = fs.open(stats.ino, libc::O_RDWR).await.unwrap();
+        file.pwrite(SIZE, b"b").await.unwrap();
+        assert!(file.flush().await.is_err());
+        assert_eq!(file.fstat().await.unwrap().size, SIZE as i64);
+
+        // Overwritten chunks are restored
+        file.pwrite(0, b"b").await.unwrap();
+        file.pwrite(SIZE - 1, b"bb").await.unwrap();
+        assert!(file.flush().await.is_err());
+        assert_eq!(file.fstat().await.unwrap().size, SIZE as i64);
+        assert_eq!(file.pread(0, 1).await.unwrap(), b"a");
+        assert_eq!(file.pread(SIZE - 1, 2).await.unwrap(), b"a");
+
+        // A change needing more than the limit saved fails closed
+        let err = file.truncate(0).await.unwrap_err();
+        assert!(matches!(err, SdkError::Io(ref e) if e.raw_os_error() == Some(libc::EFBIG)));
+        assert_eq!(file.fstat().await.unwrap().size, SIZE as i64);
+
+        file.pwrite(0, b"b").await.unwrap();
+        file.flush().await.unwrap();
+        assert_eq!(file.pread(0, 2).await.unwrap(), b"ba");
+    }
+
+    /// Upper-cases files at close and serves `masked` for files opened for
+    /// reading
+    struct Rewriter;
+
+    impl SyncHook for Rewriter {
+        fn name(&self) -> &str {
+            "rewriter"
+        }
+
+        fn execute(&self, context: &HookContext) -> lev_reactive::Result<HookDecision> {
+            let content = context.data["content"].as_str().unwrap_or_default();
+            Ok(match context.event_type.as_str() {
+                "file:close" => {
+                    HookDecision::Transform(json!({ "content": content.to_uppercase() }))
+                }
+                "file:open" => HookDecision::Transform(json!("masked")),
+                _ => HookDecision::Allow,
+            })
+        }
+
+        fn priority(&self) -> i32 {
+            0
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transform_rewrites_content() {
+        let registry = || {
+            let mut registry = HookRegistry::new();
+            registry.register_sync(Box::new(Rewriter));
+            Some(registry)
+        };
+        let agent = AgentFS::open(AgentFSOptions::ephemeral()).await.unwrap();
+        let inner: Arc<dyn FileSystem> = Arc::new(agent.fs);
+        let hooks = FsHooks::new(registry(), None, Default::default());
+        let fs = HookedFileSystem::new(inner.clone(), hooks);
+
+        // Written content is replaced by the hook's at close
+        let (stats, file) = fs.create_file(1, "a.md", 0o100644, 0, 0).await.unwrap();
+        file.pwrite(0, b"hello").await.unwrap();
+        file.flush().await.unwrap();
+        assert_eq!(file.pread(0, 64).await.unwrap(), b"HELLO");
+        let stored = inner.open(stats.ino, libc::O_RDONLY).await.unwrap();
+        assert_eq!(stored.pread(0, 64).await.unwrap(), b"HELLO");
+
+        // Reads get the hook's content, and the stored content is untouched
+        let hooks = FsHooks::new(registry(), None, Default::default()).with_read_hooks(registry());
+        let fs = HookedFileSystem::new(inner.clone(), hooks);
+        let file = fs.open(stats.ino, libc::O_RDONLY).await.unwrap();
+        assert_eq!(file.pread(0, 64).await.unwrap(), b"masked");
+        assert_eq!(file.fstat().await.unwrap().size, 6);
+        assert_eq!(stored.pread(0, 64).await.unwrap(), b"HELLO");
+    }
+
+    #[tokio::test]
+    async fn test_hooked_tracks_paths() {
+        let fs = hooked().await;
+        let dir = fs.mkdir(1, "docs", 0o755, 0, 0).await.unwrap();
+        let (file, _) = fs
+            .create_file(dir.ino, "a.md", 0o100644, 0, 0)
+            .await
+            .unwrap();
+
+        fs.rename(1, "docs", 1, "archive").await.unwrap();
+        assert_eq!(fs.path(file.ino).as_deref(), Some("/archive/a.md"));
+
+        fs.unlink(dir.ino, "a.md").await.unwrap();
+        assert_eq!(fs.path(file.ino), None);
+
+        // Paths of entries created elsewhere are learned from lookups
+        let other = hooked().await;
+        other.inner.mkdir(1, "src", 0o755, 0, 0).await.unwrap();
+        let src = other.lookup(1, "src").await.unwrap().unwrap();
+        assert_eq!(other.path(src.ino).as_deref(), Some("/src"));
+    }
+}
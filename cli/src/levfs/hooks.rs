@@ -0,0 +1,587 @@
+use super::reads::ReadAudit;
+use agentfs_sdk::filesystem::{S_IFBLK, S_IFCHR, S_IFDIR, S_IFIFO, S_IFLNK, S_IFMT, S_IFSOCK};
+use agentfs_sdk::{HookLog, HookLogDecision, HookLogRecord, HookQueue, ReadLog};
+use async_trait::async_trait;
+use lev_reactive::{AsyncHook, HookContext, HookDecision, HookRegistry, LevError};
+use parking_lot::Mutex;
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::runtime::Handle;
+
+/// Inode number of the filesystem root
+const ROOT_INO: u64 = 1;
+
+/// Mutating filesystem operations that hooks can observe
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookOp {
+    Write,
+    Create,
+    Mknod,
+    Mkdir,
+    Rmdir,
+    Unlink,
+    Rename,
+    Symlink,
+    Link,
+    Setattr,
+    Setxattr,
+    Removexattr,
+    /// A file handle with unvalidated writes was flushed or closed
+    Close,
+    /// A file is opened. This and the operations below only reach hooks
+    /// configured with `reads: true`.
+    Open,
+    /// An entry is looked up, or listed in its directory
+    Lookup,
+    /// A directory is listed
+    Readdir,
+}
+
+impl HookOp {
+    /// Every operation, in declaration order
+    pub const ALL: [HookOp; 16] = [
+        HookOp::Write,
+        HookOp::Create,
+        HookOp::Mknod,
+        HookOp::Mkdir,
+        HookOp::Rmdir,
+        HookOp::Unlink,
+        HookOp::Rename,
+        HookOp::Symlink,
+        HookOp::Link,
+        HookOp::Setattr,
+        HookOp::Setxattr,
+        HookOp::Removexattr,
+        HookOp::Close,
+        HookOp::Open,
+        HookOp::Lookup,
+        HookOp::Readdir,
+    ];
+
+    /// Parse an operation from its event type (`file:write`) or the event
+    /// type without its prefix (`write`)
+    pub fn parse(s: &str) -> Option<HookOp> {
+        let name = s.strip_prefix("file:").unwrap_or(s);
+        Self::ALL
+            .into_iter()
+            .find(|op| &op.event_type()["file:".len()..] == name)
+    }
+
+    /// Event type reported in `HookContext.event_type`
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            HookOp::Write => "file:write",
+            HookOp::Create => "file:create",
+            HookOp::Mknod => "file:mknod",
+            HookOp::Mkdir => "file:mkdir",
+            HookOp::Rmdir => "file:rmdir",
+            HookOp::Unlink => "file:unlink",
+            HookOp::Rename => "file:rename",
+            HookOp::Symlink => "file:symlink",
+            HookOp::Link => "file:link",
+            HookOp::Setattr => "file:setattr",
+            HookOp::Setxattr => "file:setxattr",
+            HookOp::Removexattr => "file:removexattr",
+            HookOp::Close => "file:close",
+            HookOp::Open => "file:open",
+            HookOp::Lookup => "file:lookup",
+            HookOp::Readdir => "file:readdir",
+        }
+    }
+
+    /// Whether the operation reads rather than mutates
+    pub fn is_read(&self) -> bool {
+        matches!(self, HookOp::Open | HookOp::Lookup | HookOp::Readdir)
+    }
+}
+
+impl fmt::Display for HookOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.event_type())
+    }
+}
+
+/// Name of the file type in `mode`, as reported in hook contexts
+pub fn file_type_name(mode: u32) -> &'static str {
+    match mode & S_IFMT {
+        S_IFDIR => "dir",
+        S_IFLNK => "symlink",
+        S_IFIFO => "fifo",
+        S_IFSOCK => "socket",
+        S_IFCHR => "char",
+        S_IFBLK => "block",
+        _ => "file",
+    }
+}
+
+/// Build the context passed to hooks for an operation
+pub fn hook_context(op: HookOp, data: JsonValue) -> HookContext {
+    HookContext {
+        event_type: op.event_type().to_string(),
+        source: "levfs".to_string(),
+        data,
+    }
+}
+
+/// Map a sync hook outcome to the errno that fails the operation, if any
+///
+/// `Deny` maps to `EPERM`. A hook error carrying an OS error (how a
+/// fail-closed hook reports its errno) maps to that errno, any other error to
+/// `EIO`.
+pub fn decision_errno(outcome: &lev_reactive::Result<HookDecision>) -> Option<i32> {
+    match outcome {
+        Ok(HookDecision::Deny) => Some(libc::EPERM),
+        Ok(HookDecision::Allow)
+        | Ok(HookDecision::AllowWithMessage(_))
+        | Ok(HookDecision::Transform(_)) => None,
+        Err(LevError::Io(e)) => Some(e.raw_os_error().unwrap_or(libc::EIO)),
+        Err(_) => Some(libc::EIO),
+    }
+}
+
+/// Outcome of sync hooks that let an operation proceed
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    /// Proceed unchanged
+    Allow,
+    /// Proceed with the hooks' replacement for the event data
+    Transform(JsonValue),
+}
+
+/// Callback waiting for the hook queue to be attached
+type QueueWaiter = Box<dyn FnOnce(&HookQueue) + Send>;
+
+/// Records hook decisions in the hook log once the mount has attached one
+///
+/// Shared by a pipeline and the hooks it was built with, which exist before
+/// the agent database is opened. It also hands those hooks the hook queue of
+/// the agent database.
+#[derive(Clone, Default)]
+pub struct HookRecorder {
+    log: Arc<OnceLock<HookLog>>,
+    queue: Arc<OnceLock<HookQueue>>,
+    queue_waiters: Arc<Mutex<Vec<QueueWaiter>>>,
+}
+
+impl HookRecorder {
+    /// Attach the hook log; later calls are ignored
+    pub fn set_log(&self, log: HookLog) {
+        let _ = self.log.set(log);
+    }
+
+    /// Attach the hook queue and run the callbacks waiting for it; later
+    /// calls are ignored
+    pub fn set_queue(&self, queue: HookQueue) {
+        let mut waiters = self.queue_waiters.lock();
+        if self.queue.set(queue).is_err() {
+            return;
+        }
+        if let Some(queue) = self.queue.get() {
+            for waiter in waiters.drain(..) {
+                waiter(queue);
+            }
+        }
+    }
+
+    /// The hook queue, once attached
+    pub fn queue(&self) -> Option<&HookQueue> {
+        self.queue.get()
+    }
+
+    /// Run `waiter` with the hook queue as soon as it is attached
+    pub fn on_queue(&self, waiter: impl FnOnce(&HookQueue) + Send + 'static) {
+        let mut waiters = self.queue_waiters.lock();
+        match self.queue.get() {
+            Some(queue) => waiter(queue),
+            None => waiters.push(Box::new(waiter)),
+        }
+    }
+
+    /// Record the decision of `hook` about an event in the background
+    pub fn record(
+        &self,
+        context: &HookContext,
+        hook: &str,
+        decision: HookLogDecision,
+        message: Option<String>,
+        latency: Duration,
+    ) {
+        let (Some(log), Ok(runtime)) = (self.log.get().cloned(), Handle::try_current()) else {
+            return;
+        };
+        let record = HookLogRecord {
+            event_type: context.event_type.clone(),
+            path: context.data["path"].as_str().map(str::to_string),
+            hook: Some(hook.to_string()),
+            decision,
+            message,
+            latency_us: Some(latency.as_micros() as i64),
+        };
+        runtime.spawn(async move {
+            if let Err(e) = log.record(&record).await {
+                tracing::warn!(
+                    "LevFS: failed to record {} in hook log: {}",
+                    record.event_type,
+                    e
+                );
+            }
+        });
+    }
+}
+
+/// The hook log decision and message for a hook outcome
+pub fn log_decision(
+    outcome: &lev_reactive::Result<HookDecision>,
+) -> (HookLogDecision, Option<String>) {
+    match outcome {
+        Ok(HookDecision::Allow) => (HookLogDecision::Allow, None),
+        Ok(HookDecision::Deny) => (HookLogDecision::Deny, None),
+        Ok(HookDecision::AllowWithMessage(message)) => {
+            (HookLogDecision::Message, Some(message.clone()))
+        }
+        Ok(HookDecision::Transform(_)) => (HookLogDecision::Transform, None),
+        Err(e) => (HookLogDecision::Error, Some(e.to_string())),
+    }
+}
+
+/// An async hook whose outcomes are recorded in the hook log
+pub struct RecordedHook {
+    hook: Box<dyn AsyncHook>,
+    recorder: HookRecorder,
+}
+
+impl RecordedHook {
+    pub fn new(hook: Box<dyn AsyncHook>, recorder: HookRecorder) -> Self {
+        Self { hook, recorder }
+    }
+}
+
+#[async_trait]
+impl AsyncHook for RecordedHook {
+    fn name(&self) -> &str {
+        self.hook.name()
+    }
+
+    async fn execute(&self, context: &HookContext) -> lev_reactive::Result<HookDecision> {
+        let start = Instant::now();
+        let outcome = self.hook.execute(context).await;
+        let (decision, message) = log_decision(&outcome);
+        self.recorder
+            .record(context, self.name(), decision, message, start.elapsed());
+        outcome
+    }
+
+    fn priority(&self) -> i32 {
+        self.hook.priority()
+    }
+}
+
+/// Default for [`FsHooks::with_validate_limit`]: 64 MiB
+const DEFAULT_VALIDATE_LIMIT: u64 = 64 * 1024 * 1024;
+
+/// Sync and async hook pipelines shared by the filesystem frontends
+#[derive(Clone, Default)]
+pub struct FsHooks {
+    /// Synchronous hooks executed before file operations
+    sync_hooks: Option<HookRegistry>,
+    /// Synchronous hooks executed before reads, a subset of `sync_hooks`
+    read_hooks: Option<HookRegistry>,
+    /// Asynchronous hooks executed after file operations
+    async_hooks: Option<HookRegistry>,
+    /// Where decisions of the pipeline and its hooks are recorded
+    recorder: HookRecorder,
+    /// Where the files read through the pipeline are recorded
+    read_audit: Option<Arc<ReadAudit>>,
+    /// Most bytes of a file read to validate it at close
+    validate_limit: Option<u64>,
+}
+
+impl FsHooks {
+    /// Create a pipeline whose hooks record their decisions with `recorder`
+    pub fn new(
+        sync_hooks: Option<HookRegistry>,
+        async_hooks: Option<HookRegistry>,
+        recorder: HookRecorder,
+    ) -> Self {
+        Self {
+            sync_hooks,
+            read_hooks: None,
+            async_hooks,
+            recorder,
+            read_audit: None,
+            validate_limit: None,
+        }
+    }
+
+    /// Run `read_hooks` before reads
+    pub fn with_read_hooks(mut self, read_hooks: Option<HookRegistry>) -> Self {
+        self.read_hooks = read_hooks;
+        self
+    }
+
+    /// Record the files read through the pipeline with `read_audit`
+    pub fn with_read_audit(mut self, read_audit: Option<ReadAudit>) -> Self {
+        self.read_audit = read_audit.map(Arc::new);
+        self
+    }
+
+    /// Limit the bytes of a file read to validate it at close
+    ///
+    /// Files larger than `limit` reach `file:close` hooks without their
+    /// content, and a handle fails with `EFBIG` when its changes would need
+    /// more than `limit` bytes of original content saved for a rollback.
+    pub fn with_validate_limit(mut self, limit: u64) -> Self {
+        self.validate_limit = Some(limit);
+        self
+    }
+
+    /// Record hook decisions in a hook log
+    pub fn with_log(self, log: HookLog) -> Self {
+        self.recorder.set_log(log);
+        self
+    }
+
+    /// Keep events for async hooks in a hook queue until they are handled
+    ///
+    /// Events left in the queue by an earlier mount are run again.
+    pub fn with_queue(self, queue: HookQueue) -> Self {
+        self.recorder.set_queue(queue);
+        self
+    }
+
+    /// Record the reads of the read audit, if configured, in a read log
+    pub fn with_read_log(self, log: ReadLog) -> Self {
+        if let Some(ref read_audit) = self.read_audit {
+            read_audit.set_log(log);
+        }
+        self
+    }
+
+    /// Whether any sync hooks are configured
+    pub fn has_sync_hooks(&self) -> bool {
+        self.sync_hooks.is_some()
+    }
+
+    /// Whether any sync hooks run before reads
+    pub fn has_read_hooks(&self) -> bool {
+        self.read_hooks.is_some()
+    }
+
+    /// Most bytes of a file read to validate it at close
+    pub fn validate_limit(&self) -> u64 {
+        self.validate_limit.unwrap_or(DEFAULT_VALIDATE_LIMIT)
+    }
+
+    /// The read audit, if configured
+    pub fn read_audit(&self) -> Option<&Arc<ReadAudit>> {
+        self.read_audit.as_ref()
+    }
+
+    /// Whether any hooks are configured
+    pub fn is_empty(&self) -> bool {
+        self.sync_hooks.is_none() && self.async_hooks.is_none() && self.read_audit.is_none()
+    }
+
+    /// Run sync hooks before an operation, or read hooks before a read
+    ///
+    /// Returns the errno the operation must fail with when a hook denies it.
+    /// A message from `AllowWithMessage` is logged here; applying a
+    /// `Transform` is up to the caller. Each hook records its own decision.
+    pub fn before(&self, op: HookOp, data: &JsonValue) -> Result<Verdict, i32> {
+        let hooks = if op.is_read() {
+            &self.read_hooks
+        } else {
+            &self.sync_hooks
+        };
+        let Some(sync_hooks) = hooks else {
+            return Ok(Verdict::Allow);
+        };
+        let ctx = hook_context(op, data.clone());
+        let outcome = sync_hooks.execute_sync(&ctx);
+        if let Some(errno) = decision_errno(&outcome) {
+            tracing::debug!("LevFS: {} denied by sync hook (errno {})", op, errno);
+            return Err(errno);
+        }
+        match outcome {
+            Ok(HookDecision::AllowWithMessage(message)) => {
+                tracing::info!("LevFS: {} {}: {}", op, display_path(data), message);
+                Ok(Verdict::Allow)
+            }
+            Ok(HookDecision::Transform(value)) => Ok(Verdict::Transform(value)),
+            _ => Ok(Verdict::Allow),
+        }
+    }
+
+    /// Fire async hooks after a successful operation (fire-and-forget)
+    pub fn after(&self, runtime: &Handle, op: HookOp, data: JsonValue) {
+        let Some(ref async_hooks) = self.async_hooks else {
+            return;
+        };
+        let ctx = hook_context(op, data);
+        let hooks = async_hooks.clone();
+        runtime.spawn(async move {
+            let _ = hooks.execute_async(&ctx).await;
+        });
+    }
+}
+
+/// Path of an event for log messages
+pub fn display_path(data: &JsonValue) -> &str {
+    data["path"].as_str().unwrap_or("<unknown>")
+}
+
+/// Paths of the inodes seen by a [`HookedFileSystem`]
+///
+/// The `FileSystem` trait addresses files by inode, while hooks match on
+/// paths. The path of every inode handed out (lookup, readdir, create) is
+/// remembered and kept in sync on rename and removal, so hook contexts can
+/// carry resolved paths. An inode with several hard links is known by the
+/// last path it was seen under.
+///
+/// [`HookedFileSystem`]: super::HookedFileSystem
+pub struct InodePaths {
+    paths: Mutex<HashMap<u64, String>>,
+}
+
+impl InodePaths {
+    pub fn new() -> Self {
+        let mut paths = HashMap::new();
+        paths.insert(ROOT_INO, "/".to_string());
+        Self {
+            paths: Mutex::new(paths),
+        }
+    }
+
+    /// Get the path of an inode, if known
+    pub fn path(&self, ino: u64) -> Option<String> {
+        self.paths.lock().get(&ino).cloned()
+    }
+
+    /// Get the path of `name` in the directory `parent`, if the directory is known
+    pub fn child(&self, parent: u64, name: &str) -> Option<String> {
+        self.path(parent).map(|dir| join(&dir, name))
+    }
+
+    /// Record `path` as the path of `ino`
+    pub fn insert(&self, ino: u64, path: String) {
+        self.paths.lock().insert(ino, path);
+    }
+
+    /// Record the path of `name` in `parent` as the path of `ino`
+    pub fn remember(&self, parent: u64, name: &str, ino: u64) {
+        if let Some(path) = self.child(parent, name) {
+            self.paths.lock().insert(ino, path);
+        }
+    }
+
+    /// Drop an inode the kernel no longer references
+    pub fn forget(&self, ino: u64) {
+        if ino != ROOT_INO {
+            self.paths.lock().remove(&ino);
+        }
+    }
+
+    /// Drop every inode known by `path` after it was unlinked or removed
+    pub fn remove(&self, path: &str) {
+        self.paths.lock().retain(|_, p| p != path);
+    }
+
+    /// Move `from` and everything below it to `to`, dropping whatever `to`
+    /// replaced
+    pub fn rename(&self, from: &str, to: &str) {
+        let mut paths = self.paths.lock();
+        paths.retain(|_, p| !is_within(p, to) || is_within(p, from));
+        for path in paths.values_mut() {
+            if is_within(path, from) {
+                *path = format!("{}{}", to, &path[from.len()..]);
+            }
+        }
+    }
+}
+
+impl Default for InodePaths {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Join a directory path and an entry name
+fn join(dir: &str, name: &str) -> String {
+    if dir == "/" {
+        format!("/{}", name)
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+/// Whether `path` is `base` or below it
+fn is_within(path: &str, base: &str) -> bool {
+    path == base || (path.starts_with(base) && (base == "/" || path.as_bytes()[base.len()] == b'/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inode_paths_track_entries() {
+        let paths = InodePaths::new();
+        paths.remember(1, "docs", 2);
+        paths.remember(2, "a.md", 3);
+        paths.remember(99, "orphan", 4);
+
+        assert_eq!(paths.path(3).as_deref(), Some("/docs/a.md"));
+        assert_eq!(paths.child(2, "b.md").as_deref(), Some("/docs/b.md"));
+        assert_eq!(paths.path(4), None);
+
+        paths.remove("/docs/a.md");
+        assert_eq!(paths.path(3), None);
+        paths.forget(1);
+        assert_eq!(paths.path(1).as_deref(), Some("/"));
+    }
+
+    #[test]
+    fn test_inode_paths_rename() {
+        let paths = InodePaths::new();
+        paths.remember(1, "docs", 2);
+        paths.remember(2, "a.md", 3);
+        paths.remember(1, "docs2", 4);
+        paths.remember(1, "archive", 5);
+        paths.remember(5, "a.md", 6);
+
+        paths.rename("/docs", "/archive");
+        assert_eq!(paths.path(2).as_deref(), Some("/archive"));
+        assert_eq!(paths.path(3).as_deref(), Some("/archive/a.md"));
+        assert_eq!(paths.path(4).as_deref(), Some("/docs2"));
+        assert_eq!(paths.path(5), None);
+        assert_eq!(paths.path(6), None);
+    }
+
+    #[test]
+    fn test_parse_hook_op() {
+        assert_eq!(HookOp::parse("file:unlink"), Some(HookOp::Unlink));
+        assert_eq!(HookOp::parse("setxattr"), Some(HookOp::Setxattr));
+        assert_eq!(HookOp::parse("file:read"), None);
+        assert!(HookOp::parse("lookup").is_some_and(|op| op.is_read()));
+        for op in HookOp::ALL {
+            assert_eq!(HookOp::parse(op.event_type()), Some(op));
+        }
+    }
+
+    #[test]
+    fn test_decision_errno() {
+        assert_eq!(decision_errno(&Ok(HookDecision::Allow)), None);
+        assert_eq!(decision_errno(&Ok(HookDecision::Deny)), Some(libc::EPERM));
+        assert_eq!(
+            decision_errno(&Ok(HookDecision::AllowWithMessage("large".to_string()))),
+            None
+        );
+        let closed = LevError::Io(std::io::Error::from_raw_os_error(libc::EACCES));
+        assert_eq!(decision_errno(&Err(closed)), Some(libc::EACCES));
+        let failed = LevError::ConfigError("bad".to_string());
+        assert_eq!(decision_errno(&Err(failed)), Some(libc::EIO));
+    }
+}
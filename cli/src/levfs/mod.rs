@@ -1,5 +1,21 @@
+pub mod config;
+pub mod guard;
+pub mod hooked;
+pub mod hooks;
+pub mod path_fs;
+pub mod policy;
+pub mod reads;
 pub mod validator;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 pub mod workflow;
 
+pub use config::load_hooks;
+pub use guard::{FailurePolicy, GuardSettings, GuardedHook};
+pub use hooked::HookedFileSystem;
+pub use hooks::{FsHooks, HookOp, InodePaths};
+pub use path_fs::PathFileSystem;
+pub use policy::LevFSPolicy;
+pub use reads::ReadAudit;
 pub use validator::LevFSValidator;
 pub use workflow::LevFSWorkflow;
@@ -0,0 +1,215 @@
+//! Path operations over an inode `FileSystem`.
+//!
+//! The MCP server and `agentfs fs write` address files by path. Instead of
+//! the SDK's path API on the bare `AgentFS`, they go through a
+//! [`PathFileSystem`], which resolves paths with lookups on any
+//! `Arc<dyn FileSystem>`. Built on the filesystem a mount would serve,
+//! wrapped in a [`HookedFileSystem`](super::HookedFileSystem), they run the
+//! same hooks as the mount: an operation a policy denies through FUSE or NFS
+//! is denied through them too, and read hooks hide what they redact.
+
+use agentfs_sdk::error::Result;
+use agentfs_sdk::{BoxedFile, FileSystem, FsError, Stats, DEFAULT_DIR_MODE, DEFAULT_FILE_MODE};
+use std::sync::Arc;
+
+const ROOT_INO: i64 = 1;
+
+/// Symlinks followed when resolving a path, as for `stat(2)`
+const MAX_SYMLINK_DEPTH: usize = 40;
+
+/// Split a path into its components, resolving `.` and `..`
+///
+/// Relative paths are taken from the root, and `..` stops there.
+fn components(path: &str) -> Vec<String> {
+    let mut components = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            name => components.push(name.to_string()),
+        }
+    }
+    components
+}
+
+/// A filesystem addressed by path
+pub struct PathFileSystem {
+    fs: Arc<dyn FileSystem>,
+}
+
+impl PathFileSystem {
+    /// Address `fs` by path
+    pub fn new(fs: Arc<dyn FileSystem>) -> Self {
+        Self { fs }
+    }
+
+    /// Look up the entry at `components`, without following a final symlink
+    async fn walk(&self, components: &[String]) -> Result<Option<Stats>> {
+        let Some(mut stats) = self.fs.getattr(ROOT_INO).await? else {
+            return Ok(None);
+        };
+        for name in components {
+            match self.fs.lookup(stats.ino, name).await? {
+                Some(child) => stats = child,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(stats))
+    }
+
+    /// Get the directory `path` is in and the name of its entry there
+    async fn parent(&self, path: &str) -> Result<(i64, String)> {
+        let mut components = components(path);
+        let name = components.pop().ok_or(FsError::RootOperation)?;
+        let parent = self.walk(&components).await?.ok_or(FsError::NotFound)?;
+        if !parent.is_directory() {
+            return Err(FsError::NotADirectory.into());
+        }
+        Ok((parent.ino, name))
+    }
+
+    /// Get the attributes of the entry at `path`, without following a final
+    /// symlink
+    pub async fn lstat(&self, path: &str) -> Result<Option<Stats>> {
+        self.walk(&components(path)).await
+    }
+
+    /// Get the attributes of the file at `path`, following symlinks
+    pub async fn stat(&self, path: &str) -> Result<Option<Stats>> {
+        let mut components = components(path);
+        for _ in 0..MAX_SYMLINK_DEPTH {
+            let Some(stats) = self.walk(&components).await? else {
+                return Ok(None);
+            };
+            if !stats.is_symlink() {
+                return Ok(Some(stats));
+            }
+            let target = self
+                .fs
+                .readlink(stats.ino)
+                .await?
+                .ok_or(FsError::NotFound)?;
+            // A relative target is resolved from the symlink's directory
+            let base = if target.starts_with('/') {
+                String::new()
+            } else {
+                components[..components.len() - 1].join("/")
+            };
+            components = self::components(&format!("{}/{}", base, target));
+        }
+        Err(FsError::SymlinkLoop.into())
+    }
+
+    /// Read the whole file at `path`
+    pub async fn read_file(&self, path: &str) -> Result<Option<Vec<u8>>> {
+        let Some(stats) = self.stat(path).await? else {
+            return Ok(None);
+        };
+        if stats.is_directory() {
+            return Err(FsError::IsADirectory.into());
+        }
+        let file = self.fs.open(stats.ino, libc::O_RDONLY).await?;
+        // The size through the handle, which a read hook may have redacted
+        let size = file.fstat().await?.size as u64;
+        Ok(Some(file.pread(0, size).await?))
+    }
+
+    /// List the directory at `path`
+    pub async fn readdir(&self, path: &str) -> Result<Option<Vec<String>>> {
+        match self.stat(path).await? {
+            Some(stats) => self.fs.readdir(stats.ino).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Create a directory at `path`
+    pub async fn mkdir(&self, path: &str, uid: u32, gid: u32) -> Result<Stats> {
+        let (parent, name) = self.parent(path).await?;
+        self.fs
+            .mkdir(parent, &name, DEFAULT_DIR_MODE, uid, gid)
+            .await
+    }
+
+    /// Create a file at `path` and open it
+    pub async fn create_file(
+        &self,
+        path: &str,
+        mode: u32,
+        uid: u32,
+        gid: u32,
+    ) -> Result<(Stats, BoxedFile)> {
+        let (parent, name) = self.parent(path).await?;
+        self.fs.create_file(parent, &name, mode, uid, gid).await
+    }
+
+    /// Replace the file at `path` with `data`, creating it if needed
+    pub async fn write_file(&self, path: &str, data: &[u8]) -> Result<()> {
+        if self.lstat(path).await?.is_some() {
+            self.remove(path).await?;
+        }
+        let (_, file) = self.create_file(path, DEFAULT_FILE_MODE, 0, 0).await?;
+        file.pwrite(0, data).await?;
+        file.flush().await
+    }
+
+    /// Remove the file or empty directory at `path`
+    pub async fn remove(&self, path: &str) -> Result<()> {
+        let (parent, name) = self.parent(path).await?;
+        let stats = self
+            .fs
+            .lookup(parent, &name)
+            .await?
+            .ok_or(FsError::NotFound)?;
+        if stats.is_directory() {
+            self.fs.rmdir(parent, &name).await
+        } else {
+            self.fs.unlink(parent, &name).await
+        }
+    }
+
+    /// Move the entry at `from` to `to`
+    pub async fn rename(&self, from: &str, to: &str) -> Result<()> {
+        let (old_parent, old_name) = self.parent(from).await?;
+        let (new_parent, new_name) = self.parent(to).await?;
+        self.fs
+            .rename(old_parent, &old_name, new_parent, &new_name)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use agentfs_sdk::{AgentFS, AgentFSOptions};
+
+    #[tokio::test]
+    async fn test_path_operations() {
+        let agent = AgentFS::open(AgentFSOptions::ephemeral()).await.unwrap();
+        let fs = PathFileSystem::new(Arc::new(agent.fs.clone()));
+
+        fs.mkdir("/docs", 0, 0).await.unwrap();
+        fs.write_file("docs/./a.md", b"hello").await.unwrap();
+        fs.write_file("/docs/a.md", b"bye").await.unwrap();
+        assert_eq!(fs.read_file("/docs/a.md").await.unwrap().unwrap(), b"bye");
+        assert_eq!(
+            agent.fs.read_file("/docs/a.md").await.unwrap().unwrap(),
+            b"bye"
+        );
+
+        agent.fs.symlink("docs/a.md", "/link", 0, 0).await.unwrap();
+        assert!(fs.lstat("/link").await.unwrap().unwrap().is_symlink());
+        assert_eq!(fs.stat("/link").await.unwrap().unwrap().size, 3);
+
+        fs.rename("/docs/a.md", "/b.md").await.unwrap();
+        assert_eq!(
+            fs.readdir("/docs").await.unwrap().unwrap(),
+            Vec::<String>::new()
+        );
+        fs.remove("/docs").await.unwrap();
+        fs.remove("/b.md").await.unwrap();
+        assert!(fs.stat("/docs/../b.md").await.unwrap().is_none());
+        assert!(fs.mkdir("/missing/dir", 0, 0).await.is_err());
+    }
+}
@@ -0,0 +1,700 @@
+//! Declarative access policy, the built-in `levfs-policy` sync hook.
+//!
+//! A policy is an ordered list of rules; the first rule matching an
+//! operation decides it, and `default` decides operations no rule matches:
+//!
+//! ```yaml
+//! hooks:
+//!   - name: levfs-policy
+//!     type: sync
+//!     config:
+//!       default: allow
+//!       rules:
+//!         - name: no-git
+//!           paths: ["/.git/**"]
+//!           action: deny
+//!           reason: agents may not touch .git
+//!         - ops: [create, mkdir, mknod, symlink]
+//!           paths: ["/out/**"]
+//!           action: allow
+//!         - ops: [create, mkdir, mknod, symlink]
+//!           action: deny
+//!         - ops: [create, setattr]
+//!           mode: "0111"
+//!           action: deny
+//!         - ops: [unlink, rmdir, rename]
+//!           created_in_session: false
+//!           action: deny
+//!         - ops: [open, lookup, readdir]
+//!           paths: ["/secrets/**"]
+//!           action: redact
+//! ```
+//!
+//! Reads (`open`, `lookup`, `readdir`) reach the policy only with
+//! `reads: true`, and only rules naming them apply to them: a rule without
+//! `ops` and the `default` action cover mutating operations alone, so
+//! enabling reads leaves what a policy does to writes unchanged. `redact`
+//! hides an entry from lookups and listings and makes an opened file read as
+//! empty; on a mutating operation it denies.
+//!
+//! A rule matches when all of its conditions do. A condition on something an
+//! event does not tell (the size of an entry it has no attributes for, say)
+//! does not match, except that path globs of `deny` and `redact` rules match
+//! events whose path is unknown, so that denials fail closed. Paths are
+//! resolved from the database for inodes the mount has not seen, leaving
+//! unknown only those of files unlinked while open. Rules are indexed by
+//! operation and glob matching is skipped for paths outside a glob's literal
+//! prefix, so large policies cost little per operation.
+
+use super::guard::{ReasonedHook, Ruling};
+use super::hooks::{display_path, file_type_name, HookOp};
+use glob::{MatchOptions, Pattern};
+use lev_reactive::{HookContext, HookDecision, LevError, Result, SyncHook};
+use serde::Deserialize;
+use serde_json::{json, Value as JsonValue};
+
+/// Glob options for rule paths: `*` stays within a path component
+const PATH_MATCH: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// What a policy does with an operation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    #[default]
+    Allow,
+    Deny,
+    /// Let the operation proceed and record it in the hook log
+    Audit,
+    /// Hide the entry from reads; deny anything else
+    Redact,
+}
+
+/// File types a rule can match
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileType {
+    File,
+    Dir,
+    Symlink,
+    Fifo,
+    Socket,
+    Char,
+    Block,
+}
+
+impl FileType {
+    fn parse(s: &str) -> Option<FileType> {
+        match s {
+            "file" => Some(FileType::File),
+            "dir" => Some(FileType::Dir),
+            "symlink" => Some(FileType::Symlink),
+            "fifo" => Some(FileType::Fifo),
+            "socket" => Some(FileType::Socket),
+            "char" => Some(FileType::Char),
+            "block" => Some(FileType::Block),
+            _ => None,
+        }
+    }
+}
+
+/// Mode bits given as a number or an octal string (`"0111"`)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum ModeBits {
+    Number(u32),
+    Octal(String),
+}
+
+/// Settings of `levfs-policy`
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PolicyConfig {
+    /// Action for operations no rule matches
+    #[serde(default)]
+    pub default: Action,
+    #[serde(default)]
+    pub rules: Vec<RuleConfig>,
+}
+
+/// A policy rule
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleConfig {
+    /// Name used in log messages
+    pub name: Option<String>,
+    /// Operations the rule applies to, by event type without `file:`; all
+    /// mutating operations when empty or `*`
+    #[serde(default)]
+    pub ops: Vec<String>,
+    /// Globs the path (or, for renames and links, the new path) must match
+    #[serde(default)]
+    pub paths: Vec<String>,
+    /// File types the entry must have
+    #[serde(default)]
+    pub types: Vec<FileType>,
+    /// Smallest size the file must have, in bytes
+    pub min_size: Option<u64>,
+    /// Largest size the file may have, in bytes
+    pub max_size: Option<u64>,
+    /// Mode bits of which the operation must set at least one
+    pub mode: Option<ModeBits>,
+    /// Owner the entry must have
+    pub uid: Option<u32>,
+    /// Whether the entry must have been created since the mount started
+    pub created_in_session: Option<bool>,
+    pub action: Action,
+    /// Reason recorded when the rule denies or audits an operation
+    pub reason: Option<String>,
+}
+
+/// A path glob with the literal prefix every path it matches starts with
+struct PathGlob {
+    prefix: String,
+    pattern: Pattern,
+}
+
+impl PathGlob {
+    fn new(glob: &str) -> std::result::Result<Self, glob::PatternError> {
+        // Hook paths are absolute
+        let glob = if glob.starts_with('/') {
+            glob.to_string()
+        } else {
+            format!("/{}", glob)
+        };
+        let prefix = glob
+            .find(['*', '?', '['])
+            .map_or(glob.as_str(), |i| &glob[..i])
+            .to_string();
+        Ok(Self {
+            pattern: Pattern::new(&glob)?,
+            prefix,
+        })
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        path.starts_with(&self.prefix) && self.pattern.matches_with(path, PATH_MATCH)
+    }
+}
+
+/// A compiled rule
+struct Rule {
+    name: Option<String>,
+    /// Globs matching the entry; `dir/**` also matches `dir` itself
+    paths: Vec<PathGlob>,
+    types: Vec<FileType>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    mode: Option<u32>,
+    uid: Option<u32>,
+    created_in_session: Option<bool>,
+    action: Action,
+    reason: Option<String>,
+}
+
+impl Rule {
+    fn compile(config: &RuleConfig) -> Result<Self> {
+        let mut paths = Vec::new();
+        for glob in &config.paths {
+            let invalid =
+                |e: glob::PatternError| config_error(format!("Invalid glob '{}': {}", glob, e));
+            paths.push(PathGlob::new(glob).map_err(invalid)?);
+            if let Some(dir) = glob.strip_suffix("/**") {
+                if !dir.is_empty() {
+                    paths.push(PathGlob::new(dir).map_err(invalid)?);
+                }
+            }
+        }
+        let mode = match &config.mode {
+            Some(ModeBits::Number(bits)) => Some(*bits),
+            Some(ModeBits::Octal(bits)) => {
+                let digits = bits.strip_prefix("0o").unwrap_or(bits);
+                Some(
+                    u32::from_str_radix(digits, 8)
+                        .map_err(|_| config_error(format!("Invalid mode '{}'", bits)))?,
+                )
+            }
+            None => None,
+        };
+        Ok(Self {
+            name: config.name.clone(),
+            paths,
+            types: config.types.clone(),
+            min_size: config.min_size,
+            max_size: config.max_size,
+            mode,
+            uid: config.uid,
+            created_in_session: config.created_in_session,
+            action: config.action,
+            reason: config.reason.clone(),
+        })
+    }
+
+    /// Whether every condition of the rule holds for an operation
+    fn matches(&self, subject: &Subject) -> bool {
+        if !self.paths.is_empty() {
+            let matches = |path: Option<&str>| {
+                path.is_some_and(|path| self.paths.iter().any(|glob| glob.matches(path)))
+            };
+            // Rules refusing an operation apply to entries of unknown path
+            let unknown = subject.path.is_none() && subject.new_path.is_none();
+            let refuses = matches!(self.action, Action::Deny | Action::Redact);
+            if !(unknown && refuses || matches(subject.path) || matches(subject.new_path)) {
+                return false;
+            }
+        }
+        if !self.types.is_empty()
+            && !subject
+                .file_type
+                .is_some_and(|file_type| self.types.contains(&file_type))
+        {
+            return false;
+        }
+        if self.min_size.is_some() || self.max_size.is_some() {
+            let Some(size) = subject.size else {
+                return false;
+            };
+            if self.min_size.is_some_and(|min| size < min)
+                || self.max_size.is_some_and(|max| size > max)
+            {
+                return false;
+            }
+        }
+        if let Some(bits) = self.mode {
+            if subject.mode.is_none_or(|mode| mode & bits == 0) {
+                return false;
+            }
+        }
+        if let Some(uid) = self.uid {
+            if subject.uid != Some(uid) {
+                return false;
+            }
+        }
+        if let Some(created) = self.created_in_session {
+            if subject.created_in_session != Some(created) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// What a policy decides on, taken from the data of a hook context
+#[derive(Debug, Default, PartialEq)]
+struct Subject<'a> {
+    path: Option<&'a str>,
+    /// Destination of a rename or link
+    new_path: Option<&'a str>,
+    file_type: Option<FileType>,
+    /// Size of the file after the operation, as far as the event tells
+    size: Option<u64>,
+    /// Mode set by the operation
+    mode: Option<u32>,
+    /// Owner of the entry after the operation
+    uid: Option<u32>,
+    created_in_session: Option<bool>,
+}
+
+impl<'a> Subject<'a> {
+    fn new(op: HookOp, data: &'a JsonValue) -> Self {
+        // The existing entry the operation acts on, if any
+        let entry = &data["entry"];
+        let u32_field = |value: &JsonValue| value.as_u64().map(|n| n as u32);
+        let creates = matches!(
+            op,
+            HookOp::Create | HookOp::Mkdir | HookOp::Mknod | HookOp::Symlink
+        );
+
+        let file_type = match op {
+            HookOp::Create => Some(FileType::File),
+            HookOp::Mkdir => Some(FileType::Dir),
+            HookOp::Symlink => Some(FileType::Symlink),
+            HookOp::Mknod => {
+                u32_field(&data["mode"]).and_then(|m| FileType::parse(file_type_name(m)))
+            }
+            _ => entry["type"].as_str().and_then(FileType::parse),
+        };
+        let size = match op {
+            HookOp::Close => data["size"].as_u64(),
+            HookOp::Write => {
+                data["offset"]
+                    .as_u64()
+                    .zip(data["size"].as_u64())
+                    .map(|(offset, size)| {
+                        // A write can only grow a file to its end
+                        (offset + size).max(entry["size"].as_u64().unwrap_or(0))
+                    })
+            }
+            HookOp::Setattr if !data["size"].is_null() => data["size"].as_u64(),
+            _ if creates => Some(0),
+            _ => entry["size"].as_u64(),
+        };
+        let mode = match op {
+            HookOp::Create | HookOp::Mkdir | HookOp::Mknod | HookOp::Setattr => {
+                u32_field(&data["mode"]).map(|mode| mode & 0o7777)
+            }
+            _ => None,
+        };
+        let uid = match op {
+            _ if creates => u32_field(&data["uid"]),
+            HookOp::Setattr if !data["uid"].is_null() => u32_field(&data["uid"]),
+            _ => u32_field(&entry["uid"]),
+        };
+        let created_in_session = if creates {
+            Some(true)
+        } else {
+            entry["created_in_session"].as_bool()
+        };
+
+        Self {
+            path: data["path"].as_str(),
+            new_path: data["new_path"].as_str(),
+            file_type,
+            size,
+            mode,
+            uid,
+            created_in_session,
+        }
+    }
+}
+
+/// LevFS Policy Plugin
+///
+/// Allows, denies, audits or redacts operations by the first matching rule
+/// of an ordered list.
+pub struct LevFSPolicy {
+    rules: Vec<Rule>,
+    /// Indexes of the rules applying to each operation, in order
+    by_op: Vec<Vec<usize>>,
+    default: Action,
+}
+
+impl LevFSPolicy {
+    /// Compile a policy
+    pub fn new(config: &PolicyConfig) -> Result<Self> {
+        let mut rules = Vec::with_capacity(config.rules.len());
+        let mut by_op = vec![Vec::new(); HookOp::ALL.len()];
+        for (index, rule) in config.rules.iter().enumerate() {
+            let describe = || {
+                rule.name
+                    .clone()
+                    .unwrap_or_else(|| format!("#{}", index + 1))
+            };
+            let all = rule.ops.is_empty() || rule.ops.iter().any(|op| op == "*");
+            for op in HookOp::ALL {
+                let named = rule.ops.iter().any(|name| HookOp::parse(name) == Some(op));
+                if named || (all && !op.is_read()) {
+                    by_op[op as usize].push(index);
+                }
+            }
+            if let Some(unknown) = rule
+                .ops
+                .iter()
+                .find(|name| *name != "*" && HookOp::parse(name).is_none())
+            {
+                return Err(config_error(format!(
+                    "Rule {}: unknown operation '{}'",
+                    describe(),
+                    unknown
+                )));
+            }
+            let compiled = Rule::compile(rule)
+                .map_err(|e| config_error(format!("Rule {}: {}", describe(), e)))?;
+            rules.push(compiled);
+        }
+        Ok(Self {
+            rules,
+            by_op,
+            default: config.default,
+        })
+    }
+
+    /// Decide an operation: the action of the first matching rule and its
+    /// index, or the default action
+    fn decide(&self, op: HookOp, data: &JsonValue) -> (Action, Option<usize>) {
+        let subject = Subject::new(op, data);
+        for &rule in &self.by_op[op as usize] {
+            if self.rules[rule].matches(&subject) {
+                return (self.rules[rule].action, Some(rule));
+            }
+        }
+        if op.is_read() {
+            return (Action::Allow, None);
+        }
+        (self.default, None)
+    }
+
+    /// Reason given for a decision
+    fn reason(&self, rule: Option<usize>) -> String {
+        let Some(index) = rule else {
+            return "no policy rule matched".to_string();
+        };
+        let rule = &self.rules[index];
+        let name = match &rule.name {
+            Some(name) => format!("policy rule {}", name),
+            None => format!("policy rule #{}", index + 1),
+        };
+        match &rule.reason {
+            Some(reason) => format!("{}: {}", name, reason),
+            None => name,
+        }
+    }
+}
+
+impl SyncHook for LevFSPolicy {
+    fn name(&self) -> &str {
+        "levfs-policy"
+    }
+
+    fn execute(&self, context: &HookContext) -> Result<HookDecision> {
+        self.judge(context).map(|ruling| ruling.decision)
+    }
+
+    fn priority(&self) -> i32 {
+        200 // Higher than the validator - access checks come first
+    }
+}
+
+impl ReasonedHook for LevFSPolicy {
+    fn judge(&self, context: &HookContext) -> Result<Ruling> {
+        let Some(op) = HookOp::parse(&context.event_type) else {
+            return Ok(HookDecision::Allow.into());
+        };
+        let (action, rule) = self.decide(op, &context.data);
+        Ok(match action {
+            Action::Allow => HookDecision::Allow.into(),
+            Action::Redact if op.is_read() => {
+                HookDecision::Transform(json!({ "content": "" })).into()
+            }
+            Action::Deny | Action::Redact => Ruling::deny(self.reason(rule)),
+            Action::Audit => {
+                let reason = self.reason(rule);
+                tracing::info!(
+                    "LevFS: audit: {} {}: {}",
+                    context.event_type,
+                    display_path(&context.data),
+                    reason
+                );
+                Ruling::audit(reason)
+            }
+        })
+    }
+}
+
+fn config_error(message: String) -> LevError {
+    LevError::ConfigError(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::levfs::{FsHooks, HookedFileSystem};
+    use agentfs_sdk::{AgentFS, AgentFSOptions, FileSystem};
+    use lev_reactive::HookRegistry;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    const POLICY: &str = r#"
+rules:
+  - name: no-git
+    paths: ["/.git/**"]
+    action: deny
+  - ops: [create, mkdir, mknod, symlink]
+    paths: ["out/**"]
+    action: allow
+  - ops: [create, mkdir, mknod, symlink]
+    action: deny
+  - ops: [create, setattr]
+    mode: "0111"
+    action: deny
+  - ops: [unlink, rmdir, rename]
+    created_in_session: false
+    action: deny
+  - ops: [write]
+    min_size: 1025
+    action: audit
+  - ops: [open, lookup]
+    paths: ["/.env"]
+    action: redact
+"#;
+
+    fn policy(yaml: &str) -> LevFSPolicy {
+        LevFSPolicy::new(&serde_yaml::from_str(yaml).unwrap()).unwrap()
+    }
+
+    fn decide(policy: &LevFSPolicy, op: HookOp, data: JsonValue) -> Action {
+        policy.decide(op, &data).0
+    }
+
+    #[test]
+    fn test_policy_rules() {
+        let policy = policy(POLICY);
+        let entry = |created: bool| json!({ "type": "file", "mode": 0o644, "uid": 1000, "size": 10, "created_in_session": created });
+
+        let git = json!({ "path": "/.git", "entry": entry(false) });
+        assert_eq!(decide(&policy, HookOp::Setxattr, git), Action::Deny);
+        let head = json!({ "path": "/.git/HEAD", "offset": 0, "size": 4 });
+        assert_eq!(decide(&policy, HookOp::Write, head), Action::Deny);
+        // Denials fail closed on entries of unknown path
+        let unknown = json!({ "offset": 0, "size": 4, "entry": entry(true) });
+        assert_eq!(decide(&policy, HookOp::Write, unknown), Action::Deny);
+
+        let create = |path: &str, mode: u32| json!({ "path": path, "mode": mode, "uid": 1000 });
+        assert_eq!(
+            decide(&policy, HookOp::Create, create("/out/a", 0o100644)),
+            Action::Allow
+        );
+        assert_eq!(
+            decide(&policy, HookOp::Create, create("/src/a", 0o100644)),
+            Action::Deny
+        );
+        assert_eq!(
+            decide(&policy, HookOp::Mkdir, create("/out", 0o755)),
+            Action::Allow
+        );
+
+        let chmod = |mode: u32| json!({ "path": "/a.sh", "mode": mode, "size": null, "uid": null, "entry": entry(true) });
+        assert_eq!(decide(&policy, HookOp::Setattr, chmod(0o755)), Action::Deny);
+        assert_eq!(
+            decide(&policy, HookOp::Setattr, chmod(0o644)),
+            Action::Allow
+        );
+
+        let unlink = |created: bool| json!({ "path": "/a.md", "entry": entry(created) });
+        assert_eq!(decide(&policy, HookOp::Unlink, unlink(false)), Action::Deny);
+        assert_eq!(decide(&policy, HookOp::Unlink, unlink(true)), Action::Allow);
+        // Nothing is known about the entry, so the rule does not match
+        assert_eq!(
+            decide(&policy, HookOp::Unlink, json!({ "path": "/a.md" })),
+            Action::Allow
+        );
+
+        let write = json!({ "path": "/big", "offset": 1000, "size": 100, "entry": entry(true) });
+        assert_eq!(decide(&policy, HookOp::Write, write), Action::Audit);
+
+        // Only rules naming reads apply to them
+        let env = json!({ "path": "/.env", "entry": entry(false) });
+        assert_eq!(decide(&policy, HookOp::Lookup, env.clone()), Action::Redact);
+        assert_eq!(decide(&policy, HookOp::Readdir, env), Action::Allow);
+        let git = json!({ "path": "/.git/HEAD", "entry": entry(false) });
+        assert_eq!(decide(&policy, HookOp::Open, git), Action::Allow);
+        let strict = LevFSPolicy::new(&serde_yaml::from_str("default: deny\n").unwrap()).unwrap();
+        assert_eq!(
+            decide(&strict, HookOp::Lookup, json!({ "path": "/a" })),
+            Action::Allow
+        );
+    }
+
+    #[test]
+    fn test_policy_config_errors() {
+        let errors = [
+            "rules:\n  - ops: [read]\n    action: deny\n",
+            "rules:\n  - paths: [\"/a/[\"]\n    action: deny\n",
+            "rules:\n  - mode: \"9\"\n    action: deny\n",
+        ];
+        for yaml in errors {
+            assert!(
+                LevFSPolicy::new(&serde_yaml::from_str(yaml).unwrap()).is_err(),
+                "{yaml}"
+            );
+        }
+        assert!(serde_yaml::from_str::<PolicyConfig>("rules:\n  - action: maybe\n").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_policy_protects_entries_older_than_the_mount() {
+        let agent = AgentFS::open(AgentFSOptions::ephemeral()).await.unwrap();
+        agent.fs.mkdir("/docs", 0, 0).await.unwrap();
+
+        let policy = policy(
+            "rules:\n  - ops: [unlink, rmdir]\n    created_in_session: false\n    action: deny\n",
+        );
+        let mut registry = HookRegistry::new();
+        registry.register_sync(Box::new(policy));
+        let hooks = FsHooks::new(Some(registry), None, Default::default());
+        let fs = HookedFileSystem::new(Arc::new(agent.fs), hooks);
+
+        fs.mkdir(1, "new", 0o755, 0, 0).await.unwrap();
+        fs.rmdir(1, "new").await.unwrap();
+        assert!(fs.rmdir(1, "docs").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_policy_resolves_paths_of_unseen_inodes() {
+        let agent = AgentFS::open(AgentFSOptions::ephemeral()).await.unwrap();
+        agent.fs.mkdir("/.git", 0, 0).await.unwrap();
+        let (head, _) = agent
+            .fs
+            .create_file("/.git/HEAD", 0o100644, 0, 0)
+            .await
+            .unwrap();
+        let (notes, _) = agent
+            .fs
+            .create_file("/notes.md", 0o100644, 0, 0)
+            .await
+            .unwrap();
+
+        let mut registry = HookRegistry::new();
+        registry.register_sync(Box::new(policy(POLICY)));
+        let hooks = FsHooks::new(Some(registry), None, Default::default());
+        let fs = HookedFileSystem::new(Arc::new(agent.fs), hooks);
+
+        // Handles kept across a restart reach the wrapper without a lookup
+        assert!(fs.setxattr(head.ino, "user.a", b"1", 0).await.is_err());
+        assert_eq!(fs.path(head.ino).as_deref(), Some("/.git/HEAD"));
+        fs.setxattr(notes.ino, "user.a", b"1", 0).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_policy_redacts_reads() {
+        let agent = AgentFS::open(AgentFSOptions::ephemeral()).await.unwrap();
+        agent.fs.mkdir("/secrets", 0, 0).await.unwrap();
+        agent
+            .fs
+            .create_file("/secrets/key", 0o100644, 0, 0)
+            .await
+            .unwrap();
+        let (_, file) = agent.fs.create_file("/.env", 0o100644, 0, 0).await.unwrap();
+        file.pwrite(0, b"TOKEN=hunter2").await.unwrap();
+        agent.fs.mkdir("/private", 0, 0).await.unwrap();
+
+        let yaml = r#"
+rules:
+  - ops: [lookup]
+    paths: ["/secrets/*"]
+    action: redact
+  - ops: [readdir]
+    paths: ["/secrets"]
+    action: redact
+  - ops: [open]
+    paths: ["/.env"]
+    action: redact
+  - ops: [lookup]
+    paths: ["/private"]
+    action: deny
+"#;
+        let registry = || {
+            let mut registry = HookRegistry::new();
+            registry.register_sync(Box::new(policy(yaml)));
+            Some(registry)
+        };
+        let hooks = FsHooks::new(registry(), None, Default::default()).with_read_hooks(registry());
+        let fs = HookedFileSystem::new(Arc::new(agent.fs), hooks);
+
+        let secrets = fs.lookup(1, "secrets").await.unwrap().unwrap();
+        assert!(fs.lookup(secrets.ino, "key").await.unwrap().is_none());
+        assert!(fs.readdir(secrets.ino).await.unwrap().unwrap().is_empty());
+        assert!(fs.lookup(1, "private").await.is_err());
+        let names = fs.readdir(1).await.unwrap().unwrap();
+        assert!(names.iter().any(|name| name == "private"));
+
+        let env = fs.lookup(1, ".env").await.unwrap().unwrap();
+        let file = fs.open(env.ino, libc::O_RDONLY).await.unwrap();
+        assert!(file.pread(0, 100).await.unwrap().is_empty());
+        assert_eq!(file.fstat().await.unwrap().size, 0);
+        assert!(fs.open(env.ino, libc::O_RDWR).await.is_err());
+    }
+}
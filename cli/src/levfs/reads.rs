@@ -0,0 +1,141 @@
+//! Read-access audit: which files an agent opened and how much it read.
+//!
+//! Reads are counted per open file handle, and the handle is accounted for
+//! when it is closed. Handles of the same path closed within one window are
+//! folded into a single read log entry, written when the window ends, so a
+//! process reopening a file in a loop costs one row per window instead of
+//! one per open. Entries of a window still open when the mount exits are
+//! lost.
+
+use agentfs_sdk::{ReadLog, ReadLogRecord};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::runtime::Handle;
+
+/// Current time as a Unix timestamp in seconds
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Reads of one path during the current window
+struct Window {
+    opened_at: i64,
+    closed_at: i64,
+    opens: i64,
+    reads: i64,
+    bytes: i64,
+}
+
+/// Aggregates the reads of closed handles into the read log
+pub struct ReadAudit {
+    /// How long the handles of a path are folded into one entry
+    window: Duration,
+    log: OnceLock<ReadLog>,
+    windows: Mutex<HashMap<String, Window>>,
+}
+
+impl ReadAudit {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            log: OnceLock::new(),
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Attach the read log; later calls are ignored
+    pub fn set_log(&self, log: ReadLog) {
+        let _ = self.log.set(log);
+    }
+
+    /// Account for a closed handle of `path`, opened at `opened_at`, through
+    /// which `reads` reads returned `bytes` bytes
+    pub fn closed(self: &Arc<Self>, path: String, opened_at: i64, reads: u64, bytes: u64) {
+        let closed_at = now();
+        let mut windows = self.windows.lock();
+        if let Some(window) = windows.get_mut(&path) {
+            window.opened_at = window.opened_at.min(opened_at);
+            window.closed_at = closed_at;
+            window.opens += 1;
+            window.reads += reads as i64;
+            window.bytes += bytes as i64;
+            return;
+        }
+        let Ok(runtime) = Handle::try_current() else {
+            return;
+        };
+        windows.insert(
+            path.clone(),
+            Window {
+                opened_at,
+                closed_at,
+                opens: 1,
+                reads: reads as i64,
+                bytes: bytes as i64,
+            },
+        );
+        let audit = self.clone();
+        runtime.spawn(async move {
+            tokio::time::sleep(audit.window).await;
+            audit.flush(path).await;
+        });
+    }
+
+    /// End the window of `path`, recording its reads
+    async fn flush(&self, path: String) {
+        let Some(window) = self.windows.lock().remove(&path) else {
+            return;
+        };
+        let Some(log) = self.log.get() else {
+            return;
+        };
+        let record = ReadLogRecord {
+            path,
+            opened_at: window.opened_at,
+            closed_at: window.closed_at,
+            opens: window.opens,
+            reads: window.reads,
+            bytes: window.bytes,
+        };
+        if let Err(e) = log.record(&record).await {
+            tracing::warn!(
+                "LevFS: failed to record reads of {} in read log: {}",
+                record.path,
+                e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use agentfs_sdk::{AgentFS, AgentFSOptions, ReadLogQuery};
+
+    #[tokio::test]
+    async fn test_read_audit_folds_handles_per_window() {
+        let agent = AgentFS::open(AgentFSOptions::ephemeral()).await.unwrap();
+        let audit = Arc::new(ReadAudit::new(Duration::from_millis(50)));
+        audit.set_log(agent.read_log.clone());
+
+        let opened_at = now();
+        audit.closed("/a.md".to_string(), opened_at, 2, 100);
+        audit.closed("/a.md".to_string(), opened_at, 1, 10);
+        audit.closed("/b.md".to_string(), opened_at, 0, 0);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let entries = agent
+            .read_log
+            .query(&ReadLogQuery::default())
+            .await
+            .unwrap();
+        assert_eq!(entries.len(), 2);
+        let a = entries.iter().find(|e| e.path == "/a.md").unwrap();
+        assert_eq!((a.opens, a.reads, a.bytes), (2, 3, 110));
+    }
+}
@@ -1,3 +1,5 @@
+use super::guard::{ReasonedHook, Ruling};
+use glob::{MatchOptions, Pattern};
 use lev_reactive::{HookContext, HookDecision, Result, SyncHook};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -20,12 +22,28 @@ pub struct Schema {
     pub max_size: Option<usize>,
 }
 
+/// Event validated by the plugin, sent with the whole file content when a
+/// handle with unvalidated writes is flushed
+const CLOSE_EVENT: &str = "file:close";
+
+/// Glob options for schema routes: `*` stays within a path component
+const ROUTE_MATCH: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
 /// LevFS Validator Plugin
+///
+/// Validates whole files on `file:close`. The schema for a file is taken from
+/// the event's `schema` field, or else from the first schema route whose glob
+/// matches the file's path.
 pub struct LevFSValidator {
     name: String,
     max_size: usize,
     schemas: HashMap<String, Schema>,
     schema_dir: PathBuf,
+    schema_routes: Vec<(Pattern, String)>,
 }
 
 impl LevFSValidator {
@@ -41,6 +59,7 @@ impl LevFSValidator {
             max_size: 10 * 1024 * 1024, // 10MB default
             schemas: HashMap::new(),
             schema_dir,
+            schema_routes: Vec::new(),
         }
     }
 
@@ -56,6 +75,27 @@ impl LevFSValidator {
         self
     }
 
+    /// Validate files whose path matches `pattern` (e.g. `/docs/**/*.md`)
+    /// against `schema`, loading it from the schema directory
+    pub fn with_schema_route(mut self, pattern: &str, schema: &str) -> Result<Self> {
+        let pattern = Pattern::new(pattern).map_err(|e| {
+            lev_reactive::LevError::ConfigError(format!("Invalid glob '{}': {}", pattern, e))
+        })?;
+        if !self.schemas.contains_key(schema) {
+            self.load_schema(schema)?;
+        }
+        self.schema_routes.push((pattern, schema.to_string()));
+        Ok(self)
+    }
+
+    /// Get the schema routed to a path, if any
+    pub fn schema_for_path(&self, path: &str) -> Option<&str> {
+        self.schema_routes
+            .iter()
+            .find(|(pattern, _)| pattern.matches_with(path, ROUTE_MATCH))
+            .map(|(_, schema)| schema.as_str())
+    }
+
     /// Parse YAML frontmatter from content
     pub fn parse_frontmatter(&self, content: &str) -> Result<Option<Frontmatter>> {
         // Check for YAML frontmatter delimiters (---)
@@ -114,44 +154,39 @@ impl LevFSValidator {
     }
 
     /// Validate frontmatter against schema
-    fn validate_against_schema(&self, frontmatter: &Frontmatter, schema: &Schema) -> Result<HookDecision> {
+    fn validate_against_schema(&self, frontmatter: &Frontmatter, schema: &Schema) -> Result<Ruling> {
         // Check required fields
         for field in &schema.required_fields {
             if !frontmatter.data.contains_key(field) {
-                return Ok(HookDecision::Block {
-                    reason: format!("Missing required field: {}", field),
-                });
+                return Ok(deny(format!("Missing required field: {}", field)));
             }
         }
 
         // All required fields present
-        Ok(HookDecision::Allow)
+        Ok(HookDecision::Allow.into())
     }
 
     /// Check file size
-    fn check_size(&self, size: usize) -> HookDecision {
+    fn check_size(&self, size: usize) -> Ruling {
         if size > self.max_size {
-            HookDecision::Block {
-                reason: format!(
-                    "File size {} exceeds maximum {}",
-                    size, self.max_size
-                ),
-            }
+            deny(format!(
+                "File size {} exceeds maximum {}",
+                size, self.max_size
+            ))
         } else if size > (self.max_size * 80 / 100) {
             // Warn at 80% threshold
-            HookDecision::Warn {
-                message: format!(
-                    "File size {} approaching maximum {}",
-                    size, self.max_size
-                ),
-            }
+            HookDecision::AllowWithMessage(format!(
+                "File size {} approaching maximum {}",
+                size, self.max_size
+            ))
+            .into()
         } else {
-            HookDecision::Allow
+            HookDecision::Allow.into()
         }
     }
 
     /// Validate file content
-    fn validate_content(&self, content: &str, schema_name: Option<&str>) -> Result<HookDecision> {
+    fn validate_content(&self, content: &str, schema_name: Option<&str>) -> Result<Ruling> {
         // Parse frontmatter
         let frontmatter = self.parse_frontmatter(content)?;
 
@@ -160,16 +195,24 @@ impl LevFSValidator {
             if let Some(schema) = self.schemas.get(schema_name) {
                 return self.validate_against_schema(frontmatter, schema);
             } else {
-                return Ok(HookDecision::Warn {
-                    message: format!("Schema '{}' not loaded", schema_name),
-                });
+                return Ok(HookDecision::AllowWithMessage(format!(
+                    "Schema '{}' not loaded",
+                    schema_name
+                ))
+                .into());
             }
         }
 
-        Ok(HookDecision::Allow)
+        Ok(HookDecision::Allow.into())
     }
 }
 
+/// Deny an operation, logging the reason
+fn deny(reason: String) -> Ruling {
+    tracing::warn!("LevFS validator: {}", reason);
+    Ruling::deny(reason)
+}
+
 impl Default for LevFSValidator {
     fn default() -> Self {
         Self::new()
@@ -182,40 +225,57 @@ impl SyncHook for LevFSValidator {
     }
 
     fn execute(&self, context: &HookContext) -> Result<HookDecision> {
-        // Extract file content from payload
-        let content = context.payload.get("content")
-            .and_then(|v| v.as_str())
-            .unwrap_or("");
+        self.judge(context).map(|ruling| ruling.decision)
+    }
 
-        let size = context.payload.get("size")
+    fn priority(&self) -> i32 {
+        100 // High priority - validate early
+    }
+}
+
+impl ReasonedHook for LevFSValidator {
+    fn judge(&self, context: &HookContext) -> Result<Ruling> {
+        // Individual writes only carry a chunk of the file
+        if context.event_type != CLOSE_EVENT {
+            return Ok(HookDecision::Allow.into());
+        }
+
+        // Binary files are sent with a null content and only size-checked
+        let content = context.data.get("content").and_then(|v| v.as_str());
+
+        let size = context
+            .data
+            .get("size")
             .and_then(|v| v.as_u64())
-            .unwrap_or(content.len() as u64) as usize;
+            .unwrap_or(content.map_or(0, str::len) as u64) as usize;
 
-        let schema_name = context.metadata.get("schema")
-            .map(|s| s.as_str());
+        let path = context.data.get("path").and_then(|v| v.as_str());
+        let schema_name = context
+            .data
+            .get("schema")
+            .and_then(|v| v.as_str())
+            .or_else(|| path.and_then(|p| self.schema_for_path(p)));
 
         // Check size first
-        let size_decision = self.check_size(size);
-        if matches!(size_decision, HookDecision::Block { .. }) {
-            return Ok(size_decision);
+        let size_ruling = self.check_size(size);
+        if matches!(size_ruling.decision, HookDecision::Deny) {
+            return Ok(size_ruling);
         }
 
         // Validate content
-        let content_decision = self.validate_content(content, schema_name)?;
+        let content_ruling = match content {
+            Some(content) => self.validate_content(content, schema_name)?,
+            None => HookDecision::Allow.into(),
+        };
 
         // Return most severe decision
-        match (&size_decision, &content_decision) {
-            (HookDecision::Block { .. }, _) => Ok(size_decision),
-            (_, HookDecision::Block { .. }) => Ok(content_decision),
-            (HookDecision::Warn { .. }, HookDecision::Allow) => Ok(size_decision),
-            (HookDecision::Allow, HookDecision::Warn { .. }) => Ok(content_decision),
-            _ => Ok(HookDecision::Allow),
+        match (&size_ruling.decision, &content_ruling.decision) {
+            (_, HookDecision::Deny) => Ok(content_ruling),
+            (HookDecision::AllowWithMessage(_), _) => Ok(size_ruling),
+            (_, HookDecision::AllowWithMessage(_)) => Ok(content_ruling),
+            _ => Ok(HookDecision::Allow.into()),
         }
     }
-
-    fn priority(&self) -> i32 {
-        100 // High priority - validate early
-    }
 }
 
 // C ABI for dynamic loading
@@ -275,12 +335,60 @@ Document content here
         let validator = LevFSValidator::new().with_max_size(1000);
 
         // Under threshold
-        assert!(matches!(validator.check_size(500), HookDecision::Allow));
+        assert!(matches!(
+            validator.check_size(500).decision,
+            HookDecision::Allow
+        ));
 
         // Warning threshold
-        assert!(matches!(validator.check_size(850), HookDecision::Warn { .. }));
+        assert!(matches!(
+            validator.check_size(850).decision,
+            HookDecision::AllowWithMessage(_)
+        ));
 
         // Over limit
-        assert!(matches!(validator.check_size(1500), HookDecision::Block { .. }));
+        let over = validator.check_size(1500);
+        assert!(matches!(over.decision, HookDecision::Deny));
+        assert_eq!(
+            over.reason.as_deref(),
+            Some("File size 1500 exceeds maximum 1000")
+        );
+    }
+
+    #[test]
+    fn test_validate_close_with_schema_route() {
+        let schema_dir = tempfile::tempdir().unwrap();
+        fs::write(
+            schema_dir.path().join("doc.yaml"),
+            "name: doc\nrequired_fields: [title]\n",
+        )
+        .unwrap();
+        let validator = LevFSValidator::new()
+            .with_schema_dir(schema_dir.path().to_path_buf())
+            .with_schema_route("/docs/**/*.md", "doc")
+            .unwrap();
+        assert_eq!(validator.schema_for_path("/docs/a/b.md"), Some("doc"));
+        assert_eq!(validator.schema_for_path("/notes/b.md"), None);
+
+        let close = |path: &str, content: &str| HookContext {
+            event_type: CLOSE_EVENT.to_string(),
+            source: "levfs".to_string(),
+            data: serde_json::json!({ "path": path, "content": content }),
+        };
+        let untitled = "---\nauthor: me\n---\nbody\n";
+        assert_eq!(
+            validator.execute(&close("/docs/a.md", untitled)).unwrap(),
+            HookDecision::Deny
+        );
+        assert_eq!(
+            validator.execute(&close("/notes/a.md", untitled)).unwrap(),
+            HookDecision::Allow
+        );
+        assert_eq!(
+            validator
+                .execute(&close("/docs/a.md", "---\ntitle: A\n---\nbody\n"))
+                .unwrap(),
+            HookDecision::Allow
+        );
     }
 }
@@ -0,0 +1,496 @@
+//! WebAssembly runtime for LevFS hooks.
+//!
+//! A hook compiled to WebAssembly runs in a wasmtime sandbox instead of being
+//! loaded into the process: it only sees the hook contexts passed to it, has
+//! no imports beyond logging, and each call is limited in fuel (roughly,
+//! instructions) and memory. A faulty or hostile plugin can fail its own
+//! calls, which the hook's failure policy then handles, but cannot crash or
+//! compromise the mount. Plugins can be written in any language that targets
+//! `wasm32-unknown-unknown`.
+//!
+//! Guest interface, version 1. A module exports:
+//!
+//! - `memory`
+//! - `levfs_abi_version() -> i32`, returning 1
+//! - `levfs_alloc(len: i32) -> i32` and `levfs_free(ptr: i32, len: i32)`,
+//!   which the host uses to pass buffers in and to release the guest's
+//!   responses
+//! - `levfs_init(ptr: i32, len: i32) -> i32`, called with the hook's
+//!   `config` as JSON when it has one; a nonzero result fails loading
+//! - `levfs_pre_op(ptr: i32, len: i32) -> i64` for a sync hook, and/or
+//!   `levfs_post_op(ptr: i32, len: i32) -> i64` for an async hook
+//!
+//! The hook functions receive the `HookContext` as JSON (`event_type`,
+//! `source`, `data`), without the `content` of `file:close` events unless the
+//! hook asks for it. They return 0 to allow the operation, or
+//! `(ptr << 32) | len` of a JSON response:
+//!
+//! ```json
+//! {"decision": "deny", "reason": "secrets may not be written"}
+//! ```
+//!
+//! `decision` is `allow`, `deny`, `audit` (allow and record `reason` in the
+//! hook log), `message` (allow and log `message`) or `transform` (replace
+//! `file:close` content with `value`). Only sync hooks decide; the response
+//! of an async hook is ignored unless it fails. A module may import
+//! `levfs.log(ptr: i32, len: i32)` to log a UTF-8 message.
+
+use super::guard::{ReasonedHook, Ruling};
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use lev_reactive::{AsyncHook, HookContext, HookDecision, LevError, SyncHook};
+use parking_lot::Mutex;
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+use std::path::Path;
+use std::sync::Arc;
+use wasmtime::{
+    Caller, Config, Engine, InstancePre, Linker, Memory, Module, Store, StoreLimits,
+    StoreLimitsBuilder, TypedFunc,
+};
+
+/// Version of the guest interface this host implements
+pub const ABI_VERSION: i32 = 1;
+
+/// Longest message a guest can log, in bytes
+const MAX_LOG: u32 = 4096;
+
+/// Which of a module's hook functions a hook calls
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WasmHookKind {
+    /// `levfs_pre_op`, run before operations
+    Pre,
+    /// `levfs_post_op`, run after operations
+    Post,
+}
+
+impl WasmHookKind {
+    fn export(&self) -> &'static str {
+        match self {
+            WasmHookKind::Pre => "levfs_pre_op",
+            WasmHookKind::Post => "levfs_post_op",
+        }
+    }
+}
+
+/// Limits and options of a WebAssembly hook
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WasmSettings {
+    /// Fuel each call may consume
+    pub fuel: u64,
+    /// Largest linear memory the module may have, in bytes
+    pub memory: usize,
+    /// Pass the `content` of `file:close` events to the module
+    pub content: bool,
+}
+
+impl Default for WasmSettings {
+    fn default() -> Self {
+        Self {
+            fuel: 10_000_000,
+            memory: 64 * 1024 * 1024,
+            content: false,
+        }
+    }
+}
+
+/// Response of a guest hook function
+#[derive(Debug, Deserialize)]
+struct Response {
+    decision: Decision,
+    reason: Option<String>,
+    message: Option<String>,
+    value: Option<JsonValue>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Decision {
+    Allow,
+    Deny,
+    Audit,
+    Message,
+    Transform,
+}
+
+struct StoreData {
+    limits: StoreLimits,
+    /// Name of the hook, for log messages
+    hook: String,
+}
+
+/// An instance of a module with the exports the host calls
+struct Guest {
+    store: Store<StoreData>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    free: TypedFunc<(i32, i32), ()>,
+    entry: TypedFunc<(i32, i32), i64>,
+}
+
+impl Guest {
+    /// Copy `bytes` into guest memory
+    fn write(&mut self, bytes: &[u8]) -> Result<(i32, i32)> {
+        let len = i32::try_from(bytes.len()).context("Input too large")?;
+        let ptr = self.alloc.call(&mut self.store, len)?;
+        self.memory
+            .write(&mut self.store, ptr as u32 as usize, bytes)
+            .context("levfs_alloc returned an invalid buffer")?;
+        Ok((ptr, len))
+    }
+
+    /// Call the hook function with `input`, returning its response, if any
+    fn invoke(&mut self, input: &[u8]) -> Result<Option<Vec<u8>>> {
+        let (ptr, len) = self.write(input)?;
+        let packed = self.entry.call(&mut self.store, (ptr, len))?;
+        self.free.call(&mut self.store, (ptr, len))?;
+        if packed == 0 {
+            return Ok(None);
+        }
+        let (out_ptr, out_len) = ((packed >> 32) as u32, packed as u32);
+        let mut output = vec![0; out_len as usize];
+        self.memory
+            .read(&self.store, out_ptr as usize, &mut output)
+            .context("Response outside of guest memory")?;
+        self.free
+            .call(&mut self.store, (out_ptr as i32, out_len as i32))?;
+        Ok(Some(output))
+    }
+}
+
+/// A LevFS hook implemented by a WebAssembly module
+///
+/// Calls are serialized on one instance of the module, which keeps its state
+/// between calls. An instance that traps (including running out of fuel or
+/// memory) is discarded, and the next call starts a fresh one.
+pub struct WasmHook {
+    name: String,
+    kind: WasmHookKind,
+    engine: Engine,
+    pre: InstancePre<StoreData>,
+    settings: WasmSettings,
+    /// The hook's `config` as JSON, passed to `levfs_init`
+    config: Option<String>,
+    guest: Mutex<Option<Guest>>,
+}
+
+impl WasmHook {
+    /// Load a module, checking its interface by instantiating it once
+    pub fn load(
+        name: &str,
+        path: &Path,
+        kind: WasmHookKind,
+        settings: WasmSettings,
+        config: Option<String>,
+    ) -> Result<Self> {
+        let mut engine_config = Config::new();
+        engine_config.consume_fuel(true);
+        let engine = Engine::new(&engine_config)?;
+        let module = Module::from_file(&engine, path)
+            .with_context(|| format!("Failed to load module {}", path.display()))?;
+        if !module
+            .get_export(kind.export())
+            .is_some_and(|export| export.func().is_some())
+        {
+            bail!("Module exports no `{}`", kind.export());
+        }
+
+        let mut linker = Linker::new(&engine);
+        linker.func_wrap(
+            "levfs",
+            "log",
+            |mut caller: Caller<'_, StoreData>, ptr: i32, len: i32| {
+                let Some(memory) = caller.get_export("memory").and_then(|e| e.into_memory()) else {
+                    return;
+                };
+                let mut message = vec![0; (len as u32).min(MAX_LOG) as usize];
+                if memory
+                    .read(&caller, ptr as u32 as usize, &mut message)
+                    .is_ok()
+                {
+                    tracing::info!(
+                        "LevFS: wasm hook {}: {}",
+                        caller.data().hook,
+                        String::from_utf8_lossy(&message)
+                    );
+                }
+            },
+        )?;
+        let pre = linker.instantiate_pre(&module)?;
+
+        let hook = Self {
+            name: name.to_string(),
+            kind,
+            engine,
+            pre,
+            settings,
+            config,
+            guest: Mutex::new(None),
+        };
+        let guest = hook.instantiate()?;
+        *hook.guest.lock() = Some(guest);
+        Ok(hook)
+    }
+
+    /// Start an instance of the module and initialize it with the config
+    fn instantiate(&self) -> Result<Guest> {
+        let data = StoreData {
+            limits: StoreLimitsBuilder::new()
+                .memory_size(self.settings.memory)
+                .instances(1)
+                .build(),
+            hook: self.name.clone(),
+        };
+        let mut store = Store::new(&self.engine, data);
+        store.limiter(|data| &mut data.limits);
+        store.set_fuel(self.settings.fuel)?;
+        let instance = self.pre.instantiate(&mut store)?;
+
+        let version = instance
+            .get_typed_func::<(), i32>(&mut store, "levfs_abi_version")
+            .context("Module exports no `levfs_abi_version`")?
+            .call(&mut store, ())?;
+        if version != ABI_VERSION {
+            bail!(
+                "Module implements interface version {}, expected {}",
+                version,
+                ABI_VERSION
+            );
+        }
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .context("Module exports no `memory`")?;
+        let alloc = instance
+            .get_typed_func(&mut store, "levfs_alloc")
+            .context("Module exports no `levfs_alloc`")?;
+        let free = instance
+            .get_typed_func(&mut store, "levfs_free")
+            .context("Module exports no `levfs_free`")?;
+        let entry = instance.get_typed_func(&mut store, self.kind.export())?;
+        let mut guest = Guest {
+            store,
+            memory,
+            alloc,
+            free,
+            entry,
+        };
+
+        if let Some(config) = &self.config {
+            let init = instance
+                .get_typed_func::<(i32, i32), i32>(&mut guest.store, "levfs_init")
+                .context("Module takes no config (no `levfs_init`)")?;
+            let (ptr, len) = guest.write(config.as_bytes())?;
+            let status = init.call(&mut guest.store, (ptr, len))?;
+            guest.free.call(&mut guest.store, (ptr, len))?;
+            if status != 0 {
+                bail!("levfs_init failed with status {}", status);
+            }
+        }
+        Ok(guest)
+    }
+
+    /// Run the module's hook function on a context
+    fn call(&self, context: &HookContext) -> Result<Option<Response>> {
+        let mut data = context.data.clone();
+        if !self.settings.content {
+            if let Some(data) = data.as_object_mut() {
+                data.remove("content");
+            }
+        }
+        let input = serde_json::to_vec(&serde_json::json!({
+            "event_type": context.event_type,
+            "source": context.source,
+            "data": data,
+        }))?;
+
+        let output = {
+            let mut slot = self.guest.lock();
+            let mut guest = match slot.take() {
+                Some(guest) => guest,
+                None => self.instantiate()?,
+            };
+            guest.store.set_fuel(self.settings.fuel)?;
+            // A failed call may leave the instance inconsistent, so it is
+            // only kept after a successful one
+            let output = guest.invoke(&input)?;
+            *slot = Some(guest);
+            output
+        };
+
+        match output {
+            Some(output) => Ok(Some(
+                serde_json::from_slice(&output).context("Invalid response")?,
+            )),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Error reported for a failed call of a WebAssembly hook
+fn hook_error(name: &str, error: anyhow::Error) -> LevError {
+    LevError::Io(std::io::Error::other(format!(
+        "wasm hook {}: {:#}",
+        name, error
+    )))
+}
+
+impl SyncHook for WasmHook {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn execute(&self, context: &HookContext) -> lev_reactive::Result<HookDecision> {
+        self.judge(context).map(|ruling| ruling.decision)
+    }
+
+    fn priority(&self) -> i32 {
+        0
+    }
+}
+
+impl ReasonedHook for WasmHook {
+    fn judge(&self, context: &HookContext) -> lev_reactive::Result<Ruling> {
+        let Some(response) = self.call(context).map_err(|e| hook_error(&self.name, e))? else {
+            return Ok(HookDecision::Allow.into());
+        };
+        Ok(match response.decision {
+            Decision::Allow => HookDecision::Allow.into(),
+            Decision::Deny => Ruling {
+                decision: HookDecision::Deny,
+                reason: response.reason,
+            },
+            Decision::Audit => Ruling::audit(response.reason.unwrap_or_default()),
+            Decision::Message => {
+                HookDecision::AllowWithMessage(response.message.unwrap_or_default()).into()
+            }
+            Decision::Transform => match response.value {
+                Some(value) => HookDecision::Transform(value).into(),
+                None => {
+                    return Err(hook_error(
+                        &self.name,
+                        anyhow::anyhow!("transform without a value"),
+                    ))
+                }
+            },
+        })
+    }
+}
+
+/// An async hook running a [`WasmHook`] off the runtime's worker threads
+pub struct WasmAsyncHook(Arc<WasmHook>);
+
+impl WasmAsyncHook {
+    pub fn new(hook: WasmHook) -> Self {
+        Self(Arc::new(hook))
+    }
+}
+
+#[async_trait]
+impl AsyncHook for WasmAsyncHook {
+    fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn execute(&self, context: &HookContext) -> lev_reactive::Result<HookDecision> {
+        let hook = self.0.clone();
+        let context = HookContext {
+            event_type: context.event_type.clone(),
+            source: context.source.clone(),
+            data: context.data.clone(),
+        };
+        tokio::task::spawn_blocking(move || hook.call(&context))
+            .await
+            .map_err(|e| hook_error(&self.0.name, e.into()))?
+            .map_err(|e| hook_error(&self.0.name, e))?;
+        Ok(HookDecision::Allow)
+    }
+
+    fn priority(&self) -> i32 {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    /// A module whose pre-op hook runs `body`, with a bump allocator and a
+    /// canned denial at offset 1024
+    fn module(version: i32, body: &str) -> NamedTempFile {
+        let wat = format!(
+            r#"(module
+  (memory (export "memory") 1)
+  (global $next (mut i32) (i32.const 4096))
+  (data (i32.const 1024) "{{\"decision\":\"deny\",\"reason\":\"no\"}}")
+  (func (export "levfs_abi_version") (result i32) (i32.const {version}))
+  (func (export "levfs_alloc") (param $len i32) (result i32)
+    (local $ptr i32)
+    (local.set $ptr (global.get $next))
+    (global.set $next (i32.add (global.get $next) (local.get $len)))
+    (local.get $ptr))
+  (func (export "levfs_free") (param i32 i32))
+  (func (export "levfs_pre_op") (param i32 i32) (result i64) {body}))"#
+        );
+        let mut file = tempfile::Builder::new().suffix(".wat").tempfile().unwrap();
+        file.write_all(wat.as_bytes()).unwrap();
+        file
+    }
+
+    fn load(file: &NamedTempFile) -> Result<WasmHook> {
+        WasmHook::load(
+            "test",
+            file.path(),
+            WasmHookKind::Pre,
+            WasmSettings {
+                fuel: 100_000,
+                ..WasmSettings::default()
+            },
+            None,
+        )
+    }
+
+    fn context() -> HookContext {
+        HookContext {
+            event_type: "file:write".to_string(),
+            source: "levfs".to_string(),
+            data: serde_json::json!({ "path": "/a.md" }),
+        }
+    }
+
+    #[test]
+    fn test_wasm_hook_decisions() {
+        let allow = load(&module(1, "(i64.const 0)")).unwrap();
+        assert!(matches!(allow.execute(&context()), Ok(HookDecision::Allow)));
+
+        let deny = load(&module(
+            1,
+            "(i64.or (i64.shl (i64.const 1024) (i64.const 32)) (i64.const 33))",
+        ))
+        .unwrap();
+        assert!(matches!(deny.execute(&context()), Ok(HookDecision::Deny)));
+        assert_eq!(
+            deny.judge(&context()).unwrap().reason.as_deref(),
+            Some("no")
+        );
+    }
+
+    #[test]
+    fn test_wasm_hook_limits() {
+        let hook = load(&module(1, "(loop $spin (br $spin)) (i64.const 0)")).unwrap();
+        assert!(hook.execute(&context()).is_err());
+        // The trapped instance is replaced, and runs out of fuel again
+        assert!(hook.execute(&context()).is_err());
+
+        assert!(load(&module(2, "(i64.const 0)")).is_err());
+        let post = WasmHook::load(
+            "test",
+            module(1, "(i64.const 0)").path(),
+            WasmHookKind::Post,
+            WasmSettings::default(),
+            None,
+        );
+        assert!(post.is_err());
+    }
+}
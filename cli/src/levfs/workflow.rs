@@ -1,21 +1,129 @@
+//! The `levfs-workflow` async hook, which runs a workflow for each event.
+//!
+//! A workflow is run by an [`Executor`]: a command (by default
+//! `flowmind run <workflow>`), a JSON-lines unix socket or a local HTTP
+//! webhook. Runs are scheduled by [`WorkflowOptions`]: bursts of events for
+//! the same path are coalesced into one run of the latest, at most
+//! `concurrency` runs are in flight, runs that exceed `timeout` fail, and
+//! failed runs are retried with exponential backoff. Events wait in the hook queue of the agent database
+//! until their run finishes, so events still pending when a mount exits are
+//! run by the next mount.
+
+use super::hooks::HookRecorder;
+use agentfs_sdk::{HookLogDecision, HookQueue};
+use anyhow::{bail, Context};
 use async_trait::async_trait;
 use lev_reactive::{AsyncHook, HookContext, HookDecision, Plugin, PluginMetadata};
+use parking_lot::Mutex;
+use serde_json::{json, Value as JsonValue};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
-use tokio::io::AsyncWriteExt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
 use tokio::process::Command;
+use tokio::runtime::Handle;
+use tokio::sync::Semaphore;
+
+/// Name of the workflow hook
+const HOOK_NAME: &str = "flowmind-workflow";
+
+/// Hook the events of workflow hooks are queued for in the hook queue
+const QUEUE_HOOK: &str = "levfs-workflow";
+
+/// How a workflow is run for an event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Executor {
+    /// Spawn `program` with `args`, writing the hook context as JSON to its
+    /// stdin
+    ///
+    /// `{workflow}`, `{event}` and `{path}` in `args` are replaced with the
+    /// workflow name, the event type and the path of the event.
+    Command { program: String, args: Vec<String> },
+    /// Write `{"workflow": ..., "context": ...}` as a line to a unix socket
+    Socket { path: PathBuf },
+    /// POST `{"workflow": ..., "context": ...}` to an `http://` URL
+    Http { url: String },
+}
 
-/// LevFS Workflow Plugin - spawns Flowmind CLI workflows for hook events
+impl Default for Executor {
+    fn default() -> Self {
+        Executor::Command {
+            program: "flowmind".to_string(),
+            args: vec!["run".to_string(), "{workflow}".to_string()],
+        }
+    }
+}
+
+/// How the runs of a workflow are scheduled
+#[derive(Debug, Clone)]
+pub struct WorkflowOptions {
+    pub executor: Executor,
+    /// Time an event for a path waits before its workflow runs; a later event
+    /// for the same path replaces it. Zero runs every event.
+    pub debounce: Duration,
+    /// Runs in flight at once
+    pub concurrency: usize,
+    /// Attempts after the first that a failed run gets
+    pub retries: u32,
+    /// Delay before the first retry, doubled for each one after it
+    pub backoff: Duration,
+    /// Time an attempt may take, including connecting and waiting for the
+    /// command or webhook, before it fails
+    pub timeout: Duration,
+    /// Keep events in the hook queue until their run finishes
+    pub persist: bool,
+}
+
+impl Default for WorkflowOptions {
+    fn default() -> Self {
+        Self {
+            executor: Executor::default(),
+            debounce: Duration::from_millis(500),
+            concurrency: 4,
+            retries: 2,
+            backoff: Duration::from_secs(1),
+            timeout: Duration::from_secs(30),
+            persist: true,
+        }
+    }
+}
+
+/// LevFS Workflow Plugin - runs workflows for hook events
 pub struct LevFSWorkflow {
     workflow_name: String,
+    options: WorkflowOptions,
+    recorder: Option<HookRecorder>,
 }
 
 impl LevFSWorkflow {
     pub fn new(workflow_name: impl Into<String>) -> Self {
         Self {
             workflow_name: workflow_name.into(),
+            options: WorkflowOptions::default(),
+            recorder: None,
         }
     }
 
+    /// Set how workflows are run and scheduled
+    pub fn with_options(mut self, options: WorkflowOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Record the outcome of each workflow run in the hook log
+    ///
+    /// The hook returns before its workflow finishes, so the run is recorded
+    /// once it has. Events are kept in the hook queue attached to the
+    /// recorder, and events an earlier mount left there are run again.
+    pub fn with_recorder(mut self, recorder: HookRecorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
     pub fn boxed(self) -> Box<Self> {
         Box::new(self)
     }
@@ -32,39 +140,35 @@ impl Plugin for LevFSWorkflow {
     }
 
     fn async_hooks(&self) -> Vec<Box<dyn AsyncHook>> {
-        vec![Box::new(WorkflowHook {
-            workflow_name: self.workflow_name.clone(),
-        })]
+        let dispatcher = Arc::new(Dispatcher::new(
+            self.workflow_name.clone(),
+            self.options.clone(),
+            self.recorder.clone(),
+        ));
+        if let Some(recorder) = self.recorder.as_ref().filter(|_| self.options.persist) {
+            let dispatcher = dispatcher.clone();
+            recorder.on_queue(move |queue| dispatcher.replay(queue.clone()));
+        }
+        vec![Box::new(WorkflowHook { dispatcher })]
     }
 }
 
-/// Async hook that executes Flowmind workflows
+/// Async hook that hands events to its dispatcher
 struct WorkflowHook {
-    workflow_name: String,
+    dispatcher: Arc<Dispatcher>,
 }
 
 #[async_trait]
 impl AsyncHook for WorkflowHook {
     fn name(&self) -> &str {
-        "flowmind-workflow"
+        HOOK_NAME
     }
 
     async fn execute(&self, context: &HookContext) -> lev_reactive::Result<HookDecision> {
-        let workflow_name = self.workflow_name.clone();
-        let context_json = serde_json::to_string(context).map_err(|e| {
-            lev_reactive::LevError::Serialization(e)
-        })?;
-
-        // Spawn async workflow execution without blocking
-        tokio::spawn(async move {
-            if let Err(e) = execute_workflow(&workflow_name, &context_json).await {
-                tracing::error!(
-                    workflow = %workflow_name,
-                    error = %e,
-                    "Workflow execution failed"
-                );
-            }
-        });
+        if self.dispatcher.available {
+            // Runs in the background, after debouncing and queueing
+            tokio::spawn(self.dispatcher.clone().submit(clone_context(context)));
+        }
 
         // Return Allow immediately - workflow runs in background
         Ok(HookDecision::Allow)
@@ -75,19 +179,335 @@ impl AsyncHook for WorkflowHook {
     }
 }
 
-/// Execute Flowmind CLI with workflow name and context
-async fn execute_workflow(workflow_name: &str, context_json: &str) -> anyhow::Result<()> {
+/// A workflow run waiting to happen
+struct Job {
+    context: HookContext,
+    /// Hook queue entry of the event, if it was queued
+    queued: Option<i64>,
+}
+
+/// An event waiting out the debounce period of its path
+struct Waiting {
+    generation: u64,
+    job: Job,
+}
+
+/// Schedules the runs of one workflow
+struct Dispatcher {
+    workflow: String,
+    options: WorkflowOptions,
+    recorder: Option<HookRecorder>,
+    /// Whether the executor can run at all; a missing command is reported
+    /// once instead of failing every event
+    available: bool,
+    permits: Semaphore,
+    /// Events waiting out the debounce period, by path
+    waiting: Mutex<HashMap<String, Waiting>>,
+    next_generation: AtomicU64,
+    /// Connection of a socket executor, reopened after an error
+    #[cfg(unix)]
+    socket: tokio::sync::Mutex<Option<tokio::net::UnixStream>>,
+}
+
+impl Dispatcher {
+    fn new(workflow: String, options: WorkflowOptions, recorder: Option<HookRecorder>) -> Self {
+        let available = match &options.executor {
+            Executor::Command { program, .. } if !program_exists(program) => {
+                tracing::warn!(
+                    workflow = %workflow,
+                    "LevFS: workflow command '{}' not found, the workflow will not run",
+                    program
+                );
+                false
+            }
+            _ => true,
+        };
+        Self {
+            permits: Semaphore::new(options.concurrency.max(1)),
+            workflow,
+            options,
+            recorder,
+            available,
+            waiting: Mutex::new(HashMap::new()),
+            next_generation: AtomicU64::new(0),
+            #[cfg(unix)]
+            socket: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// Queue an event and run its workflow once it is due
+    async fn submit(self: Arc<Self>, context: HookContext) {
+        let queued = self.enqueue(&context).await;
+        let job = Job { context, queued };
+        match job.context.data["path"].as_str() {
+            Some(path) if !self.options.debounce.is_zero() => {
+                let path = path.to_string();
+                self.debounce(path, job).await
+            }
+            _ => self.run(job).await,
+        }
+    }
+
+    /// Run `job` unless another event for `path` arrives within the debounce
+    /// period, in which case that one runs instead
+    async fn debounce(&self, path: String, job: Job) {
+        let generation = self.next_generation.fetch_add(1, Ordering::Relaxed);
+        let replaced = self
+            .waiting
+            .lock()
+            .insert(path.clone(), Waiting { generation, job });
+        if let Some(replaced) = replaced {
+            self.dequeue(replaced.job.queued).await;
+        }
+
+        tokio::time::sleep(self.options.debounce).await;
+        let job = {
+            let mut waiting = self.waiting.lock();
+            match waiting.get(&path) {
+                Some(latest) if latest.generation == generation => {
+                    waiting.remove(&path).map(|latest| latest.job)
+                }
+                _ => None,
+            }
+        };
+        if let Some(job) = job {
+            self.run(job).await;
+        }
+    }
+
+    /// Run the workflow for a job, retrying failures, and record the outcome
+    async fn run(&self, job: Job) {
+        // The semaphore is never closed
+        let Ok(_permit) = self.permits.acquire().await else {
+            return;
+        };
+        let start = Instant::now();
+        let mut attempt = 0;
+        let result = loop {
+            let outcome = tokio::time::timeout(self.options.timeout, self.execute(&job.context))
+                .await
+                .unwrap_or_else(|_| bail!("Timed out after {:?}", self.options.timeout));
+            match outcome {
+                Ok(()) => break Ok(()),
+                Err(e) if attempt < self.options.retries => {
+                    let delay = self
+                        .options
+                        .backoff
+                        .saturating_mul(2u32.saturating_pow(attempt));
+                    tracing::warn!(
+                        workflow = %self.workflow,
+                        error = %e,
+                        "Workflow failed, retrying in {:?}",
+                        delay
+                    );
+                    attempt += 1;
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => break Err(e),
+            }
+        };
+        if let Err(e) = &result {
+            tracing::error!(
+                workflow = %self.workflow,
+                error = %e,
+                "Workflow execution failed"
+            );
+        }
+
+        // The event is done with once its workflow ran or ran out of retries
+        self.dequeue(job.queued).await;
+        if let Some(recorder) = &self.recorder {
+            let (decision, message) = match result {
+                Ok(()) => (HookLogDecision::Allow, None),
+                Err(e) => (HookLogDecision::Error, Some(e.to_string())),
+            };
+            recorder.record(&job.context, HOOK_NAME, decision, message, start.elapsed());
+        }
+    }
+
+    /// Run the workflow once for an event
+    async fn execute(&self, context: &HookContext) -> anyhow::Result<()> {
+        match &self.options.executor {
+            Executor::Command { program, args } => {
+                let args = args
+                    .iter()
+                    .map(|arg| expand_arg(arg, &self.workflow, context));
+                let context_json = serde_json::to_string(context)?;
+                execute_command(&self.workflow, program, args, &context_json).await
+            }
+            Executor::Socket { path } => {
+                let line = envelope(&self.workflow, context).to_string();
+                self.send(path, &line).await
+            }
+            Executor::Http { url } => {
+                let body = envelope(&self.workflow, context).to_string();
+                post(url, &body).await
+            }
+        }
+    }
+
+    /// Write a line to the socket, connecting first if needed
+    #[cfg(unix)]
+    async fn send(&self, path: &Path, line: &str) -> anyhow::Result<()> {
+        let mut socket = self.socket.lock().await;
+        let mut stream = match socket.take() {
+            Some(stream) => stream,
+            None => tokio::net::UnixStream::connect(path)
+                .await
+                .with_context(|| format!("Failed to connect to {}", path.display()))?,
+        };
+        stream.write_all(line.as_bytes()).await?;
+        stream.write_all(b"\n").await?;
+        stream.flush().await?;
+        // Only a connection that worked is kept
+        *socket = Some(stream);
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    async fn send(&self, path: &Path, _line: &str) -> anyhow::Result<()> {
+        bail!(
+            "Unix socket {} is not supported on this platform",
+            path.display()
+        )
+    }
+
+    /// Keep an event in the hook queue until its run finishes
+    async fn enqueue(&self, context: &HookContext) -> Option<i64> {
+        if !self.options.persist {
+            return None;
+        }
+        let queue = self.recorder.as_ref()?.queue()?;
+        let payload = envelope(&self.workflow, context).to_string();
+        match queue.push(QUEUE_HOOK, &payload).await {
+            Ok(id) => Some(id),
+            Err(e) => {
+                tracing::warn!(
+                    "LevFS: failed to queue {} for workflow {}: {}",
+                    context.event_type,
+                    self.workflow,
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    /// Remove an event from the hook queue
+    async fn dequeue(&self, queued: Option<i64>) {
+        let (Some(id), Some(queue)) = (queued, self.recorder.as_ref().and_then(|r| r.queue()))
+        else {
+            return;
+        };
+        if let Err(e) = queue.remove(id).await {
+            tracing::warn!("LevFS: failed to remove hook queue entry {}: {}", id, e);
+        }
+    }
+
+    /// Run the events an earlier mount left in the hook queue for this
+    /// workflow
+    ///
+    /// Without an executor that can run, the events stay queued.
+    fn replay(self: Arc<Self>, queue: HookQueue) {
+        if !self.available {
+            return;
+        }
+        let Ok(runtime) = Handle::try_current() else {
+            tracing::warn!("LevFS: no runtime to replay queued workflow events");
+            return;
+        };
+        runtime.spawn(async move {
+            let entries = match queue.pending(QUEUE_HOOK).await {
+                Ok(entries) => entries,
+                Err(e) => {
+                    tracing::warn!("LevFS: failed to read hook queue: {}", e);
+                    return;
+                }
+            };
+            for entry in entries {
+                let Some((workflow, context)) = parse_envelope(&entry.payload) else {
+                    tracing::warn!("LevFS: dropping invalid hook queue entry {}", entry.id);
+                    self.dequeue(Some(entry.id)).await;
+                    continue;
+                };
+                if workflow != self.workflow {
+                    continue;
+                }
+                let job = Job {
+                    context,
+                    queued: Some(entry.id),
+                };
+                let dispatcher = self.clone();
+                tokio::spawn(async move { dispatcher.run(job).await });
+            }
+        });
+    }
+}
+
+/// Copy a hook context for a run that outlives the hook call
+fn clone_context(context: &HookContext) -> HookContext {
+    HookContext {
+        event_type: context.event_type.clone(),
+        source: context.source.clone(),
+        data: context.data.clone(),
+    }
+}
+
+/// The JSON sent to socket and HTTP executors and kept in the hook queue
+fn envelope(workflow: &str, context: &HookContext) -> JsonValue {
+    json!({ "workflow": workflow, "context": context })
+}
+
+/// Parse a hook queue entry written by [`envelope`]
+fn parse_envelope(payload: &str) -> Option<(String, HookContext)> {
+    let value: JsonValue = serde_json::from_str(payload).ok()?;
+    let context = &value["context"];
+    Some((
+        value["workflow"].as_str()?.to_string(),
+        HookContext {
+            event_type: context["event_type"].as_str()?.to_string(),
+            source: context["source"].as_str().unwrap_or("levfs").to_string(),
+            data: context["data"].clone(),
+        },
+    ))
+}
+
+/// Replace the placeholders of a command argument
+fn expand_arg(arg: &str, workflow: &str, context: &HookContext) -> String {
+    arg.replace("{workflow}", workflow)
+        .replace("{event}", &context.event_type)
+        .replace("{path}", context.data["path"].as_str().unwrap_or(""))
+}
+
+/// Whether `program` can be spawned, either as a path or from `PATH`
+fn program_exists(program: &str) -> bool {
+    if program.contains('/') {
+        return Path::new(program).is_file();
+    }
+    std::env::var_os("PATH")
+        .is_some_and(|paths| std::env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+}
+
+/// Execute a workflow command with the context on its stdin
+async fn execute_command(
+    workflow_name: &str,
+    program: &str,
+    args: impl Iterator<Item = String>,
+    context_json: &str,
+) -> anyhow::Result<()> {
     tracing::info!(
         workflow = %workflow_name,
-        "Spawning Flowmind workflow"
+        "Spawning workflow command {}",
+        program
     );
 
-    let mut child = Command::new("flowmind")
-        .arg("run")
-        .arg(workflow_name)
+    // A run that times out drops the child, which kills it
+    let mut child = Command::new(program)
+        .args(args)
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
+        .kill_on_drop(true)
         .spawn()?;
 
     // Write context as JSON to stdin
@@ -118,6 +538,48 @@ async fn execute_workflow(workflow_name: &str, context_json: &str) -> anyhow::Re
     Ok(())
 }
 
+/// POST a JSON body to an `http://` URL, failing unless the response is 2xx
+async fn post(url: &str, body: &str) -> anyhow::Result<()> {
+    let Some(rest) = url.strip_prefix("http://") else {
+        bail!("Unsupported webhook URL {}, only http:// is supported", url);
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(slash) => rest.split_at(slash),
+        None => (rest, "/"),
+    };
+    let address = if authority.rsplit(']').next().unwrap_or("").contains(':') {
+        authority.to_string()
+    } else {
+        format!("{}:80", authority)
+    };
+
+    let mut stream = TcpStream::connect(&address)
+        .await
+        .with_context(|| format!("Failed to connect to {}", address))?;
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        authority,
+        body.len(),
+        body
+    );
+    stream.write_all(request.as_bytes()).await?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+
+    let status_line = response.split(|&b| b == b'\n').next().unwrap_or_default();
+    let status_line = String::from_utf8_lossy(status_line);
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse::<u16>().ok())
+        .with_context(|| format!("Invalid response from {}", url))?;
+    if !(200..300).contains(&status) {
+        bail!("Webhook {} returned {}", url, status_line.trim());
+    }
+    Ok(())
+}
+
 /// C ABI entry point for dynamic plugin loading
 #[no_mangle]
 pub extern "C" fn _plugin_create() -> *mut dyn Plugin {
@@ -128,6 +590,22 @@ pub extern "C" fn _plugin_create() -> *mut dyn Plugin {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use agentfs_sdk::{AgentFS, AgentFSOptions};
+    use tokio::io::AsyncBufReadExt;
+    use tokio::net::{TcpListener, UnixListener};
+
+    fn options(executor: Executor) -> WorkflowOptions {
+        WorkflowOptions {
+            executor,
+            debounce: Duration::ZERO,
+            retries: 0,
+            ..WorkflowOptions::default()
+        }
+    }
+
+    fn write_event(path: &str) -> HookContext {
+        HookContext::new("file:write", json!({ "path": path }))
+    }
 
     #[test]
     fn test_plugin_metadata() {
@@ -149,17 +627,167 @@ mod tests {
 
     #[tokio::test]
     async fn test_hook_returns_allow() {
-        let hook = WorkflowHook {
-            workflow_name: "test".to_string(),
-        };
+        let plugin = LevFSWorkflow::new("test").with_options(options(Executor::Command {
+            program: "/nonexistent/flowmind".to_string(),
+            args: vec![],
+        }));
+        let hooks = plugin.async_hooks();
 
-        let context = HookContext::new(
-            "test-event",
-            json!({"key": "value"})
-        );
+        let context = HookContext::new("test-event", json!({"key": "value"}));
 
-        let result = hook.execute(&context).await;
+        let result = hooks[0].execute(&context).await;
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), HookDecision::Allow);
     }
+
+    #[test]
+    fn test_expand_arg_and_envelope() {
+        let context = write_event("/docs/a.md");
+        assert_eq!(
+            expand_arg("{workflow}:{event}:{path}", "lint", &context),
+            "lint:file:write:/docs/a.md"
+        );
+        let payload = envelope("lint", &context).to_string();
+        let (workflow, parsed) = parse_envelope(&payload).unwrap();
+        assert_eq!(workflow, "lint");
+        assert_eq!(parsed.event_type, "file:write");
+        assert_eq!(parsed.data["path"], "/docs/a.md");
+        assert!(parse_envelope("{}").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_socket_executor_debounces_by_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("workflows.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+
+        let mut options = options(Executor::Socket { path: socket });
+        options.debounce = Duration::from_millis(50);
+        let hooks = LevFSWorkflow::new("lint")
+            .with_options(options)
+            .async_hooks();
+        for path in ["/a.md", "/a.md", "/b.md", "/a.md"] {
+            hooks[0].execute(&write_event(path)).await.unwrap();
+        }
+
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut lines = tokio::io::BufReader::new(stream).lines();
+        let mut paths = Vec::new();
+        for _ in 0..2 {
+            let line = lines.next_line().await.unwrap().unwrap();
+            let value: JsonValue = serde_json::from_str(&line).unwrap();
+            assert_eq!(value["workflow"], "lint");
+            paths.push(
+                value["context"]["data"]["path"]
+                    .as_str()
+                    .unwrap()
+                    .to_string(),
+            );
+        }
+        paths.sort();
+        assert_eq!(paths, ["/a.md", "/b.md"]);
+    }
+
+    #[tokio::test]
+    async fn test_http_executor_retries() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hooks", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for status in ["500 Internal Server Error", "204 No Content"] {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 4096];
+                let n = stream.read(&mut buf).await.unwrap();
+                requests.push(String::from_utf8_lossy(&buf[..n]).to_string());
+                let response = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status);
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+
+        let mut options = options(Executor::Http { url });
+        options.retries = 1;
+        options.backoff = Duration::from_millis(1);
+        let dispatcher = Arc::new(Dispatcher::new("lint".to_string(), options, None));
+        dispatcher
+            .run(Job {
+                context: write_event("/a.md"),
+                queued: None,
+            })
+            .await;
+
+        let requests = server.await.unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1].starts_with("POST /hooks HTTP/1.1"));
+        assert!(requests[1].contains(r#""workflow":"lint""#));
+    }
+
+    #[tokio::test]
+    async fn test_timeout_fails_attempt() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hooks", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            // Accept both attempts and never answer
+            let mut streams = Vec::new();
+            for _ in 0..2 {
+                streams.push(listener.accept().await.unwrap().0);
+            }
+            streams
+        });
+
+        let mut options = options(Executor::Http { url });
+        options.retries = 1;
+        options.backoff = Duration::from_millis(1);
+        options.timeout = Duration::from_millis(50);
+        let dispatcher = Arc::new(Dispatcher::new("lint".to_string(), options, None));
+        let run = dispatcher.run(Job {
+            context: write_event("/a.md"),
+            queued: None,
+        });
+        // The timed-out first attempt is retried, and the run then gives up
+        tokio::time::timeout(Duration::from_secs(5), run)
+            .await
+            .unwrap();
+        assert_eq!(server.await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_queued_events_are_replayed() {
+        let agent = AgentFS::open(AgentFSOptions::ephemeral()).await.unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("workflows.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+
+        // Left behind by an earlier mount
+        let payload = envelope("lint", &write_event("/a.md")).to_string();
+        agent.hook_queue.push(QUEUE_HOOK, &payload).await.unwrap();
+        let other = envelope("other", &write_event("/b.md")).to_string();
+        agent.hook_queue.push(QUEUE_HOOK, &other).await.unwrap();
+
+        let recorder = HookRecorder::default();
+        let _hooks = LevFSWorkflow::new("lint")
+            .with_options(options(Executor::Socket { path: socket }))
+            .with_recorder(recorder.clone())
+            .async_hooks();
+        recorder.set_queue(agent.hook_queue.clone());
+
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut lines = tokio::io::BufReader::new(stream).lines();
+        let line = lines.next_line().await.unwrap().unwrap();
+        assert_eq!(
+            serde_json::from_str::<JsonValue>(&line).unwrap(),
+            serde_json::from_str::<JsonValue>(&payload).unwrap()
+        );
+
+        // Only the other workflow's event is left once the run finishes
+        for _ in 0..100 {
+            if agent.hook_queue.pending(QUEUE_HOOK).await.unwrap().len() == 1 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let pending = agent.hook_queue.pending(QUEUE_HOOK).await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].payload, other);
+    }
 }
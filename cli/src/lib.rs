@@ -2,6 +2,7 @@ pub mod cmd;
 pub mod levfs;
 pub mod opts;
 pub mod sandbox;
+pub mod tool_call;
 
 #[cfg(target_os = "linux")]
 pub mod daemon;
@@ -12,6 +13,9 @@ pub mod fuse;
 #[cfg(target_os = "linux")]
 pub mod fuser;
 
+#[cfg(unix)]
+pub mod locks;
+
 #[cfg(unix)]
 pub mod nfsserve;
 
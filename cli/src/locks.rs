@@ -0,0 +1,438 @@
+//! Advisory byte-range lock manager for the mount backends.
+//!
+//! Once `FUSE_POSIX_LOCKS`/`FUSE_FLOCK_LOCKS` are negotiated the kernel stops
+//! handling `fcntl(F_SETLK)`, OFD and `flock()` locks locally and forwards them
+//! to the daemon, so every lock has to be tracked here. Locks are keyed by
+//! inode and lock owner (the kernel's opaque identifier for a process, an open
+//! file description or a `flock()` holder) and follow POSIX semantics: read
+//! locks are shared, write locks are exclusive, and a new lock from the same
+//! owner replaces, splits or merges that owner's existing locks on the range.
+//!
+//! A blocking request that would wait on an owner which is itself waiting,
+//! directly or through other owners, on a lock of the requester fails with
+//! `EDEADLK` instead of hanging both.
+//!
+//! The manager has no FUSE-specific types so other frontends can hold the same
+//! `Arc<LockManager>` and see the same locks. So far only FUSE does. NFSv3
+//! locks travel over the separate NLM protocol, which clients only reach
+//! through the portmapper on port 111; serving NLM from this manager is a
+//! follow-up (see "Key Gaps" in `docs/FEATURE_PARITY.md`). Until then NFS
+//! mounts use `nolock`, the client kernel arbitrates locks between the
+//! processes on the mounting host, and FUSE clients do not see those locks.
+
+use parking_lot::Mutex;
+use std::collections::{HashMap, HashSet};
+use tokio::sync::Notify;
+
+/// A byte-range lock held by an owner.
+///
+/// Ranges are inclusive on both ends; an `end` of `u64::MAX` (or the kernel's
+/// `OFFSET_MAX`) extends to the end of the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileLock {
+    pub start: u64,
+    pub end: u64,
+    /// `libc::F_RDLCK` or `libc::F_WRLCK`
+    pub typ: i32,
+    pub pid: u32,
+    pub owner: u64,
+}
+
+impl FileLock {
+    fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.start <= end && start <= self.end
+    }
+
+    fn conflicts_with(&self, owner: u64, start: u64, end: u64, typ: i32) -> bool {
+        self.owner != owner
+            && self.overlaps(start, end)
+            && (self.typ == libc::F_WRLCK || typ == libc::F_WRLCK)
+    }
+}
+
+#[derive(Default)]
+struct LockTable {
+    /// Inode -> locks currently held on it
+    locks: HashMap<u64, Vec<FileLock>>,
+    /// (inode, owner) -> (epoch, waiter count) for blocked lock requests.
+    ///
+    /// Releasing all of an owner's locks bumps the epoch, which cancels any
+    /// request the owner still has waiting (the process closed the file or
+    /// exited while blocked).
+    waiters: HashMap<(u64, u64), (u64, usize)>,
+    /// Requests currently waiting, with their inode, for deadlock detection
+    blocked: Vec<(u64, FileLock)>,
+}
+
+impl LockTable {
+    fn conflict(&self, ino: u64, owner: u64, start: u64, end: u64, typ: i32) -> Option<FileLock> {
+        self.locks
+            .get(&ino)?
+            .iter()
+            .find(|l| l.conflicts_with(owner, start, end, typ))
+            .copied()
+    }
+
+    /// Owners holding locks that conflict with `request`
+    fn blockers(&self, ino: u64, request: &FileLock) -> impl Iterator<Item = u64> + '_ {
+        let request = *request;
+        self.locks.get(&ino).into_iter().flat_map(move |locks| {
+            locks
+                .iter()
+                .filter(move |l| {
+                    l.conflicts_with(request.owner, request.start, request.end, request.typ)
+                })
+                .map(|l| l.owner)
+        })
+    }
+
+    /// Whether waiting for `request` would close a cycle of owners waiting on
+    /// each other's locks
+    fn would_deadlock(&self, ino: u64, request: &FileLock) -> bool {
+        let mut pending: Vec<u64> = self.blockers(ino, request).collect();
+        let mut seen = HashSet::new();
+        while let Some(owner) = pending.pop() {
+            if owner == request.owner {
+                return true;
+            }
+            if !seen.insert(owner) {
+                continue;
+            }
+            for (ino, waiting) in self.blocked.iter().filter(|(_, l)| l.owner == owner) {
+                pending.extend(self.blockers(*ino, waiting));
+            }
+        }
+        false
+    }
+
+    fn try_lock(&mut self, new: FileLock, ino: u64) -> Result<(), FileLock> {
+        if let Some(conflict) = self.conflict(ino, new.owner, new.start, new.end, new.typ) {
+            return Err(conflict);
+        }
+
+        let locks = self.locks.entry(ino).or_default();
+        carve(locks, new.owner, new.start, new.end);
+
+        // Merge with adjacent locks of the same owner and type
+        let mut merged = new;
+        locks.retain(|l| {
+            let adjacent = l.end.checked_add(1) == Some(merged.start)
+                || merged.end.checked_add(1) == Some(l.start);
+            if l.owner == merged.owner && l.typ == merged.typ && adjacent {
+                merged.start = merged.start.min(l.start);
+                merged.end = merged.end.max(l.end);
+                false
+            } else {
+                true
+            }
+        });
+        locks.push(merged);
+        Ok(())
+    }
+
+    fn unlock(&mut self, ino: u64, owner: u64, start: u64, end: u64) {
+        if let Some(locks) = self.locks.get_mut(&ino) {
+            carve(locks, owner, start, end);
+            if locks.is_empty() {
+                self.locks.remove(&ino);
+            }
+        }
+    }
+}
+
+/// Remove `[start, end]` from every lock held by `owner`, splitting locks
+/// that extend past either side of the range.
+fn carve(locks: &mut Vec<FileLock>, owner: u64, start: u64, end: u64) {
+    let mut kept = Vec::with_capacity(locks.len() + 1);
+    for l in locks.drain(..) {
+        if l.owner != owner || !l.overlaps(start, end) {
+            kept.push(l);
+            continue;
+        }
+        if l.start < start {
+            kept.push(FileLock {
+                end: start - 1,
+                ..l
+            });
+        }
+        if l.end > end {
+            kept.push(FileLock {
+                start: end + 1,
+                ..l
+            });
+        }
+    }
+    *locks = kept;
+}
+
+/// Outcome of a blocking lock request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockWait {
+    Acquired,
+    /// All of the owner's locks on the inode were released while it waited
+    Cancelled,
+    /// Waiting would deadlock with the owners holding the conflicting locks
+    Deadlock,
+}
+
+/// Tracks advisory byte-range locks for a mounted filesystem.
+#[derive(Default)]
+pub struct LockManager {
+    table: Mutex<LockTable>,
+    /// Signalled whenever locks are released so blocked requests can retry
+    released: Notify,
+}
+
+impl LockManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the first lock that would block `owner` from taking `typ` on
+    /// the range (F_GETLK semantics), or `None` if the lock could be placed.
+    pub fn test(&self, ino: u64, owner: u64, start: u64, end: u64, typ: i32) -> Option<FileLock> {
+        self.table.lock().conflict(ino, owner, start, end, typ)
+    }
+
+    /// Try to acquire a lock without waiting (F_SETLK semantics).
+    ///
+    /// Returns the conflicting lock if another owner holds an incompatible
+    /// lock on an overlapping range.
+    pub fn try_lock(
+        &self,
+        ino: u64,
+        owner: u64,
+        pid: u32,
+        start: u64,
+        end: u64,
+        typ: i32,
+    ) -> Result<(), FileLock> {
+        let new = FileLock {
+            start,
+            end,
+            typ,
+            pid,
+            owner,
+        };
+        let result = self.table.lock().try_lock(new, ino);
+        if result.is_ok() {
+            // Downgrading a write lock to a read lock can unblock readers
+            self.released.notify_waiters();
+        }
+        result
+    }
+
+    /// Acquire a lock, waiting until conflicting locks are released
+    /// (F_SETLKW semantics).
+    ///
+    /// Returns [`LockWait::Cancelled`] if all of the owner's locks on the
+    /// inode were released while it waited (see
+    /// [`LockManager::release_owner`]), and [`LockWait::Deadlock`] if the
+    /// owners it would wait on are waiting on it.
+    pub async fn lock(
+        &self,
+        ino: u64,
+        owner: u64,
+        pid: u32,
+        start: u64,
+        end: u64,
+        typ: i32,
+    ) -> LockWait {
+        let key = (ino, owner);
+        let epoch = {
+            let mut table = self.table.lock();
+            let waiter = table.waiters.entry(key).or_insert((0, 0));
+            waiter.1 += 1;
+            waiter.0
+        };
+
+        let new = FileLock {
+            start,
+            end,
+            typ,
+            pid,
+            owner,
+        };
+        let outcome = loop {
+            // Register for wakeups before checking so a release between the
+            // check and the await is not missed
+            let notified = self.released.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            {
+                let mut table = self.table.lock();
+                if table.waiters.get(&key).map(|w| w.0) != Some(epoch) {
+                    break LockWait::Cancelled;
+                }
+                if table.try_lock(new, ino).is_ok() {
+                    break LockWait::Acquired;
+                }
+                // The conflicting locks may have changed hands since the last
+                // check, so look for a cycle before every wait
+                if table.would_deadlock(ino, &new) {
+                    break LockWait::Deadlock;
+                }
+                table.blocked.push((ino, new));
+            }
+
+            notified.await;
+            let mut table = self.table.lock();
+            if let Some(i) = table
+                .blocked
+                .iter()
+                .position(|(i, l)| *i == ino && *l == new)
+            {
+                table.blocked.swap_remove(i);
+            }
+        };
+
+        let mut table = self.table.lock();
+        if let Some(waiter) = table.waiters.get_mut(&key) {
+            waiter.1 -= 1;
+            if waiter.1 == 0 {
+                table.waiters.remove(&key);
+            }
+        }
+        outcome
+    }
+
+    /// Release the owner's locks on a range (F_UNLCK).
+    pub fn unlock(&self, ino: u64, owner: u64, start: u64, end: u64) {
+        self.table.lock().unlock(ino, owner, start, end);
+        self.released.notify_waiters();
+    }
+
+    /// Release every lock the owner holds on an inode and cancel any of its
+    /// blocked requests.
+    ///
+    /// Called when the owner closes the file (`flush`/`release`).
+    pub fn release_owner(&self, ino: u64, owner: u64) {
+        {
+            let mut table = self.table.lock();
+            table.unlock(ino, owner, 0, u64::MAX);
+            if let Some(waiter) = table.waiters.get_mut(&(ino, owner)) {
+                waiter.0 += 1;
+            }
+        }
+        self.released.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    const EOF: u64 = u64::MAX;
+
+    #[test]
+    fn test_read_locks_are_shared() {
+        let locks = LockManager::new();
+        assert!(locks.try_lock(1, 10, 100, 0, EOF, libc::F_RDLCK).is_ok());
+        assert!(locks.try_lock(1, 20, 200, 0, EOF, libc::F_RDLCK).is_ok());
+
+        let conflict = locks.test(1, 30, 0, 10, libc::F_WRLCK).unwrap();
+        assert_eq!(conflict.typ, libc::F_RDLCK);
+        assert!(locks.try_lock(1, 30, 300, 0, 10, libc::F_WRLCK).is_err());
+
+        // Other inodes are unaffected
+        assert!(locks.try_lock(2, 30, 300, 0, 10, libc::F_WRLCK).is_ok());
+    }
+
+    #[test]
+    fn test_write_lock_excludes_overlapping_ranges_only() {
+        let locks = LockManager::new();
+        assert!(locks.try_lock(1, 10, 100, 0, 99, libc::F_WRLCK).is_ok());
+
+        let conflict = locks
+            .try_lock(1, 20, 200, 50, 150, libc::F_RDLCK)
+            .unwrap_err();
+        assert_eq!((conflict.start, conflict.end, conflict.pid), (0, 99, 100));
+        assert!(locks.try_lock(1, 20, 200, 100, 199, libc::F_WRLCK).is_ok());
+
+        // The owner can always relock its own range
+        assert!(locks.try_lock(1, 10, 100, 0, 99, libc::F_RDLCK).is_ok());
+    }
+
+    #[test]
+    fn test_partial_unlock_splits_lock() {
+        let locks = LockManager::new();
+        assert!(locks.try_lock(1, 10, 100, 0, 99, libc::F_WRLCK).is_ok());
+        locks.unlock(1, 10, 40, 59);
+
+        assert!(locks.test(1, 20, 40, 59, libc::F_WRLCK).is_none());
+        assert!(locks.test(1, 20, 0, 39, libc::F_WRLCK).is_some());
+        assert!(locks.test(1, 20, 60, 99, libc::F_WRLCK).is_some());
+
+        locks.release_owner(1, 10);
+        assert!(locks.test(1, 20, 0, EOF, libc::F_WRLCK).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_blocking_lock_waits_for_release() {
+        let locks = Arc::new(LockManager::new());
+        assert!(locks.try_lock(1, 10, 100, 0, EOF, libc::F_WRLCK).is_ok());
+
+        let waiter = {
+            let locks = locks.clone();
+            tokio::spawn(async move { locks.lock(1, 20, 200, 0, EOF, libc::F_WRLCK).await })
+        };
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiter.is_finished());
+
+        locks.release_owner(1, 10);
+        assert_eq!(waiter.await.unwrap(), LockWait::Acquired);
+        assert!(locks.test(1, 10, 0, 0, libc::F_RDLCK).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_release_owner_cancels_blocked_request() {
+        let locks = Arc::new(LockManager::new());
+        assert!(locks.try_lock(1, 10, 100, 0, EOF, libc::F_WRLCK).is_ok());
+
+        let waiter = {
+            let locks = locks.clone();
+            tokio::spawn(async move { locks.lock(1, 20, 200, 0, EOF, libc::F_WRLCK).await })
+        };
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        locks.release_owner(1, 20);
+        assert_eq!(waiter.await.unwrap(), LockWait::Cancelled);
+        assert!(locks.test(1, 20, 0, EOF, libc::F_WRLCK).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_deadlock_is_detected() {
+        let locks = Arc::new(LockManager::new());
+        assert!(locks.try_lock(1, 10, 100, 0, EOF, libc::F_WRLCK).is_ok());
+        assert!(locks.try_lock(2, 20, 200, 0, EOF, libc::F_WRLCK).is_ok());
+        assert!(locks.try_lock(3, 30, 300, 0, EOF, libc::F_WRLCK).is_ok());
+
+        // 10 waits on 20, which waits on 30
+        let first = {
+            let locks = locks.clone();
+            tokio::spawn(async move { locks.lock(2, 10, 100, 0, EOF, libc::F_WRLCK).await })
+        };
+        let second = {
+            let locks = locks.clone();
+            tokio::spawn(async move { locks.lock(3, 20, 200, 0, EOF, libc::F_WRLCK).await })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        // 30 waiting on 10 would close the cycle
+        assert_eq!(
+            locks.lock(1, 30, 300, 0, EOF, libc::F_WRLCK).await,
+            LockWait::Deadlock
+        );
+        assert!(!first.is_finished() && !second.is_finished());
+
+        locks.release_owner(3, 30);
+        assert_eq!(second.await.unwrap(), LockWait::Acquired);
+        locks.release_owner(2, 20);
+        locks.release_owner(3, 20);
+        assert_eq!(first.await.unwrap(), LockWait::Acquired);
+    }
+}
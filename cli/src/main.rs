@@ -1,7 +1,10 @@
 use agentfs::{
     cmd::{self, completions::handle_completions},
     get_runtime,
-    opts::{Args, Command, FsCommand, PruneCommand, ServeCommand, SyncCommand},
+    opts::{
+        Args, Command, FsCommand, HooksCommand, PruneCommand, ServeCommand, SnapshotCommand,
+        SyncCommand,
+    },
 };
 use clap::{CommandFactory, Parser};
 use clap_complete::CompleteEnv;
@@ -110,6 +113,7 @@ fn main() {
             system,
             key,
             cipher,
+            hooks,
             command,
             args,
         } => {
@@ -124,6 +128,7 @@ fn main() {
                 session,
                 system,
                 encryption,
+                hooks,
                 command,
                 args,
             )) {
@@ -159,6 +164,7 @@ fn main() {
             uid,
             gid,
             backend,
+            hooks,
         } => match (id_or_path, mountpoint) {
             (Some(id_or_path), Some(mountpoint)) => {
                 if let Err(e) = cmd::mount(cmd::MountArgs {
@@ -171,6 +177,7 @@ fn main() {
                     uid,
                     gid,
                     backend,
+                    hooks,
                 }) {
                     eprintln!("Error: {}", e);
                     std::process::exit(1);
@@ -197,6 +204,7 @@ fn main() {
             filter,
             status,
             format,
+            files,
         } => {
             let rt = get_runtime();
             let options = cmd::timeline::TimelineOptions {
@@ -204,6 +212,7 @@ fn main() {
                 filter,
                 status,
                 format,
+                files,
             };
             if let Err(e) = rt.block_on(cmd::timeline::show_timeline(
                 &mut std::io::stdout(),
@@ -214,6 +223,130 @@ fn main() {
                 std::process::exit(1);
             }
         }
+        Command::Log {
+            id_or_path,
+            limit,
+            since,
+            path,
+            follow,
+            format,
+        } => {
+            let rt = get_runtime();
+            let options = cmd::log::LogOptions {
+                limit,
+                since,
+                path,
+                follow,
+                format,
+            };
+            if let Err(e) = rt.block_on(cmd::log::show_log(
+                &mut std::io::stdout(),
+                &id_or_path,
+                &options,
+            )) {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Command::Hooks { command } => match command {
+            HooksCommand::Log {
+                id_or_path,
+                limit,
+                since,
+                path,
+                hook,
+                event,
+                decision,
+                format,
+            } => {
+                let rt = get_runtime();
+                let options = cmd::hooks::HookLogOptions {
+                    limit,
+                    since,
+                    path,
+                    hook,
+                    event,
+                    decision,
+                    format,
+                };
+                if let Err(e) = rt.block_on(cmd::hooks::show_hook_log(
+                    &mut std::io::stdout(),
+                    &id_or_path,
+                    &options,
+                )) {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+            HooksCommand::Reads {
+                id_or_path,
+                limit,
+                since,
+                path,
+                format,
+            } => {
+                let rt = get_runtime();
+                let options = cmd::hooks::ReadLogOptions {
+                    limit,
+                    since,
+                    path,
+                    format,
+                };
+                if let Err(e) = rt.block_on(cmd::hooks::show_read_log(
+                    &mut std::io::stdout(),
+                    &id_or_path,
+                    &options,
+                )) {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        },
+        Command::Snapshot {
+            id_or_path,
+            command,
+        } => {
+            let rt = get_runtime();
+            let stdout = &mut std::io::stdout();
+            let result = match command {
+                SnapshotCommand::Create { name } => {
+                    rt.block_on(cmd::snapshot::create_snapshot(stdout, &id_or_path, &name))
+                }
+                SnapshotCommand::List { format } => {
+                    rt.block_on(cmd::snapshot::list_snapshots(stdout, &id_or_path, &format))
+                }
+                SnapshotCommand::Restore { name } => {
+                    rt.block_on(cmd::snapshot::restore_snapshot(stdout, &id_or_path, &name))
+                }
+                SnapshotCommand::Rm { name } => {
+                    rt.block_on(cmd::snapshot::remove_snapshot(stdout, &id_or_path, &name))
+                }
+            };
+            if let Err(e) = result {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Command::Fsck {
+            id_or_path,
+            repair,
+            format,
+        } => {
+            let rt = get_runtime();
+            match rt.block_on(cmd::fsck::check_filesystem(
+                &mut std::io::stdout(),
+                &id_or_path,
+                repair,
+                &format,
+            )) {
+                Ok(report) if report.has_errors() => std::process::exit(1),
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
         Command::Fs {
             command,
             id_or_path,
@@ -245,12 +378,27 @@ fn main() {
                         std::process::exit(1);
                     }
                 }
-                FsCommand::Write { file_path, content } => {
+                FsCommand::Write {
+                    file_path,
+                    content,
+                    hooks,
+                } => {
                     if let Err(e) = rt.block_on(cmd::fs::write_filesystem(
                         id_or_path,
                         &file_path,
                         &content,
                         encryption.as_ref(),
+                        &hooks,
+                    )) {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+                FsCommand::Dedup => {
+                    if let Err(e) = rt.block_on(cmd::fs::dedup_filesystem(
+                        &mut std::io::stdout(),
+                        id_or_path,
+                        encryption.as_ref(),
                     )) {
                         eprintln!("Error: {}", e);
                         std::process::exit(1);
@@ -264,21 +412,27 @@ fn main() {
             id_or_path,
             bind,
             port,
+            hooks,
         } => {
             eprintln!("Warning: `agentfs nfs` is deprecated, use `agentfs serve nfs` instead");
             let rt = get_runtime();
-            if let Err(e) = rt.block_on(cmd::nfs::handle_nfs_command(id_or_path, bind, port)) {
+            if let Err(e) = rt.block_on(cmd::nfs::handle_nfs_command(id_or_path, bind, port, hooks))
+            {
                 eprintln!("Error: {}", e);
                 std::process::exit(1);
             }
         }
-        Command::McpServer { id_or_path, tools } => {
+        Command::McpServer {
+            id_or_path,
+            tools,
+            hooks,
+        } => {
             eprintln!(
                 "Warning: `agentfs mcp-server` is deprecated, use `agentfs serve mcp` instead"
             );
             let rt = get_runtime();
             if let Err(e) = rt.block_on(cmd::mcp_server::handle_mcp_server_command(
-                id_or_path, tools,
+                id_or_path, tools, hooks,
             )) {
                 eprintln!("Error: {}", e);
                 std::process::exit(1);
@@ -290,17 +444,24 @@ fn main() {
                 id_or_path,
                 bind,
                 port,
+                hooks,
             } => {
                 let rt = get_runtime();
-                if let Err(e) = rt.block_on(cmd::nfs::handle_nfs_command(id_or_path, bind, port)) {
+                if let Err(e) =
+                    rt.block_on(cmd::nfs::handle_nfs_command(id_or_path, bind, port, hooks))
+                {
                     eprintln!("Error: {}", e);
                     std::process::exit(1);
                 }
             }
-            ServeCommand::Mcp { id_or_path, tools } => {
+            ServeCommand::Mcp {
+                id_or_path,
+                tools,
+                hooks,
+            } => {
                 let rt = get_runtime();
                 if let Err(e) = rt.block_on(cmd::mcp_server::handle_mcp_server_command(
-                    id_or_path, tools,
+                    id_or_path, tools, hooks,
                 )) {
                     eprintln!("Error: {}", e);
                     std::process::exit(1);
@@ -263,4 +263,48 @@ impl agentfs_sdk::FileSystem for MutexFsAdapter {
     ) -> std::result::Result<agentfs_sdk::FilesystemStats, agentfs_sdk::error::Error> {
         self.inner.lock().await.statfs().await
     }
+
+    async fn getxattr(
+        &self,
+        ino: i64,
+        name: &str,
+    ) -> std::result::Result<Option<Vec<u8>>, agentfs_sdk::error::Error> {
+        self.inner.lock().await.getxattr(ino, name).await
+    }
+
+    async fn setxattr(
+        &self,
+        ino: i64,
+        name: &str,
+        value: &[u8],
+        flags: i32,
+    ) -> std::result::Result<(), agentfs_sdk::error::Error> {
+        self.inner
+            .lock()
+            .await
+            .setxattr(ino, name, value, flags)
+            .await
+    }
+
+    async fn listxattr(
+        &self,
+        ino: i64,
+    ) -> std::result::Result<Vec<String>, agentfs_sdk::error::Error> {
+        self.inner.lock().await.listxattr(ino).await
+    }
+
+    async fn removexattr(
+        &self,
+        ino: i64,
+        name: &str,
+    ) -> std::result::Result<(), agentfs_sdk::error::Error> {
+        self.inner.lock().await.removexattr(ino, name).await
+    }
+
+    async fn inode_path(
+        &self,
+        ino: i64,
+    ) -> std::result::Result<Option<String>, agentfs_sdk::error::Error> {
+        self.inner.lock().await.inode_path(ino).await
+    }
 }
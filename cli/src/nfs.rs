@@ -30,10 +30,23 @@ fn id_to_fs_ino(id: fileid3) -> i64 {
     id as i64
 }
 
+/// Convert an errno to an NFS status code.
+fn errno_to_nfsstat(errno: i32) -> nfsstat3 {
+    match errno {
+        libc::EPERM => nfsstat3::NFS3ERR_PERM,
+        libc::EACCES => nfsstat3::NFS3ERR_ACCES,
+        libc::EROFS => nfsstat3::NFS3ERR_ROFS,
+        libc::ENOSPC => nfsstat3::NFS3ERR_NOSPC,
+        _ => nfsstat3::NFS3ERR_IO,
+    }
+}
+
 /// Convert an SDK error to an NFS status code.
 ///
 /// Connection pool timeouts return NFS3ERR_JUKEBOX to signal the client
-/// should retry the operation later. Other errors map to NFS3ERR_IO.
+/// should retry the operation later. IO errors carrying an errno, such as
+/// operations denied by LevFS hooks, map through that errno. Other errors
+/// map to NFS3ERR_IO.
 fn error_to_nfsstat(e: SdkError) -> nfsstat3 {
     match e {
         SdkError::Fs(ref fs_err) => match fs_err {
@@ -46,6 +59,10 @@ fn error_to_nfsstat(e: SdkError) -> nfsstat3 {
             FsError::RootOperation => nfsstat3::NFS3ERR_ACCES,
             _ => nfsstat3::NFS3ERR_IO,
         },
+        SdkError::Io(ref io_err) => io_err
+            .raw_os_error()
+            .map(errno_to_nfsstat)
+            .unwrap_or(nfsstat3::NFS3ERR_IO),
         SdkError::ConnectionPoolTimeout => nfsstat3::NFS3ERR_JUKEBOX,
         _ => nfsstat3::NFS3ERR_IO,
     }
@@ -262,6 +279,8 @@ impl NFSFileSystem for AgentNFS {
             .await
             .map_err(error_to_nfsstat)?;
         file.pwrite(offset, data).await.map_err(error_to_nfsstat)?;
+        // NFS has no close, and replies that writes are stored
+        file.flush().await.map_err(error_to_nfsstat)?;
 
         let stats = fs
             .getattr(id_to_fs_ino(id))
@@ -62,6 +62,18 @@ pub struct SyncCommandOptions {
     pub sync_partial_bootstrap_length: Option<usize>,
 }
 
+/// LevFS hook options of commands that serve or change a filesystem
+#[derive(Debug, Clone, Default, Parser)]
+pub struct HookOptions {
+    /// Hooks config file (default: ~/.config/lev/reactive/hooks.yaml if it exists)
+    #[arg(long = "hooks", value_name = "FILE", add = ArgValueCompleter::new(PathCompleter::file()))]
+    pub config: Option<PathBuf>,
+
+    /// Record what sync hooks would deny in the hook log instead of enforcing it
+    #[arg(long = "hooks-audit")]
+    pub audit: bool,
+}
+
 #[derive(Subcommand, Debug)]
 pub enum Command {
     /// Manage shell completions
@@ -174,6 +186,9 @@ pub enum Command {
         #[arg(long, env = "AGENTFS_CIPHER")]
         cipher: Option<String>,
 
+        #[command(flatten)]
+        hooks: HookOptions,
+
         /// Command to execute (defaults to bash on Linux, zsh on macOS)
         command: Option<PathBuf>,
 
@@ -251,6 +266,9 @@ pub enum Command {
         /// Backend to use for mounting
         #[arg(long, default_value_t = MountBackend::default())]
         backend: MountBackend,
+
+        #[command(flatten)]
+        hooks: HookOptions,
     },
     /// Show differences between base filesystem and delta (overlay mode only)
     Diff {
@@ -279,6 +297,64 @@ pub enum Command {
         /// Output format
         #[arg(long, default_value = "table", value_parser = ["table", "json"])]
         format: String,
+
+        /// Show the files each tool call changed
+        #[arg(long)]
+        files: bool,
+    },
+    /// Show the journal of filesystem changes
+    Log {
+        /// Agent ID or database path
+        #[arg(add = ArgValueCompleter::new(id_or_path_completer))]
+        id_or_path: String,
+
+        /// Limit number of entries to display
+        #[arg(long, default_value = "100")]
+        limit: i64,
+
+        /// Show entries recorded after this entry ID (oldest first)
+        #[arg(long)]
+        since: Option<i64>,
+
+        /// Only show changes to this path or below it
+        #[arg(long)]
+        path: Option<String>,
+
+        /// Keep printing new entries as they are recorded
+        #[arg(long, short = 'f')]
+        follow: bool,
+
+        /// Output format
+        #[arg(long, default_value = "table", value_parser = ["table", "json"])]
+        format: String,
+    },
+    /// Inspect LevFS hook decisions
+    Hooks {
+        #[command(subcommand)]
+        command: HooksCommand,
+    },
+    /// Manage named snapshots of the filesystem and key-value store
+    Snapshot {
+        /// Agent ID or database path
+        #[arg(add = ArgValueCompleter::new(id_or_path_completer))]
+        id_or_path: String,
+
+        #[command(subcommand)]
+        command: SnapshotCommand,
+    },
+    /// Check filesystem integrity and verify chunk checksums
+    Fsck {
+        /// Agent ID or database path
+        #[arg(add = ArgValueCompleter::new(id_or_path_completer))]
+        id_or_path: String,
+
+        /// Fix inconsistencies (lost or corrupted file contents cannot be fixed)
+        #[arg(long)]
+        repair: bool,
+
+        /// Output format
+        #[arg(long, default_value = "table", value_parser = ["table", "json"])]
+        format: String,
     },
     /// Start an NFS server to export an AgentFS filesystem over the network
     /// (deprecated: use `agentfs serve nfs` instead)
@@ -295,6 +371,9 @@ pub enum Command {
         /// Port to listen on
         #[arg(long, default_value = "11111")]
         port: u32,
+
+        #[command(flatten)]
+        hooks: HookOptions,
     },
 
     /// Start an MCP server exposing filesystem and KV-store tools
@@ -309,6 +388,9 @@ pub enum Command {
         /// copy_file, rename, stat, access, kv_get, kv_set, kv_delete, kv_list
         #[arg(long, value_delimiter = ',')]
         tools: Option<Vec<String>>,
+
+        #[command(flatten)]
+        hooks: HookOptions,
     },
 
     /// Serve an AgentFS filesystem via different protocols
@@ -345,7 +427,12 @@ pub enum FsCommand {
 
         /// Content of the file
         content: String,
+
+        #[command(flatten)]
+        hooks: HookOptions,
     },
+    /// Enable chunk deduplication and report logical vs physical bytes
+    Dedup,
 }
 
 #[derive(Subcommand, Debug)]
@@ -360,6 +447,91 @@ pub enum SyncCommand {
     Checkpoint,
 }
 
+#[derive(Subcommand, Debug)]
+pub enum SnapshotCommand {
+    /// Take a snapshot of the current state
+    Create {
+        /// Snapshot name
+        name: String,
+    },
+    /// List snapshots
+    List {
+        /// Output format
+        #[arg(long, default_value = "table", value_parser = ["table", "json"])]
+        format: String,
+    },
+    /// Restore a snapshot (the filesystem should not be mounted)
+    Restore {
+        /// Snapshot name
+        name: String,
+    },
+    /// Delete a snapshot
+    Rm {
+        /// Snapshot name
+        name: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum HooksCommand {
+    /// Show the log of hook decisions
+    Log {
+        /// Agent ID or database path
+        #[arg(add = ArgValueCompleter::new(id_or_path_completer))]
+        id_or_path: String,
+
+        /// Limit number of entries to display
+        #[arg(long, default_value = "100")]
+        limit: i64,
+
+        /// Show entries recorded after this entry ID (oldest first)
+        #[arg(long)]
+        since: Option<i64>,
+
+        /// Only show decisions about this path or below it
+        #[arg(long)]
+        path: Option<String>,
+
+        /// Only show decisions of this hook
+        #[arg(long)]
+        hook: Option<String>,
+
+        /// Only show decisions about this event (e.g. file:close)
+        #[arg(long)]
+        event: Option<String>,
+
+        /// Only show this decision
+        #[arg(long, value_parser = ["allow", "deny", "message", "transform", "audit", "error"])]
+        decision: Option<String>,
+
+        /// Output format
+        #[arg(long, default_value = "table", value_parser = ["table", "json"])]
+        format: String,
+    },
+    /// Show the log of files read through the mount
+    Reads {
+        /// Agent ID or database path
+        #[arg(add = ArgValueCompleter::new(id_or_path_completer))]
+        id_or_path: String,
+
+        /// Limit number of entries to display
+        #[arg(long, default_value = "100")]
+        limit: i64,
+
+        /// Show entries recorded after this entry ID (oldest first)
+        #[arg(long)]
+        since: Option<i64>,
+
+        /// Only show reads of this path or below it
+        #[arg(long)]
+        path: Option<String>,
+
+        /// Output format
+        #[arg(long, default_value = "table", value_parser = ["table", "json"])]
+        format: String,
+    },
+}
+
 #[derive(Subcommand, Debug)]
 pub enum ServeCommand {
     /// Start an NFS server to export an AgentFS filesystem over the network
@@ -376,6 +548,9 @@ pub enum ServeCommand {
         /// Port to listen on
         #[arg(long, default_value = "11111")]
         port: u32,
+
+        #[command(flatten)]
+        hooks: HookOptions,
     },
 
     /// Start an MCP server exposing filesystem and KV-store tools
@@ -389,6 +564,9 @@ pub enum ServeCommand {
         /// copy_file, rename, stat, access, kv_get, kv_set, kv_delete, kv_list
         #[arg(long, value_delimiter = ',')]
         tools: Option<Vec<String>>,
+
+        #[command(flatten)]
+        hooks: HookOptions,
     },
 }
 
@@ -32,7 +32,6 @@ use std::{
         Arc,
     },
 };
-use tokio::sync::Mutex;
 
 /// Global child PID for signal forwarding.
 /// Set by the parent before installing signal handlers.
@@ -42,7 +41,9 @@ static CHILD_PID: AtomicI32 = AtomicI32::new(0);
 /// First signal forwards to child, second signal sends SIGKILL.
 static TERM_SIGNAL_COUNT: AtomicI32 = AtomicI32::new(0);
 
+use crate::levfs::{load_hooks, HookedFileSystem};
 use crate::mount::{is_mountpoint, mount_fs, MountBackend, MountHandle, MountOpts};
+use crate::opts::HookOptions;
 
 /// Exit code returned when exec fails (standard shell convention for "command not found")
 const EXIT_COMMAND_NOT_FOUND: i32 = 127;
@@ -144,10 +145,12 @@ pub async fn run_cmd(
     session_id: Option<String>,
     system: bool,
     encryption: Option<(String, String)>,
+    hooks: HookOptions,
     command: PathBuf,
     args: Vec<String>,
 ) -> Result<()> {
     let cwd = std::env::current_dir().context("Failed to get current directory")?;
+    let hooks = load_hooks(&hooks)?;
 
     // Build the list of allowed writable paths
     let allowed_paths = build_allowed_paths(&allow, no_default_allows)?;
@@ -197,6 +200,10 @@ pub async fn run_cmd(
     let agentfs = AgentFS::open(options)
         .await
         .context("Failed to create delta AgentFS")?;
+    let hooks = hooks
+        .with_log(agentfs.hook_log.clone())
+        .with_queue(agentfs.hook_queue.clone())
+        .with_read_log(agentfs.read_log.clone());
 
     let hostfs = HostFS::new(&fd_path).context("Failed to create HostFS")?;
     #[cfg(target_family = "unix")]
@@ -240,7 +247,7 @@ pub async fn run_cmd(
     };
 
     // Mount the overlay filesystem
-    let mount_handle = mount_fs(Arc::new(Mutex::new(overlay)), mount_opts).await?;
+    let mount_handle = mount_fs(HookedFileSystem::wrap_locked(overlay, hooks), mount_opts).await?;
 
     // Create pipes for parent-child coordination.
     // The parent needs to write uid_map/gid_map for the child after unshare.
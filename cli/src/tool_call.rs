@@ -0,0 +1,79 @@
+//! Active tool call context for long-running servers.
+//!
+//! Filesystem changes made while a tool call is active are attributed to it
+//! in the change journal. Servers pick the active tool call up from:
+//!
+//! - `AGENTFS_TOOL_CALL_ID`, read once at startup, and
+//! - a control file next to the database (`<db>-tool-call`) holding the id of
+//!   the active tool call, or nothing when no call is active. A harness writes
+//!   the id returned by `ToolCalls::start` before running the tool and
+//!   truncates the file once it has finished. The file is read before every
+//!   change, so there is no window in which changes go to the previous id.
+
+use agentfs_sdk::filesystem::AgentFS;
+use std::path::PathBuf;
+
+/// Environment variable holding the initial tool call id
+pub const TOOL_CALL_ENV: &str = "AGENTFS_TOOL_CALL_ID";
+
+/// Get the control file for a database path, if the database is on disk
+pub fn control_file(db_path: &str) -> Option<PathBuf> {
+    if db_path == ":memory:" {
+        return None;
+    }
+    Some(PathBuf::from(format!("{db_path}-tool-call")))
+}
+
+/// Get the tool call id from `AGENTFS_TOOL_CALL_ID`
+pub fn from_env() -> Option<i64> {
+    std::env::var(TOOL_CALL_ENV)
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .filter(|id| *id > 0)
+}
+
+/// Attribute a filesystem's changes to the active tool call
+///
+/// The control file is read as each change is made, so a change made right
+/// after the file is written is attributed to the new id. The control file
+/// takes precedence over the environment once it exists.
+pub fn attach(fs: &AgentFS, control: Option<PathBuf>) {
+    fs.set_tool_call(from_env());
+    fs.set_tool_call_file(control);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use agentfs_sdk::{AgentFS as Agent, AgentFSOptions, JournalQuery};
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_control_file_path() {
+        assert_eq!(control_file(":memory:"), None);
+        assert_eq!(
+            control_file(".agentfs/agent.db"),
+            Some(PathBuf::from(".agentfs/agent.db-tool-call"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_attach_control_file() {
+        let agent = Agent::open(AgentFSOptions::ephemeral()).await.unwrap();
+        let control = NamedTempFile::new().unwrap();
+        attach(&agent.fs, Some(control.path().to_path_buf()));
+
+        std::fs::write(control.path(), "42\n").unwrap();
+        assert_eq!(agent.fs.tool_call(), Some(42));
+        agent.fs.mkdir("/a", 0, 0).await.unwrap();
+        std::fs::write(control.path(), "43").unwrap();
+        agent.fs.mkdir("/b", 0, 0).await.unwrap();
+
+        let entries = agent.journal.tail(&JournalQuery::default()).await.unwrap();
+        let ids: Vec<_> = entries.iter().map(|e| e.tool_call_id).collect();
+        assert_eq!(ids, vec![Some(42), Some(43)]);
+
+        std::fs::write(control.path(), "").unwrap();
+        assert_eq!(agent.fs.tool_call(), None);
+    }
+}
@@ -494,6 +494,9 @@ impl FileOps for SqliteFileOps {
         file.truncate(data.len() as u64)
             .await
             .map_err(|e| VfsError::Other(format!("Failed to truncate file: {}", e)))?;
+        file.flush()
+            .await
+            .map_err(|e| VfsError::Other(format!("Failed to flush file: {}", e)))?;
 
         // Clear dirty flag after successful write
         *self.dirty.lock().unwrap() = false;
@@ -3,8 +3,12 @@
 //! This benchmark simulates realistic workloads based on observed operation
 //! distributions from tools like `npx create-react-app`.
 //!
+//! It also measures how reads from parallel requests scale with the number of
+//! read-only connections in the connection pool.
+//!
 //! Run with: cargo bench --bench workload
 
+use agentfs_sdk::connection_pool::ConnectionPool;
 use agentfs_sdk::filesystem::{AgentFS, FileSystem, HostFS, OverlayFS};
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
 use rand::distributions::WeightedIndex;
@@ -302,27 +306,50 @@ impl WorkloadGenerator {
     }
 }
 
+/// Resolve a path to an inode by looking up each component from the root.
+async fn resolve(overlay: &OverlayFS, path: &str) -> Option<i64> {
+    let mut ino = 1;
+    for name in path.split('/').filter(|name| !name.is_empty()) {
+        ino = overlay.lookup(ino, name).await.ok()??.ino;
+    }
+    Some(ino)
+}
+
+/// Resolve the parent directory of a path, returning it with the last component.
+async fn resolve_parent<'a>(overlay: &OverlayFS, path: &'a str) -> Option<(i64, &'a str)> {
+    let (parent, name) = path.rsplit_once('/')?;
+    Some((resolve(overlay, parent).await?, name))
+}
+
 /// Execute a single operation on the overlay filesystem.
+///
+/// Errors are ignored: paths may not exist, which is expected.
 async fn execute_operation(overlay: &OverlayFS, op: Operation, path: &str) {
     match op {
         Operation::CreateFile => {
-            // Ignore errors - path may not exist, which is expected
-            let _ = overlay.create_file(path, 0o100644, 0, 0).await;
+            if let Some((parent, name)) = resolve_parent(overlay, path).await {
+                let _ = overlay.create_file(parent, name, 0o100644, 0, 0).await;
+            }
         }
-        Operation::Lstat => {
-            let _ = overlay.lstat(path).await;
+        Operation::Lstat | Operation::Stat => {
+            if let Some(ino) = resolve(overlay, path).await {
+                let _ = overlay.getattr(ino).await;
+            }
         }
         Operation::Mkdir => {
-            let _ = overlay.mkdir(path, 0, 0).await;
+            if let Some((parent, name)) = resolve_parent(overlay, path).await {
+                let _ = overlay.mkdir(parent, name, 0o755, 0, 0).await;
+            }
         }
         Operation::Open => {
-            let _ = overlay.open(path).await;
+            if let Some(ino) = resolve(overlay, path).await {
+                let _ = overlay.open(ino, libc::O_RDONLY).await;
+            }
         }
         Operation::ReaddirPlus => {
-            let _ = overlay.readdir_plus(path).await;
-        }
-        Operation::Stat => {
-            let _ = overlay.stat(path).await;
+            if let Some(ino) = resolve(overlay, path).await {
+                let _ = overlay.readdir_plus(ino).await;
+            }
         }
     }
 }
@@ -454,5 +481,88 @@ fn bench_individual_ops(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, bench_workload, bench_individual_ops);
+/// Number of files read by the parallel read benchmark.
+const PARALLEL_READ_FILES: usize = 64;
+
+/// Number of concurrent tasks in the parallel read benchmark.
+const PARALLEL_READ_TASKS: usize = 8;
+
+/// Reads each task performs per iteration of the parallel read benchmark.
+const PARALLEL_READS_PER_TASK: usize = 100;
+
+/// Benchmark lookups, getattrs and reads issued from parallel tasks, like
+/// FUSE requests with `FUSE_ASYNC_READ` and `FUSE_PARALLEL_DIROPS`, with a
+/// growing number of read-only connections. With no readers every read
+/// waits for the single writer connection.
+fn bench_parallel_reads(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let mut group = c.benchmark_group("parallel_reads");
+    group.throughput(Throughput::Elements(
+        (PARALLEL_READ_TASKS * PARALLEL_READS_PER_TASK) as u64,
+    ));
+
+    for readers in [0, 1, 2, 4, 8] {
+        let dir = tempdir().expect("Failed to create temp dir");
+        let fs = rt.block_on(async {
+            let db_path = dir.path().join("agent.db");
+            let db = turso::Builder::new_local(db_path.to_str().unwrap())
+                .build()
+                .await
+                .expect("Failed to open database");
+            let fs = AgentFS::from_pool(ConnectionPool::with_readers(db, readers))
+                .await
+                .expect("Failed to create AgentFS");
+            let data = vec![b'x'; 16 * 1024];
+            for i in 0..PARALLEL_READ_FILES {
+                let (_, file) =
+                    FileSystem::create_file(&fs, 1, &format!("file{}", i), 0o100644, 0, 0)
+                        .await
+                        .expect("Failed to create file");
+                file.pwrite(0, &data).await.expect("Failed to write file");
+                file.flush().await.expect("Failed to flush file");
+            }
+            Arc::new(fs)
+        });
+
+        group.bench_with_input(BenchmarkId::new("readers", readers), &fs, |b, fs| {
+            b.iter(|| {
+                rt.block_on(async {
+                    let tasks: Vec<_> = (0..PARALLEL_READ_TASKS)
+                        .map(|task| {
+                            let fs = fs.clone();
+                            tokio::spawn(async move {
+                                for i in 0..PARALLEL_READS_PER_TASK {
+                                    let name =
+                                        format!("file{}", (task * 7 + i) % PARALLEL_READ_FILES);
+                                    let stats = fs.lookup(1, &name).await.unwrap().unwrap();
+                                    fs.getattr(stats.ino).await.unwrap();
+                                    let file = FileSystem::open(&*fs, stats.ino, libc::O_RDONLY)
+                                        .await
+                                        .unwrap();
+                                    file.pread(0, 4096).await.unwrap();
+                                }
+                            })
+                        })
+                        .collect();
+                    for task in tasks {
+                        task.await.unwrap();
+                    }
+                });
+            });
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_workload,
+    bench_individual_ops,
+    bench_parallel_reads
+);
 criterion_main!(benches);
@@ -3,19 +3,93 @@
 //! This module provides a thread-safe connection pool that manages database
 //! connections with a maximum limit. When the pool is exhausted, callers block
 //! until a connection becomes available or timeout occurs.
-
-use std::{sync::Arc, time::Duration};
+//!
+//! The pool hands out one writer connection and, for local databases, up to
+//! `readers` read-only connections alongside it. The database runs in WAL
+//! mode, so a reader sees the last committed state and never waits for the
+//! writer: reads from parallel requests proceed concurrently while writes stay
+//! serialized. Each statement on a reader sees its own snapshot, so an
+//! operation made of several statements may observe a write committed between
+//! them.
+//!
+//! A pool can also hand out several writer connections to a database in
+//! turso's MVCC mode. Write transactions then begin with `BEGIN CONCURRENT`
+//! and run side by side, and a write to a row that a concurrent transaction
+//! has changed fails with a write-write conflict, rolling back its
+//! transaction. Operations that write run through [`ConnectionPool::retry`],
+//! which runs them again from the start after a conflict. Under MVCC a
+//! statement dropped before its rows are exhausted leaves its connection on a
+//! stale snapshot, so connections are rolled back before they're handed out
+//! and before a write transaction begins.
+//!
+//! Turso locks the database file for the process that opens it, so writers
+//! only run concurrently within one process. Processes sharing a database
+//! take turns opening it.
+//!
+//! Every connection waits up to [`BUSY_TIMEOUT`] for a lock held by another
+//! connection, and [`begin_immediate`] retries a write transaction that still
+//! finds the database busy.
+
+use std::{
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore};
+use turso::transaction::{Transaction, TransactionBehavior};
 use turso::{Connection, Database};
 
 use crate::error::{Error, Result};
 
-/// Maximum number of connections in the pool.
-const MAX_CONNECTIONS: usize = 1;
+/// Default maximum number of writer connections in the pool.
+pub const DEFAULT_WRITERS: usize = 1;
+
+/// Default maximum number of read-only connections in the pool.
+pub const DEFAULT_READERS: usize = 4;
 
 /// Default timeout for acquiring a connection from the pool.
 const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
 
+/// How long a statement waits for a lock held by another connection, and
+/// opening a database for another process to close it.
+pub const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Number of times beginning a write transaction is retried after it fails
+/// with a busy error.
+const BUSY_RETRIES: u32 = 5;
+
+/// Message of the error a write fails with when it conflicts with a
+/// concurrent transaction.
+const WRITE_CONFLICT: &str = "Write-write conflict";
+
+/// Begin an immediate write transaction on `conn`, retrying with backoff
+/// while the database is busy.
+pub(crate) async fn begin_immediate(conn: &Connection) -> Result<Transaction<'_>> {
+    let mut delay = Duration::from_millis(10);
+    for _ in 0..BUSY_RETRIES {
+        match Transaction::new_unchecked(conn, TransactionBehavior::Immediate).await {
+            Err(turso::Error::Busy(_) | turso::Error::BusySnapshot(_)) => {
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+            result => return Ok(result?),
+        }
+    }
+    Ok(Transaction::new_unchecked(conn, TransactionBehavior::Immediate).await?)
+}
+
+/// Whether `err` is a write that conflicted with a concurrent transaction.
+///
+/// Under MVCC a write on a stale snapshot fails with a busy error instead.
+/// Either way the transaction it was made in has been rolled back.
+fn is_conflict(err: &Error) -> bool {
+    match err {
+        Error::Database(turso::Error::Error(msg)) => msg == WRITE_CONFLICT,
+        Error::Database(turso::Error::Busy(_) | turso::Error::BusySnapshot(_)) => true,
+        _ => false,
+    }
+}
+
 /// Database wrapper that supports both regular and sync databases.
 enum DatabaseType {
     Local(Database),
@@ -27,6 +101,7 @@ enum DatabaseType {
 /// The pool enforces a maximum number of concurrent connections. When all
 /// connections are in use, `get_connection()` blocks until one becomes
 /// available or the timeout expires (returning `ConnectionPoolTimeout`).
+/// `get_read_connection()` does the same for the read-only connections.
 #[derive(Clone)]
 pub struct ConnectionPool {
     inner: Arc<ConnectionPoolInner>,
@@ -38,6 +113,14 @@ struct ConnectionPoolInner {
     pool: Mutex<Vec<Connection>>,
     /// Semaphore to limit concurrent connections
     semaphore: Arc<Semaphore>,
+    /// Whether there are several writer connections, with write
+    /// transactions running concurrently under MVCC
+    concurrent: bool,
+    /// Available read-only connections ready to be reused
+    readers: Mutex<Vec<Connection>>,
+    /// Semaphore to limit concurrent read-only connections, `None` when
+    /// reads share the writer connection
+    reader_semaphore: Option<Arc<Semaphore>>,
     /// Timeout for acquiring a connection
     timeout: Duration,
 }
@@ -45,26 +128,58 @@ struct ConnectionPoolInner {
 impl ConnectionPool {
     /// Create a new connection pool from a database.
     pub fn new(db: Database) -> Self {
-        Self::with_timeout(DatabaseType::Local(db), DEFAULT_TIMEOUT)
+        Self::with_readers(db, DEFAULT_READERS)
+    }
+
+    /// Create a new connection pool from a database with up to `readers`
+    /// read-only connections. With no readers, reads share the writer
+    /// connection.
+    pub fn with_readers(db: Database, readers: usize) -> Self {
+        Self::with_writers(db, DEFAULT_WRITERS, readers)
+    }
+
+    /// Create a new connection pool from a database with up to `writers`
+    /// writer connections and `readers` read-only connections.
+    ///
+    /// With more than one writer, the database must be in turso's MVCC mode
+    /// (`PRAGMA journal_mode = 'experimental_mvcc'`), and write transactions
+    /// run concurrently.
+    pub fn with_writers(db: Database, writers: usize, readers: usize) -> Self {
+        Self::with_timeout(DatabaseType::Local(db), DEFAULT_TIMEOUT, writers, readers)
     }
 
     /// Create a new connection pool from a sync database.
+    ///
+    /// Reads share the writer connection, since pulling from the remote
+    /// replaces the local database under open connections.
     pub fn new_sync(db: turso::sync::Database) -> Self {
-        Self::with_timeout(DatabaseType::Sync(db), DEFAULT_TIMEOUT)
+        Self::with_timeout(DatabaseType::Sync(db), DEFAULT_TIMEOUT, DEFAULT_WRITERS, 0)
     }
 
     /// Create a connection pool with a custom timeout.
-    fn with_timeout(db: DatabaseType, timeout: Duration) -> Self {
+    fn with_timeout(db: DatabaseType, timeout: Duration, writers: usize, readers: usize) -> Self {
+        let writers = writers.max(1);
         Self {
             inner: Arc::new(ConnectionPoolInner {
                 db,
                 pool: Mutex::new(Vec::new()),
-                semaphore: Arc::new(Semaphore::new(MAX_CONNECTIONS)),
+                semaphore: Arc::new(Semaphore::new(writers)),
+                concurrent: writers > 1,
+                readers: Mutex::new(Vec::new()),
+                reader_semaphore: (readers > 0).then(|| Arc::new(Semaphore::new(readers))),
                 timeout,
             }),
         }
     }
 
+    /// Wait for a permit of `semaphore`, up to the pool's timeout
+    async fn acquire(&self, semaphore: &Arc<Semaphore>) -> Result<OwnedSemaphorePermit> {
+        tokio::time::timeout(self.inner.timeout, Arc::clone(semaphore).acquire_owned())
+            .await
+            .map_err(|_| Error::ConnectionPoolTimeout)?
+            .map_err(|_| Error::Internal("semaphore closed".to_string()))
+    }
+
     /// Get a connection from the pool.
     ///
     /// If a pooled connection is available, it is returned immediately.
@@ -78,13 +193,7 @@ impl ConnectionPool {
     /// available within the timeout period.
     pub async fn get_connection(&self) -> Result<PooledConnection> {
         // Try to acquire a permit with timeout
-        let permit = tokio::time::timeout(
-            self.inner.timeout,
-            Arc::clone(&self.inner.semaphore).acquire_owned(),
-        )
-        .await
-        .map_err(|_| Error::ConnectionPoolTimeout)?
-        .map_err(|_| Error::Internal("semaphore closed".to_string()))?;
+        let permit = self.acquire(&self.inner.semaphore).await?;
 
         // We have a permit - try to get an existing connection or create new one
         let conn = {
@@ -94,19 +203,105 @@ impl ConnectionPool {
 
         let conn = match conn {
             Some(c) => c,
-            None => match &self.inner.db {
-                DatabaseType::Local(db) => db.connect()?,
-                DatabaseType::Sync(db) => db.connect().await?,
-            },
+            None => {
+                let conn = match &self.inner.db {
+                    DatabaseType::Local(db) => db.connect()?,
+                    DatabaseType::Sync(db) => db.connect().await?,
+                };
+                conn.busy_timeout(BUSY_TIMEOUT)?;
+                conn
+            }
+        };
+
+        // An operation that failed inside a transaction returns the
+        // connection with the transaction still open, and under MVCC one that
+        // dropped a statement early leaves it on a stale snapshot; roll back
+        // so this caller starts from the committed state.
+        if self.inner.concurrent || !conn.is_autocommit()? {
+            let _ = conn.execute("ROLLBACK", ()).await;
+        }
+
+        Ok(PooledConnection {
+            conn: Some(conn),
+            pool: self.inner.clone(),
+            reader: false,
+            _permit: permit,
+        })
+    }
+
+    /// Get a read-only connection from the pool.
+    ///
+    /// Read-only connections see the last committed state of the database
+    /// and don't wait for the writer. A statement that writes fails on them.
+    /// When the pool has no readers, this returns the writer connection.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ConnectionPoolTimeout` if no connection becomes
+    /// available within the timeout period.
+    pub async fn get_read_connection(&self) -> Result<PooledConnection> {
+        let (Some(semaphore), DatabaseType::Local(db)) =
+            (&self.inner.reader_semaphore, &self.inner.db)
+        else {
+            return self.get_connection().await;
+        };
+        let permit = self.acquire(semaphore).await?;
+
+        let conn = {
+            let mut readers = self.inner.readers.lock().await;
+            readers.pop()
+        };
+
+        let conn = match conn {
+            Some(c) => c,
+            None => {
+                let conn = db.connect()?;
+                conn.execute("PRAGMA query_only = 1", ()).await?;
+                conn.busy_timeout(BUSY_TIMEOUT)?;
+                conn
+            }
         };
 
+        if self.inner.concurrent {
+            let _ = conn.execute("ROLLBACK", ()).await;
+        }
+
         Ok(PooledConnection {
             conn: Some(conn),
             pool: self.inner.clone(),
+            reader: true,
             _permit: permit,
         })
     }
 
+    /// Run `op` again while it conflicts with a concurrent write transaction.
+    ///
+    /// A conflict rolls back the transaction it happens in, so `op` must get
+    /// its connection from the pool and write from the start each time it
+    /// runs. Retries back off and stop after [`BUSY_TIMEOUT`], returning the
+    /// conflict. Without concurrent writers nothing conflicts and `op` runs
+    /// once.
+    pub(crate) async fn retry<T, F, Fut>(&self, mut op: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        if !self.inner.concurrent {
+            return op().await;
+        }
+        let started = Instant::now();
+        let mut delay = Duration::from_millis(1);
+        loop {
+            match op().await {
+                Err(e) if is_conflict(&e) && started.elapsed() < BUSY_TIMEOUT => {
+                    tokio::time::sleep(delay).await;
+                    delay = std::cmp::min(delay * 2, Duration::from_millis(100));
+                }
+                result => return result,
+            }
+        }
+    }
+
     /// Get the underlying database reference (for creating additional connections).
     /// Returns None if this is a sync database.
     pub fn database(&self) -> Option<&Database> {
@@ -132,6 +327,8 @@ impl ConnectionPool {
 pub struct PooledConnection {
     conn: Option<Connection>,
     pool: Arc<ConnectionPoolInner>,
+    /// Whether this is a read-only connection
+    reader: bool,
     /// Held permit - released when this is dropped
     _permit: OwnedSemaphorePermit,
 }
@@ -141,6 +338,56 @@ impl PooledConnection {
     pub fn connection(&self) -> &Connection {
         self.conn.as_ref().expect("connection already taken")
     }
+
+    /// Begin a write transaction on this connection.
+    ///
+    /// With concurrent writers this is `BEGIN CONCURRENT`, from a fresh
+    /// snapshot. Otherwise it's an immediate transaction, see
+    /// [`begin_immediate`].
+    pub(crate) async fn begin_write(&self) -> Result<WriteTransaction<'_>> {
+        let conn = self.connection();
+        if self.pool.concurrent {
+            if conn.is_autocommit()? {
+                let _ = conn.execute("ROLLBACK", ()).await;
+            }
+            conn.execute("BEGIN CONCURRENT", ()).await?;
+            return Ok(WriteTransaction::Concurrent(conn));
+        }
+        Ok(WriteTransaction::Immediate(begin_immediate(conn).await?))
+    }
+}
+
+/// A write transaction begun by [`PooledConnection::begin_write`].
+///
+/// Dropping it without committing leaves it open until the connection is
+/// handed out again, which rolls it back.
+pub(crate) enum WriteTransaction<'conn> {
+    Immediate(Transaction<'conn>),
+    Concurrent(&'conn Connection),
+}
+
+impl WriteTransaction<'_> {
+    /// Commit the transaction
+    pub(crate) async fn commit(self) -> Result<()> {
+        match self {
+            Self::Immediate(txn) => txn.commit().await?,
+            Self::Concurrent(conn) => {
+                conn.execute("COMMIT", ()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Roll the transaction back
+    pub(crate) async fn rollback(self) -> Result<()> {
+        match self {
+            Self::Immediate(txn) => txn.rollback().await?,
+            Self::Concurrent(conn) => {
+                conn.execute("ROLLBACK", ()).await?;
+            }
+        }
+        Ok(())
+    }
 }
 
 impl std::ops::Deref for PooledConnection {
@@ -156,7 +403,12 @@ impl Drop for PooledConnection {
         if let Some(conn) = self.conn.take() {
             // Return connection to pool - use try_lock to avoid blocking in drop
             // If we can't get the lock, just drop the connection (it will be recreated)
-            if let Ok(mut pool) = self.pool.pool.try_lock() {
+            let pool = if self.reader {
+                &self.pool.readers
+            } else {
+                &self.pool.pool
+            };
+            if let Ok(mut pool) = pool.try_lock() {
                 pool.push(conn);
             }
             // Permit is automatically released when _permit is dropped
@@ -214,7 +466,8 @@ mod tests {
     async fn test_connection_pool_timeout_error() {
         // Create pool with very short timeout
         let db = Builder::new_local(":memory:").build().await.unwrap();
-        let pool = ConnectionPool::with_timeout(DatabaseType::Local(db), Duration::from_millis(50));
+        let pool =
+            ConnectionPool::with_timeout(DatabaseType::Local(db), Duration::from_millis(50), 1, 0);
 
         // Hold the one connection
         let _conn1 = pool.get_connection().await.unwrap();
@@ -251,4 +504,151 @@ mod tests {
         // All 5 should have completed (serially, since max=1)
         assert_eq!(counter.load(Ordering::SeqCst), 5);
     }
+
+    #[tokio::test]
+    async fn test_connection_pool_readers_alongside_writer() {
+        let db = Builder::new_local(":memory:").build().await.unwrap();
+        let pool = ConnectionPool::with_readers(db, 2);
+
+        let writer = pool.get_connection().await.unwrap();
+        writer
+            .execute("CREATE TABLE t (x INTEGER)", ())
+            .await
+            .unwrap();
+        writer
+            .execute("INSERT INTO t VALUES (1)", ())
+            .await
+            .unwrap();
+        writer.execute("BEGIN", ()).await.unwrap();
+        writer
+            .execute("INSERT INTO t VALUES (2)", ())
+            .await
+            .unwrap();
+
+        // Readers don't wait for the writer and see the last commit
+        let reader1 = pool.get_read_connection().await.unwrap();
+        let reader2 = pool.get_read_connection().await.unwrap();
+        let mut rows = reader2.query("SELECT count(*) FROM t", ()).await.unwrap();
+        let row = rows.next().await.unwrap().unwrap();
+        assert_eq!(row.get_value(0).unwrap().as_integer().copied(), Some(1));
+        assert!(reader1
+            .execute("INSERT INTO t VALUES (3)", ())
+            .await
+            .is_err());
+
+        // Both readers are taken
+        let result =
+            tokio::time::timeout(Duration::from_millis(100), pool.get_read_connection()).await;
+        assert!(result.is_err());
+        drop(reader1);
+        assert!(pool.get_read_connection().await.unwrap().reader);
+
+        writer.execute("COMMIT", ()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_connection_pool_without_readers() {
+        let db = Builder::new_local(":memory:").build().await.unwrap();
+        let pool = ConnectionPool::with_readers(db, 0);
+
+        // Reads share the writer connection
+        let conn = pool.get_read_connection().await.unwrap();
+        assert!(!conn.reader);
+        let result = tokio::time::timeout(Duration::from_millis(100), pool.get_connection()).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_connection_pool_rolls_back_abandoned_transaction() {
+        let db = Builder::new_local(":memory:").build().await.unwrap();
+        let pool = ConnectionPool::new(db);
+
+        let conn = pool.get_connection().await.unwrap();
+        conn.execute("CREATE TABLE t (x INTEGER)", ())
+            .await
+            .unwrap();
+        conn.execute("BEGIN IMMEDIATE", ()).await.unwrap();
+        conn.execute("INSERT INTO t VALUES (1)", ()).await.unwrap();
+        drop(conn);
+
+        // The next caller doesn't inherit the open transaction
+        let conn = pool.get_connection().await.unwrap();
+        assert!(conn.is_autocommit().unwrap());
+        let mut stmt = conn.prepare_cached("SELECT COUNT(*) FROM t").await.unwrap();
+        let row = stmt.query_row(()).await.unwrap();
+        assert_eq!(row.get::<i64>(0).unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_connection_pool_concurrent_writers() {
+        let db = Builder::new_local(":memory:").build().await.unwrap();
+        let pool = ConnectionPool::with_writers(db, 2, 0);
+
+        let conn = pool.get_connection().await.unwrap();
+        let mut rows = conn
+            .query("PRAGMA journal_mode = 'experimental_mvcc'", ())
+            .await
+            .unwrap();
+        while rows.next().await.unwrap().is_some() {}
+        drop(rows);
+        conn.execute("CREATE TABLE t (k INTEGER PRIMARY KEY, v INTEGER)", ())
+            .await
+            .unwrap();
+        conn.execute("INSERT INTO t VALUES (1, 0), (2, 0)", ())
+            .await
+            .unwrap();
+        drop(conn);
+
+        // Two write transactions are open at once and change different rows
+        let a = pool.get_connection().await.unwrap();
+        let b = pool.get_connection().await.unwrap();
+        let txn_a = a.begin_write().await.unwrap();
+        let txn_b = b.begin_write().await.unwrap();
+        a.execute("UPDATE t SET v = 1 WHERE k = 1", ())
+            .await
+            .unwrap();
+        b.execute("UPDATE t SET v = 2 WHERE k = 2", ())
+            .await
+            .unwrap();
+        txn_a.commit().await.unwrap();
+        txn_b.commit().await.unwrap();
+        drop(b);
+
+        // A write to a row an open transaction changed conflicts, and is
+        // retried until that transaction commits
+        let txn_a = a.begin_write().await.unwrap();
+        a.execute("UPDATE t SET v = 10 WHERE k = 1", ())
+            .await
+            .unwrap();
+        let attempts = &AtomicUsize::new(0);
+        let pool_ref = &pool;
+        let (retried, committed) = tokio::join!(
+            pool.retry(|| async move {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                let conn = pool_ref.get_connection().await?;
+                let txn = conn.begin_write().await?;
+                conn.execute("UPDATE t SET v = v + 1 WHERE k = 1", ())
+                    .await?;
+                txn.commit().await
+            }),
+            async {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                txn_a.commit().await
+            }
+        );
+        retried.unwrap();
+        committed.unwrap();
+        assert!(attempts.load(Ordering::SeqCst) > 1);
+
+        let mut stmt = a
+            .prepare_cached("SELECT v FROM t ORDER BY k")
+            .await
+            .unwrap();
+        let mut rows = stmt.query(()).await.unwrap();
+        let mut values = Vec::new();
+        while let Some(row) = rows.next().await.unwrap() {
+            values.push(row.get::<i64>(0).unwrap());
+        }
+        assert_eq!(values, vec![11, 2]);
+    }
 }
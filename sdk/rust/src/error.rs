@@ -49,10 +49,22 @@ pub enum Error {
     #[error("tool call not found")]
     ToolCallNotFound,
 
+    /// Snapshot not found
+    #[error("snapshot '{0}' not found")]
+    SnapshotNotFound(String),
+
+    /// Snapshot name already in use
+    #[error("snapshot '{0}' already exists")]
+    SnapshotExists(String),
+
     /// Sync not enabled for this database
     #[error("sync is not enabled for this database")]
     SyncNotEnabled,
 
+    /// Database file locked by another process
+    #[error("database '{0}' is in use by another process")]
+    DatabaseInUse(String),
+
     /// Connection pool timeout - no connections available
     #[error("connection pool timeout: no connections available")]
     ConnectionPoolTimeout,
@@ -1,31 +1,130 @@
 use crate::error::{Error, Result};
 use async_trait::async_trait;
 use lru::LruCache;
+use sha2::{Digest, Sha256};
+use std::collections::{btree_map, hash_map, BTreeMap, HashMap};
 use std::num::NonZeroUsize;
-use std::path::Path;
-use std::sync::{Arc, Mutex};
-use std::time::{SystemTime, UNIX_EPOCH};
-use turso::transaction::{Transaction, TransactionBehavior};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use turso::{Builder, Connection, Value};
 
 use super::{
     BoxedFile, DirEntry, File, FileSystem, FilesystemStats, FsError, Stats, TimeChange,
-    DEFAULT_DIR_MODE, DEFAULT_FILE_MODE, MAX_NAME_LEN, S_IFLNK, S_IFMT, S_IFREG,
+    DEFAULT_DIR_MODE, DEFAULT_FILE_MODE, MAX_NAME_LEN, S_IFLNK, S_IFMT, S_IFREG, XATTR_CREATE,
+    XATTR_NAME_MAX, XATTR_REPLACE,
 };
-use crate::connection_pool::ConnectionPool;
+use crate::connection_pool::{ConnectionPool, WriteTransaction};
+use crate::journal::{self, Change, JournalOp};
 
 const ROOT_INO: i64 = 1;
 const DEFAULT_CHUNK_SIZE: usize = 4096;
 const DENTRY_CACHE_MAX_SIZE: usize = 10000;
+/// Bytes of buffered writes, across all files, above which they are flushed
+const WRITE_BACK_MAX_BYTES: usize = 32 * 1024 * 1024;
+/// How long buffered writes wait before they are flushed
+const WRITE_BACK_DELAY: Duration = Duration::from_secs(1);
+/// Largest file stored in `fs_inode.inline_data` instead of in chunks
+const INLINE_DATA_MAX_SIZE: u64 = 2048;
+
+/// Tool call that changes are made on behalf of
+#[derive(Debug, Default)]
+struct ToolCall {
+    /// Id set with `AgentFS::set_tool_call`, 0 if none
+    id: AtomicI64,
+    /// Control file holding the active id, read before every change
+    control: RwLock<Option<PathBuf>>,
+}
+
+impl ToolCall {
+    /// Get the active tool call
+    ///
+    /// The control file takes precedence over the set id once it exists.
+    /// Empty or invalid contents mean no tool call is active.
+    fn load(&self) -> Option<i64> {
+        let control = self.control.read().unwrap();
+        if let Some(path) = control.as_deref() {
+            match std::fs::read_to_string(path) {
+                Ok(s) => return s.trim().parse().ok().filter(|id| *id > 0),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(_) => return None,
+            }
+        }
+        match self.id.load(Ordering::Relaxed) {
+            0 => None,
+            id => Some(id),
+        }
+    }
+}
+
+/// Content address of a deduplicated chunk: hex-encoded SHA-256
+pub(crate) fn chunk_hash(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Check chunk contents against their stored checksum.
+///
+/// The checksum is the `fs_data.checksum` of an inline chunk or the
+/// `fs_data.hash` of a deduplicated one. Chunks written before checksums
+/// were introduced have neither and are not verified.
+fn verify_chunk(data: &[u8], checksum: Option<&str>) -> Result<()> {
+    match checksum {
+        Some(expected) if chunk_hash(data) != expected => Err(FsError::Corrupted.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Whether a file of `size` bytes is stored in its inode rather than in
+/// chunks. Inline data never exceeds a chunk.
+fn stores_inline(size: u64, chunk_size: usize) -> bool {
+    size > 0 && size <= std::cmp::min(INLINE_DATA_MAX_SIZE, chunk_size as u64)
+}
+
+/// Read `size` bytes at `offset` from a file stored in its inode
+fn read_inline_range(data: &[u8], offset: u64, size: u64) -> Vec<u8> {
+    let start = std::cmp::min(offset, data.len() as u64) as usize;
+    let end = std::cmp::min(offset.saturating_add(size), data.len() as u64) as usize;
+    data[start..end].to_vec()
+}
+
+/// Read the checksum column selected next to a chunk
+fn row_checksum(row: &turso::Row, idx: usize) -> Option<String> {
+    match row.get_value(idx) {
+        Ok(Value::Text(checksum)) => Some(checksum),
+        _ => None,
+    }
+}
+
+/// Commit the transaction a mutation ran in.
+///
+/// Each mutation makes all of its changes, journal entry included, in one
+/// transaction, so a crash leaves either all of them or none. Tests can make
+/// the process abort here instead, with every statement run but uncommitted.
+async fn commit(txn: WriteTransaction<'_>) -> Result<()> {
+    #[cfg(test)]
+    if tests::CRASH_BEFORE_COMMIT.load(Ordering::SeqCst) {
+        std::process::abort();
+    }
+    txn.commit().await?;
+    Ok(())
+}
 
 /// LRU cache for directory entry lookups.
 ///
 /// Maps (parent_ino, name) -> child_ino to avoid repeated database queries
 /// during path resolution. For a path like `/a/b/c/d`, this reduces queries
 /// from 4 to potentially 0 on cache hits.
+///
+/// Reads run on their own connections, concurrently with the writer, so a
+/// reader can look up an entry just before a writer removes it. Readers
+/// therefore cache what they found with [`DentryCache::insert_since`], which
+/// drops the entry if anything was removed since the reader started.
 struct DentryCache {
     // Mutex required because LruCache::get() mutates internal order
     entries: Mutex<LruCache<(i64, String), i64>>,
+    /// Incremented, with `entries` locked, whenever entries are removed
+    generation: AtomicU64,
 }
 
 impl DentryCache {
@@ -34,9 +133,15 @@ impl DentryCache {
             entries: Mutex::new(LruCache::new(
                 NonZeroUsize::new(max_size).expect("cache size must be > 0"),
             )),
+            generation: AtomicU64::new(0),
         }
     }
 
+    /// Current generation, to pass to [`DentryCache::insert_since`]
+    fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
     /// Look up a cached entry (updates LRU order)
     fn get(&self, parent_ino: i64, name: &str) -> Option<i64> {
         self.entries
@@ -54,12 +159,376 @@ impl DentryCache {
             .put((parent_ino, name.to_string()), child_ino);
     }
 
+    /// Insert an entry read from the database, unless an entry was removed
+    /// since `generation`
+    fn insert_since(&self, generation: u64, parent_ino: i64, name: &str, child_ino: i64) {
+        let mut entries = self.entries.lock().unwrap();
+        if self.generation.load(Ordering::Acquire) == generation {
+            entries.put((parent_ino, name.to_string()), child_ino);
+        }
+    }
+
     /// Remove an entry from the cache
     fn remove(&self, parent_ino: i64, name: &str) {
-        self.entries
+        let mut entries = self.entries.lock().unwrap();
+        entries.pop(&(parent_ino, name.to_string()));
+        self.generation.fetch_add(1, Ordering::Release);
+    }
+
+    /// Remove all entries from the cache
+    fn clear(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.clear();
+        self.generation.fetch_add(1, Ordering::Release);
+    }
+}
+
+/// A buffered write, recorded in the journal when it is flushed
+struct PendingWrite {
+    tool_call: Option<i64>,
+    offset: u64,
+    length: usize,
+    size: u64,
+}
+
+/// Writes to an inode that haven't been flushed yet
+struct DirtyInode {
+    /// Size of the file in the database
+    stored_size: u64,
+    /// Whether the file is stored in its inode in the database, in which case
+    /// its contents are buffered as chunk 0
+    stored_inline: bool,
+    /// Size of the file including the buffered writes
+    size: u64,
+    /// Modification time of the last buffered write (seconds, nanoseconds)
+    mtime: (i64, i64),
+    /// Contents of every chunk written, by chunk index
+    chunks: BTreeMap<i64, Vec<u8>>,
+    /// Writes to journal, with contiguous writes merged
+    writes: Vec<PendingWrite>,
+}
+
+/// Write-back buffer for file contents, shared by a filesystem and its open
+/// files.
+///
+/// Writes through open files are collected per inode as whole chunks, so a
+/// chunk written many times is stored once, and are written out in a single
+/// transaction when a handle is flushed, synced or truncated, when more than
+/// [`WRITE_BACK_MAX_BYTES`] are buffered, or [`WRITE_BACK_DELAY`] after they
+/// were made. Reads through any handle, and the size and modification time
+/// reported for the file, include the buffered writes.
+struct WriteBack {
+    pool: ConnectionPool,
+    chunk_size: usize,
+    dedup: Arc<AtomicBool>,
+    /// Buffered writes by inode, locked while they are read or flushed
+    inodes: tokio::sync::Mutex<HashMap<i64, DirtyInode>>,
+    /// Size and modification time of each inode in `inodes`, readable
+    /// without waiting for a flush
+    attrs: Mutex<HashMap<i64, (u64, i64, i64)>>,
+    /// Bytes buffered across all inodes
+    bytes: AtomicUsize,
+    /// Whether a delayed flush is scheduled
+    flush_scheduled: AtomicBool,
+}
+
+impl WriteBack {
+    fn new(pool: ConnectionPool, chunk_size: usize, dedup: Arc<AtomicBool>) -> Self {
+        Self {
+            pool,
+            chunk_size,
+            dedup,
+            inodes: tokio::sync::Mutex::new(HashMap::new()),
+            attrs: Mutex::new(HashMap::new()),
+            bytes: AtomicUsize::new(0),
+            flush_scheduled: AtomicBool::new(false),
+        }
+    }
+
+    /// Whether writes to `ino` are buffered
+    fn is_dirty(&self, ino: i64) -> bool {
+        self.attrs.lock().unwrap().contains_key(&ino)
+    }
+
+    /// Include buffered writes in the size and modification time of `stats`
+    fn apply(&self, stats: &mut Stats) {
+        if let Some(&(size, mtime, mtime_nsec)) = self.attrs.lock().unwrap().get(&stats.ino) {
+            stats.size = size as i64;
+            stats.mtime = mtime;
+            stats.mtime_nsec = mtime_nsec as u32;
+        }
+    }
+
+    /// Buffer a write of `data` at `offset` to `ino`
+    async fn write(
+        self: &Arc<Self>,
+        ino: i64,
+        offset: u64,
+        data: &[u8],
+        tool_call: Option<i64>,
+    ) -> Result<()> {
+        let chunk_size = self.chunk_size as u64;
+        let mut inodes = self.inodes.lock().await;
+        let conn = self.pool.get_read_connection().await?;
+
+        let mut added = 0usize;
+        let dirty = match inodes.entry(ino) {
+            hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            hash_map::Entry::Vacant(entry) => {
+                let mut stmt = conn
+                    .prepare_cached("SELECT size, inline_data FROM fs_inode WHERE ino = ?")
+                    .await?;
+                let mut rows = stmt.query((ino,)).await?;
+                let (stored_size, inline_data) = match rows.next().await? {
+                    Some(row) => (
+                        row.get_value(0)
+                            .ok()
+                            .and_then(|v| v.as_integer().copied())
+                            .unwrap_or(0) as u64,
+                        match row.get_value(1) {
+                            Ok(Value::Blob(data)) => Some(data),
+                            _ => None,
+                        },
+                    ),
+                    None => return Err(FsError::NotFound.into()),
+                };
+                let mut chunks = BTreeMap::new();
+                let stored_inline = inline_data.is_some();
+                if let Some(data) = inline_data.filter(|data| !data.is_empty()) {
+                    added += data.len();
+                    chunks.insert(0, data);
+                }
+                entry.insert(DirtyInode {
+                    stored_size,
+                    stored_inline,
+                    size: stored_size,
+                    mtime: (0, 0),
+                    chunks,
+                    writes: Vec::new(),
+                })
+            }
+        };
+
+        let mut written = 0usize;
+        while written < data.len() {
+            let current_offset = offset + written as u64;
+            let chunk_index = (current_offset / chunk_size) as i64;
+            let offset_in_chunk = (current_offset % chunk_size) as usize;
+            let to_write = std::cmp::min(self.chunk_size - offset_in_chunk, data.len() - written);
+
+            let chunk = match dirty.chunks.entry(chunk_index) {
+                btree_map::Entry::Occupied(entry) => entry.into_mut(),
+                btree_map::Entry::Vacant(entry) => {
+                    // Start from the stored contents, unless all of them are
+                    // overwritten or there are none
+                    let chunk_start = current_offset - offset_in_chunk as u64;
+                    let stored = if to_write == self.chunk_size
+                        || chunk_start >= dirty.stored_size
+                        || dirty.stored_inline
+                    {
+                        None
+                    } else {
+                        AgentFS::read_chunk(&conn, ino, chunk_index).await?
+                    };
+                    // Counted as buffered, since flushing releases the whole
+                    // chunk
+                    let stored = stored.unwrap_or_default();
+                    added += stored.len();
+                    entry.insert(stored)
+                }
+            };
+
+            let before = chunk.len();
+            if chunk.len() < offset_in_chunk + to_write {
+                chunk.resize(offset_in_chunk + to_write, 0);
+            }
+            chunk[offset_in_chunk..offset_in_chunk + to_write]
+                .copy_from_slice(&data[written..written + to_write]);
+            added += chunk.len() - before;
+            written += to_write;
+        }
+        drop(conn);
+
+        let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        dirty.size = std::cmp::max(dirty.size, offset + data.len() as u64);
+        dirty.mtime = (dur.as_secs() as i64, dur.subsec_nanos() as i64);
+        match dirty.writes.last_mut() {
+            Some(last)
+                if last.tool_call == tool_call && last.offset + last.length as u64 == offset =>
+            {
+                last.length += data.len();
+                last.size = dirty.size;
+            }
+            _ => dirty.writes.push(PendingWrite {
+                tool_call,
+                offset,
+                length: data.len(),
+                size: dirty.size,
+            }),
+        }
+        self.attrs
             .lock()
             .unwrap()
-            .pop(&(parent_ino, name.to_string()));
+            .insert(ino, (dirty.size, dirty.mtime.0, dirty.mtime.1));
+        drop(inodes);
+
+        if self.bytes.fetch_add(added, Ordering::SeqCst) + added > WRITE_BACK_MAX_BYTES {
+            self.flush(None).await
+        } else {
+            self.schedule_flush();
+            Ok(())
+        }
+    }
+
+    /// Read `size` bytes at `offset` from `ino`, including buffered writes.
+    ///
+    /// Returns `None` if no writes to `ino` are buffered.
+    async fn read(&self, ino: i64, offset: u64, size: u64) -> Result<Option<Vec<u8>>> {
+        if !self.is_dirty(ino) {
+            return Ok(None);
+        }
+        let inodes = self.inodes.lock().await;
+        let Some(dirty) = inodes.get(&ino) else {
+            return Ok(None);
+        };
+
+        let conn = self.pool.get_read_connection().await?;
+        let mut data =
+            AgentFS::read_range(&conn, ino, self.chunk_size, offset, size, dirty.size).await?;
+
+        let chunk_size = self.chunk_size as u64;
+        let end = offset + data.len() as u64;
+        let first_chunk = (offset / chunk_size) as i64;
+        for (&chunk_index, chunk) in dirty.chunks.range(first_chunk..) {
+            let chunk_start = chunk_index as u64 * chunk_size;
+            if chunk_start >= end {
+                break;
+            }
+            // Bytes of the chunk past its buffered contents are zeros
+            let from = std::cmp::max(chunk_start, offset);
+            let to = std::cmp::min(chunk_start + chunk_size, end);
+            for pos in from..to {
+                data[(pos - offset) as usize] = chunk
+                    .get((pos - chunk_start) as usize)
+                    .copied()
+                    .unwrap_or(0);
+            }
+        }
+        Ok(Some(data))
+    }
+
+    /// Write out the buffered writes to `ino`, or to every inode
+    async fn flush(&self, ino: Option<i64>) -> Result<()> {
+        let mut inodes = self.inodes.lock().await;
+        self.flush_locked(&mut inodes, ino).await
+    }
+
+    /// Write out the buffered writes to `ino`, or to every inode, in a single
+    /// transaction
+    async fn flush_locked(
+        &self,
+        inodes: &mut HashMap<i64, DirtyInode>,
+        ino: Option<i64>,
+    ) -> Result<()> {
+        let inos: Vec<i64> = match ino {
+            Some(ino) if inodes.contains_key(&ino) => vec![ino],
+            Some(_) => Vec::new(),
+            None => inodes.keys().copied().collect(),
+        };
+        if inos.is_empty() {
+            return Ok(());
+        }
+
+        let (dirty, inos_ref) = (&*inodes, &inos);
+        self.pool
+            .retry(|| async move {
+                let conn = self.pool.get_connection().await?;
+                let txn = conn.begin_write().await?;
+                for ino in inos_ref {
+                    self.write_out(&conn, *ino, &dirty[ino]).await?;
+                }
+                commit(txn).await
+            })
+            .await?;
+
+        let mut attrs = self.attrs.lock().unwrap();
+        for ino in inos {
+            if let Some(dirty) = inodes.remove(&ino) {
+                let bytes = dirty.chunks.values().map(Vec::len).sum();
+                self.bytes.fetch_sub(bytes, Ordering::SeqCst);
+            }
+            attrs.remove(&ino);
+        }
+        Ok(())
+    }
+
+    /// Store the buffered writes to `ino` in the transaction open on `conn`
+    async fn write_out(&self, conn: &Connection, ino: i64, dirty: &DirtyInode) -> Result<()> {
+        // A file removed since it was written has nowhere to store them
+        let mut stmt = conn
+            .prepare_cached("SELECT 1 FROM fs_inode WHERE ino = ?")
+            .await?;
+        let mut rows = stmt.query((ino,)).await?;
+        if rows.next().await?.is_none() {
+            return Ok(());
+        }
+
+        if stores_inline(dirty.size, self.chunk_size) {
+            // The whole file is in the buffered chunk 0
+            let mut data = dirty.chunks.get(&0).cloned().unwrap_or_default();
+            data.resize(dirty.size as usize, 0);
+            if !dirty.stored_inline && dirty.stored_size > 0 {
+                AgentFS::delete_chunks(conn, ino, 0, i64::MAX).await?;
+            }
+            AgentFS::set_inline(conn, ino, Some(&data)).await?;
+        } else {
+            let dedup = self.dedup.load(Ordering::Relaxed);
+            for (&chunk_index, data) in &dirty.chunks {
+                AgentFS::write_chunk(conn, ino, chunk_index, data, dedup).await?;
+            }
+            if dirty.stored_inline {
+                AgentFS::set_inline(conn, ino, None).await?;
+            }
+        }
+
+        let mut stmt = conn
+            .prepare_cached("UPDATE fs_inode SET size = ?, mtime = ?, mtime_nsec = ? WHERE ino = ?")
+            .await?;
+        stmt.execute((dirty.size as i64, dirty.mtime.0, dirty.mtime.1, ino))
+            .await?;
+
+        for write in &dirty.writes {
+            Change::new(JournalOp::Write)
+                .ino(ino)
+                .range(write.offset, write.length)
+                .size(write.size)
+                .record(conn, write.tool_call)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Flush everything after [`WRITE_BACK_DELAY`], unless a flush is
+    /// already scheduled
+    fn schedule_flush(self: &Arc<Self>) {
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        if self.flush_scheduled.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let write_back = Arc::downgrade(self);
+        runtime.spawn(async move {
+            tokio::time::sleep(WRITE_BACK_DELAY).await;
+            let Some(write_back) = write_back.upgrade() else {
+                return;
+            };
+            write_back.flush_scheduled.store(false, Ordering::SeqCst);
+            if let Err(e) = write_back.flush(None).await {
+                tracing::warn!("failed to flush buffered writes: {}", e);
+                write_back.schedule_flush();
+            }
+        });
     }
 }
 
@@ -68,31 +537,53 @@ impl DentryCache {
 pub struct AgentFS {
     pool: ConnectionPool,
     chunk_size: usize,
+    /// Whether new chunks are stored deduplicated (shared across clones)
+    dedup: Arc<AtomicBool>,
     /// Cache for directory entry lookups (shared across clones)
     dentry_cache: Arc<DentryCache>,
+    /// Tool call that changes are made on behalf of (shared across clones and
+    /// open files)
+    tool_call: Arc<ToolCall>,
+    /// Writes through open files not yet stored (shared across clones and
+    /// open files)
+    write_back: Arc<WriteBack>,
 }
 
 /// An open file handle for AgentFS.
 ///
 /// This struct holds the inode number resolved at open time, allowing
 /// efficient read/write/fsync operations without path lookups.
+///
+/// Writes are buffered and stored when the handle is flushed or synced, or
+/// shortly after they are made. Flush the handle before the process exits
+/// to keep them.
 pub struct AgentFSFile {
     pool: ConnectionPool,
     ino: i64,
     chunk_size: usize,
+    dedup: bool,
+    tool_call: Arc<ToolCall>,
+    write_back: Arc<WriteBack>,
 }
 
 #[async_trait]
 impl File for AgentFSFile {
     async fn pread(&self, offset: u64, size: u64) -> Result<Vec<u8>> {
-        let conn = self.pool.get_connection().await?;
+        if let Some(data) = self.write_back.read(self.ino, offset, size).await? {
+            return Ok(data);
+        }
+        let conn = self.pool.get_read_connection().await?;
 
-        // Get the file size to avoid returning data beyond EOF
+        // Get the file size to avoid returning data beyond EOF, and the
+        // contents of a small file stored in the inode
         let mut size_stmt = conn
-            .prepare_cached("SELECT size FROM fs_inode WHERE ino = ?")
+            .prepare_cached("SELECT size, inline_data FROM fs_inode WHERE ino = ?")
             .await?;
         let mut size_rows = size_stmt.query((self.ino,)).await?;
         let file_size = if let Some(row) = size_rows.next().await? {
+            if let Ok(Value::Blob(data)) = row.get_value(1) {
+                return Ok(read_inline_range(&data, offset, size));
+            }
             row.get_value(0)
                 .ok()
                 .and_then(|v| v.as_integer().copied())
@@ -101,213 +592,111 @@ impl File for AgentFSFile {
             0
         };
 
-        // If offset is at or beyond EOF, return empty
-        if offset >= file_size {
-            return Ok(Vec::new());
-        }
-
-        // Limit size to not exceed EOF
-        let size = std::cmp::min(size, file_size - offset);
-
-        let chunk_size = self.chunk_size as u64;
-        let start_chunk = offset / chunk_size;
-        let end_chunk = (offset + size).saturating_sub(1) / chunk_size;
+        AgentFS::read_range(&conn, self.ino, self.chunk_size, offset, size, file_size).await
+    }
 
-        let mut stmt = conn
-            .prepare_cached("SELECT chunk_index, data FROM fs_data WHERE ino = ? AND chunk_index >= ? AND chunk_index <= ? ORDER BY chunk_index")
-            .await?;
-        let mut rows = stmt
-            .query((self.ino, start_chunk as i64, end_chunk as i64))
-            .await?;
+    async fn pwrite(&self, offset: u64, data: &[u8]) -> Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        self.write_back
+            .write(self.ino, offset, data, self.tool_call())
+            .await
+    }
 
-        let mut result = Vec::with_capacity(size as usize);
-        let start_offset_in_chunk = (offset % chunk_size) as usize;
-        let mut next_expected_chunk = start_chunk;
+    async fn truncate(&self, new_size: u64) -> Result<()> {
+        self.pool
+            .retry(|| async move {
+                // Buffered writes come before the truncation, and no more are
+                // buffered until it's done
+                let mut inodes = self.write_back.inodes.lock().await;
+                self.write_back
+                    .flush_locked(&mut inodes, Some(self.ino))
+                    .await?;
 
-        while let Some(row) = rows.next().await? {
-            let chunk_index = row
-                .get_value(0)
-                .ok()
-                .and_then(|v| v.as_integer().copied())
-                .unwrap_or(0) as u64;
+                let conn = self.pool.get_connection().await?;
+                let txn = conn.begin_write().await?;
 
-            // Fill gaps with zeros for sparse files
-            while next_expected_chunk < chunk_index && result.len() < size as usize {
-                let skip = if next_expected_chunk == start_chunk {
-                    start_offset_in_chunk
+                // Get current size
+                let mut stmt = conn
+                    .prepare_cached("SELECT size FROM fs_inode WHERE ino = ?")
+                    .await?;
+                let mut rows = stmt.query((self.ino,)).await?;
+                let current_size = if let Some(row) = rows.next().await? {
+                    row.get_value(0)
+                        .ok()
+                        .and_then(|v| v.as_integer().copied())
+                        .unwrap_or(0) as u64
                 } else {
                     0
                 };
-                let zeros_needed =
-                    std::cmp::min(chunk_size as usize - skip, size as usize - result.len());
-                result.extend(std::iter::repeat_n(0u8, zeros_needed));
-                next_expected_chunk += 1;
-            }
 
-            if let Ok(Value::Blob(chunk_data)) = row.get_value(1) {
-                let skip = if chunk_index == start_chunk {
-                    start_offset_in_chunk
-                } else {
-                    0
-                };
-                if skip >= chunk_data.len() {
-                    // Chunk is smaller than skip offset, fill with zeros
-                    let zeros_needed =
-                        std::cmp::min(chunk_size as usize - skip, size as usize - result.len());
-                    result.extend(std::iter::repeat_n(0u8, zeros_needed));
-                } else {
-                    let remaining = size as usize - result.len();
-                    let take = std::cmp::min(chunk_data.len() - skip, remaining);
-                    result.extend_from_slice(&chunk_data[skip..skip + take]);
+                let chunk_size = self.chunk_size as u64;
 
-                    // If chunk is smaller than chunk_size, pad with zeros
-                    let chunk_end = skip + take;
-                    if chunk_end < chunk_size as usize && result.len() < size as usize {
-                        let zeros_needed = std::cmp::min(
-                            chunk_size as usize - chunk_end,
-                            size as usize - result.len(),
-                        );
-                        result.extend(std::iter::repeat_n(0u8, zeros_needed));
+                let result: Result<()> = async {
+                    if AgentFS::truncate_inline(&conn, self.ino, self.chunk_size, self.dedup, new_size)
+                        .await?
+                    {
+                        // Small enough to be stored in the inode
+                    } else if new_size == 0 {
+                        // Special case: truncate to zero - just delete all chunks
+                        AgentFS::delete_chunks(&conn, self.ino, 0, i64::MAX).await?;
+                    } else if new_size < current_size {
+                        // Shrinking: delete excess chunks and truncate last chunk if needed
+                        let last_chunk_idx = ((new_size - 1) / chunk_size) as i64;
+
+                        // Delete all chunks beyond the last one we need
+                        AgentFS::delete_chunks(&conn, self.ino, last_chunk_idx + 1, i64::MAX).await?;
+
+                        // Truncate the last chunk if needed
+                        let offset_in_chunk = (new_size % chunk_size) as usize;
+                        if offset_in_chunk > 0 {
+                            if let Some(mut chunk_data) =
+                                AgentFS::read_chunk(&conn, self.ino, last_chunk_idx).await?
+                            {
+                                if chunk_data.len() > offset_in_chunk {
+                                    chunk_data.truncate(offset_in_chunk);
+                                    AgentFS::write_chunk(&conn, self.ino, last_chunk_idx, &chunk_data, self.dedup)
+                                        .await?;
+                                }
+                            }
+                        }
                     }
+                    // For extending (new_size > current_size), we just update the size
+                    // The sparse regions will be handled by pread returning zeros
+
+                    // Update the inode size, mtime, and ctime
+                    let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                    let now_secs = dur.as_secs() as i64;
+                    let now_nsec = dur.subsec_nanos() as i64;
+                    let mut stmt = conn
+                        .prepare_cached("UPDATE fs_inode SET size = ?, mtime = ?, ctime = ?, mtime_nsec = ?, ctime_nsec = ? WHERE ino = ?")
+                        .await?;
+                    stmt.execute((new_size as i64, now_secs, now_secs, now_nsec, now_nsec, self.ino)).await?;
+
+                    Change::new(JournalOp::Truncate)
+                        .ino(self.ino)
+                        .size(new_size)
+                        .record(&conn, self.tool_call())
+                        .await?;
+
+                    Ok(())
                 }
-            }
-            next_expected_chunk = chunk_index + 1;
-        }
+                .await;
 
-        // Fill any remaining space with zeros (for sparse file tail or missing chunks at end)
-        if result.len() < size as usize {
-            result.resize(size as usize, 0);
-        }
+                if result.is_err() {
+                    let _ = txn.rollback().await;
+                    return result;
+                }
+                commit(txn).await?;
 
-        Ok(result)
-    }
-
-    async fn pwrite(&self, offset: u64, data: &[u8]) -> Result<()> {
-        if data.is_empty() {
-            return Ok(());
-        }
-
-        let conn = self.pool.get_connection().await?;
-        let txn = Transaction::new_unchecked(&conn, TransactionBehavior::Immediate).await?;
-        // Get current file size
-        let mut stmt = conn
-            .prepare_cached("SELECT size FROM fs_inode WHERE ino = ?")
-            .await?;
-        let mut rows = stmt.query((self.ino,)).await?;
-        let current_size = if let Some(row) = rows.next().await? {
-            row.get_value(0)
-                .ok()
-                .and_then(|v| v.as_integer().copied())
-                .unwrap_or(0) as u64
-        } else {
-            0
-        };
-
-        // Write the actual data (sparse gaps are handled by pread which fills
-        // missing chunks with zeros, so no need to zero-fill here)
-        self.write_data_at_offset_with_conn(&conn, offset, data)
-            .await?;
-
-        // Update file size and mtime
-        let new_size = std::cmp::max(current_size, offset + data.len() as u64);
-        let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
-        let now_secs = dur.as_secs() as i64;
-        let now_nsec = dur.subsec_nanos() as i64;
-        let mut stmt = conn
-            .prepare_cached("UPDATE fs_inode SET size = ?, mtime = ?, mtime_nsec = ? WHERE ino = ?")
-            .await?;
-        stmt.execute((new_size as i64, now_secs, now_nsec, self.ino))
-            .await?;
-        txn.commit().await?;
-
-        Ok(())
-    }
-
-    async fn truncate(&self, new_size: u64) -> Result<()> {
-        let conn = self.pool.get_connection().await?;
-
-        // Get current size
-        let mut stmt = conn
-            .prepare_cached("SELECT size FROM fs_inode WHERE ino = ?")
-            .await?;
-        let mut rows = stmt.query((self.ino,)).await?;
-        let current_size = if let Some(row) = rows.next().await? {
-            row.get_value(0)
-                .ok()
-                .and_then(|v| v.as_integer().copied())
-                .unwrap_or(0) as u64
-        } else {
-            0
-        };
-
-        let chunk_size = self.chunk_size as u64;
-
-        let txn = Transaction::new_unchecked(&conn, TransactionBehavior::Immediate).await?;
-
-        let result: Result<()> = async {
-            if new_size == 0 {
-                // Special case: truncate to zero - just delete all chunks
-                let mut stmt = conn
-                    .prepare_cached("DELETE FROM fs_data WHERE ino = ?")
-                    .await?;
-                stmt.execute((self.ino,)).await?;
-            } else if new_size < current_size {
-                // Shrinking: delete excess chunks and truncate last chunk if needed
-                let last_chunk_idx = (new_size - 1) / chunk_size;
-
-                // Delete all chunks beyond the last one we need
-                conn.execute(
-                    "DELETE FROM fs_data WHERE ino = ? AND chunk_index > ?",
-                    (self.ino, last_chunk_idx as i64),
-                )
-                .await?;
-
-                // Truncate the last chunk if needed
-                let offset_in_chunk = (new_size % chunk_size) as usize;
-                if offset_in_chunk > 0 {
-                    let mut stmt = conn
-                        .prepare_cached("SELECT data FROM fs_data WHERE ino = ? AND chunk_index = ?")
-                        .await?;
-                    let mut rows = stmt.query((self.ino, last_chunk_idx as i64)).await?;
-
-                    if let Some(row) = rows.next().await? {
-                        if let Ok(Value::Blob(mut chunk_data)) = row.get_value(0) {
-                            if chunk_data.len() > offset_in_chunk {
-                                chunk_data.truncate(offset_in_chunk);
-                                let mut stmt = conn
-                                    .prepare_cached("UPDATE fs_data SET data = ? WHERE ino = ? AND chunk_index = ?")
-                                    .await?;
-                                stmt.execute((Value::Blob(chunk_data), self.ino, last_chunk_idx as i64)).await?;
-                            }
-                        }
-                    }
-                }
-            }
-            // For extending (new_size > current_size), we just update the size
-            // The sparse regions will be handled by pread returning zeros
-
-            // Update the inode size, mtime, and ctime
-            let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
-            let now_secs = dur.as_secs() as i64;
-            let now_nsec = dur.subsec_nanos() as i64;
-            let mut stmt = conn
-                .prepare_cached("UPDATE fs_inode SET size = ?, mtime = ?, ctime = ?, mtime_nsec = ?, ctime_nsec = ? WHERE ino = ?")
-                .await?;
-            stmt.execute((new_size as i64, now_secs, now_secs, now_nsec, now_nsec, self.ino)).await?;
-
-            Ok(())
-        }
-        .await;
-
-        if result.is_err() {
-            let _ = txn.rollback().await;
-            return result;
-        }
-        txn.commit().await?;
-        Ok(())
+                Ok(())
+            })
+            .await
     }
 
     async fn fsync(&self) -> Result<()> {
+        self.write_back.flush(Some(self.ino)).await?;
         let conn = self.pool.get_connection().await?;
         conn.prepare_cached("PRAGMA synchronous = FULL")
             .await?
@@ -323,98 +712,30 @@ impl File for AgentFSFile {
     }
 
     async fn fstat(&self) -> Result<Stats> {
-        let conn = self.pool.get_connection().await?;
+        let conn = self.pool.get_read_connection().await?;
         let mut stmt = conn
             .prepare_cached("SELECT ino, mode, nlink, uid, gid, size, atime, mtime, ctime, rdev, atime_nsec, mtime_nsec, ctime_nsec FROM fs_inode WHERE ino = ?")
             .await?;
         let mut rows = stmt.query((self.ino,)).await?;
 
         if let Some(row) = rows.next().await? {
-            AgentFS::build_stats_from_row(&row)
+            let mut stats = AgentFS::build_stats_from_row(&row)?;
+            self.write_back.apply(&mut stats);
+            Ok(stats)
         } else {
             Err(FsError::NotFound.into())
         }
     }
+
+    async fn flush(&self) -> Result<()> {
+        self.write_back.flush(Some(self.ino)).await
+    }
 }
 
 impl AgentFSFile {
-    /// Write data at a specific offset, handling chunk boundaries.
-    /// Uses a provided connection to allow reuse within a transaction.
-    async fn write_data_at_offset_with_conn(
-        &self,
-        conn: &Connection,
-        offset: u64,
-        data: &[u8],
-    ) -> Result<()> {
-        let chunk_size = self.chunk_size as u64;
-        let mut written = 0usize;
-
-        if data.is_empty() {
-            return Ok(());
-        }
-
-        // get statements only once (in order to avoid heavy clone on every while iteration)
-        let mut select_stmt = conn
-            .prepare_cached("SELECT data FROM fs_data WHERE ino = ? AND chunk_index = ?")
-            .await?;
-        let mut insert_stmt = conn
-            .prepare_cached(
-                "INSERT OR REPLACE INTO fs_data (ino, chunk_index, data) VALUES (?, ?, ?)",
-            )
-            .await?;
-        while written < data.len() {
-            let current_offset = offset + written as u64;
-            let chunk_index = (current_offset / chunk_size) as i64;
-            let offset_in_chunk = (current_offset % chunk_size) as usize;
-
-            // How much can we write in this chunk?
-            let remaining_in_chunk = self.chunk_size - offset_in_chunk;
-            let remaining_data = data.len() - written;
-            let to_write = std::cmp::min(remaining_in_chunk, remaining_data);
-
-            let mut chunk_data;
-            if to_write != chunk_size as usize {
-                // Get existing chunk data (if any)
-                let mut rows = select_stmt.query((self.ino, chunk_index)).await?;
-
-                chunk_data = if let Some(row) = rows.next().await? {
-                    row.get_value(0)
-                        .ok()
-                        .and_then(|v| {
-                            if let Value::Blob(b) = v {
-                                Some(b)
-                            } else {
-                                None
-                            }
-                        })
-                        .unwrap_or_default()
-                } else {
-                    Vec::new()
-                };
-                select_stmt.reset()?;
-
-                // Extend chunk if needed
-                if chunk_data.len() < offset_in_chunk + to_write {
-                    chunk_data.resize(offset_in_chunk + to_write, 0);
-                }
-
-                // Write data into chunk
-                chunk_data[offset_in_chunk..offset_in_chunk + to_write]
-                    .copy_from_slice(&data[written..written + to_write]);
-            } else {
-                chunk_data = data[written..written + to_write].to_vec();
-            }
-
-            // Save chunk
-            insert_stmt
-                .execute((self.ino, chunk_index, Value::Blob(chunk_data)))
-                .await?;
-            insert_stmt.reset()?;
-
-            written += to_write;
-        }
-
-        Ok(())
+    /// Tool call that changes are currently made on behalf of
+    fn tool_call(&self) -> Option<i64> {
+        self.tool_call.load()
     }
 }
 
@@ -435,17 +756,18 @@ impl AgentFS {
         // Disable synchronous mode for filesystem fsync() semantics.
         conn.execute("PRAGMA synchronous = OFF", ()).await?;
 
-        // Set busy timeout to handle concurrent access gracefully.
-        // Without this, concurrent transactions fail immediately with SQLITE_BUSY.
-        conn.execute("PRAGMA busy_timeout = 5000", ()).await?;
-
         // Get chunk_size from config (or use default)
         let chunk_size = Self::read_chunk_size(&conn).await?;
+        let dedup = Self::read_dedup(&conn).await?;
 
+        let dedup = Arc::new(AtomicBool::new(dedup));
         let fs = Self {
+            write_back: Arc::new(WriteBack::new(pool.clone(), chunk_size, dedup.clone())),
             pool,
             chunk_size,
+            dedup,
             dentry_cache: Arc::new(DentryCache::new(DENTRY_CACHE_MAX_SIZE)),
+            tool_call: Arc::new(ToolCall::default()),
         };
         Ok(fs)
     }
@@ -455,6 +777,119 @@ impl AgentFS {
         self.chunk_size
     }
 
+    /// Whether chunk deduplication is enabled
+    pub fn dedup_enabled(&self) -> bool {
+        self.dedup.load(Ordering::Relaxed)
+    }
+
+    /// Set the tool call that subsequent changes are made on behalf of.
+    ///
+    /// Changes recorded in the journal, including writes through files that
+    /// are already open, carry this id until it is cleared with `None`.
+    pub fn set_tool_call(&self, id: Option<i64>) {
+        self.tool_call.id.store(id.unwrap_or(0), Ordering::Relaxed);
+    }
+
+    /// Follow the active tool call in a control file.
+    ///
+    /// The file holds the id of the active tool call, or nothing when no call
+    /// is active. It is read as each change is made, so a change is
+    /// attributed to the id the file held at that moment. Until the file
+    /// exists, the id from `set_tool_call` is used.
+    pub fn set_tool_call_file(&self, path: Option<PathBuf>) {
+        *self.tool_call.control.write().unwrap() = path;
+    }
+
+    /// Get the tool call that changes are currently made on behalf of
+    pub fn tool_call(&self) -> Option<i64> {
+        self.tool_call.load()
+    }
+
+    /// Enable content-addressed chunk deduplication.
+    ///
+    /// From now on every chunk written is stored once in `fs_chunk` under the
+    /// SHA-256 hash of its contents and shared by all chunks with the same
+    /// contents. Chunks already stored inline in `fs_data` are migrated in the
+    /// same transaction. Enabling is persistent and idempotent.
+    pub async fn enable_dedup(&self) -> Result<()> {
+        self.pool
+            .retry(|| async move {
+                let conn = self.pool.get_connection().await?;
+                let txn = conn.begin_write().await?;
+
+                let result: Result<()> = async {
+                    conn.execute(
+                        "INSERT OR REPLACE INTO fs_config (key, value) VALUES ('dedup', '1')",
+                        (),
+                    )
+                    .await?;
+
+                    let mut rows = conn
+                        .query(
+                            "SELECT ino, chunk_index, data FROM fs_data WHERE hash IS NULL",
+                            (),
+                        )
+                        .await?;
+                    let mut chunks = Vec::new();
+                    while let Some(row) = rows.next().await? {
+                        let ino = row
+                            .get_value(0)
+                            .ok()
+                            .and_then(|v| v.as_integer().copied())
+                            .unwrap_or(0);
+                        let chunk_index = row
+                            .get_value(1)
+                            .ok()
+                            .and_then(|v| v.as_integer().copied())
+                            .unwrap_or(0);
+                        let data = match row.get_value(2) {
+                            Ok(Value::Blob(data)) => data,
+                            _ => Vec::new(),
+                        };
+                        chunks.push((ino, chunk_index, data));
+                    }
+
+                    for (ino, chunk_index, data) in chunks {
+                        let hash = chunk_hash(&data);
+                        conn.execute(
+                            "INSERT INTO fs_chunk (hash, data, refcount) VALUES (?, ?, 1)
+                            ON CONFLICT(hash) DO UPDATE SET refcount = refcount + 1",
+                            (hash.as_str(), data),
+                        )
+                        .await?;
+                        conn.execute(
+                            "UPDATE fs_data SET data = X'', hash = ?, checksum = NULL WHERE ino = ? AND chunk_index = ?",
+                            (hash.as_str(), ino, chunk_index),
+                        )
+                        .await?;
+                    }
+                    Ok(())
+                }
+                .await;
+
+                match result {
+                    Ok(()) => {
+                        txn.commit().await?;
+                        self.dedup.store(true, Ordering::Relaxed);
+                        Ok(())
+                    }
+                    Err(e) => {
+                        let _ = txn.rollback().await;
+                        Err(e)
+                    }
+                }
+            })
+            .await
+    }
+
+    /// Drop cached directory entries.
+    ///
+    /// Needed after the tables are rewritten behind the filesystem's back,
+    /// e.g. when a snapshot is restored.
+    pub fn invalidate_cache(&self) {
+        self.dentry_cache.clear();
+    }
+
     /// Get a database connection from the pool
     pub async fn get_connection(&self) -> Result<crate::connection_pool::PooledConnection> {
         self.pool.get_connection().await
@@ -548,6 +983,35 @@ impl AgentFS {
         )
         .await?;
 
+        // Deduplicated chunks reference their contents in fs_chunk by hash and
+        // keep an empty data blob (backward compatible migration)
+        conn.execute("ALTER TABLE fs_data ADD COLUMN hash TEXT", ())
+            .await
+            .ok();
+
+        // Inline chunks carry the SHA-256 of their contents, verified on read
+        // (backward compatible migration; older chunks have no checksum)
+        conn.execute("ALTER TABLE fs_data ADD COLUMN checksum TEXT", ())
+            .await
+            .ok();
+
+        // Small files keep their contents in the inode instead of in fs_data
+        // (backward compatible migration; NULL for files stored in chunks)
+        conn.execute("ALTER TABLE fs_inode ADD COLUMN inline_data BLOB", ())
+            .await
+            .ok();
+
+        // Create content-addressed chunk table
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS fs_chunk (
+                hash TEXT PRIMARY KEY,
+                data BLOB NOT NULL,
+                refcount INTEGER NOT NULL DEFAULT 0
+            )",
+            (),
+        )
+        .await?;
+
         // Create symlink table
         conn.execute(
             "CREATE TABLE IF NOT EXISTS fs_symlink (
@@ -558,6 +1022,75 @@ impl AgentFS {
         )
         .await?;
 
+        // Create extended attributes table
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS fs_xattr (
+                ino INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                value BLOB NOT NULL,
+                PRIMARY KEY (ino, name)
+            )",
+            (),
+        )
+        .await?;
+
+        // Create snapshot tables. Chunk writes copy the previous contents into
+        // fs_snapshot_data, so these have to exist before any data is written.
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS fs_snapshot (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL UNIQUE,
+                created_at INTEGER NOT NULL
+            )",
+            (),
+        )
+        .await?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS fs_snapshot_data (
+                snapshot_id INTEGER NOT NULL,
+                ino INTEGER NOT NULL,
+                chunk_index INTEGER NOT NULL,
+                data BLOB,
+                PRIMARY KEY (snapshot_id, ino, chunk_index)
+            )",
+            (),
+        )
+        .await?;
+
+        // Create the change journal, appended to by every mutation
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS fs_journal (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                timestamp INTEGER NOT NULL,
+                op TEXT NOT NULL,
+                ino INTEGER,
+                path TEXT,
+                new_path TEXT,
+                offset INTEGER,
+                length INTEGER,
+                size INTEGER,
+                detail TEXT,
+                tool_call_id INTEGER
+            )",
+            (),
+        )
+        .await?;
+
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_fs_journal_ino
+            ON fs_journal(ino)",
+            (),
+        )
+        .await?;
+
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_fs_journal_tool_call
+            ON fs_journal(tool_call_id)",
+            (),
+        )
+        .await?;
+
         // Ensure chunk_size config exists
         let mut rows = conn
             .query("SELECT value FROM fs_config WHERE key = 'chunk_size'", ())
@@ -605,7 +1138,7 @@ impl AgentFS {
     }
 
     /// Read chunk size from config
-    async fn read_chunk_size(conn: &Connection) -> Result<usize> {
+    pub(crate) async fn read_chunk_size(conn: &Connection) -> Result<usize> {
         let mut rows = conn
             .query("SELECT value FROM fs_config WHERE key = 'chunk_size'", ())
             .await?;
@@ -626,6 +1159,19 @@ impl AgentFS {
         }
     }
 
+    /// Read the dedup flag from config
+    pub(crate) async fn read_dedup(conn: &Connection) -> Result<bool> {
+        let mut rows = conn
+            .query("SELECT value FROM fs_config WHERE key = 'dedup'", ())
+            .await?;
+
+        if let Some(row) = rows.next().await? {
+            Ok(matches!(row.get_value(0), Ok(Value::Text(s)) if s == "1"))
+        } else {
+            Ok(false)
+        }
+    }
+
     /// Normalize a path
     fn normalize_path(&self, path: &str) -> String {
         let normalized = path.trim_end_matches('/');
@@ -728,50 +1274,412 @@ impl AgentFS {
         }
     }
 
-    /// Get file attributes by inode using an existing connection
-    async fn getattr_with_conn(&self, conn: &Connection, ino: i64) -> Result<Option<Stats>> {
+    /// Return `NotFound` unless the inode exists
+    async fn ensure_inode_exists(&self, conn: &Connection, ino: i64) -> Result<()> {
         let mut stmt = conn
-            .prepare_cached("SELECT ino, mode, nlink, uid, gid, size, atime, mtime, ctime, rdev, atime_nsec, mtime_nsec, ctime_nsec FROM fs_inode WHERE ino = ?")
+            .prepare_cached("SELECT ino FROM fs_inode WHERE ino = ?")
             .await?;
         let mut rows = stmt.query((ino,)).await?;
 
-        if let Some(row) = rows.next().await? {
-            let stats = Self::build_stats_from_row(&row)?;
-            Ok(Some(stats))
-        } else {
-            Ok(None)
+        if rows.next().await?.is_none() {
+            return Err(FsError::NotFound.into());
         }
+        Ok(())
     }
 
-    /// Build a Stats object from a database row
+    /// Save a chunk's current contents into the most recent snapshot before
+    /// it is modified.
     ///
-    /// The row should contain columns in this order:
-    /// ino, mode, nlink, uid, gid, size, atime, mtime, ctime
-    fn build_stats_from_row(row: &turso::Row) -> Result<Stats> {
-        Ok(Stats {
-            ino: row
-                .get_value(0)
-                .ok()
-                .and_then(|v| v.as_integer().copied())
-                .unwrap_or(0),
-            mode: row
-                .get_value(1)
-                .ok()
-                .and_then(|v| v.as_integer().copied())
-                .unwrap_or(0) as u32,
-            nlink: row
-                .get_value(2)
-                .ok()
-                .and_then(|v| v.as_integer().copied())
-                .unwrap_or(1) as u32,
-            uid: row
-                .get_value(3)
-                .ok()
-                .and_then(|v| v.as_integer().copied())
-                .unwrap_or(0) as u32,
-            gid: row
-                .get_value(4)
-                .ok()
+    /// Only the first modification after a snapshot is recorded since the
+    /// snapshot already holds the original after that. A NULL `data` records
+    /// that the chunk did not exist yet. See [`crate::snapshot`].
+    async fn preserve_chunk(conn: &Connection, ino: i64, chunk_index: i64) -> Result<()> {
+        let mut stmt = conn
+            .prepare_cached(
+                "INSERT OR IGNORE INTO fs_snapshot_data (snapshot_id, ino, chunk_index, data)
+                SELECT (SELECT MAX(id) FROM fs_snapshot), ?1, ?2,
+                    (SELECT COALESCE(c.data, d.data) FROM fs_data d
+                    LEFT JOIN fs_chunk c ON c.hash = d.hash
+                    WHERE d.ino = ?1 AND d.chunk_index = ?2)
+                WHERE EXISTS (SELECT 1 FROM fs_snapshot)",
+            )
+            .await?;
+        stmt.execute((ino, chunk_index)).await?;
+        Ok(())
+    }
+
+    /// Save the stored chunks of an inode in `[first_chunk, last_chunk]` into
+    /// the most recent snapshot before deleting them
+    async fn preserve_chunks(
+        conn: &Connection,
+        ino: i64,
+        first_chunk: i64,
+        last_chunk: i64,
+    ) -> Result<()> {
+        let mut stmt = conn
+            .prepare_cached(
+                "INSERT OR IGNORE INTO fs_snapshot_data (snapshot_id, ino, chunk_index, data)
+                SELECT (SELECT MAX(id) FROM fs_snapshot), d.ino, d.chunk_index, COALESCE(c.data, d.data)
+                FROM fs_data d LEFT JOIN fs_chunk c ON c.hash = d.hash
+                WHERE d.ino = ? AND d.chunk_index >= ? AND d.chunk_index <= ?
+                AND EXISTS (SELECT 1 FROM fs_snapshot)",
+            )
+            .await?;
+        stmt.execute((ino, first_chunk, last_chunk)).await?;
+        Ok(())
+    }
+
+    /// Read `size` bytes at `offset` from the stored chunks of a file of
+    /// `file_size` bytes, with holes read as zeros
+    async fn read_range(
+        conn: &Connection,
+        ino: i64,
+        chunk_size: usize,
+        offset: u64,
+        size: u64,
+        file_size: u64,
+    ) -> Result<Vec<u8>> {
+        // If offset is at or beyond EOF, return empty
+        if offset >= file_size {
+            return Ok(Vec::new());
+        }
+
+        // Limit size to not exceed EOF
+        let size = std::cmp::min(size, file_size - offset);
+
+        let chunk_size = chunk_size as u64;
+        let start_chunk = offset / chunk_size;
+        let end_chunk = (offset + size).saturating_sub(1) / chunk_size;
+
+        let mut stmt = conn
+            .prepare_cached("SELECT d.chunk_index, COALESCE(c.data, d.data), COALESCE(d.checksum, d.hash) FROM fs_data d LEFT JOIN fs_chunk c ON c.hash = d.hash WHERE d.ino = ? AND d.chunk_index >= ? AND d.chunk_index <= ? ORDER BY d.chunk_index")
+            .await?;
+        let mut rows = stmt
+            .query((ino, start_chunk as i64, end_chunk as i64))
+            .await?;
+
+        let mut result = Vec::with_capacity(size as usize);
+        let start_offset_in_chunk = (offset % chunk_size) as usize;
+        let mut next_expected_chunk = start_chunk;
+
+        while let Some(row) = rows.next().await? {
+            let chunk_index = row
+                .get_value(0)
+                .ok()
+                .and_then(|v| v.as_integer().copied())
+                .unwrap_or(0) as u64;
+
+            // Fill gaps with zeros for sparse files
+            while next_expected_chunk < chunk_index && result.len() < size as usize {
+                let skip = if next_expected_chunk == start_chunk {
+                    start_offset_in_chunk
+                } else {
+                    0
+                };
+                let zeros_needed =
+                    std::cmp::min(chunk_size as usize - skip, size as usize - result.len());
+                result.extend(std::iter::repeat_n(0u8, zeros_needed));
+                next_expected_chunk += 1;
+            }
+
+            if let Ok(Value::Blob(chunk_data)) = row.get_value(1) {
+                verify_chunk(&chunk_data, row_checksum(&row, 2).as_deref())?;
+                let skip = if chunk_index == start_chunk {
+                    start_offset_in_chunk
+                } else {
+                    0
+                };
+                if skip >= chunk_data.len() {
+                    // Chunk is smaller than skip offset, fill with zeros
+                    let zeros_needed =
+                        std::cmp::min(chunk_size as usize - skip, size as usize - result.len());
+                    result.extend(std::iter::repeat_n(0u8, zeros_needed));
+                } else {
+                    let remaining = size as usize - result.len();
+                    let take = std::cmp::min(chunk_data.len() - skip, remaining);
+                    result.extend_from_slice(&chunk_data[skip..skip + take]);
+
+                    // If chunk is smaller than chunk_size, pad with zeros
+                    let chunk_end = skip + take;
+                    if chunk_end < chunk_size as usize && result.len() < size as usize {
+                        let zeros_needed = std::cmp::min(
+                            chunk_size as usize - chunk_end,
+                            size as usize - result.len(),
+                        );
+                        result.extend(std::iter::repeat_n(0u8, zeros_needed));
+                    }
+                }
+            }
+            next_expected_chunk = chunk_index + 1;
+        }
+
+        // Fill any remaining space with zeros (for sparse file tail or missing chunks at end)
+        if result.len() < size as usize {
+            result.resize(size as usize, 0);
+        }
+
+        Ok(result)
+    }
+
+    /// Read a chunk, whether it is stored inline or deduplicated, verifying
+    /// its checksum
+    pub(crate) async fn read_chunk(
+        conn: &Connection,
+        ino: i64,
+        chunk_index: i64,
+    ) -> Result<Option<Vec<u8>>> {
+        let mut stmt = conn
+            .prepare_cached(
+                "SELECT COALESCE(c.data, d.data), COALESCE(d.checksum, d.hash) FROM fs_data d
+                LEFT JOIN fs_chunk c ON c.hash = d.hash
+                WHERE d.ino = ? AND d.chunk_index = ?",
+            )
+            .await?;
+        let mut rows = stmt.query((ino, chunk_index)).await?;
+
+        if let Some(row) = rows.next().await? {
+            let data = match row.get_value(0) {
+                Ok(Value::Blob(data)) => data,
+                _ => Vec::new(),
+            };
+            verify_chunk(&data, row_checksum(&row, 1).as_deref())?;
+            Ok(Some(data))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Store a chunk, replacing its previous contents.
+    ///
+    /// With `dedup` the contents are stored once in `fs_chunk` under their
+    /// SHA-256 hash and the `fs_data` row only references them; otherwise they
+    /// are stored inline in `fs_data` along with their SHA-256 checksum.
+    pub(crate) async fn write_chunk(
+        conn: &Connection,
+        ino: i64,
+        chunk_index: i64,
+        data: &[u8],
+        dedup: bool,
+    ) -> Result<()> {
+        Self::preserve_chunk(conn, ino, chunk_index).await?;
+        Self::release_chunks(conn, ino, chunk_index, chunk_index).await?;
+
+        if dedup {
+            let hash = chunk_hash(data);
+            let mut stmt = conn
+                .prepare_cached(
+                    "INSERT INTO fs_chunk (hash, data, refcount) VALUES (?, ?, 1)
+                    ON CONFLICT(hash) DO UPDATE SET refcount = refcount + 1",
+                )
+                .await?;
+            stmt.execute((hash.as_str(), data)).await?;
+            let mut stmt = conn
+                .prepare_cached(
+                    "INSERT OR REPLACE INTO fs_data (ino, chunk_index, data, hash, checksum) VALUES (?, ?, X'', ?, NULL)",
+                )
+                .await?;
+            stmt.execute((ino, chunk_index, hash.as_str())).await?;
+        } else {
+            let checksum = chunk_hash(data);
+            let mut stmt = conn
+                .prepare_cached(
+                    "INSERT OR REPLACE INTO fs_data (ino, chunk_index, data, hash, checksum) VALUES (?, ?, ?, NULL, ?)",
+                )
+                .await?;
+            stmt.execute((ino, chunk_index, data, checksum.as_str()))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Delete the chunks of an inode in `[first_chunk, last_chunk]`
+    pub(crate) async fn delete_chunks(
+        conn: &Connection,
+        ino: i64,
+        first_chunk: i64,
+        last_chunk: i64,
+    ) -> Result<()> {
+        Self::preserve_chunks(conn, ino, first_chunk, last_chunk).await?;
+        Self::release_chunks(conn, ino, first_chunk, last_chunk).await?;
+
+        let mut stmt = conn
+            .prepare_cached(
+                "DELETE FROM fs_data WHERE ino = ? AND chunk_index >= ? AND chunk_index <= ?",
+            )
+            .await?;
+        stmt.execute((ino, first_chunk, last_chunk)).await?;
+        Ok(())
+    }
+
+    /// Read the contents of a file stored in its inode, or `None` if it is
+    /// stored in chunks
+    pub(crate) async fn read_inline(conn: &Connection, ino: i64) -> Result<Option<Vec<u8>>> {
+        let mut stmt = conn
+            .prepare_cached("SELECT inline_data FROM fs_inode WHERE ino = ?")
+            .await?;
+        let mut rows = stmt.query((ino,)).await?;
+        match rows.next().await? {
+            Some(row) => match row.get_value(0) {
+                Ok(Value::Blob(data)) => Ok(Some(data)),
+                _ => Ok(None),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Store the contents of a file in its inode, or mark it as stored in
+    /// chunks with `None`
+    async fn set_inline(conn: &Connection, ino: i64, data: Option<&[u8]>) -> Result<()> {
+        let data = data.map_or(Value::Null, |data| Value::Blob(data.to_vec()));
+        let mut stmt = conn
+            .prepare_cached("UPDATE fs_inode SET inline_data = ? WHERE ino = ?")
+            .await?;
+        stmt.execute((data, ino)).await?;
+        Ok(())
+    }
+
+    /// Take the contents of a file that is about to be stored in its inode,
+    /// deleting its chunks if it was stored in them.
+    ///
+    /// The file must fit in its first chunk.
+    async fn take_small_contents(conn: &Connection, ino: i64) -> Result<Vec<u8>> {
+        if let Some(data) = Self::read_inline(conn, ino).await? {
+            return Ok(data);
+        }
+        let data = Self::read_chunk(conn, ino, 0).await?.unwrap_or_default();
+        Self::delete_chunks(conn, ino, 0, i64::MAX).await?;
+        Ok(data)
+    }
+
+    /// Move the contents of a file stored in its inode into its first chunk,
+    /// before it is modified chunk by chunk
+    async fn promote_inline(conn: &Connection, ino: i64, dedup: bool) -> Result<()> {
+        if let Some(data) = Self::read_inline(conn, ino).await? {
+            if !data.is_empty() {
+                Self::write_chunk(conn, ino, 0, &data, dedup).await?;
+            }
+            Self::set_inline(conn, ino, None).await?;
+        }
+        Ok(())
+    }
+
+    /// Truncate a file stored in its inode, or that is small enough to be
+    /// once truncated to `new_size`.
+    ///
+    /// Returns whether the file is now stored in its inode. Otherwise its
+    /// contents are in chunks, ready to be truncated as usual.
+    async fn truncate_inline(
+        conn: &Connection,
+        ino: i64,
+        chunk_size: usize,
+        dedup: bool,
+        new_size: u64,
+    ) -> Result<bool> {
+        if !stores_inline(new_size, chunk_size) {
+            if new_size == 0 {
+                Self::set_inline(conn, ino, None).await?;
+            } else {
+                Self::promote_inline(conn, ino, dedup).await?;
+            }
+            return Ok(false);
+        }
+        let mut data = Self::take_small_contents(conn, ino).await?;
+        data.resize(new_size as usize, 0);
+        Self::set_inline(conn, ino, Some(&data)).await?;
+        Ok(true)
+    }
+
+    /// Drop the `fs_chunk` references held by an inode's chunks in
+    /// `[first_chunk, last_chunk]`, deleting contents no longer referenced
+    async fn release_chunks(
+        conn: &Connection,
+        ino: i64,
+        first_chunk: i64,
+        last_chunk: i64,
+    ) -> Result<()> {
+        let mut stmt = conn
+            .prepare_cached(
+                "SELECT hash FROM fs_data
+                WHERE ino = ? AND chunk_index >= ? AND chunk_index <= ? AND hash IS NOT NULL",
+            )
+            .await?;
+        let mut rows = stmt.query((ino, first_chunk, last_chunk)).await?;
+        let mut hashes = Vec::new();
+        while let Some(row) = rows.next().await? {
+            if let Ok(Value::Text(hash)) = row.get_value(0) {
+                hashes.push(hash);
+            }
+        }
+
+        for hash in hashes {
+            let mut stmt = conn
+                .prepare_cached("UPDATE fs_chunk SET refcount = refcount - 1 WHERE hash = ?")
+                .await?;
+            stmt.execute((hash.as_str(),)).await?;
+            let mut stmt = conn
+                .prepare_cached("DELETE FROM fs_chunk WHERE hash = ? AND refcount <= 0")
+                .await?;
+            stmt.execute((hash.as_str(),)).await?;
+        }
+        Ok(())
+    }
+
+    /// Validate an extended attribute name
+    fn check_xattr_name(name: &str) -> Result<()> {
+        if name.is_empty() {
+            return Err(FsError::InvalidPath.into());
+        }
+        if name.len() > XATTR_NAME_MAX {
+            return Err(FsError::NameTooLong.into());
+        }
+        Ok(())
+    }
+
+    /// Get file attributes by inode using an existing connection
+    async fn getattr_with_conn(&self, conn: &Connection, ino: i64) -> Result<Option<Stats>> {
+        let mut stmt = conn
+            .prepare_cached("SELECT ino, mode, nlink, uid, gid, size, atime, mtime, ctime, rdev, atime_nsec, mtime_nsec, ctime_nsec FROM fs_inode WHERE ino = ?")
+            .await?;
+        let mut rows = stmt.query((ino,)).await?;
+
+        if let Some(row) = rows.next().await? {
+            let mut stats = Self::build_stats_from_row(&row)?;
+            self.write_back.apply(&mut stats);
+            Ok(Some(stats))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Build a Stats object from a database row
+    ///
+    /// The row should contain columns in this order:
+    /// ino, mode, nlink, uid, gid, size, atime, mtime, ctime
+    fn build_stats_from_row(row: &turso::Row) -> Result<Stats> {
+        Ok(Stats {
+            ino: row
+                .get_value(0)
+                .ok()
+                .and_then(|v| v.as_integer().copied())
+                .unwrap_or(0),
+            mode: row
+                .get_value(1)
+                .ok()
+                .and_then(|v| v.as_integer().copied())
+                .unwrap_or(0) as u32,
+            nlink: row
+                .get_value(2)
+                .ok()
+                .and_then(|v| v.as_integer().copied())
+                .unwrap_or(1) as u32,
+            uid: row
+                .get_value(3)
+                .ok()
+                .and_then(|v| v.as_integer().copied())
+                .unwrap_or(0) as u32,
+            gid: row
+                .get_value(4)
+                .ok()
                 .and_then(|v| v.as_integer().copied())
                 .unwrap_or(0) as u32,
             size: row
@@ -819,7 +1727,7 @@ impl AgentFS {
 
     /// Resolve a path to an inode number
     async fn resolve_path(&self, path: &str) -> Result<Option<i64>> {
-        let conn = self.pool.get_connection().await?;
+        let conn = self.pool.get_read_connection().await?;
         self.resolve_path_with_conn(&conn, path).await
     }
 
@@ -830,6 +1738,7 @@ impl AgentFS {
             return Ok(Some(ROOT_INO));
         }
 
+        let generation = self.dentry_cache.generation();
         let mut statement: Option<turso::Statement> = None;
         let mut current_ino = ROOT_INO;
         for component in components {
@@ -873,7 +1782,8 @@ impl AgentFS {
                     .unwrap_or(0);
 
                 // Populate cache
-                self.dentry_cache.insert(current_ino, &component, child_ino);
+                self.dentry_cache
+                    .insert_since(generation, current_ino, &component, child_ino);
                 current_ino = child_ino;
             } else {
                 return Ok(None);
@@ -885,7 +1795,7 @@ impl AgentFS {
 
     /// Get file statistics without following symlinks
     pub async fn lstat(&self, path: &str) -> Result<Option<Stats>> {
-        let conn = self.pool.get_connection().await?;
+        let conn = self.pool.get_read_connection().await?;
         let path = self.normalize_path(path);
         let ino = match self.resolve_path_with_conn(&conn, &path).await? {
             Some(ino) => ino,
@@ -898,7 +1808,8 @@ impl AgentFS {
         let mut rows = stmt.query((ino,)).await?;
 
         if let Some(row) = rows.next().await? {
-            let stats = Self::build_stats_from_row(&row)?;
+            let mut stats = Self::build_stats_from_row(&row)?;
+            self.write_back.apply(&mut stats);
             Ok(Some(stats))
         } else {
             Ok(None)
@@ -907,7 +1818,7 @@ impl AgentFS {
 
     /// Get file statistics, following symlinks
     pub async fn stat(&self, path: &str) -> Result<Option<Stats>> {
-        let conn = self.pool.get_connection().await?;
+        let conn = self.pool.get_read_connection().await?;
         let path = self.normalize_path(path);
 
         // Follow symlinks with a maximum depth to prevent infinite loops
@@ -956,7 +1867,8 @@ impl AgentFS {
                 }
 
                 // Not a symlink, return the stats
-                let stats = Self::build_stats_from_row(&row)?;
+                let mut stats = Self::build_stats_from_row(&row)?;
+                self.write_back.apply(&mut stats);
                 return Ok(Some(stats));
             } else {
                 return Ok(None);
@@ -1018,7 +1930,8 @@ impl AgentFS {
                 }
 
                 // Not a symlink, return the stats
-                let stats = Self::build_stats_from_row(&row)?;
+                let mut stats = Self::build_stats_from_row(&row)?;
+                self.write_back.apply(&mut stats);
                 return Ok(Some(stats));
             } else {
                 return Ok(None);
@@ -1031,164 +1944,190 @@ impl AgentFS {
 
     /// Create a directory
     pub async fn mkdir(&self, path: &str, uid: u32, gid: u32) -> Result<()> {
-        let conn = self.pool.get_connection().await?;
-        let path = self.normalize_path(path);
-        let components = self.split_path(&path);
+        self.pool
+            .retry(|| async move {
+                let conn = self.pool.get_connection().await?;
+                let txn = conn.begin_write().await?;
+                let path = self.normalize_path(path);
+                let components = self.split_path(&path);
+
+                if components.is_empty() {
+                    return Err(FsError::RootOperation.into());
+                }
 
-        if components.is_empty() {
-            return Err(FsError::RootOperation.into());
-        }
+                let parent_path = if components.len() == 1 {
+                    "/".to_string()
+                } else {
+                    format!("/{}", components[..components.len() - 1].join("/"))
+                };
 
-        let parent_path = if components.len() == 1 {
-            "/".to_string()
-        } else {
-            format!("/{}", components[..components.len() - 1].join("/"))
-        };
+                let parent_ino = self
+                    .resolve_path_with_conn(&conn, &parent_path)
+                    .await?
+                    .ok_or(FsError::NotFound)?;
 
-        let parent_ino = self
-            .resolve_path_with_conn(&conn, &parent_path)
-            .await?
-            .ok_or(FsError::NotFound)?;
+                let name = components.last().unwrap();
 
-        let name = components.last().unwrap();
+                // Check if already exists (single query using parent_ino we already have)
+                if self.lookup_child(&conn, parent_ino, name).await?.is_some() {
+                    return Err(FsError::AlreadyExists.into());
+                }
 
-        // Check if already exists (single query using parent_ino we already have)
-        if self.lookup_child(&conn, parent_ino, name).await?.is_some() {
-            return Err(FsError::AlreadyExists.into());
-        }
+                // Create inode with default directory mode (path-based API doesn't accept mode)
+                let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                let now_secs = dur.as_secs() as i64;
+                let now_nsec = dur.subsec_nanos() as i64;
+                let mut stmt = conn
+                    .prepare_cached(
+                        "INSERT INTO fs_inode (mode, uid, gid, size, atime, mtime, ctime, atime_nsec, mtime_nsec, ctime_nsec)
+                        VALUES (?, ?, ?, 0, ?, ?, ?, ?, ?, ?) RETURNING ino",
+                    )
+                    .await?;
+                let row = stmt
+                    .query_row((
+                        DEFAULT_DIR_MODE as i64,
+                        uid,
+                        gid,
+                        now_secs,
+                        now_secs,
+                        now_secs,
+                        now_nsec,
+                        now_nsec,
+                        now_nsec,
+                    ))
+                    .await?;
 
-        // Create inode with default directory mode (path-based API doesn't accept mode)
-        let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
-        let now_secs = dur.as_secs() as i64;
-        let now_nsec = dur.subsec_nanos() as i64;
-        let mut stmt = conn
-            .prepare_cached(
-                "INSERT INTO fs_inode (mode, uid, gid, size, atime, mtime, ctime, atime_nsec, mtime_nsec, ctime_nsec)
-                VALUES (?, ?, ?, 0, ?, ?, ?, ?, ?, ?) RETURNING ino",
-            )
-            .await?;
-        let row = stmt
-            .query_row((
-                DEFAULT_DIR_MODE as i64,
-                uid,
-                gid,
-                now_secs,
-                now_secs,
-                now_secs,
-                now_nsec,
-                now_nsec,
-                now_nsec,
-            ))
-            .await?;
+                let ino = row
+                    .get_value(0)
+                    .ok()
+                    .and_then(|v| v.as_integer().copied())
+                    .ok_or_else(|| Error::Internal("failed to get inode".to_string()))?;
 
-        let ino = row
-            .get_value(0)
-            .ok()
-            .and_then(|v| v.as_integer().copied())
-            .ok_or_else(|| Error::Internal("failed to get inode".to_string()))?;
+                // Create directory entry
+                let mut stmt = conn
+                    .prepare_cached("INSERT INTO fs_dentry (name, parent_ino, ino) VALUES (?, ?, ?)")
+                    .await?;
+                stmt.execute((name.as_str(), parent_ino, ino)).await?;
 
-        // Create directory entry
-        let mut stmt = conn
-            .prepare_cached("INSERT INTO fs_dentry (name, parent_ino, ino) VALUES (?, ?, ?)")
-            .await?;
-        stmt.execute((name.as_str(), parent_ino, ino)).await?;
+                // Set nlink to 2 for new directory (self "." + parent's dentry)
+                let mut stmt = conn
+                    .prepare_cached("UPDATE fs_inode SET nlink = 2 WHERE ino = ?")
+                    .await?;
+                stmt.execute((ino,)).await?;
 
-        // Set nlink to 2 for new directory (self "." + parent's dentry)
-        let mut stmt = conn
-            .prepare_cached("UPDATE fs_inode SET nlink = 2 WHERE ino = ?")
-            .await?;
-        stmt.execute((ino,)).await?;
+                // Increment parent nlink (new directory's ".." link) and update timestamps
+                let mut stmt = conn
+                    .prepare_cached(
+                        "UPDATE fs_inode SET nlink = nlink + 1, ctime = ?, mtime = ?, ctime_nsec = ?, mtime_nsec = ? WHERE ino = ?",
+                    )
+                    .await?;
+                stmt.execute((now_secs, now_secs, now_nsec, now_nsec, parent_ino))
+                    .await?;
 
-        // Increment parent nlink (new directory's ".." link) and update timestamps
-        let mut stmt = conn
-            .prepare_cached(
-                "UPDATE fs_inode SET nlink = nlink + 1, ctime = ?, mtime = ?, ctime_nsec = ?, mtime_nsec = ? WHERE ino = ?",
-            )
-            .await?;
-        stmt.execute((now_secs, now_secs, now_nsec, now_nsec, parent_ino))
-            .await?;
+                Change::new(JournalOp::Mkdir)
+                    .ino(ino)
+                    .path(path.as_str())
+                    .record(&conn, self.tool_call())
+                    .await?;
 
-        // Populate dentry cache
-        self.dentry_cache.insert(parent_ino, name, ino);
+                commit(txn).await?;
 
-        Ok(())
+                // Populate dentry cache
+                self.dentry_cache.insert(parent_ino, name, ino);
+
+                Ok(())
+            })
+            .await
     }
 
     /// Create a special file node (FIFO, device, socket, or regular file)
     pub async fn mknod(&self, path: &str, mode: u32, rdev: u64, uid: u32, gid: u32) -> Result<()> {
-        let conn = self.pool.get_connection().await?;
-        let path = self.normalize_path(path);
-        let components = self.split_path(&path);
+        self.pool
+            .retry(|| async move {
+                let conn = self.pool.get_connection().await?;
+                let txn = conn.begin_write().await?;
+                let path = self.normalize_path(path);
+                let components = self.split_path(&path);
+
+                if components.is_empty() {
+                    return Err(FsError::RootOperation.into());
+                }
 
-        if components.is_empty() {
-            return Err(FsError::RootOperation.into());
-        }
+                let parent_path = if components.len() == 1 {
+                    "/".to_string()
+                } else {
+                    format!("/{}", components[..components.len() - 1].join("/"))
+                };
 
-        let parent_path = if components.len() == 1 {
-            "/".to_string()
-        } else {
-            format!("/{}", components[..components.len() - 1].join("/"))
-        };
+                let parent_ino = self
+                    .resolve_path_with_conn(&conn, &parent_path)
+                    .await?
+                    .ok_or(FsError::NotFound)?;
 
-        let parent_ino = self
-            .resolve_path_with_conn(&conn, &parent_path)
-            .await?
-            .ok_or(FsError::NotFound)?;
+                let name = components.last().unwrap();
 
-        let name = components.last().unwrap();
+                // Check if already exists
+                if self.lookup_child(&conn, parent_ino, name).await?.is_some() {
+                    return Err(FsError::AlreadyExists.into());
+                }
 
-        // Check if already exists
-        if self.lookup_child(&conn, parent_ino, name).await?.is_some() {
-            return Err(FsError::AlreadyExists.into());
-        }
+                // Create inode with mode and rdev
+                let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                let now_secs = dur.as_secs() as i64;
+                let now_nsec = dur.subsec_nanos() as i64;
+                let mut stmt = conn
+                    .prepare_cached(
+                        "INSERT INTO fs_inode (mode, uid, gid, size, atime, mtime, ctime, rdev, atime_nsec, mtime_nsec, ctime_nsec)
+                        VALUES (?, ?, ?, 0, ?, ?, ?, ?, ?, ?, ?) RETURNING ino",
+                    )
+                    .await?;
+                let row = stmt
+                    .query_row((
+                        mode as i64,
+                        uid,
+                        gid,
+                        now_secs,
+                        now_secs,
+                        now_secs,
+                        rdev as i64,
+                        now_nsec,
+                        now_nsec,
+                        now_nsec,
+                    ))
+                    .await?;
 
-        // Create inode with mode and rdev
-        let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
-        let now_secs = dur.as_secs() as i64;
-        let now_nsec = dur.subsec_nanos() as i64;
-        let mut stmt = conn
-            .prepare_cached(
-                "INSERT INTO fs_inode (mode, uid, gid, size, atime, mtime, ctime, rdev, atime_nsec, mtime_nsec, ctime_nsec)
-                VALUES (?, ?, ?, 0, ?, ?, ?, ?, ?, ?, ?) RETURNING ino",
-            )
-            .await?;
-        let row = stmt
-            .query_row((
-                mode as i64,
-                uid,
-                gid,
-                now_secs,
-                now_secs,
-                now_secs,
-                rdev as i64,
-                now_nsec,
-                now_nsec,
-                now_nsec,
-            ))
-            .await?;
+                let ino = row
+                    .get_value(0)
+                    .ok()
+                    .and_then(|v| v.as_integer().copied())
+                    .ok_or_else(|| Error::Internal("failed to get inode".to_string()))?;
 
-        let ino = row
-            .get_value(0)
-            .ok()
-            .and_then(|v| v.as_integer().copied())
-            .ok_or_else(|| Error::Internal("failed to get inode".to_string()))?;
+                // Create directory entry
+                let mut stmt = conn
+                    .prepare_cached("INSERT INTO fs_dentry (name, parent_ino, ino) VALUES (?, ?, ?)")
+                    .await?;
+                stmt.execute((name.as_str(), parent_ino, ino)).await?;
 
-        // Create directory entry
-        let mut stmt = conn
-            .prepare_cached("INSERT INTO fs_dentry (name, parent_ino, ino) VALUES (?, ?, ?)")
-            .await?;
-        stmt.execute((name.as_str(), parent_ino, ino)).await?;
+                // Increment link count
+                let mut stmt = conn
+                    .prepare_cached("UPDATE fs_inode SET nlink = nlink + 1 WHERE ino = ?")
+                    .await?;
+                stmt.execute((ino,)).await?;
 
-        // Increment link count
-        let mut stmt = conn
-            .prepare_cached("UPDATE fs_inode SET nlink = nlink + 1 WHERE ino = ?")
-            .await?;
-        stmt.execute((ino,)).await?;
+                Change::new(JournalOp::Mknod)
+                    .ino(ino)
+                    .path(path.as_str())
+                    .record(&conn, self.tool_call())
+                    .await?;
 
-        // Populate dentry cache
-        self.dentry_cache.insert(parent_ino, name, ino);
+                commit(txn).await?;
 
-        Ok(())
+                // Populate dentry cache
+                self.dentry_cache.insert(parent_ino, name, ino);
+
+                Ok(())
+            })
+            .await
     }
 
     /// Create a new empty file with the specified mode and ownership.
@@ -1203,112 +2142,136 @@ impl AgentFS {
         uid: u32,
         gid: u32,
     ) -> Result<(Stats, BoxedFile)> {
-        let conn = self.pool.get_connection().await?;
-        let path = self.normalize_path(path);
-        let components = self.split_path(&path);
-
-        if components.is_empty() {
-            return Err(FsError::RootOperation.into());
-        }
-
-        let parent_path = match components.len() {
-            1 => "/".to_string(),
-            _ => format!("/{}", components[..components.len() - 1].join("/")),
-        };
+        self.pool
+            .retry(|| async move {
+                let conn = self.pool.get_connection().await?;
+                let path = self.normalize_path(path);
+                let components = self.split_path(&path);
+
+                if components.is_empty() {
+                    return Err(FsError::RootOperation.into());
+                }
 
-        let parent_ino = self
-            .resolve_path_with_conn(&conn, &parent_path)
-            .await?
-            .ok_or(FsError::NotFound)?;
+                let parent_path = match components.len() {
+                    1 => "/".to_string(),
+                    _ => format!("/{}", components[..components.len() - 1].join("/")),
+                };
 
-        let name = components.last().unwrap();
+                let parent_ino = self
+                    .resolve_path_with_conn(&conn, &parent_path)
+                    .await?
+                    .ok_or(FsError::NotFound)?;
 
-        if self.lookup_child(&conn, parent_ino, name).await?.is_some() {
-            return Err(FsError::AlreadyExists.into());
-        }
+                let name = components.last().unwrap();
 
-        // Prepare statements before starting the transaction
-        let mut inode_stmt = conn
-            .prepare_cached(
-                "INSERT INTO fs_inode (mode, nlink, uid, gid, size, atime, mtime, ctime, atime_nsec, mtime_nsec, ctime_nsec)
-                 VALUES (?, 1, ?, ?, 0, ?, ?, ?, ?, ?, ?) RETURNING ino",
-            )
-            .await?;
-        let mut dentry_stmt = conn
-            .prepare_cached("INSERT INTO fs_dentry (name, parent_ino, ino) VALUES (?, ?, ?)")
-            .await?;
+                if self.lookup_child(&conn, parent_ino, name).await?.is_some() {
+                    return Err(FsError::AlreadyExists.into());
+                }
 
-        let txn = Transaction::new_unchecked(&conn, TransactionBehavior::Immediate).await?;
+                // Prepare statements before starting the transaction
+                let mut inode_stmt = conn
+                    .prepare_cached(
+                        "INSERT INTO fs_inode (mode, nlink, uid, gid, size, atime, mtime, ctime, atime_nsec, mtime_nsec, ctime_nsec)
+                         VALUES (?, 1, ?, ?, 0, ?, ?, ?, ?, ?, ?) RETURNING ino",
+                    )
+                    .await?;
+                let mut dentry_stmt = conn
+                    .prepare_cached("INSERT INTO fs_dentry (name, parent_ino, ino) VALUES (?, ?, ?)")
+                    .await?;
 
-        let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
-        let now_secs = dur.as_secs() as i64;
-        let now_nsec = dur.subsec_nanos() as i64;
-        let file_mode = S_IFREG | (mode & 0o7777);
-
-        let row = inode_stmt
-            .query_row((
-                file_mode as i64,
-                uid,
-                gid,
-                now_secs,
-                now_secs,
-                now_secs,
-                now_nsec,
-                now_nsec,
-                now_nsec,
-            ))
-            .await?;
+                let txn = conn.begin_write().await?;
 
-        let ino = row
-            .get_value(0)
-            .ok()
-            .and_then(|v| v.as_integer().copied())
-            .ok_or_else(|| Error::Internal("failed to get inode".to_string()))?;
+                let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                let now_secs = dur.as_secs() as i64;
+                let now_nsec = dur.subsec_nanos() as i64;
+                let file_mode = S_IFREG | (mode & 0o7777);
+
+                let row = inode_stmt
+                    .query_row((
+                        file_mode as i64,
+                        uid,
+                        gid,
+                        now_secs,
+                        now_secs,
+                        now_secs,
+                        now_nsec,
+                        now_nsec,
+                        now_nsec,
+                    ))
+                    .await?;
 
-        dentry_stmt
-            .execute((name.as_str(), parent_ino, ino))
-            .await?;
+                let ino = row
+                    .get_value(0)
+                    .ok()
+                    .and_then(|v| v.as_integer().copied())
+                    .ok_or_else(|| Error::Internal("failed to get inode".to_string()))?;
 
-        txn.commit().await?;
+                dentry_stmt
+                    .execute((name.as_str(), parent_ino, ino))
+                    .await?;
 
-        self.dentry_cache.insert(parent_ino, name, ino);
+                Change::new(JournalOp::Create)
+                    .ino(ino)
+                    .path(path.as_str())
+                    .record(&conn, self.tool_call())
+                    .await?;
 
-        let stats = Stats {
-            ino,
-            mode: file_mode,
-            nlink: 1,
-            uid,
-            gid,
-            size: 0,
-            atime: now_secs,
-            mtime: now_secs,
-            ctime: now_secs,
-            atime_nsec: now_nsec as u32,
-            mtime_nsec: now_nsec as u32,
-            ctime_nsec: now_nsec as u32,
-            rdev: 0,
-        };
+                commit(txn).await?;
+
+                self.dentry_cache.insert(parent_ino, name, ino);
+
+                let stats = Stats {
+                    ino,
+                    mode: file_mode,
+                    nlink: 1,
+                    uid,
+                    gid,
+                    size: 0,
+                    atime: now_secs,
+                    mtime: now_secs,
+                    ctime: now_secs,
+                    atime_nsec: now_nsec as u32,
+                    mtime_nsec: now_nsec as u32,
+                    ctime_nsec: now_nsec as u32,
+                    rdev: 0,
+                };
 
-        let file: BoxedFile = Arc::new(AgentFSFile {
-            pool: self.pool.clone(),
-            ino,
-            chunk_size: self.chunk_size,
-        });
+                let file: BoxedFile = Arc::new(AgentFSFile {
+                    pool: self.pool.clone(),
+                    ino,
+                    chunk_size: self.chunk_size,
+                    dedup: self.dedup_enabled(),
+                    tool_call: self.tool_call.clone(),
+                    write_back: self.write_back.clone(),
+                });
 
-        Ok((stats, file))
+                Ok((stats, file))
+            })
+            .await
     }
 
     /// Read data from a file
     pub async fn read_file(&self, path: &str) -> Result<Option<Vec<u8>>> {
-        let conn = self.pool.get_connection().await?;
+        let conn = self.pool.get_read_connection().await?;
         let ino = match self.resolve_path_with_conn(&conn, path).await? {
             Some(ino) => ino,
             None => return Ok(None),
         };
+        // Without readers the read connection is the writer, which the flush
+        // needs
+        drop(conn);
+        self.write_back.flush(Some(ino)).await?;
+        let conn = self.pool.get_read_connection().await?;
+
+        if let Some(data) = Self::read_inline(&conn, ino).await? {
+            return Ok(Some(data));
+        }
 
         let mut rows = conn
             .query(
-                "SELECT data FROM fs_data WHERE ino = ? ORDER BY chunk_index",
+                "SELECT COALESCE(c.data, d.data), COALESCE(d.checksum, d.hash) FROM fs_data d
+                LEFT JOIN fs_chunk c ON c.hash = d.hash
+                WHERE d.ino = ? ORDER BY d.chunk_index",
                 (ino,),
             )
             .await?;
@@ -1316,6 +2279,7 @@ impl AgentFS {
         let mut data = Vec::new();
         while let Some(row) = rows.next().await? {
             if let Ok(Value::Blob(chunk)) = row.get_value(0) {
+                verify_chunk(&chunk, row_checksum(&row, 1).as_deref())?;
                 data.extend_from_slice(&chunk);
             }
         }
@@ -1330,11 +2294,20 @@ impl AgentFS {
     ///
     /// Returns `Ok(None)` if the file does not exist.
     pub async fn pread(&self, path: &str, offset: u64, size: u64) -> Result<Option<Vec<u8>>> {
-        let conn = self.pool.get_connection().await?;
+        let conn = self.pool.get_read_connection().await?;
         let ino = match self.resolve_path_with_conn(&conn, path).await? {
             Some(ino) => ino,
             None => return Ok(None),
         };
+        // Without readers the read connection is the writer, which the flush
+        // needs
+        drop(conn);
+        self.write_back.flush(Some(ino)).await?;
+        let conn = self.pool.get_read_connection().await?;
+
+        if let Some(data) = Self::read_inline(&conn, ino).await? {
+            return Ok(Some(read_inline_range(&data, offset, size)));
+        }
 
         // Calculate which chunks we need
         let chunk_size = self.chunk_size as u64;
@@ -1343,7 +2316,7 @@ impl AgentFS {
 
         let mut rows = conn
             .query(
-                "SELECT chunk_index, data FROM fs_data WHERE ino = ? AND chunk_index >= ? AND chunk_index <= ? ORDER BY chunk_index",
+                "SELECT d.chunk_index, COALESCE(c.data, d.data), COALESCE(d.checksum, d.hash) FROM fs_data d LEFT JOIN fs_chunk c ON c.hash = d.hash WHERE d.ino = ? AND d.chunk_index >= ? AND d.chunk_index <= ? ORDER BY d.chunk_index",
                 (ino, start_chunk as i64, end_chunk as i64),
             )
             .await?;
@@ -1353,6 +2326,7 @@ impl AgentFS {
 
         while let Some(row) = rows.next().await? {
             if let Ok(Value::Blob(chunk_data)) = row.get_value(1) {
+                verify_chunk(&chunk_data, row_checksum(&row, 2).as_deref())?;
                 let skip = if result.is_empty() {
                     start_offset_in_chunk
                 } else {
@@ -1378,201 +2352,219 @@ impl AgentFS {
     /// If the offset is beyond the current file size, the file is extended with zeros.
     /// If the file does not exist, it will be created.
     pub async fn pwrite(&self, path: &str, offset: u64, data: &[u8]) -> Result<()> {
-        let conn = self.pool.get_connection().await?;
-        let path = self.normalize_path(path);
-        let components = self.split_path(&path);
-
-        if components.is_empty() {
-            return Err(FsError::RootOperation.into());
-        }
-
-        let parent_path = if components.len() == 1 {
-            "/".to_string()
-        } else {
-            format!("/{}", components[..components.len() - 1].join("/"))
-        };
-
-        let parent_ino = self
-            .resolve_path_with_conn(&conn, &parent_path)
-            .await?
-            .ok_or(FsError::NotFound)?;
-
-        let name = components.last().unwrap();
-
-        let txn = Transaction::new_unchecked(&conn, TransactionBehavior::Immediate).await?;
-
-        let result: Result<()> = async {
-            // Calculate the final size upfront
-            let write_end = offset + data.len() as u64;
+        self.pool
+            .retry(|| async move {
+                // Keep writes through open files from being buffered meanwhile
+                let mut buffered = self.write_back.inodes.lock().await;
+                self.write_back.flush_locked(&mut buffered, None).await?;
+                let conn = self.pool.get_connection().await?;
+                let path = self.normalize_path(path);
+                let components = self.split_path(&path);
+
+                if components.is_empty() {
+                    return Err(FsError::RootOperation.into());
+                }
 
-            // Get or create the inode
-            let (ino, current_size, is_new) =
-                if let Some(ino) = self.resolve_path_with_conn(&conn, &path).await? {
-                    // Get current file size
-                    let mut stmt = conn
-                        .prepare_cached("SELECT size FROM fs_inode WHERE ino = ?")
-                        .await?;
-                    let mut rows = stmt.query((ino,)).await?;
-                    let size = if let Some(row) = rows.next().await? {
-                        row.get_value(0)
-                            .ok()
-                            .and_then(|v| v.as_integer().copied())
-                            .unwrap_or(0) as u64
-                    } else {
-                        0
-                    };
-                    (ino, size, false)
+                let parent_path = if components.len() == 1 {
+                    "/".to_string()
                 } else {
-                    // Create new inode with correct size upfront
-                    let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
-                    let now_secs = dur.as_secs() as i64;
-                    let now_nsec = dur.subsec_nanos() as i64;
-                    let new_size = write_end as i64;
-                    let mut stmt = conn
-                        .prepare_cached(
-                            "INSERT INTO fs_inode (mode, uid, gid, size, atime, mtime, ctime, nlink, atime_nsec, mtime_nsec, ctime_nsec)
-                        VALUES (?, 0, 0, ?, ?, ?, ?, 1, ?, ?, ?) RETURNING ino",
-                        )
-                        .await?;
-                    let row = stmt
-                        .query_row((DEFAULT_FILE_MODE as i64, new_size, now_secs, now_secs, now_secs, now_nsec, now_nsec, now_nsec))
-                        .await?;
-
-                    let ino = row
-                        .get_value(0)
-                        .ok()
-                        .and_then(|v| v.as_integer().copied())
-                        .ok_or_else(|| Error::Internal("failed to get inode".to_string()))?;
-
-                    // Create directory entry
-                    let mut stmt = conn
-                        .prepare_cached(
-                            "INSERT INTO fs_dentry (name, parent_ino, ino) VALUES (?, ?, ?)",
-                        )
-                        .await?;
-                    stmt.execute((name.as_str(), parent_ino, ino)).await?;
-
-                    (ino, 0, true)
+                    format!("/{}", components[..components.len() - 1].join("/"))
                 };
 
-            // Handle empty writes - just update mtime
-            if data.is_empty() {
-                let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
-                let now_secs = dur.as_secs() as i64;
-                let now_nsec = dur.subsec_nanos() as i64;
-                conn.prepare_cached("UPDATE fs_inode SET mtime = ?, mtime_nsec = ? WHERE ino = ?")
+                let parent_ino = self
+                    .resolve_path_with_conn(&conn, &parent_path)
                     .await?
-                    .execute((now_secs, now_nsec, ino))
-                    .await?;
-                return Ok(());
-            }
+                    .ok_or(FsError::NotFound)?;
+
+                let name = components.last().unwrap();
+
+                let txn = conn.begin_write().await?;
+
+                let result: Result<()> = async {
+                    // Calculate the final size upfront
+                    let write_end = offset + data.len() as u64;
+
+                    // Get or create the inode
+                    let (ino, current_size, is_new) =
+                        if let Some(ino) = self.resolve_path_with_conn(&conn, &path).await? {
+                            // Get current file size
+                            let mut stmt = conn
+                                .prepare_cached("SELECT size FROM fs_inode WHERE ino = ?")
+                                .await?;
+                            let mut rows = stmt.query((ino,)).await?;
+                            let size = if let Some(row) = rows.next().await? {
+                                row.get_value(0)
+                                    .ok()
+                                    .and_then(|v| v.as_integer().copied())
+                                    .unwrap_or(0) as u64
+                            } else {
+                                0
+                            };
+                            (ino, size, false)
+                        } else {
+                            // Create new inode with correct size upfront
+                            let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                            let now_secs = dur.as_secs() as i64;
+                            let now_nsec = dur.subsec_nanos() as i64;
+                            let new_size = write_end as i64;
+                            let mut stmt = conn
+                                .prepare_cached(
+                                    "INSERT INTO fs_inode (mode, uid, gid, size, atime, mtime, ctime, nlink, atime_nsec, mtime_nsec, ctime_nsec)
+                                VALUES (?, 0, 0, ?, ?, ?, ?, 1, ?, ?, ?) RETURNING ino",
+                                )
+                                .await?;
+                            let row = stmt
+                                .query_row((DEFAULT_FILE_MODE as i64, new_size, now_secs, now_secs, now_secs, now_nsec, now_nsec, now_nsec))
+                                .await?;
+
+                            let ino = row
+                                .get_value(0)
+                                .ok()
+                                .and_then(|v| v.as_integer().copied())
+                                .ok_or_else(|| Error::Internal("failed to get inode".to_string()))?;
+
+                            // Create directory entry
+                            let mut stmt = conn
+                                .prepare_cached(
+                                    "INSERT INTO fs_dentry (name, parent_ino, ino) VALUES (?, ?, ?)",
+                                )
+                                .await?;
+                            stmt.execute((name.as_str(), parent_ino, ino)).await?;
+
+                            Change::new(JournalOp::Create)
+                                .ino(ino)
+                                .path(path.as_str())
+                                .record(&conn, self.tool_call())
+                                .await?;
+
+                            (ino, 0, true)
+                        };
+
+                    // Handle empty writes - just update mtime
+                    if data.is_empty() {
+                        let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                        let now_secs = dur.as_secs() as i64;
+                        let now_nsec = dur.subsec_nanos() as i64;
+                        conn.prepare_cached("UPDATE fs_inode SET mtime = ?, mtime_nsec = ? WHERE ino = ?")
+                            .await?
+                            .execute((now_secs, now_nsec, ino))
+                            .await?;
+                        return Ok(());
+                    }
+
+                    let dedup = self.dedup_enabled();
+                    let new_size = std::cmp::max(current_size, write_end);
+
+                    if stores_inline(new_size, self.chunk_size) {
+                        // Small enough to be stored in the inode
+                        let mut contents = if is_new {
+                            Vec::new()
+                        } else {
+                            Self::take_small_contents(&conn, ino).await?
+                        };
+                        contents.resize(new_size as usize, 0);
+                        contents[offset as usize..write_end as usize].copy_from_slice(data);
+                        Self::set_inline(&conn, ino, Some(&contents)).await?;
+                    } else {
+                        if !is_new {
+                            Self::promote_inline(&conn, ino, dedup).await?;
+                        }
+                        let chunk_size = self.chunk_size as u64;
 
-            let chunk_size = self.chunk_size as u64;
+                        // Calculate affected chunk range
+                        let start_chunk = offset / chunk_size;
+                        let end_chunk = (write_end - 1) / chunk_size;
 
-            // Calculate affected chunk range
-            let start_chunk = offset / chunk_size;
-            let end_chunk = (write_end - 1) / chunk_size;
+                        // Process each affected chunk
+                        for chunk_idx in start_chunk..=end_chunk {
+                            let chunk_start = chunk_idx * chunk_size;
 
-            // Process each affected chunk
-            for chunk_idx in start_chunk..=end_chunk {
-                let chunk_start = chunk_idx * chunk_size;
+                            // Calculate what part of data goes into this chunk
+                            let data_start = if offset > chunk_start {
+                                (offset - chunk_start) as usize
+                            } else {
+                                0
+                            };
+                            let data_end =
+                                std::cmp::min(chunk_size as usize, (write_end - chunk_start) as usize);
 
-                // Calculate what part of data goes into this chunk
-                let data_start = if offset > chunk_start {
-                    (offset - chunk_start) as usize
-                } else {
-                    0
-                };
-                let data_end =
-                    std::cmp::min(chunk_size as usize, (write_end - chunk_start) as usize);
+                            // Calculate what part of data to copy
+                            let src_start = if chunk_start > offset {
+                                (chunk_start - offset) as usize
+                            } else {
+                                0
+                            };
+                            let src_end = std::cmp::min(data.len(), src_start + (data_end - data_start));
+
+                            // Read existing chunk if we need to preserve some data
+                            let needs_read = data_start > 0 || data_end < chunk_size as usize;
+                            let mut chunk_data = if needs_read {
+                                let mut v = Self::read_chunk(&conn, ino, chunk_idx as i64)
+                                    .await?
+                                    .unwrap_or_default();
+                                v.resize(chunk_size as usize, 0);
+                                v
+                            } else {
+                                vec![0u8; chunk_size as usize]
+                            };
 
-                // Calculate what part of data to copy
-                let src_start = if chunk_start > offset {
-                    (chunk_start - offset) as usize
-                } else {
-                    0
-                };
-                let src_end = std::cmp::min(data.len(), src_start + (data_end - data_start));
+                            // Copy the new data into the chunk
+                            chunk_data[data_start..data_end].copy_from_slice(&data[src_start..src_end]);
+
+                            // Trim trailing zeros for the last chunk
+                            let actual_len = if chunk_idx == end_chunk {
+                                let file_end_in_chunk = (write_end - chunk_start) as usize;
+                                let old_end_in_chunk = if current_size > chunk_start {
+                                    std::cmp::min((current_size - chunk_start) as usize, chunk_size as usize)
+                                } else {
+                                    0
+                                };
+                                std::cmp::max(file_end_in_chunk, old_end_in_chunk)
+                            } else {
+                                chunk_size as usize
+                            };
 
-                // Read existing chunk if we need to preserve some data
-                let needs_read = data_start > 0 || data_end < chunk_size as usize;
-                let mut chunk_data = if needs_read {
-                    let mut rows = conn
-                        .query(
-                            "SELECT data FROM fs_data WHERE ino = ? AND chunk_index = ?",
-                            (ino, chunk_idx as i64),
-                        )
-                        .await?;
-                    if let Some(row) = rows.next().await? {
-                        if let Ok(Value::Blob(data)) = row.get_value(0) {
-                            let mut v = data.clone();
-                            v.resize(chunk_size as usize, 0);
-                            v
-                        } else {
-                            vec![0u8; chunk_size as usize]
+                            // Write the chunk, replacing any existing one
+                            Self::write_chunk(&conn, ino, chunk_idx as i64, &chunk_data[..actual_len], dedup)
+                                .await?;
                         }
-                    } else {
-                        vec![0u8; chunk_size as usize]
                     }
-                } else {
-                    vec![0u8; chunk_size as usize]
-                };
-
-                // Copy the new data into the chunk
-                chunk_data[data_start..data_end].copy_from_slice(&data[src_start..src_end]);
-
-                // Trim trailing zeros for the last chunk
-                let actual_len = if chunk_idx == end_chunk {
-                    let file_end_in_chunk = (write_end - chunk_start) as usize;
-                    let old_end_in_chunk = if current_size > chunk_start {
-                        std::cmp::min((current_size - chunk_start) as usize, chunk_size as usize)
-                    } else {
-                        0
-                    };
-                    std::cmp::max(file_end_in_chunk, old_end_in_chunk)
-                } else {
-                    chunk_size as usize
-                };
 
-                // Write the chunk - delete existing then insert
-                conn.execute(
-                    "DELETE FROM fs_data WHERE ino = ? AND chunk_index = ?",
-                    (ino, chunk_idx as i64),
-                )
-                .await?;
-                conn.execute(
-                    "INSERT INTO fs_data (ino, chunk_index, data) VALUES (?, ?, ?)",
-                    (ino, chunk_idx as i64, &chunk_data[..actual_len]),
-                )
-                .await?;
-            }
+                    // Update size and mtime (only if not new, since new inodes already have correct values)
+                    if !is_new {
+                        let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                        let now_secs = dur.as_secs() as i64;
+                        let now_nsec = dur.subsec_nanos() as i64;
+                        let mut stmt = conn
+                            .prepare_cached("UPDATE fs_inode SET size = ?, mtime = ?, mtime_nsec = ? WHERE ino = ?")
+                            .await?;
+                        stmt.execute((new_size as i64, now_secs, now_nsec, ino)).await?;
+                    }
 
-            // Update size and mtime (only if not new, since new inodes already have correct values)
-            if !is_new {
-                let new_size = std::cmp::max(current_size, write_end);
-                let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
-                let now_secs = dur.as_secs() as i64;
-                let now_nsec = dur.subsec_nanos() as i64;
-                let mut stmt = conn
-                    .prepare_cached("UPDATE fs_inode SET size = ?, mtime = ?, mtime_nsec = ? WHERE ino = ?")
-                    .await?;
-                stmt.execute((new_size as i64, now_secs, now_nsec, ino)).await?;
-            }
+                    Change::new(JournalOp::Write)
+                        .ino(ino)
+                        .path(path.as_str())
+                        .range(offset, data.len())
+                        .size(new_size)
+                        .record(&conn, self.tool_call())
+                        .await?;
 
-            Ok(())
-        }
-        .await;
+                    Ok(())
+                }
+                .await;
 
-        match result {
-            Ok(()) => {
-                txn.commit().await?;
-                Ok(())
-            }
-            Err(e) => {
-                let _ = txn.rollback().await;
-                Err(e)
-            }
-        }
+                match result {
+                    Ok(()) => {
+                        commit(txn).await?;
+                        Ok(())
+                    }
+                    Err(e) => {
+                        let _ = txn.rollback().await;
+                        Err(e)
+                    }
+                }
+            })
+            .await
     }
 
     /// Truncate a file to a specific size.
@@ -1581,157 +2573,159 @@ impl AgentFS {
     /// - Shrinking: deletes chunks beyond new size, truncates the last chunk if needed
     /// - Extending: pads with zeros up to the new size
     pub async fn truncate(&self, path: &str, new_size: u64) -> Result<()> {
-        let conn = self.pool.get_connection().await?;
-        let path = self.normalize_path(path);
-        let ino = self
-            .resolve_path_with_conn(&conn, &path)
-            .await?
-            .ok_or(FsError::NotFound)?;
+        self.pool
+            .retry(|| async move {
+                // Keep writes through open files from being buffered meanwhile
+                let mut buffered = self.write_back.inodes.lock().await;
+                self.write_back.flush_locked(&mut buffered, None).await?;
+                let conn = self.pool.get_connection().await?;
+                let path = self.normalize_path(path);
+                let ino = self
+                    .resolve_path_with_conn(&conn, &path)
+                    .await?
+                    .ok_or(FsError::NotFound)?;
 
-        // Get current size
-        let mut stmt = conn
-            .prepare_cached("SELECT size FROM fs_inode WHERE ino = ?")
-            .await?;
-        let mut rows = stmt.query((ino,)).await?;
-        let current_size = if let Some(row) = rows.next().await? {
-            row.get_value(0)
-                .ok()
-                .and_then(|v| v.as_integer().copied())
-                .unwrap_or(0) as u64
-        } else {
-            0
-        };
-
-        let chunk_size = self.chunk_size as u64;
-
-        let txn = Transaction::new_unchecked(&conn, TransactionBehavior::Immediate).await?;
-
-        let result: Result<()> = async {
-            if new_size == 0 {
-                // Special case: truncate to zero - just delete all chunks
+                // Get current size
                 let mut stmt = conn
-                    .prepare_cached("DELETE FROM fs_data WHERE ino = ?")
+                    .prepare_cached("SELECT size FROM fs_inode WHERE ino = ?")
                     .await?;
-                stmt.execute((ino,)).await?;
-            } else if new_size < current_size {
-                // Shrinking: delete excess chunks and truncate last chunk if needed
-                let last_chunk_idx = (new_size - 1) / chunk_size;
-
-                // Delete all chunks beyond the last one we need
-                conn.execute(
-                    "DELETE FROM fs_data WHERE ino = ? AND chunk_index > ?",
-                    (ino, last_chunk_idx as i64),
-                )
-                .await?;
-
-                // Calculate where in the last chunk the file should end
-                let end_in_last_chunk = ((new_size - 1) % chunk_size) + 1;
+                let mut rows = stmt.query((ino,)).await?;
+                let current_size = if let Some(row) = rows.next().await? {
+                    row.get_value(0)
+                        .ok()
+                        .and_then(|v| v.as_integer().copied())
+                        .unwrap_or(0) as u64
+                } else {
+                    0
+                };
 
-                // If the last chunk needs to be truncated (not a full chunk),
-                // read it, truncate, and rewrite
-                if end_in_last_chunk < chunk_size {
-                    let mut stmt = conn
-                        .prepare_cached("SELECT data FROM fs_data WHERE ino = ? AND chunk_index = ?")
-                        .await?;
-                    let mut rows = stmt.query((ino, last_chunk_idx as i64)).await?;
-
-                    if let Some(row) = rows.next().await? {
-                        if let Ok(Value::Blob(chunk_data)) = row.get_value(0) {
-                            if chunk_data.len() > end_in_last_chunk as usize {
-                                let truncated = &chunk_data[..end_in_last_chunk as usize];
-                                let mut stmt = conn
-                                    .prepare_cached("UPDATE fs_data SET data = ? WHERE ino = ? AND chunk_index = ?")
+                let chunk_size = self.chunk_size as u64;
+                let dedup = self.dedup_enabled();
+
+                let txn = conn.begin_write().await?;
+
+                let result: Result<()> = async {
+                    if Self::truncate_inline(&conn, ino, self.chunk_size, dedup, new_size).await? {
+                        // Small enough to be stored in the inode
+                    } else if new_size == 0 {
+                        // Special case: truncate to zero - just delete all chunks
+                        Self::delete_chunks(&conn, ino, 0, i64::MAX).await?;
+                    } else if new_size < current_size {
+                        // Shrinking: delete excess chunks and truncate last chunk if needed
+                        let last_chunk_idx = (new_size - 1) / chunk_size;
+
+                        // Delete all chunks beyond the last one we need
+                        Self::delete_chunks(&conn, ino, last_chunk_idx as i64 + 1, i64::MAX)
+                            .await?;
+
+                        // Calculate where in the last chunk the file should end
+                        let end_in_last_chunk = ((new_size - 1) % chunk_size) + 1;
+
+                        // If the last chunk needs to be truncated (not a full chunk),
+                        // read it, truncate, and rewrite
+                        if end_in_last_chunk < chunk_size {
+                            if let Some(chunk_data) =
+                                Self::read_chunk(&conn, ino, last_chunk_idx as i64).await?
+                            {
+                                if chunk_data.len() > end_in_last_chunk as usize {
+                                    let truncated = &chunk_data[..end_in_last_chunk as usize];
+                                    Self::write_chunk(
+                                        &conn,
+                                        ino,
+                                        last_chunk_idx as i64,
+                                        truncated,
+                                        dedup,
+                                    )
                                     .await?;
-                                stmt.execute((truncated, ino, last_chunk_idx as i64)).await?;
+                                }
+                            }
+                        }
+                    } else if new_size > current_size {
+                        // Extending: pad last existing chunk and add zero chunks as needed
+                        let last_existing_chunk = if current_size == 0 {
+                            None
+                        } else {
+                            Some((current_size - 1) / chunk_size)
+                        };
+                        let last_new_chunk = (new_size - 1) / chunk_size;
+
+                        // Pad the last existing chunk with zeros if it's not full
+                        if let Some(last_idx) = last_existing_chunk {
+                            if let Some(chunk_data) =
+                                Self::read_chunk(&conn, ino, last_idx as i64).await?
+                            {
+                                let current_chunk_len = chunk_data.len();
+                                let needed_len = if last_idx == last_new_chunk {
+                                    // Last existing chunk is also the last new chunk
+                                    ((new_size - 1) % chunk_size + 1) as usize
+                                } else {
+                                    // Need to fill this chunk completely
+                                    chunk_size as usize
+                                };
+
+                                if needed_len > current_chunk_len {
+                                    let mut padded = chunk_data;
+                                    padded.resize(needed_len, 0);
+                                    Self::write_chunk(&conn, ino, last_idx as i64, &padded, dedup)
+                                        .await?;
+                                }
                             }
                         }
-                    }
-                }
-            } else if new_size > current_size {
-                // Extending: pad last existing chunk and add zero chunks as needed
-                let last_existing_chunk = if current_size == 0 {
-                    None
-                } else {
-                    Some((current_size - 1) / chunk_size)
-                };
-                let last_new_chunk = (new_size - 1) / chunk_size;
-
-                // Pad the last existing chunk with zeros if it's not full
-                if let Some(last_idx) = last_existing_chunk {
-                    let mut stmt = conn
-                        .prepare_cached("SELECT data FROM fs_data WHERE ino = ? AND chunk_index = ?")
-                        .await?;
-                    let mut rows = stmt.query((ino, last_idx as i64)).await?;
 
-                    if let Some(row) = rows.next().await? {
-                        if let Ok(Value::Blob(chunk_data)) = row.get_value(0) {
-                            let current_chunk_len = chunk_data.len();
-                            let needed_len = if last_idx == last_new_chunk {
-                                // Last existing chunk is also the last new chunk
+                        // Add new zero-filled chunks if needed
+                        let start_new_chunk = last_existing_chunk.map(|i| i + 1).unwrap_or(0);
+                        for chunk_idx in start_new_chunk..=last_new_chunk {
+                            let chunk_len = if chunk_idx == last_new_chunk {
                                 ((new_size - 1) % chunk_size + 1) as usize
                             } else {
-                                // Need to fill this chunk completely
                                 chunk_size as usize
                             };
-
-                            if needed_len > current_chunk_len {
-                                let mut padded = chunk_data.clone();
-                                padded.resize(needed_len, 0);
-                                let mut stmt = conn
-                                    .prepare_cached("UPDATE fs_data SET data = ? WHERE ino = ? AND chunk_index = ?")
-                                    .await?;
-                                stmt.execute((&padded[..], ino, last_idx as i64)).await?;
-                            }
+                            let zeros = vec![0u8; chunk_len];
+                            Self::write_chunk(&conn, ino, chunk_idx as i64, &zeros, dedup).await?;
                         }
                     }
-                }
+                    // else: new_size == current_size, nothing to do for data
 
-                // Add new zero-filled chunks if needed
-                let start_new_chunk = last_existing_chunk.map(|i| i + 1).unwrap_or(0);
-                for chunk_idx in start_new_chunk..=last_new_chunk {
-                    let chunk_len = if chunk_idx == last_new_chunk {
-                        ((new_size - 1) % chunk_size + 1) as usize
-                    } else {
-                        chunk_size as usize
-                    };
-                    let zeros = vec![0u8; chunk_len];
-                    conn.execute(
-                        "INSERT INTO fs_data (ino, chunk_index, data) VALUES (?, ?, ?)",
-                        (ino, chunk_idx as i64, &zeros[..]),
-                    )
-                    .await?;
-                }
-            }
-            // else: new_size == current_size, nothing to do for data
+                    // Update size and mtime
+                    let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                    let now_secs = dur.as_secs() as i64;
+                    let now_nsec = dur.subsec_nanos() as i64;
+                    let mut stmt = conn
+                        .prepare_cached(
+                            "UPDATE fs_inode SET size = ?, mtime = ?, mtime_nsec = ? WHERE ino = ?",
+                        )
+                        .await?;
+                    stmt.execute((new_size as i64, now_secs, now_nsec, ino))
+                        .await?;
 
-            // Update size and mtime
-            let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
-            let now_secs = dur.as_secs() as i64;
-            let now_nsec = dur.subsec_nanos() as i64;
-            let mut stmt = conn
-                .prepare_cached("UPDATE fs_inode SET size = ?, mtime = ?, mtime_nsec = ? WHERE ino = ?")
-                .await?;
-            stmt.execute((new_size as i64, now_secs, now_nsec, ino)).await?;
+                    Change::new(JournalOp::Truncate)
+                        .ino(ino)
+                        .path(path.as_str())
+                        .size(new_size)
+                        .record(&conn, self.tool_call())
+                        .await?;
 
-            Ok(())
-        }
-        .await;
+                    Ok(())
+                }
+                .await;
 
-        match result {
-            Ok(()) => {
-                txn.commit().await?;
-                Ok(())
-            }
-            Err(e) => {
-                let _ = txn.rollback().await;
-                Err(e)
-            }
-        }
+                match result {
+                    Ok(()) => {
+                        commit(txn).await?;
+                        Ok(())
+                    }
+                    Err(e) => {
+                        let _ = txn.rollback().await;
+                        Err(e)
+                    }
+                }
+            })
+            .await
     }
 
     /// List directory contents
     pub async fn readdir(&self, ino: i64) -> Result<Option<Vec<String>>> {
-        let conn = self.pool.get_connection().await?;
+        let conn = self.pool.get_read_connection().await?;
         let mut rows = conn
             .query(
                 "SELECT name FROM fs_dentry WHERE parent_ino = ? ORDER BY name",
@@ -1764,14 +2758,15 @@ impl AgentFS {
     ///
     /// Returns entries with their stats in a single JOIN query, avoiding N+1 queries.
     pub async fn readdir_plus(&self, ino: i64) -> Result<Option<Vec<DirEntry>>> {
-        let conn = self.pool.get_connection().await?;
+        let conn = self.pool.get_read_connection().await?;
         let mut stmt = conn.prepare_cached("SELECT d.name, i.ino, i.mode, i.nlink, i.uid, i.gid, i.size, i.atime, i.mtime, i.ctime, i.rdev, i.atime_nsec, i.mtime_nsec, i.ctime_nsec
             FROM fs_dentry d
             JOIN fs_inode i ON d.ino = i.ino
             WHERE d.parent_ino = ?
             ORDER BY d.name"
         ).await?;
-        // Single JOIN query to get all entry names and their stats (including link count)
+        // Single JOIN query to get all entry names and their stats (including
+        // link count), leaving out the contents of files stored in their inode
         let mut rows = stmt.query((ino,)).await?;
 
         let mut entries = Vec::new();
@@ -1804,7 +2799,7 @@ impl AgentFS {
                 .and_then(|v| v.as_integer().copied())
                 .unwrap_or(1) as u32;
 
-            let stats = Stats {
+            let mut stats = Stats {
                 ino: entry_ino,
                 mode: row
                     .get_value(2)
@@ -1863,6 +2858,7 @@ impl AgentFS {
                     .and_then(|v| v.as_integer().copied())
                     .unwrap_or(0) as u64,
             };
+            self.write_back.apply(&mut stats);
 
             entries.push(DirEntry { name, stats });
         }
@@ -1872,85 +2868,99 @@ impl AgentFS {
 
     /// Create a symbolic link with the specified ownership
     pub async fn symlink(&self, target: &str, linkpath: &str, uid: u32, gid: u32) -> Result<()> {
-        let conn = self.pool.get_connection().await?;
-        let linkpath = self.normalize_path(linkpath);
-        let components = self.split_path(&linkpath);
+        self.pool
+            .retry(|| async move {
+                let conn = self.pool.get_connection().await?;
+                let txn = conn.begin_write().await?;
+                let linkpath = self.normalize_path(linkpath);
+                let components = self.split_path(&linkpath);
+
+                if components.is_empty() {
+                    return Err(FsError::RootOperation.into());
+                }
 
-        if components.is_empty() {
-            return Err(FsError::RootOperation.into());
-        }
+                // Get parent directory
+                let parent_path = if components.len() == 1 {
+                    "/".to_string()
+                } else {
+                    format!("/{}", components[..components.len() - 1].join("/"))
+                };
 
-        // Get parent directory
-        let parent_path = if components.len() == 1 {
-            "/".to_string()
-        } else {
-            format!("/{}", components[..components.len() - 1].join("/"))
-        };
+                let parent_ino = self
+                    .resolve_path_with_conn(&conn, &parent_path)
+                    .await?
+                    .ok_or(FsError::NotFound)?;
 
-        let parent_ino = self
-            .resolve_path_with_conn(&conn, &parent_path)
-            .await?
-            .ok_or(FsError::NotFound)?;
+                let name = components.last().unwrap();
 
-        let name = components.last().unwrap();
+                // Check if entry already exists (single query using parent_ino we already have)
+                if self.lookup_child(&conn, parent_ino, name).await?.is_some() {
+                    return Err(FsError::AlreadyExists.into());
+                }
 
-        // Check if entry already exists (single query using parent_ino we already have)
-        if self.lookup_child(&conn, parent_ino, name).await?.is_some() {
-            return Err(FsError::AlreadyExists.into());
-        }
+                // Create inode for symlink
+                let dur = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+                let now_secs = dur.as_secs() as i64;
+                let now_nsec = dur.subsec_nanos() as i64;
 
-        // Create inode for symlink
-        let dur = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-        let now_secs = dur.as_secs() as i64;
-        let now_nsec = dur.subsec_nanos() as i64;
+                let mode = S_IFLNK | 0o777; // Symlinks typically have 777 permissions
+                let size = target.len() as i64;
 
-        let mode = S_IFLNK | 0o777; // Symlinks typically have 777 permissions
-        let size = target.len() as i64;
+                let mut stmt = conn
+                    .prepare_cached(
+                        "INSERT INTO fs_inode (mode, uid, gid, size, atime, mtime, ctime, atime_nsec, mtime_nsec, ctime_nsec)
+                         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING ino",
+                    )
+                    .await?;
+                let row = stmt
+                    .query_row((
+                        mode, uid, gid, size, now_secs, now_secs, now_secs, now_nsec, now_nsec, now_nsec,
+                    ))
+                    .await?;
 
-        let mut stmt = conn
-            .prepare_cached(
-                "INSERT INTO fs_inode (mode, uid, gid, size, atime, mtime, ctime, atime_nsec, mtime_nsec, ctime_nsec)
-                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING ino",
-            )
-            .await?;
-        let row = stmt
-            .query_row((
-                mode, uid, gid, size, now_secs, now_secs, now_secs, now_nsec, now_nsec, now_nsec,
-            ))
-            .await?;
+                // Get the newly created inode
+                let ino = row
+                    .get_value(0)
+                    .ok()
+                    .and_then(|v| v.as_integer().copied())
+                    .unwrap_or(0);
 
-        // Get the newly created inode
-        let ino = row
-            .get_value(0)
-            .ok()
-            .and_then(|v| v.as_integer().copied())
-            .unwrap_or(0);
+                // Store symlink target
+                conn.execute(
+                    "INSERT INTO fs_symlink (ino, target) VALUES (?, ?)",
+                    (ino, target),
+                )
+                .await?;
 
-        // Store symlink target
-        conn.execute(
-            "INSERT INTO fs_symlink (ino, target) VALUES (?, ?)",
-            (ino, target),
-        )
-        .await?;
+                // Create directory entry
+                conn.execute(
+                    "INSERT INTO fs_dentry (name, parent_ino, ino) VALUES (?, ?, ?)",
+                    (name.as_str(), parent_ino, ino),
+                )
+                .await?;
 
-        // Create directory entry
-        conn.execute(
-            "INSERT INTO fs_dentry (name, parent_ino, ino) VALUES (?, ?, ?)",
-            (name.as_str(), parent_ino, ino),
-        )
-        .await?;
+                // Increment link count
+                conn.execute(
+                    "UPDATE fs_inode SET nlink = nlink + 1 WHERE ino = ?",
+                    (ino,),
+                )
+                .await?;
 
-        // Increment link count
-        conn.execute(
-            "UPDATE fs_inode SET nlink = nlink + 1 WHERE ino = ?",
-            (ino,),
-        )
-        .await?;
+                Change::new(JournalOp::Symlink)
+                    .ino(ino)
+                    .path(linkpath.as_str())
+                    .detail(target)
+                    .record(&conn, self.tool_call())
+                    .await?;
 
-        // Populate dentry cache
-        self.dentry_cache.insert(parent_ino, name, ino);
+                commit(txn).await?;
 
-        Ok(())
+                // Populate dentry cache
+                self.dentry_cache.insert(parent_ino, name, ino);
+
+                Ok(())
+            })
+            .await
     }
 
     /// Create a hard link
@@ -1959,82 +2969,96 @@ impl AgentFS {
     /// Both paths will share the same file data and metadata (except for the name).
     /// The link count (nlink) of the inode is incremented.
     pub async fn link(&self, oldpath: &str, newpath: &str) -> Result<()> {
-        let conn = self.pool.get_connection().await?;
-        let oldpath = self.normalize_path(oldpath);
-        let newpath = self.normalize_path(newpath);
-        let components = self.split_path(&newpath);
+        self.pool
+            .retry(|| async move {
+                let conn = self.pool.get_connection().await?;
+                let txn = conn.begin_write().await?;
+                let oldpath = self.normalize_path(oldpath);
+                let newpath = self.normalize_path(newpath);
+                let components = self.split_path(&newpath);
+
+                if components.is_empty() {
+                    return Err(FsError::RootOperation.into());
+                }
 
-        if components.is_empty() {
-            return Err(FsError::RootOperation.into());
-        }
+                // Resolve old path to get its inode
+                let ino = self
+                    .resolve_path_with_conn(&conn, &oldpath)
+                    .await?
+                    .ok_or(FsError::NotFound)?;
 
-        // Resolve old path to get its inode
-        let ino = self
-            .resolve_path_with_conn(&conn, &oldpath)
-            .await?
-            .ok_or(FsError::NotFound)?;
+                // Check if source is a directory (hard links to directories are not allowed)
+                let mut rows = conn
+                    .query("SELECT mode FROM fs_inode WHERE ino = ?", (ino,))
+                    .await?;
 
-        // Check if source is a directory (hard links to directories are not allowed)
-        let mut rows = conn
-            .query("SELECT mode FROM fs_inode WHERE ino = ?", (ino,))
-            .await?;
+                if let Some(row) = rows.next().await? {
+                    let mode = row
+                        .get_value(0)
+                        .ok()
+                        .and_then(|v| v.as_integer().copied())
+                        .unwrap_or(0) as u32;
 
-        if let Some(row) = rows.next().await? {
-            let mode = row
-                .get_value(0)
-                .ok()
-                .and_then(|v| v.as_integer().copied())
-                .unwrap_or(0) as u32;
+                    if (mode & S_IFMT) == super::S_IFDIR {
+                        return Err(FsError::IsADirectory.into());
+                    }
+                } else {
+                    return Err(FsError::NotFound.into());
+                }
 
-            if (mode & S_IFMT) == super::S_IFDIR {
-                return Err(FsError::IsADirectory.into());
-            }
-        } else {
-            return Err(FsError::NotFound.into());
-        }
+                // Get parent directory of new path
+                let parent_path = if components.len() == 1 {
+                    "/".to_string()
+                } else {
+                    format!("/{}", components[..components.len() - 1].join("/"))
+                };
 
-        // Get parent directory of new path
-        let parent_path = if components.len() == 1 {
-            "/".to_string()
-        } else {
-            format!("/{}", components[..components.len() - 1].join("/"))
-        };
+                let parent_ino = self
+                    .resolve_path_with_conn(&conn, &parent_path)
+                    .await?
+                    .ok_or(FsError::NotFound)?;
 
-        let parent_ino = self
-            .resolve_path_with_conn(&conn, &parent_path)
-            .await?
-            .ok_or(FsError::NotFound)?;
+                let name = components.last().unwrap();
 
-        let name = components.last().unwrap();
+                // Check if new path already exists (single query using parent_ino we already have)
+                if self.lookup_child(&conn, parent_ino, name).await?.is_some() {
+                    return Err(FsError::AlreadyExists.into());
+                }
 
-        // Check if new path already exists (single query using parent_ino we already have)
-        if self.lookup_child(&conn, parent_ino, name).await?.is_some() {
-            return Err(FsError::AlreadyExists.into());
-        }
+                // Create directory entry pointing to the same inode
+                conn.execute(
+                    "INSERT INTO fs_dentry (name, parent_ino, ino) VALUES (?, ?, ?)",
+                    (name.as_str(), parent_ino, ino),
+                )
+                .await?;
 
-        // Create directory entry pointing to the same inode
-        conn.execute(
-            "INSERT INTO fs_dentry (name, parent_ino, ino) VALUES (?, ?, ?)",
-            (name.as_str(), parent_ino, ino),
-        )
-        .await?;
+                // Increment link count
+                conn.execute(
+                    "UPDATE fs_inode SET nlink = nlink + 1 WHERE ino = ?",
+                    (ino,),
+                )
+                .await?;
 
-        // Increment link count
-        conn.execute(
-            "UPDATE fs_inode SET nlink = nlink + 1 WHERE ino = ?",
-            (ino,),
-        )
-        .await?;
+                Change::new(JournalOp::Link)
+                    .ino(ino)
+                    .path(oldpath.as_str())
+                    .new_path(newpath.as_str())
+                    .record(&conn, self.tool_call())
+                    .await?;
 
-        // Populate dentry cache
-        self.dentry_cache.insert(parent_ino, name, ino);
+                commit(txn).await?;
 
-        Ok(())
+                // Populate dentry cache
+                self.dentry_cache.insert(parent_ino, name, ino);
+
+                Ok(())
+            })
+            .await
     }
 
     /// Read the target of a symbolic link
     pub async fn readlink(&self, path: &str) -> Result<Option<String>> {
-        let conn = self.pool.get_connection().await?;
+        let conn = self.pool.get_read_connection().await?;
         self.readlink_with_conn(&conn, path).await
     }
 
@@ -2089,113 +3113,135 @@ impl AgentFS {
 
     /// Remove a file or empty directory
     pub async fn remove(&self, path: &str) -> Result<()> {
-        let conn = self.pool.get_connection().await?;
-        let path = self.normalize_path(path);
-        let components = self.split_path(&path);
-
-        if components.is_empty() {
-            return Err(FsError::RootOperation.into());
-        }
+        self.pool
+            .retry(|| async move {
+                let conn = self.pool.get_connection().await?;
+                let txn = conn.begin_write().await?;
+                let path = self.normalize_path(path);
+                let components = self.split_path(&path);
+
+                if components.is_empty() {
+                    return Err(FsError::RootOperation.into());
+                }
 
-        let ino = self
-            .resolve_path_with_conn(&conn, &path)
-            .await?
-            .ok_or(FsError::NotFound)?;
+                let ino = self
+                    .resolve_path_with_conn(&conn, &path)
+                    .await?
+                    .ok_or(FsError::NotFound)?;
 
-        if ino == ROOT_INO {
-            return Err(FsError::RootOperation.into());
-        }
+                if ino == ROOT_INO {
+                    return Err(FsError::RootOperation.into());
+                }
 
-        // Get stats to check if it's a directory
-        let stats = self
-            .stat_with_conn(&conn, &path)
-            .await?
-            .ok_or(FsError::NotFound)?;
+                // Get stats to check if it's a directory
+                let stats = self
+                    .stat_with_conn(&conn, &path)
+                    .await?
+                    .ok_or(FsError::NotFound)?;
 
-        // Check if directory is empty
-        let mut stmt = conn
-            .prepare_cached("SELECT COUNT(*) FROM fs_dentry WHERE parent_ino = ?")
-            .await?;
-        let mut rows = stmt.query((ino,)).await?;
+                // Check if directory is empty
+                let mut stmt = conn
+                    .prepare_cached("SELECT COUNT(*) FROM fs_dentry WHERE parent_ino = ?")
+                    .await?;
+                let mut rows = stmt.query((ino,)).await?;
 
-        if let Some(row) = rows.next().await? {
-            let count = row
-                .get_value(0)
-                .ok()
-                .and_then(|v| v.as_integer().copied())
-                .unwrap_or(0);
-            if count > 0 {
-                return Err(FsError::NotEmpty.into());
-            }
-        }
+                if let Some(row) = rows.next().await? {
+                    let count = row
+                        .get_value(0)
+                        .ok()
+                        .and_then(|v| v.as_integer().copied())
+                        .unwrap_or(0);
+                    if count > 0 {
+                        return Err(FsError::NotEmpty.into());
+                    }
+                }
 
-        // Get parent directory and name
-        let parent_path = if components.len() == 1 {
-            "/".to_string()
-        } else {
-            format!("/{}", components[..components.len() - 1].join("/"))
-        };
+                // Get parent directory and name
+                let parent_path = if components.len() == 1 {
+                    "/".to_string()
+                } else {
+                    format!("/{}", components[..components.len() - 1].join("/"))
+                };
 
-        let parent_ino = self
-            .resolve_path_with_conn(&conn, &parent_path)
-            .await?
-            .ok_or(FsError::NotFound)?;
+                let parent_ino = self
+                    .resolve_path_with_conn(&conn, &parent_path)
+                    .await?
+                    .ok_or(FsError::NotFound)?;
 
-        let name = components.last().unwrap();
+                let name = components.last().unwrap();
 
-        // Delete the specific directory entry (not all entries pointing to this inode)
-        let mut stmt = conn
-            .prepare_cached("DELETE FROM fs_dentry WHERE parent_ino = ? AND name = ?")
-            .await?;
-        stmt.execute((parent_ino, name.as_str())).await?;
+                // Delete the specific directory entry (not all entries pointing to this inode)
+                let mut stmt = conn
+                    .prepare_cached("DELETE FROM fs_dentry WHERE parent_ino = ? AND name = ?")
+                    .await?;
+                stmt.execute((parent_ino, name.as_str())).await?;
 
-        // Invalidate cache for this entry
-        self.dentry_cache.remove(parent_ino, name);
+                // Decrement link count
+                let mut stmt = conn
+                    .prepare_cached("UPDATE fs_inode SET nlink = nlink - 1 WHERE ino = ?")
+                    .await?;
+                stmt.execute((ino,)).await?;
 
-        // Decrement link count
-        let mut stmt = conn
-            .prepare_cached("UPDATE fs_inode SET nlink = nlink - 1 WHERE ino = ?")
-            .await?;
-        stmt.execute((ino,)).await?;
+                // If removing a directory, decrement parent nlink (removed dir's ".." link)
+                if stats.is_directory() {
+                    let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                    let now_secs = dur.as_secs() as i64;
+                    let now_nsec = dur.subsec_nanos() as i64;
+                    let mut stmt = conn
+                        .prepare_cached(
+                            "UPDATE fs_inode SET nlink = nlink - 1, ctime = ?, mtime = ?, ctime_nsec = ?, mtime_nsec = ? WHERE ino = ?",
+                        )
+                        .await?;
+                    stmt.execute((now_secs, now_secs, now_nsec, now_nsec, parent_ino))
+                        .await?;
+                }
 
-        // If removing a directory, decrement parent nlink (removed dir's ".." link)
-        if stats.is_directory() {
-            let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
-            let now_secs = dur.as_secs() as i64;
-            let now_nsec = dur.subsec_nanos() as i64;
-            let mut stmt = conn
-                .prepare_cached(
-                    "UPDATE fs_inode SET nlink = nlink - 1, ctime = ?, mtime = ?, ctime_nsec = ?, mtime_nsec = ? WHERE ino = ?",
-                )
-                .await?;
-            stmt.execute((now_secs, now_secs, now_nsec, now_nsec, parent_ino))
-                .await?;
-        }
+                // Check if this was the last link to the inode (a directory's only
+                // other link is its own ".")
+                let link_count = self.get_link_count(&conn, ino).await?;
+                if link_count == 0 || stats.is_directory() {
+                    // Manually handle cascading deletes since we don't use foreign keys
+                    // Delete data blocks
+                    Self::delete_chunks(&conn, ino, 0, i64::MAX).await?;
 
-        // Check if this was the last link to the inode
-        let link_count = self.get_link_count(&conn, ino).await?;
-        if link_count == 0 {
-            // Manually handle cascading deletes since we don't use foreign keys
-            // Delete data blocks
-            let mut stmt = conn
-                .prepare_cached("DELETE FROM fs_data WHERE ino = ?")
-                .await?;
-            stmt.execute((ino,)).await?;
+                    // Delete symlink if exists
+                    let mut stmt = conn
+                        .prepare_cached("DELETE FROM fs_symlink WHERE ino = ?")
+                        .await?;
+                    stmt.execute((ino,)).await?;
 
-            // Delete symlink if exists
-            let mut stmt = conn
-                .prepare_cached("DELETE FROM fs_symlink WHERE ino = ?")
-                .await?;
-            stmt.execute((ino,)).await?;
+                    // Delete extended attributes
+                    let mut stmt = conn
+                        .prepare_cached("DELETE FROM fs_xattr WHERE ino = ?")
+                        .await?;
+                    stmt.execute((ino,)).await?;
 
-            // Delete inode
-            let mut stmt = conn
-                .prepare_cached("DELETE FROM fs_inode WHERE ino = ?")
-                .await?;
-            stmt.execute((ino,)).await?;
-        }
+                    // Delete inode
+                    let mut stmt = conn
+                        .prepare_cached("DELETE FROM fs_inode WHERE ino = ?")
+                        .await?;
+                    stmt.execute((ino,)).await?;
+                }
 
-        Ok(())
+                let op = if stats.is_directory() {
+                    JournalOp::Rmdir
+                } else {
+                    JournalOp::Unlink
+                };
+                Change::new(op)
+                    .ino(ino)
+                    .path(path.as_str())
+                    .record(&conn, self.tool_call())
+                    .await?;
+
+                commit(txn).await?;
+
+                // Invalidate cache
+                self.dentry_cache.remove(parent_ino, name);
+
+                Ok(())
+            })
+            .await
     }
 
     /// Change file ownership
@@ -2203,249 +3249,284 @@ impl AgentFS {
     /// Changes the user and/or group ownership of a file.
     /// Pass None for uid or gid to leave that value unchanged.
     pub async fn chown(&self, ino: i64, uid: Option<u32>, gid: Option<u32>) -> Result<()> {
-        if uid.is_none() && gid.is_none() {
-            return Ok(());
-        }
+        self.pool
+            .retry(|| async move {
+                if uid.is_none() && gid.is_none() {
+                    return Ok(());
+                }
 
-        let conn = self.pool.get_connection().await?;
+                let conn = self.pool.get_connection().await?;
+                let txn = conn.begin_write().await?;
 
-        // Build the update query dynamically based on which values are provided
-        let mut updates = Vec::new();
-        let mut values: Vec<Value> = Vec::new();
+                // Build the update query dynamically based on which values are provided
+                let mut updates = Vec::new();
+                let mut values: Vec<Value> = Vec::new();
 
-        if let Some(uid) = uid {
-            updates.push("uid = ?");
-            values.push(Value::Integer(uid as i64));
-        }
-        if let Some(gid) = gid {
-            updates.push("gid = ?");
-            values.push(Value::Integer(gid as i64));
-        }
+                if let Some(uid) = uid {
+                    updates.push("uid = ?");
+                    values.push(Value::Integer(uid as i64));
+                }
+                if let Some(gid) = gid {
+                    updates.push("gid = ?");
+                    values.push(Value::Integer(gid as i64));
+                }
 
-        values.push(Value::Integer(ino));
-        let sql = format!("UPDATE fs_inode SET {} WHERE ino = ?", updates.join(", "));
-        conn.execute(&sql, values).await?;
+                values.push(Value::Integer(ino));
+                let sql = format!("UPDATE fs_inode SET {} WHERE ino = ?", updates.join(", "));
+                conn.execute(&sql, values).await?;
 
-        Ok(())
+                Change::new(JournalOp::Chown)
+                    .ino(ino)
+                    .owner(uid, gid)
+                    .record(&conn, self.tool_call())
+                    .await?;
+
+                commit(txn).await?;
+
+                Ok(())
+            })
+            .await
     }
 
     /// Rename/move a file or directory.
     ///
     /// This operation is atomic - either all changes succeed or none do.
     pub async fn rename(&self, from: &str, to: &str) -> Result<()> {
-        let conn = self.pool.get_connection().await?;
-        let from_path = self.normalize_path(from);
-        let to_path = self.normalize_path(to);
+        self.pool
+            .retry(|| async move {
+                let conn = self.pool.get_connection().await?;
+                let from_path = self.normalize_path(from);
+                let to_path = self.normalize_path(to);
+
+                // Cannot rename root
+                if from_path == "/" {
+                    return Err(FsError::RootOperation.into());
+                }
 
-        // Cannot rename root
-        if from_path == "/" {
-            return Err(FsError::RootOperation.into());
-        }
+                // Get source inode
+                let src_ino = self
+                    .resolve_path_with_conn(&conn, &from_path)
+                    .await?
+                    .ok_or(FsError::NotFound)?;
 
-        // Get source inode
-        let src_ino = self
-            .resolve_path_with_conn(&conn, &from_path)
-            .await?
-            .ok_or(FsError::NotFound)?;
+                // Get source stats to check if it's a directory
+                let src_stats = self
+                    .stat_with_conn(&conn, &from_path)
+                    .await?
+                    .ok_or(FsError::NotFound)?;
 
-        // Get source stats to check if it's a directory
-        let src_stats = self
-            .stat_with_conn(&conn, &from_path)
-            .await?
-            .ok_or(FsError::NotFound)?;
+                // Prevent renaming a directory into its own subtree (would create a cycle)
+                if src_stats.is_directory() {
+                    let from_prefix = format!("{}/", from_path);
+                    if to_path.starts_with(&from_prefix) || to_path == from_path {
+                        return Err(FsError::InvalidRename.into());
+                    }
+                }
 
-        // Prevent renaming a directory into its own subtree (would create a cycle)
-        if src_stats.is_directory() {
-            let from_prefix = format!("{}/", from_path);
-            if to_path.starts_with(&from_prefix) || to_path == from_path {
-                return Err(FsError::InvalidRename.into());
-            }
-        }
+                // Parse source path to get parent and name
+                let from_components = self.split_path(&from_path);
+                let src_name = from_components.last().ok_or(FsError::InvalidPath)?;
+                let src_parent_path = if from_components.len() == 1 {
+                    "/".to_string()
+                } else {
+                    format!(
+                        "/{}",
+                        from_components[..from_components.len() - 1].join("/")
+                    )
+                };
+                let src_parent_ino = self
+                    .resolve_path_with_conn(&conn, &src_parent_path)
+                    .await?
+                    .ok_or(FsError::NotFound)?;
 
-        // Parse source path to get parent and name
-        let from_components = self.split_path(&from_path);
-        let src_name = from_components.last().ok_or(FsError::InvalidPath)?;
-        let src_parent_path = if from_components.len() == 1 {
-            "/".to_string()
-        } else {
-            format!(
-                "/{}",
-                from_components[..from_components.len() - 1].join("/")
-            )
-        };
-        let src_parent_ino = self
-            .resolve_path_with_conn(&conn, &src_parent_path)
-            .await?
-            .ok_or(FsError::NotFound)?;
+                // Parse destination path to get parent and name
+                let to_components = self.split_path(&to_path);
+                if to_components.is_empty() {
+                    return Err(FsError::RootOperation.into());
+                }
+                let dst_name = to_components.last().unwrap();
+                let dst_parent_path = if to_components.len() == 1 {
+                    "/".to_string()
+                } else {
+                    format!("/{}", to_components[..to_components.len() - 1].join("/"))
+                };
+                let dst_parent_ino = self
+                    .resolve_path_with_conn(&conn, &dst_parent_path)
+                    .await?
+                    .ok_or(FsError::NotFound)?;
 
-        // Parse destination path to get parent and name
-        let to_components = self.split_path(&to_path);
-        if to_components.is_empty() {
-            return Err(FsError::RootOperation.into());
-        }
-        let dst_name = to_components.last().unwrap();
-        let dst_parent_path = if to_components.len() == 1 {
-            "/".to_string()
-        } else {
-            format!("/{}", to_components[..to_components.len() - 1].join("/"))
-        };
-        let dst_parent_ino = self
-            .resolve_path_with_conn(&conn, &dst_parent_path)
-            .await?
-            .ok_or(FsError::NotFound)?;
+                // Clone strings for use inside the transaction closure
+                let src_name = src_name.clone();
+                let dst_name = dst_name.clone();
 
-        // Clone strings for use inside the transaction closure
-        let src_name = src_name.clone();
-        let dst_name = dst_name.clone();
+                let txn = conn.begin_write().await?;
 
-        let txn = Transaction::new_unchecked(&conn, TransactionBehavior::Immediate).await?;
+                let result: Result<()> = async {
+                    // Check if destination exists (inside transaction for atomicity)
+                    if let Some(dst_ino) = self.resolve_path_with_conn(&conn, &to_path).await? {
+                        let dst_stats = self.stat_with_conn(&conn, &to_path).await?.ok_or(FsError::NotFound)?;
 
-        let result: Result<()> = async {
-            // Check if destination exists (inside transaction for atomicity)
-            if let Some(dst_ino) = self.resolve_path_with_conn(&conn, &to_path).await? {
-                let dst_stats = self.stat_with_conn(&conn, &to_path).await?.ok_or(FsError::NotFound)?;
+                        // Can't replace directory with non-directory
+                        if dst_stats.is_directory() && !src_stats.is_directory() {
+                            return Err(FsError::IsADirectory.into());
+                        }
 
-                // Can't replace directory with non-directory
-                if dst_stats.is_directory() && !src_stats.is_directory() {
-                    return Err(FsError::IsADirectory.into());
-                }
+                        // Can't replace non-directory with directory
+                        if !dst_stats.is_directory() && src_stats.is_directory() {
+                            return Err(FsError::NotADirectory.into());
+                        }
 
-                // Can't replace non-directory with directory
-                if !dst_stats.is_directory() && src_stats.is_directory() {
-                    return Err(FsError::NotADirectory.into());
-                }
+                        // If destination is directory, it must be empty
+                        if dst_stats.is_directory() {
+                            let mut stmt = conn
+                                .prepare_cached("SELECT COUNT(*) FROM fs_dentry WHERE parent_ino = ?")
+                                .await?;
+                            let mut rows = stmt.query((dst_ino,)).await?;
+
+                            if let Some(row) = rows.next().await? {
+                                let count = row
+                                    .get_value(0)
+                                    .ok()
+                                    .and_then(|v| v.as_integer().copied())
+                                    .unwrap_or(0);
+                                if count > 0 {
+                                    return Err(FsError::NotEmpty.into());
+                                }
+                            }
+                        }
 
-                // If destination is directory, it must be empty
-                if dst_stats.is_directory() {
-                    let mut stmt = conn
-                        .prepare_cached("SELECT COUNT(*) FROM fs_dentry WHERE parent_ino = ?")
-                        .await?;
-                    let mut rows = stmt.query((dst_ino,)).await?;
+                        // Remove destination entry
+                        let mut stmt = conn
+                            .prepare_cached("DELETE FROM fs_dentry WHERE parent_ino = ? AND name = ?")
+                            .await?;
+                        stmt.execute((dst_parent_ino, dst_name.as_str())).await?;
+
+                        // Decrement link count
+                        let mut stmt = conn
+                            .prepare_cached("UPDATE fs_inode SET nlink = nlink - 1 WHERE ino = ?")
+                            .await?;
+                        stmt.execute((dst_ino,)).await?;
+
+                        // A replaced directory goes away with its entry, along with
+                        // its ".." link to the parent
+                        if dst_stats.is_directory() {
+                            let mut stmt = conn
+                                .prepare_cached("UPDATE fs_inode SET nlink = nlink - 1 WHERE ino = ?")
+                                .await?;
+                            stmt.execute((dst_parent_ino,)).await?;
+                        }
 
-                    if let Some(row) = rows.next().await? {
-                        let count = row
-                            .get_value(0)
-                            .ok()
-                            .and_then(|v| v.as_integer().copied())
-                            .unwrap_or(0);
-                        if count > 0 {
-                            return Err(FsError::NotEmpty.into());
+                        // Clean up destination inode if no more links
+                        let link_count = self.get_link_count(&conn, dst_ino).await?;
+                        if link_count == 0 || dst_stats.is_directory() {
+                            Self::delete_chunks(&conn, dst_ino, 0, i64::MAX).await?;
+                            let mut stmt = conn
+                                .prepare_cached("DELETE FROM fs_symlink WHERE ino = ?")
+                                .await?;
+                            stmt.execute((dst_ino,)).await?;
+                            let mut stmt = conn
+                                .prepare_cached("DELETE FROM fs_xattr WHERE ino = ?")
+                                .await?;
+                            stmt.execute((dst_ino,)).await?;
+                            let mut stmt = conn
+                                .prepare_cached("DELETE FROM fs_inode WHERE ino = ?")
+                                .await?;
+                            stmt.execute((dst_ino,)).await?;
                         }
                     }
-                }
-
-                // Remove destination entry
-                let mut stmt = conn
-                    .prepare_cached("DELETE FROM fs_dentry WHERE parent_ino = ? AND name = ?")
-                    .await?;
-                stmt.execute((dst_parent_ino, dst_name.as_str())).await?;
-
-                // Decrement link count
-                let mut stmt = conn
-                    .prepare_cached("UPDATE fs_inode SET nlink = nlink - 1 WHERE ino = ?")
-                    .await?;
-                stmt.execute((dst_ino,)).await?;
 
-                // Clean up destination inode if no more links
-                let link_count = self.get_link_count(&conn, dst_ino).await?;
-                if link_count == 0 {
+                    // Update the dentry: change parent and/or name
                     let mut stmt = conn
-                        .prepare_cached("DELETE FROM fs_data WHERE ino = ?")
-                        .await?;
-                    stmt.execute((dst_ino,)).await?;
-                    let mut stmt = conn
-                        .prepare_cached("DELETE FROM fs_symlink WHERE ino = ?")
-                        .await?;
-                    stmt.execute((dst_ino,)).await?;
-                    let mut stmt = conn
-                        .prepare_cached("DELETE FROM fs_inode WHERE ino = ?")
+                        .prepare_cached(
+                            "UPDATE fs_dentry SET parent_ino = ?, name = ? WHERE parent_ino = ? AND name = ?",
+                        )
                         .await?;
-                    stmt.execute((dst_ino,)).await?;
-                }
-            }
-
-            // Update the dentry: change parent and/or name
-            let mut stmt = conn
-                .prepare_cached(
-                    "UPDATE fs_dentry SET parent_ino = ?, name = ? WHERE parent_ino = ? AND name = ?",
-                )
-                .await?;
-            stmt.execute((
-                dst_parent_ino,
-                dst_name.as_str(),
-                src_parent_ino,
-                src_name.as_str(),
-            ))
-            .await?;
-
-            // If renaming a directory across parents, adjust parent nlink counts
-            if src_stats.is_directory() && src_parent_ino != dst_parent_ino {
-                let mut stmt = conn
-                    .prepare_cached("UPDATE fs_inode SET nlink = nlink - 1 WHERE ino = ?")
+                    stmt.execute((
+                        dst_parent_ino,
+                        dst_name.as_str(),
+                        src_parent_ino,
+                        src_name.as_str(),
+                    ))
                     .await?;
-                stmt.execute((src_parent_ino,)).await?;
 
-                let mut stmt = conn
-                    .prepare_cached("UPDATE fs_inode SET nlink = nlink + 1 WHERE ino = ?")
-                    .await?;
-                stmt.execute((dst_parent_ino,)).await?;
-            }
+                    // If renaming a directory across parents, adjust parent nlink counts
+                    if src_stats.is_directory() && src_parent_ino != dst_parent_ino {
+                        let mut stmt = conn
+                            .prepare_cached("UPDATE fs_inode SET nlink = nlink - 1 WHERE ino = ?")
+                            .await?;
+                        stmt.execute((src_parent_ino,)).await?;
+
+                        let mut stmt = conn
+                            .prepare_cached("UPDATE fs_inode SET nlink = nlink + 1 WHERE ino = ?")
+                            .await?;
+                        stmt.execute((dst_parent_ino,)).await?;
+                    }
 
-            // Update ctime of the inode
-            let dur = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap_or_default();
-            let now_secs = dur.as_secs() as i64;
-            let now_nsec = dur.subsec_nanos() as i64;
+                    // Update ctime of the inode
+                    let dur = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default();
+                    let now_secs = dur.as_secs() as i64;
+                    let now_nsec = dur.subsec_nanos() as i64;
 
-            let mut stmt = conn
-                .prepare_cached("UPDATE fs_inode SET ctime = ?, ctime_nsec = ? WHERE ino = ?")
-                .await?;
-            stmt.execute((now_secs, now_nsec, src_ino)).await?;
+                    let mut stmt = conn
+                        .prepare_cached("UPDATE fs_inode SET ctime = ?, ctime_nsec = ? WHERE ino = ?")
+                        .await?;
+                    stmt.execute((now_secs, now_nsec, src_ino)).await?;
 
-            // Update source parent directory timestamps
-            let mut stmt = conn
-                .prepare_cached("UPDATE fs_inode SET mtime = ?, ctime = ?, mtime_nsec = ?, ctime_nsec = ? WHERE ino = ?")
-                .await?;
-            stmt.execute((now_secs, now_secs, now_nsec, now_nsec, src_parent_ino)).await?;
+                    // Update source parent directory timestamps
+                    let mut stmt = conn
+                        .prepare_cached("UPDATE fs_inode SET mtime = ?, ctime = ?, mtime_nsec = ?, ctime_nsec = ? WHERE ino = ?")
+                        .await?;
+                    stmt.execute((now_secs, now_secs, now_nsec, now_nsec, src_parent_ino)).await?;
+
+                    // Update destination parent directory timestamps
+                    if dst_parent_ino != src_parent_ino {
+                        let mut stmt = conn
+                            .prepare_cached("UPDATE fs_inode SET mtime = ?, ctime = ?, mtime_nsec = ?, ctime_nsec = ? WHERE ino = ?")
+                            .await?;
+                        stmt.execute((now_secs, now_secs, now_nsec, now_nsec, dst_parent_ino)).await?;
+                    }
 
-            // Update destination parent directory timestamps
-            if dst_parent_ino != src_parent_ino {
-                let mut stmt = conn
-                    .prepare_cached("UPDATE fs_inode SET mtime = ?, ctime = ?, mtime_nsec = ?, ctime_nsec = ? WHERE ino = ?")
-                    .await?;
-                stmt.execute((now_secs, now_secs, now_nsec, now_nsec, dst_parent_ino)).await?;
-            }
+                    Change::new(JournalOp::Rename)
+                        .ino(src_ino)
+                        .path(from_path.as_str())
+                        .new_path(to_path.as_str())
+                        .record(&conn, self.tool_call())
+                        .await?;
 
-            Ok(())
-        }
-        .await;
+                    Ok(())
+                }
+                .await;
 
-        match result {
-            Ok(()) => {
-                txn.commit().await?;
+                match result {
+                    Ok(()) => {
+                        commit(txn).await?;
 
-                // Invalidate cache for source and destination
-                self.dentry_cache.remove(src_parent_ino, &src_name);
-                self.dentry_cache.remove(dst_parent_ino, &dst_name);
+                        // Invalidate cache for source and destination
+                        self.dentry_cache.remove(src_parent_ino, &src_name);
+                        self.dentry_cache.remove(dst_parent_ino, &dst_name);
 
-                // Add new entry to cache (source inode is now at destination)
-                self.dentry_cache.insert(dst_parent_ino, &dst_name, src_ino);
+                        // Add new entry to cache (source inode is now at destination)
+                        self.dentry_cache.insert(dst_parent_ino, &dst_name, src_ino);
 
-                Ok(())
-            }
-            Err(e) => {
-                let _ = txn.rollback().await;
-                Err(e)
-            }
-        }
+                        Ok(())
+                    }
+                    Err(e) => {
+                        let _ = txn.rollback().await;
+                        Err(e)
+                    }
+                }
+            })
+            .await
     }
 
     /// Get filesystem statistics
     ///
-    /// Returns the total number of inodes and bytes used by file contents.
+    /// Returns the total number of inodes, the logical bytes used by file
+    /// contents and the bytes physically stored for them.
     pub async fn statfs(&self) -> Result<FilesystemStats> {
-        let conn = self.pool.get_connection().await?;
+        let conn = self.pool.get_read_connection().await?;
         // Count total inodes
         let mut stmt = conn.prepare_cached("SELECT COUNT(*) FROM fs_inode").await?;
         let mut rows = stmt.query(()).await?;
@@ -2474,7 +3555,40 @@ impl AgentFS {
             0
         };
 
-        Ok(FilesystemStats { inodes, bytes_used })
+        // Sum bytes stored inline plus each deduplicated chunk once, and the
+        // small files stored in their inode
+        let mut stmt = conn
+            .prepare_cached(
+                "SELECT (SELECT COALESCE(SUM(LENGTH(data)), 0) FROM fs_data WHERE hash IS NULL)
+                + (SELECT COALESCE(SUM(LENGTH(data)), 0) FROM fs_chunk)
+                + (SELECT COALESCE(SUM(LENGTH(inline_data)), 0) FROM fs_inode)",
+            )
+            .await?;
+        let mut rows = stmt.query(()).await?;
+
+        let physical_bytes = if let Some(row) = rows.next().await? {
+            row.get_value(0)
+                .ok()
+                .and_then(|v| v.as_integer().copied())
+                .unwrap_or(0) as u64
+        } else {
+            0
+        };
+
+        Ok(FilesystemStats {
+            inodes,
+            bytes_used,
+            physical_bytes,
+        })
+    }
+
+    /// Store the writes buffered by open files
+    ///
+    /// Writes through open files are otherwise stored when the file is
+    /// flushed, or shortly after they are made. Call this before reading the
+    /// database directly.
+    pub async fn flush_writes(&self) -> Result<()> {
+        self.write_back.flush(None).await
     }
 
     /// Synchronize file data to persistent storage
@@ -2485,6 +3599,7 @@ impl AgentFS {
     ///
     /// Note: The path parameter is ignored since all data is in a single database.
     pub async fn fsync(&self, _path: &str) -> Result<()> {
+        self.write_back.flush(None).await?;
         let conn = self.pool.get_connection().await?;
         conn.prepare_cached("PRAGMA synchronous = FULL")
             .await?
@@ -2511,13 +3626,16 @@ impl AgentFS {
             pool: self.pool.clone(),
             ino,
             chunk_size: self.chunk_size,
+            dedup: self.dedup_enabled(),
+            tool_call: self.tool_call.clone(),
+            write_back: self.write_back.clone(),
         }))
     }
 
     /// Get the number of chunks for a given inode (for testing)
     #[cfg(test)]
     async fn get_chunk_count(&self, ino: i64) -> Result<i64> {
-        let conn = self.pool.get_connection().await?;
+        let conn = self.pool.get_read_connection().await?;
         let mut rows = conn
             .query("SELECT COUNT(*) FROM fs_data WHERE ino = ?", (ino,))
             .await?;
@@ -2540,7 +3658,8 @@ impl FileSystem for AgentFS {
         if name.len() > MAX_NAME_LEN {
             return Err(FsError::NameTooLong.into());
         }
-        let conn = self.pool.get_connection().await?;
+        let generation = self.dentry_cache.generation();
+        let conn = self.pool.get_read_connection().await?;
 
         // Handle ".." by finding the parent of parent_ino
         if name == ".." {
@@ -2576,9 +3695,11 @@ impl FileSystem for AgentFS {
         let mut rows = stmt.query((child_ino,)).await?;
 
         if let Some(row) = rows.next().await? {
-            let stats = Self::build_stats_from_row(&row)?;
+            let mut stats = Self::build_stats_from_row(&row)?;
+            self.write_back.apply(&mut stats);
             // Cache the lookup result
-            self.dentry_cache.insert(parent_ino, name, child_ino);
+            self.dentry_cache
+                .insert_since(generation, parent_ino, name, child_ino);
             Ok(Some(stats))
         } else {
             Ok(None)
@@ -2586,12 +3707,12 @@ impl FileSystem for AgentFS {
     }
 
     async fn getattr(&self, ino: i64) -> Result<Option<Stats>> {
-        let conn = self.pool.get_connection().await?;
+        let conn = self.pool.get_read_connection().await?;
         self.getattr_with_conn(&conn, ino).await
     }
 
     async fn readlink(&self, ino: i64) -> Result<Option<String>> {
-        let conn = self.pool.get_connection().await?;
+        let conn = self.pool.get_read_connection().await?;
 
         // Check if the inode exists and is a symlink
         let mut stmt = conn
@@ -2635,7 +3756,7 @@ impl FileSystem for AgentFS {
     }
 
     async fn readdir(&self, ino: i64) -> Result<Option<Vec<String>>> {
-        let conn = self.pool.get_connection().await?;
+        let conn = self.pool.get_read_connection().await?;
 
         // Check if inode exists and is a directory
         let mut stmt = conn
@@ -2684,7 +3805,7 @@ impl FileSystem for AgentFS {
     }
 
     async fn readdir_plus(&self, ino: i64) -> Result<Option<Vec<DirEntry>>> {
-        let conn = self.pool.get_connection().await?;
+        let conn = self.pool.get_read_connection().await?;
 
         // Check if inode exists and is a directory
         let mut stmt = conn
@@ -2738,7 +3859,7 @@ impl FileSystem for AgentFS {
                 .and_then(|v| v.as_integer().copied())
                 .unwrap_or(0);
 
-            let stats = Stats {
+            let mut stats = Stats {
                 ino: entry_ino,
                 mode: row
                     .get_value(2)
@@ -2801,6 +3922,7 @@ impl FileSystem for AgentFS {
                     .and_then(|v| v.as_integer().copied())
                     .unwrap_or(0) as u64,
             };
+            self.write_back.apply(&mut stats);
 
             entries.push(DirEntry { name, stats });
         }
@@ -2809,145 +3931,187 @@ impl FileSystem for AgentFS {
     }
 
     async fn chmod(&self, ino: i64, mode: u32) -> Result<()> {
-        let conn = self.pool.get_connection().await?;
+        self.pool
+            .retry(|| async move {
+                let conn = self.pool.get_connection().await?;
+                let txn = conn.begin_write().await?;
 
-        // Get current mode to preserve file type bits
-        let mut stmt = conn
-            .prepare_cached("SELECT mode FROM fs_inode WHERE ino = ?")
-            .await?;
-        let mut rows = stmt.query((ino,)).await?;
+                // Get current mode to preserve file type bits
+                let mut stmt = conn
+                    .prepare_cached("SELECT mode FROM fs_inode WHERE ino = ?")
+                    .await?;
+                let mut rows = stmt.query((ino,)).await?;
 
-        let current_mode = if let Some(row) = rows.next().await? {
-            row.get_value(0)
-                .ok()
-                .and_then(|v| v.as_integer().copied())
-                .unwrap_or(0) as u32
-        } else {
-            return Err(FsError::NotFound.into());
-        };
+                let current_mode = if let Some(row) = rows.next().await? {
+                    row.get_value(0)
+                        .ok()
+                        .and_then(|v| v.as_integer().copied())
+                        .unwrap_or(0) as u32
+                } else {
+                    return Err(FsError::NotFound.into());
+                };
 
-        // Preserve file type bits (upper bits), replace permission bits (lower 12 bits)
-        let new_mode = (current_mode & S_IFMT) | (mode & 0o7777);
+                // Preserve file type bits (upper bits), replace permission bits (lower 12 bits)
+                let new_mode = (current_mode & S_IFMT) | (mode & 0o7777);
 
-        let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
-        let now_secs = dur.as_secs() as i64;
-        let now_nsec = dur.subsec_nanos() as i64;
-        let mut stmt = conn
-            .prepare_cached("UPDATE fs_inode SET mode = ?, ctime = ?, ctime_nsec = ? WHERE ino = ?")
-            .await?;
-        stmt.execute((new_mode as i64, now_secs, now_nsec, ino))
-            .await?;
+                let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                let now_secs = dur.as_secs() as i64;
+                let now_nsec = dur.subsec_nanos() as i64;
+                let mut stmt = conn
+                    .prepare_cached(
+                        "UPDATE fs_inode SET mode = ?, ctime = ?, ctime_nsec = ? WHERE ino = ?",
+                    )
+                    .await?;
+                stmt.execute((new_mode as i64, now_secs, now_nsec, ino))
+                    .await?;
 
-        Ok(())
+                Change::new(JournalOp::Chmod)
+                    .ino(ino)
+                    .mode(new_mode)
+                    .record(&conn, self.tool_call())
+                    .await?;
+
+                commit(txn).await?;
+
+                Ok(())
+            })
+            .await
     }
 
     async fn chown(&self, ino: i64, uid: Option<u32>, gid: Option<u32>) -> Result<()> {
-        if uid.is_none() && gid.is_none() {
-            return Ok(());
-        }
+        self.pool
+            .retry(|| async move {
+                if uid.is_none() && gid.is_none() {
+                    return Ok(());
+                }
 
-        let conn = self.pool.get_connection().await?;
+                let conn = self.pool.get_connection().await?;
+                let txn = conn.begin_write().await?;
 
-        // Verify inode exists
-        let mut stmt = conn
-            .prepare_cached("SELECT ino FROM fs_inode WHERE ino = ?")
-            .await?;
-        let mut rows = stmt.query((ino,)).await?;
+                // Verify inode exists
+                let mut stmt = conn
+                    .prepare_cached("SELECT ino FROM fs_inode WHERE ino = ?")
+                    .await?;
+                let mut rows = stmt.query((ino,)).await?;
 
-        if rows.next().await?.is_none() {
-            return Err(FsError::NotFound.into());
-        }
+                if rows.next().await?.is_none() {
+                    return Err(FsError::NotFound.into());
+                }
 
-        // Build the update query dynamically based on which values are provided
-        let mut updates = Vec::new();
-        let mut values: Vec<Value> = Vec::new();
+                // Build the update query dynamically based on which values are provided
+                let mut updates = Vec::new();
+                let mut values: Vec<Value> = Vec::new();
 
-        if let Some(uid) = uid {
-            updates.push("uid = ?");
-            values.push(Value::Integer(uid as i64));
-        }
-        if let Some(gid) = gid {
-            updates.push("gid = ?");
-            values.push(Value::Integer(gid as i64));
-        }
+                if let Some(uid) = uid {
+                    updates.push("uid = ?");
+                    values.push(Value::Integer(uid as i64));
+                }
+                if let Some(gid) = gid {
+                    updates.push("gid = ?");
+                    values.push(Value::Integer(gid as i64));
+                }
 
-        let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
-        let now_secs = dur.as_secs() as i64;
-        let now_nsec = dur.subsec_nanos() as i64;
-        updates.push("ctime = ?");
-        values.push(Value::Integer(now_secs));
-        updates.push("ctime_nsec = ?");
-        values.push(Value::Integer(now_nsec));
+                let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                let now_secs = dur.as_secs() as i64;
+                let now_nsec = dur.subsec_nanos() as i64;
+                updates.push("ctime = ?");
+                values.push(Value::Integer(now_secs));
+                updates.push("ctime_nsec = ?");
+                values.push(Value::Integer(now_nsec));
+
+                values.push(Value::Integer(ino));
+                let sql = format!("UPDATE fs_inode SET {} WHERE ino = ?", updates.join(", "));
+                conn.execute(&sql, values).await?;
+
+                Change::new(JournalOp::Chown)
+                    .ino(ino)
+                    .owner(uid, gid)
+                    .record(&conn, self.tool_call())
+                    .await?;
 
-        values.push(Value::Integer(ino));
-        let sql = format!("UPDATE fs_inode SET {} WHERE ino = ?", updates.join(", "));
-        conn.execute(&sql, values).await?;
+                commit(txn).await?;
 
-        Ok(())
+                Ok(())
+            })
+            .await
     }
 
     async fn utimens(&self, ino: i64, atime: TimeChange, mtime: TimeChange) -> Result<()> {
-        let conn = self.pool.get_connection().await?;
+        self.pool
+            .retry(|| async move {
+                // Store earlier writes first, so their modification time doesn't win
+                self.write_back.flush(Some(ino)).await?;
+                let conn = self.pool.get_connection().await?;
+                let txn = conn.begin_write().await?;
+
+                // Verify inode exists
+                let mut stmt = conn
+                    .prepare_cached("SELECT ino FROM fs_inode WHERE ino = ?")
+                    .await?;
+                let mut rows = stmt.query((ino,)).await?;
+                if rows.next().await?.is_none() {
+                    return Err(FsError::NotFound.into());
+                }
 
-        // Verify inode exists
-        let mut stmt = conn
-            .prepare_cached("SELECT ino FROM fs_inode WHERE ino = ?")
-            .await?;
-        let mut rows = stmt.query((ino,)).await?;
-        if rows.next().await?.is_none() {
-            return Err(FsError::NotFound.into());
-        }
+                let mut updates = Vec::new();
+                let mut values: Vec<Value> = Vec::new();
 
-        let mut updates = Vec::new();
-        let mut values: Vec<Value> = Vec::new();
+                let resolve = |tc: TimeChange| -> (i64, i64) {
+                    match tc {
+                        TimeChange::Set(secs, nsec) => (secs, nsec as i64),
+                        TimeChange::Now => {
+                            let dur = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+                            (dur.as_secs() as i64, dur.subsec_nanos() as i64)
+                        }
+                        TimeChange::Omit => unreachable!(),
+                    }
+                };
 
-        let resolve = |tc: TimeChange| -> (i64, i64) {
-            match tc {
-                TimeChange::Set(secs, nsec) => (secs, nsec as i64),
-                TimeChange::Now => {
-                    let dur = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-                    (dur.as_secs() as i64, dur.subsec_nanos() as i64)
+                if !matches!(atime, TimeChange::Omit) {
+                    let (secs, nsec) = resolve(atime);
+                    updates.push("atime = ?");
+                    values.push(Value::Integer(secs));
+                    updates.push("atime_nsec = ?");
+                    values.push(Value::Integer(nsec));
                 }
-                TimeChange::Omit => unreachable!(),
-            }
-        };
 
-        if !matches!(atime, TimeChange::Omit) {
-            let (secs, nsec) = resolve(atime);
-            updates.push("atime = ?");
-            values.push(Value::Integer(secs));
-            updates.push("atime_nsec = ?");
-            values.push(Value::Integer(nsec));
-        }
+                if !matches!(mtime, TimeChange::Omit) {
+                    let (secs, nsec) = resolve(mtime);
+                    updates.push("mtime = ?");
+                    values.push(Value::Integer(secs));
+                    updates.push("mtime_nsec = ?");
+                    values.push(Value::Integer(nsec));
+                }
 
-        if !matches!(mtime, TimeChange::Omit) {
-            let (secs, nsec) = resolve(mtime);
-            updates.push("mtime = ?");
-            values.push(Value::Integer(secs));
-            updates.push("mtime_nsec = ?");
-            values.push(Value::Integer(nsec));
-        }
-
-        if updates.is_empty() {
-            return Ok(());
-        }
+                if updates.is_empty() {
+                    return Ok(());
+                }
 
-        // Also update ctime
-        let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
-        updates.push("ctime = ?");
-        values.push(Value::Integer(dur.as_secs() as i64));
-        updates.push("ctime_nsec = ?");
-        values.push(Value::Integer(dur.subsec_nanos() as i64));
+                // Also update ctime
+                let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                updates.push("ctime = ?");
+                values.push(Value::Integer(dur.as_secs() as i64));
+                updates.push("ctime_nsec = ?");
+                values.push(Value::Integer(dur.subsec_nanos() as i64));
+
+                values.push(Value::Integer(ino));
+                let sql = format!("UPDATE fs_inode SET {} WHERE ino = ?", updates.join(", "));
+                conn.execute(&sql, values).await?;
+
+                Change::new(JournalOp::Utimens)
+                    .ino(ino)
+                    .record(&conn, self.tool_call())
+                    .await?;
 
-        values.push(Value::Integer(ino));
-        let sql = format!("UPDATE fs_inode SET {} WHERE ino = ?", updates.join(", "));
-        conn.execute(&sql, values).await?;
+                commit(txn).await?;
 
-        Ok(())
+                Ok(())
+            })
+            .await
     }
 
     async fn open(&self, ino: i64, _flags: i32) -> Result<BoxedFile> {
-        let conn = self.pool.get_connection().await?;
+        let conn = self.pool.get_read_connection().await?;
 
         // Verify inode exists
         let mut stmt = conn
@@ -2963,6 +4127,9 @@ impl FileSystem for AgentFS {
             pool: self.pool.clone(),
             ino,
             chunk_size: self.chunk_size,
+            dedup: self.dedup_enabled(),
+            tool_call: self.tool_call.clone(),
+            write_back: self.write_back.clone(),
         }))
     }
 
@@ -2974,86 +4141,99 @@ impl FileSystem for AgentFS {
         uid: u32,
         gid: u32,
     ) -> Result<Stats> {
-        if name.len() > MAX_NAME_LEN {
-            return Err(FsError::NameTooLong.into());
-        }
-        let conn = self.pool.get_connection().await?;
+        self.pool
+            .retry(|| async move {
+                if name.len() > MAX_NAME_LEN {
+                    return Err(FsError::NameTooLong.into());
+                }
+                let conn = self.pool.get_connection().await?;
+                let txn = conn.begin_write().await?;
 
-        // Check if already exists
-        if self.lookup_child(&conn, parent_ino, name).await?.is_some() {
-            return Err(FsError::AlreadyExists.into());
-        }
+                // Check if already exists
+                if self.lookup_child(&conn, parent_ino, name).await?.is_some() {
+                    return Err(FsError::AlreadyExists.into());
+                }
 
-        // Create inode
-        let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
-        let now_secs = dur.as_secs() as i64;
-        let now_nsec = dur.subsec_nanos() as i64;
-        let mut stmt = conn
-            .prepare_cached(
-                "INSERT INTO fs_inode (mode, uid, gid, size, atime, mtime, ctime, atime_nsec, mtime_nsec, ctime_nsec)
-                VALUES (?, ?, ?, 0, ?, ?, ?, ?, ?, ?) RETURNING ino",
-            )
-            .await?;
-        let dir_mode = super::S_IFDIR | (mode & 0o7777);
-        let row = stmt
-            .query_row((
-                dir_mode as i64,
-                uid,
-                gid,
-                now_secs,
-                now_secs,
-                now_secs,
-                now_nsec,
-                now_nsec,
-                now_nsec,
-            ))
-            .await?;
+                // Create inode
+                let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                let now_secs = dur.as_secs() as i64;
+                let now_nsec = dur.subsec_nanos() as i64;
+                let mut stmt = conn
+                    .prepare_cached(
+                        "INSERT INTO fs_inode (mode, uid, gid, size, atime, mtime, ctime, atime_nsec, mtime_nsec, ctime_nsec)
+                        VALUES (?, ?, ?, 0, ?, ?, ?, ?, ?, ?) RETURNING ino",
+                    )
+                    .await?;
+                let dir_mode = super::S_IFDIR | (mode & 0o7777);
+                let row = stmt
+                    .query_row((
+                        dir_mode as i64,
+                        uid,
+                        gid,
+                        now_secs,
+                        now_secs,
+                        now_secs,
+                        now_nsec,
+                        now_nsec,
+                        now_nsec,
+                    ))
+                    .await?;
 
-        let ino = row
-            .get_value(0)
-            .ok()
-            .and_then(|v| v.as_integer().copied())
-            .ok_or_else(|| Error::Internal("failed to get inode".to_string()))?;
+                let ino = row
+                    .get_value(0)
+                    .ok()
+                    .and_then(|v| v.as_integer().copied())
+                    .ok_or_else(|| Error::Internal("failed to get inode".to_string()))?;
 
-        // Create directory entry
-        let mut stmt = conn
-            .prepare_cached("INSERT INTO fs_dentry (name, parent_ino, ino) VALUES (?, ?, ?)")
-            .await?;
-        stmt.execute((name, parent_ino, ino)).await?;
+                // Create directory entry
+                let mut stmt = conn
+                    .prepare_cached("INSERT INTO fs_dentry (name, parent_ino, ino) VALUES (?, ?, ?)")
+                    .await?;
+                stmt.execute((name, parent_ino, ino)).await?;
 
-        // Set nlink to 2 for new directory (self "." + parent's dentry)
-        let mut stmt = conn
-            .prepare_cached("UPDATE fs_inode SET nlink = 2 WHERE ino = ?")
-            .await?;
-        stmt.execute((ino,)).await?;
+                // Set nlink to 2 for new directory (self "." + parent's dentry)
+                let mut stmt = conn
+                    .prepare_cached("UPDATE fs_inode SET nlink = 2 WHERE ino = ?")
+                    .await?;
+                stmt.execute((ino,)).await?;
 
-        // Increment parent nlink (new directory's ".." link) and update timestamps
-        let mut stmt = conn
-            .prepare_cached(
-                "UPDATE fs_inode SET nlink = nlink + 1, ctime = ?, mtime = ?, ctime_nsec = ?, mtime_nsec = ? WHERE ino = ?",
-            )
-            .await?;
-        stmt.execute((now_secs, now_secs, now_nsec, now_nsec, parent_ino))
-            .await?;
+                // Increment parent nlink (new directory's ".." link) and update timestamps
+                let mut stmt = conn
+                    .prepare_cached(
+                        "UPDATE fs_inode SET nlink = nlink + 1, ctime = ?, mtime = ?, ctime_nsec = ?, mtime_nsec = ? WHERE ino = ?",
+                    )
+                    .await?;
+                stmt.execute((now_secs, now_secs, now_nsec, now_nsec, parent_ino))
+                    .await?;
 
-        // Populate dentry cache
-        self.dentry_cache.insert(parent_ino, name, ino);
+                Change::new(JournalOp::Mkdir)
+                    .ino(ino)
+                    .path(journal::child_path(&conn, parent_ino, name).await?)
+                    .record(&conn, self.tool_call())
+                    .await?;
 
-        Ok(Stats {
-            ino,
-            mode: dir_mode,
-            nlink: 2,
-            uid,
-            gid,
-            size: 0,
-            atime: now_secs,
-            mtime: now_secs,
-            ctime: now_secs,
-            atime_nsec: now_nsec as u32,
-            mtime_nsec: now_nsec as u32,
-            ctime_nsec: now_nsec as u32,
-            rdev: 0,
-        })
+                commit(txn).await?;
+
+                // Populate dentry cache
+                self.dentry_cache.insert(parent_ino, name, ino);
+
+                Ok(Stats {
+                    ino,
+                    mode: dir_mode,
+                    nlink: 2,
+                    uid,
+                    gid,
+                    size: 0,
+                    atime: now_secs,
+                    mtime: now_secs,
+                    ctime: now_secs,
+                    atime_nsec: now_nsec as u32,
+                    mtime_nsec: now_nsec as u32,
+                    ctime_nsec: now_nsec as u32,
+                    rdev: 0,
+                })
+            })
+            .await
     }
 
     async fn create_file(
@@ -3064,90 +4244,103 @@ impl FileSystem for AgentFS {
         uid: u32,
         gid: u32,
     ) -> Result<(Stats, BoxedFile)> {
-        if name.len() > MAX_NAME_LEN {
-            return Err(FsError::NameTooLong.into());
-        }
-        let conn = self.pool.get_connection().await?;
-
-        // Check if already exists
-        if self.lookup_child(&conn, parent_ino, name).await?.is_some() {
-            return Err(FsError::AlreadyExists.into());
-        }
+        self.pool
+            .retry(|| async move {
+                if name.len() > MAX_NAME_LEN {
+                    return Err(FsError::NameTooLong.into());
+                }
+                let conn = self.pool.get_connection().await?;
 
-        // Prepare statements before starting the transaction
-        let mut inode_stmt = conn
-            .prepare_cached(
-                "INSERT INTO fs_inode (mode, nlink, uid, gid, size, atime, mtime, ctime, atime_nsec, mtime_nsec, ctime_nsec)
-                 VALUES (?, 1, ?, ?, 0, ?, ?, ?, ?, ?, ?) RETURNING ino",
-            )
-            .await?;
-        let mut dentry_stmt = conn
-            .prepare_cached("INSERT INTO fs_dentry (name, parent_ino, ino) VALUES (?, ?, ?)")
-            .await?;
+                // Check if already exists
+                if self.lookup_child(&conn, parent_ino, name).await?.is_some() {
+                    return Err(FsError::AlreadyExists.into());
+                }
 
-        let txn = Transaction::new_unchecked(&conn, TransactionBehavior::Immediate).await?;
+                // Prepare statements before starting the transaction
+                let mut inode_stmt = conn
+                    .prepare_cached(
+                        "INSERT INTO fs_inode (mode, nlink, uid, gid, size, atime, mtime, ctime, atime_nsec, mtime_nsec, ctime_nsec)
+                         VALUES (?, 1, ?, ?, 0, ?, ?, ?, ?, ?, ?) RETURNING ino",
+                    )
+                    .await?;
+                let mut dentry_stmt = conn
+                    .prepare_cached("INSERT INTO fs_dentry (name, parent_ino, ino) VALUES (?, ?, ?)")
+                    .await?;
 
-        let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
-        let now_secs = dur.as_secs() as i64;
-        let now_nsec = dur.subsec_nanos() as i64;
-        let file_mode = S_IFREG | (mode & 0o7777);
-
-        let row = inode_stmt
-            .query_row((
-                file_mode as i64,
-                uid,
-                gid,
-                now_secs,
-                now_secs,
-                now_secs,
-                now_nsec,
-                now_nsec,
-                now_nsec,
-            ))
-            .await?;
+                let txn = conn.begin_write().await?;
 
-        let ino = row
-            .get_value(0)
-            .ok()
-            .and_then(|v| v.as_integer().copied())
-            .ok_or_else(|| Error::Internal("failed to get inode".to_string()))?;
+                let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                let now_secs = dur.as_secs() as i64;
+                let now_nsec = dur.subsec_nanos() as i64;
+                let file_mode = S_IFREG | (mode & 0o7777);
+
+                let row = inode_stmt
+                    .query_row((
+                        file_mode as i64,
+                        uid,
+                        gid,
+                        now_secs,
+                        now_secs,
+                        now_secs,
+                        now_nsec,
+                        now_nsec,
+                        now_nsec,
+                    ))
+                    .await?;
 
-        dentry_stmt.execute((name, parent_ino, ino)).await?;
+                let ino = row
+                    .get_value(0)
+                    .ok()
+                    .and_then(|v| v.as_integer().copied())
+                    .ok_or_else(|| Error::Internal("failed to get inode".to_string()))?;
 
-        // Update parent directory ctime and mtime
-        conn.execute(
-            "UPDATE fs_inode SET ctime = ?, mtime = ?, ctime_nsec = ?, mtime_nsec = ? WHERE ino = ?",
-            (now_secs, now_secs, now_nsec, now_nsec, parent_ino),
-        )
-        .await?;
+                dentry_stmt.execute((name, parent_ino, ino)).await?;
 
-        txn.commit().await?;
+                // Update parent directory ctime and mtime
+                conn.execute(
+                    "UPDATE fs_inode SET ctime = ?, mtime = ?, ctime_nsec = ?, mtime_nsec = ? WHERE ino = ?",
+                    (now_secs, now_secs, now_nsec, now_nsec, parent_ino),
+                )
+                .await?;
 
-        self.dentry_cache.insert(parent_ino, name, ino);
+                Change::new(JournalOp::Create)
+                    .ino(ino)
+                    .path(journal::child_path(&conn, parent_ino, name).await?)
+                    .record(&conn, self.tool_call())
+                    .await?;
 
-        let stats = Stats {
-            ino,
-            mode: file_mode,
-            nlink: 1,
-            uid,
-            gid,
-            size: 0,
-            atime: now_secs,
-            mtime: now_secs,
-            ctime: now_secs,
-            atime_nsec: now_nsec as u32,
-            mtime_nsec: now_nsec as u32,
-            ctime_nsec: now_nsec as u32,
-            rdev: 0,
-        };
+                commit(txn).await?;
+
+                self.dentry_cache.insert(parent_ino, name, ino);
+
+                let stats = Stats {
+                    ino,
+                    mode: file_mode,
+                    nlink: 1,
+                    uid,
+                    gid,
+                    size: 0,
+                    atime: now_secs,
+                    mtime: now_secs,
+                    ctime: now_secs,
+                    atime_nsec: now_nsec as u32,
+                    mtime_nsec: now_nsec as u32,
+                    ctime_nsec: now_nsec as u32,
+                    rdev: 0,
+                };
 
-        let file: BoxedFile = Arc::new(AgentFSFile {
-            pool: self.pool.clone(),
-            ino,
-            chunk_size: self.chunk_size,
-        });
+                let file: BoxedFile = Arc::new(AgentFSFile {
+                    pool: self.pool.clone(),
+                    ino,
+                    chunk_size: self.chunk_size,
+                    dedup: self.dedup_enabled(),
+                    tool_call: self.tool_call.clone(),
+                    write_back: self.write_back.clone(),
+                });
 
-        Ok((stats, file))
+                Ok((stats, file))
+            })
+            .await
     }
 
     async fn mknod(
@@ -3159,84 +4352,97 @@ impl FileSystem for AgentFS {
         uid: u32,
         gid: u32,
     ) -> Result<Stats> {
-        if name.len() > MAX_NAME_LEN {
-            return Err(FsError::NameTooLong.into());
-        }
-        let conn = self.pool.get_connection().await?;
+        self.pool
+            .retry(|| async move {
+                if name.len() > MAX_NAME_LEN {
+                    return Err(FsError::NameTooLong.into());
+                }
+                let conn = self.pool.get_connection().await?;
+                let txn = conn.begin_write().await?;
 
-        // Check if already exists
-        if self.lookup_child(&conn, parent_ino, name).await?.is_some() {
-            return Err(FsError::AlreadyExists.into());
-        }
+                // Check if already exists
+                if self.lookup_child(&conn, parent_ino, name).await?.is_some() {
+                    return Err(FsError::AlreadyExists.into());
+                }
 
-        // Create inode with mode and rdev
-        let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
-        let now_secs = dur.as_secs() as i64;
-        let now_nsec = dur.subsec_nanos() as i64;
-        let mut stmt = conn
-            .prepare_cached(
-                "INSERT INTO fs_inode (mode, uid, gid, size, atime, mtime, ctime, rdev, atime_nsec, mtime_nsec, ctime_nsec)
-                VALUES (?, ?, ?, 0, ?, ?, ?, ?, ?, ?, ?) RETURNING ino",
-            )
-            .await?;
-        let row = stmt
-            .query_row((
-                mode as i64,
-                uid,
-                gid,
-                now_secs,
-                now_secs,
-                now_secs,
-                rdev as i64,
-                now_nsec,
-                now_nsec,
-                now_nsec,
-            ))
-            .await?;
+                // Create inode with mode and rdev
+                let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                let now_secs = dur.as_secs() as i64;
+                let now_nsec = dur.subsec_nanos() as i64;
+                let mut stmt = conn
+                    .prepare_cached(
+                        "INSERT INTO fs_inode (mode, uid, gid, size, atime, mtime, ctime, rdev, atime_nsec, mtime_nsec, ctime_nsec)
+                        VALUES (?, ?, ?, 0, ?, ?, ?, ?, ?, ?, ?) RETURNING ino",
+                    )
+                    .await?;
+                let row = stmt
+                    .query_row((
+                        mode as i64,
+                        uid,
+                        gid,
+                        now_secs,
+                        now_secs,
+                        now_secs,
+                        rdev as i64,
+                        now_nsec,
+                        now_nsec,
+                        now_nsec,
+                    ))
+                    .await?;
 
-        let ino = row
-            .get_value(0)
-            .ok()
-            .and_then(|v| v.as_integer().copied())
-            .ok_or_else(|| Error::Internal("failed to get inode".to_string()))?;
+                let ino = row
+                    .get_value(0)
+                    .ok()
+                    .and_then(|v| v.as_integer().copied())
+                    .ok_or_else(|| Error::Internal("failed to get inode".to_string()))?;
 
-        // Create directory entry
-        let mut stmt = conn
-            .prepare_cached("INSERT INTO fs_dentry (name, parent_ino, ino) VALUES (?, ?, ?)")
-            .await?;
-        stmt.execute((name, parent_ino, ino)).await?;
+                // Create directory entry
+                let mut stmt = conn
+                    .prepare_cached("INSERT INTO fs_dentry (name, parent_ino, ino) VALUES (?, ?, ?)")
+                    .await?;
+                stmt.execute((name, parent_ino, ino)).await?;
 
-        // Increment link count
-        let mut stmt = conn
-            .prepare_cached("UPDATE fs_inode SET nlink = nlink + 1 WHERE ino = ?")
-            .await?;
-        stmt.execute((ino,)).await?;
+                // Increment link count
+                let mut stmt = conn
+                    .prepare_cached("UPDATE fs_inode SET nlink = nlink + 1 WHERE ino = ?")
+                    .await?;
+                stmt.execute((ino,)).await?;
 
-        // Update parent directory ctime and mtime
-        let mut stmt = conn
-            .prepare_cached("UPDATE fs_inode SET ctime = ?, mtime = ?, ctime_nsec = ?, mtime_nsec = ? WHERE ino = ?")
-            .await?;
-        stmt.execute((now_secs, now_secs, now_nsec, now_nsec, parent_ino))
-            .await?;
+                // Update parent directory ctime and mtime
+                let mut stmt = conn
+                    .prepare_cached("UPDATE fs_inode SET ctime = ?, mtime = ?, ctime_nsec = ?, mtime_nsec = ? WHERE ino = ?")
+                    .await?;
+                stmt.execute((now_secs, now_secs, now_nsec, now_nsec, parent_ino))
+                    .await?;
 
-        // Populate dentry cache
-        self.dentry_cache.insert(parent_ino, name, ino);
+                Change::new(JournalOp::Mknod)
+                    .ino(ino)
+                    .path(journal::child_path(&conn, parent_ino, name).await?)
+                    .record(&conn, self.tool_call())
+                    .await?;
 
-        Ok(Stats {
-            ino,
-            mode,
-            nlink: 1,
-            uid,
-            gid,
-            size: 0,
-            atime: now_secs,
-            mtime: now_secs,
-            ctime: now_secs,
-            atime_nsec: now_nsec as u32,
-            mtime_nsec: now_nsec as u32,
-            ctime_nsec: now_nsec as u32,
-            rdev,
-        })
+                commit(txn).await?;
+
+                // Populate dentry cache
+                self.dentry_cache.insert(parent_ino, name, ino);
+
+                Ok(Stats {
+                    ino,
+                    mode,
+                    nlink: 1,
+                    uid,
+                    gid,
+                    size: 0,
+                    atime: now_secs,
+                    mtime: now_secs,
+                    ctime: now_secs,
+                    atime_nsec: now_nsec as u32,
+                    mtime_nsec: now_nsec as u32,
+                    ctime_nsec: now_nsec as u32,
+                    rdev,
+                })
+            })
+            .await
     }
 
     async fn symlink(
@@ -3247,329 +4453,383 @@ impl FileSystem for AgentFS {
         uid: u32,
         gid: u32,
     ) -> Result<Stats> {
-        if name.len() > MAX_NAME_LEN {
-            return Err(FsError::NameTooLong.into());
-        }
-        let conn = self.pool.get_connection().await?;
+        self.pool
+            .retry(|| async move {
+                if name.len() > MAX_NAME_LEN {
+                    return Err(FsError::NameTooLong.into());
+                }
+                let conn = self.pool.get_connection().await?;
+                let txn = conn.begin_write().await?;
 
-        // Check if entry already exists
-        if self.lookup_child(&conn, parent_ino, name).await?.is_some() {
-            return Err(FsError::AlreadyExists.into());
-        }
+                // Check if entry already exists
+                if self.lookup_child(&conn, parent_ino, name).await?.is_some() {
+                    return Err(FsError::AlreadyExists.into());
+                }
 
-        // Create inode for symlink
-        let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
-        let now_secs = dur.as_secs() as i64;
-        let now_nsec = dur.subsec_nanos() as i64;
-        let mode = S_IFLNK | 0o777; // Symlinks typically have 777 permissions
-        let size = target.len() as i64;
+                // Create inode for symlink
+                let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                let now_secs = dur.as_secs() as i64;
+                let now_nsec = dur.subsec_nanos() as i64;
+                let mode = S_IFLNK | 0o777; // Symlinks typically have 777 permissions
+                let size = target.len() as i64;
 
-        let mut stmt = conn
-            .prepare_cached(
-                "INSERT INTO fs_inode (mode, uid, gid, size, atime, mtime, ctime, atime_nsec, mtime_nsec, ctime_nsec)
-                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING ino",
-            )
-            .await?;
-        let row = stmt
-            .query_row((
-                mode, uid, gid, size, now_secs, now_secs, now_secs, now_nsec, now_nsec, now_nsec,
-            ))
-            .await?;
+                let mut stmt = conn
+                    .prepare_cached(
+                        "INSERT INTO fs_inode (mode, uid, gid, size, atime, mtime, ctime, atime_nsec, mtime_nsec, ctime_nsec)
+                         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING ino",
+                    )
+                    .await?;
+                let row = stmt
+                    .query_row((
+                        mode, uid, gid, size, now_secs, now_secs, now_secs, now_nsec, now_nsec, now_nsec,
+                    ))
+                    .await?;
 
-        let ino = row
-            .get_value(0)
-            .ok()
-            .and_then(|v| v.as_integer().copied())
-            .ok_or_else(|| Error::Internal("failed to get inode".to_string()))?;
+                let ino = row
+                    .get_value(0)
+                    .ok()
+                    .and_then(|v| v.as_integer().copied())
+                    .ok_or_else(|| Error::Internal("failed to get inode".to_string()))?;
 
-        // Store symlink target
-        conn.execute(
-            "INSERT INTO fs_symlink (ino, target) VALUES (?, ?)",
-            (ino, target),
-        )
-        .await?;
+                // Store symlink target
+                conn.execute(
+                    "INSERT INTO fs_symlink (ino, target) VALUES (?, ?)",
+                    (ino, target),
+                )
+                .await?;
 
-        // Create directory entry
-        conn.execute(
-            "INSERT INTO fs_dentry (name, parent_ino, ino) VALUES (?, ?, ?)",
-            (name, parent_ino, ino),
-        )
-        .await?;
+                // Create directory entry
+                conn.execute(
+                    "INSERT INTO fs_dentry (name, parent_ino, ino) VALUES (?, ?, ?)",
+                    (name, parent_ino, ino),
+                )
+                .await?;
 
-        // Increment link count
-        conn.execute(
-            "UPDATE fs_inode SET nlink = nlink + 1 WHERE ino = ?",
-            (ino,),
-        )
-        .await?;
+                // Increment link count
+                conn.execute(
+                    "UPDATE fs_inode SET nlink = nlink + 1 WHERE ino = ?",
+                    (ino,),
+                )
+                .await?;
 
-        // Update parent directory ctime and mtime
-        conn.execute(
-            "UPDATE fs_inode SET ctime = ?, mtime = ?, ctime_nsec = ?, mtime_nsec = ? WHERE ino = ?",
-            (now_secs, now_secs, now_nsec, now_nsec, parent_ino),
-        )
-        .await?;
+                // Update parent directory ctime and mtime
+                conn.execute(
+                    "UPDATE fs_inode SET ctime = ?, mtime = ?, ctime_nsec = ?, mtime_nsec = ? WHERE ino = ?",
+                    (now_secs, now_secs, now_nsec, now_nsec, parent_ino),
+                )
+                .await?;
 
-        // Populate dentry cache
-        self.dentry_cache.insert(parent_ino, name, ino);
+                Change::new(JournalOp::Symlink)
+                    .ino(ino)
+                    .path(journal::child_path(&conn, parent_ino, name).await?)
+                    .detail(target)
+                    .record(&conn, self.tool_call())
+                    .await?;
 
-        Ok(Stats {
-            ino,
-            mode,
-            nlink: 1,
-            uid,
-            gid,
-            size,
-            atime: now_secs,
-            mtime: now_secs,
-            ctime: now_secs,
-            atime_nsec: now_nsec as u32,
-            mtime_nsec: now_nsec as u32,
-            ctime_nsec: now_nsec as u32,
-            rdev: 0,
-        })
+                commit(txn).await?;
+
+                // Populate dentry cache
+                self.dentry_cache.insert(parent_ino, name, ino);
+
+                Ok(Stats {
+                    ino,
+                    mode,
+                    nlink: 1,
+                    uid,
+                    gid,
+                    size,
+                    atime: now_secs,
+                    mtime: now_secs,
+                    ctime: now_secs,
+                    atime_nsec: now_nsec as u32,
+                    mtime_nsec: now_nsec as u32,
+                    ctime_nsec: now_nsec as u32,
+                    rdev: 0,
+                })
+            })
+            .await
     }
 
     async fn unlink(&self, parent_ino: i64, name: &str) -> Result<()> {
-        if name.len() > MAX_NAME_LEN {
-            return Err(FsError::NameTooLong.into());
-        }
-        let conn = self.pool.get_connection().await?;
+        self.pool
+            .retry(|| async move {
+                if name.len() > MAX_NAME_LEN {
+                    return Err(FsError::NameTooLong.into());
+                }
+                let conn = self.pool.get_connection().await?;
+                let txn = conn.begin_write().await?;
 
-        // Look up the child inode
-        let ino = self
-            .lookup_child(&conn, parent_ino, name)
-            .await?
-            .ok_or(FsError::NotFound)?;
+                // Look up the child inode
+                let ino = self
+                    .lookup_child(&conn, parent_ino, name)
+                    .await?
+                    .ok_or(FsError::NotFound)?;
 
-        // Check if it's a directory (use rmdir for directories)
-        let mut stmt = conn
-            .prepare_cached("SELECT mode FROM fs_inode WHERE ino = ?")
-            .await?;
-        let mut rows = stmt.query((ino,)).await?;
+                // Check if it's a directory (use rmdir for directories)
+                let mut stmt = conn
+                    .prepare_cached("SELECT mode FROM fs_inode WHERE ino = ?")
+                    .await?;
+                let mut rows = stmt.query((ino,)).await?;
 
-        if let Some(row) = rows.next().await? {
-            let mode = row
-                .get_value(0)
-                .ok()
-                .and_then(|v| v.as_integer().copied())
-                .unwrap_or(0) as u32;
+                if let Some(row) = rows.next().await? {
+                    let mode = row
+                        .get_value(0)
+                        .ok()
+                        .and_then(|v| v.as_integer().copied())
+                        .unwrap_or(0) as u32;
 
-            if (mode & S_IFMT) == super::S_IFDIR {
-                return Err(FsError::IsADirectory.into());
-            }
-        }
+                    if (mode & S_IFMT) == super::S_IFDIR {
+                        return Err(FsError::IsADirectory.into());
+                    }
+                }
 
-        // Delete the directory entry
-        let mut stmt = conn
-            .prepare_cached("DELETE FROM fs_dentry WHERE parent_ino = ? AND name = ?")
-            .await?;
-        stmt.execute((parent_ino, name)).await?;
+                // Delete the directory entry
+                let mut stmt = conn
+                    .prepare_cached("DELETE FROM fs_dentry WHERE parent_ino = ? AND name = ?")
+                    .await?;
+                stmt.execute((parent_ino, name)).await?;
 
-        // Invalidate cache
-        self.dentry_cache.remove(parent_ino, name);
+                // Update parent directory mtime and ctime
+                let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                let now_secs = dur.as_secs() as i64;
+                let now_nsec = dur.subsec_nanos() as i64;
+                let mut stmt = conn
+                    .prepare_cached("UPDATE fs_inode SET mtime = ?, ctime = ?, mtime_nsec = ?, ctime_nsec = ? WHERE ino = ?")
+                    .await?;
+                stmt.execute((now_secs, now_secs, now_nsec, now_nsec, parent_ino))
+                    .await?;
 
-        // Update parent directory mtime and ctime
-        let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
-        let now_secs = dur.as_secs() as i64;
-        let now_nsec = dur.subsec_nanos() as i64;
-        let mut stmt = conn
-            .prepare_cached("UPDATE fs_inode SET mtime = ?, ctime = ?, mtime_nsec = ?, ctime_nsec = ? WHERE ino = ?")
-            .await?;
-        stmt.execute((now_secs, now_secs, now_nsec, now_nsec, parent_ino))
-            .await?;
+                // Decrement link count and update ctime
+                let mut stmt = conn
+                    .prepare_cached(
+                        "UPDATE fs_inode SET nlink = nlink - 1, ctime = ?, ctime_nsec = ? WHERE ino = ?",
+                    )
+                    .await?;
+                stmt.execute((now_secs, now_nsec, ino)).await?;
 
-        // Decrement link count and update ctime
-        let mut stmt = conn
-            .prepare_cached(
-                "UPDATE fs_inode SET nlink = nlink - 1, ctime = ?, ctime_nsec = ? WHERE ino = ?",
-            )
-            .await?;
-        stmt.execute((now_secs, now_nsec, ino)).await?;
+                // Check if this was the last link to the inode
+                let link_count = self.get_link_count(&conn, ino).await?;
+                if link_count == 0 {
+                    // Delete data blocks
+                    Self::delete_chunks(&conn, ino, 0, i64::MAX).await?;
 
-        // Check if this was the last link to the inode
-        let link_count = self.get_link_count(&conn, ino).await?;
-        if link_count == 0 {
-            // Delete data blocks
-            let mut stmt = conn
-                .prepare_cached("DELETE FROM fs_data WHERE ino = ?")
-                .await?;
-            stmt.execute((ino,)).await?;
+                    // Delete symlink if exists
+                    let mut stmt = conn
+                        .prepare_cached("DELETE FROM fs_symlink WHERE ino = ?")
+                        .await?;
+                    stmt.execute((ino,)).await?;
 
-            // Delete symlink if exists
-            let mut stmt = conn
-                .prepare_cached("DELETE FROM fs_symlink WHERE ino = ?")
-                .await?;
-            stmt.execute((ino,)).await?;
+                    // Delete extended attributes
+                    let mut stmt = conn
+                        .prepare_cached("DELETE FROM fs_xattr WHERE ino = ?")
+                        .await?;
+                    stmt.execute((ino,)).await?;
 
-            // Delete inode
-            let mut stmt = conn
-                .prepare_cached("DELETE FROM fs_inode WHERE ino = ?")
-                .await?;
-            stmt.execute((ino,)).await?;
-        }
+                    // Delete inode
+                    let mut stmt = conn
+                        .prepare_cached("DELETE FROM fs_inode WHERE ino = ?")
+                        .await?;
+                    stmt.execute((ino,)).await?;
+                }
 
-        Ok(())
+                Change::new(JournalOp::Unlink)
+                    .ino(ino)
+                    .path(journal::child_path(&conn, parent_ino, name).await?)
+                    .record(&conn, self.tool_call())
+                    .await?;
+
+                commit(txn).await?;
+
+                // Invalidate cache
+                self.dentry_cache.remove(parent_ino, name);
+
+                Ok(())
+            })
+            .await
     }
 
     async fn rmdir(&self, parent_ino: i64, name: &str) -> Result<()> {
-        if name.len() > MAX_NAME_LEN {
-            return Err(FsError::NameTooLong.into());
-        }
-        let conn = self.pool.get_connection().await?;
+        self.pool
+            .retry(|| async move {
+                if name.len() > MAX_NAME_LEN {
+                    return Err(FsError::NameTooLong.into());
+                }
+                let conn = self.pool.get_connection().await?;
+                let txn = conn.begin_write().await?;
 
-        // Look up the child inode
-        let ino = self
-            .lookup_child(&conn, parent_ino, name)
-            .await?
-            .ok_or(FsError::NotFound)?;
+                // Look up the child inode
+                let ino = self
+                    .lookup_child(&conn, parent_ino, name)
+                    .await?
+                    .ok_or(FsError::NotFound)?;
 
-        if ino == ROOT_INO {
-            return Err(FsError::RootOperation.into());
-        }
+                if ino == ROOT_INO {
+                    return Err(FsError::RootOperation.into());
+                }
 
-        // Check if it's a directory
-        let mut stmt = conn
-            .prepare_cached("SELECT mode FROM fs_inode WHERE ino = ?")
-            .await?;
-        let mut rows = stmt.query((ino,)).await?;
+                // Check if it's a directory
+                let mut stmt = conn
+                    .prepare_cached("SELECT mode FROM fs_inode WHERE ino = ?")
+                    .await?;
+                let mut rows = stmt.query((ino,)).await?;
 
-        if let Some(row) = rows.next().await? {
-            let mode = row
-                .get_value(0)
-                .ok()
-                .and_then(|v| v.as_integer().copied())
-                .unwrap_or(0) as u32;
+                if let Some(row) = rows.next().await? {
+                    let mode = row
+                        .get_value(0)
+                        .ok()
+                        .and_then(|v| v.as_integer().copied())
+                        .unwrap_or(0) as u32;
 
-            if (mode & S_IFMT) != super::S_IFDIR {
-                return Err(FsError::NotADirectory.into());
-            }
-        } else {
-            return Err(FsError::NotFound.into());
-        }
+                    if (mode & S_IFMT) != super::S_IFDIR {
+                        return Err(FsError::NotADirectory.into());
+                    }
+                } else {
+                    return Err(FsError::NotFound.into());
+                }
 
-        // Check if directory is empty
-        let mut stmt = conn
-            .prepare_cached("SELECT COUNT(*) FROM fs_dentry WHERE parent_ino = ?")
-            .await?;
-        let mut rows = stmt.query((ino,)).await?;
+                // Check if directory is empty
+                let mut stmt = conn
+                    .prepare_cached("SELECT COUNT(*) FROM fs_dentry WHERE parent_ino = ?")
+                    .await?;
+                let mut rows = stmt.query((ino,)).await?;
 
-        if let Some(row) = rows.next().await? {
-            let count = row
-                .get_value(0)
-                .ok()
-                .and_then(|v| v.as_integer().copied())
-                .unwrap_or(0);
-            if count > 0 {
-                return Err(FsError::NotEmpty.into());
-            }
-        }
+                if let Some(row) = rows.next().await? {
+                    let count = row
+                        .get_value(0)
+                        .ok()
+                        .and_then(|v| v.as_integer().copied())
+                        .unwrap_or(0);
+                    if count > 0 {
+                        return Err(FsError::NotEmpty.into());
+                    }
+                }
 
-        // Delete the directory entry
-        let mut stmt = conn
-            .prepare_cached("DELETE FROM fs_dentry WHERE parent_ino = ? AND name = ?")
-            .await?;
-        stmt.execute((parent_ino, name)).await?;
+                // Delete the directory entry
+                let mut stmt = conn
+                    .prepare_cached("DELETE FROM fs_dentry WHERE parent_ino = ? AND name = ?")
+                    .await?;
+                stmt.execute((parent_ino, name)).await?;
 
-        // Invalidate cache
-        self.dentry_cache.remove(parent_ino, name);
+                // Decrement parent nlink (removed directory's ".." link) and update timestamps
+                let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                let now_secs = dur.as_secs() as i64;
+                let now_nsec = dur.subsec_nanos() as i64;
+                let mut stmt = conn
+                    .prepare_cached(
+                        "UPDATE fs_inode SET nlink = nlink - 1, ctime = ?, mtime = ?, ctime_nsec = ?, mtime_nsec = ? WHERE ino = ?",
+                    )
+                    .await?;
+                stmt.execute((now_secs, now_secs, now_nsec, now_nsec, parent_ino))
+                    .await?;
 
-        // Decrement link count on removed directory
-        let mut stmt = conn
-            .prepare_cached("UPDATE fs_inode SET nlink = nlink - 1 WHERE ino = ?")
-            .await?;
-        stmt.execute((ino,)).await?;
+                // A directory's only other link is its own ".", so the inode goes
+                // away with its entry
+                let mut stmt = conn
+                    .prepare_cached("DELETE FROM fs_xattr WHERE ino = ?")
+                    .await?;
+                stmt.execute((ino,)).await?;
+                let mut stmt = conn
+                    .prepare_cached("DELETE FROM fs_inode WHERE ino = ?")
+                    .await?;
+                stmt.execute((ino,)).await?;
 
-        // Decrement parent nlink (removed directory's ".." link) and update timestamps
-        let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
-        let now_secs = dur.as_secs() as i64;
-        let now_nsec = dur.subsec_nanos() as i64;
-        let mut stmt = conn
-            .prepare_cached(
-                "UPDATE fs_inode SET nlink = nlink - 1, ctime = ?, mtime = ?, ctime_nsec = ?, mtime_nsec = ? WHERE ino = ?",
-            )
-            .await?;
-        stmt.execute((now_secs, now_secs, now_nsec, now_nsec, parent_ino))
-            .await?;
+                Change::new(JournalOp::Rmdir)
+                    .ino(ino)
+                    .path(journal::child_path(&conn, parent_ino, name).await?)
+                    .record(&conn, self.tool_call())
+                    .await?;
 
-        // Delete inode if no more links
-        let link_count = self.get_link_count(&conn, ino).await?;
-        if link_count == 0 {
-            let mut stmt = conn
-                .prepare_cached("DELETE FROM fs_inode WHERE ino = ?")
-                .await?;
-            stmt.execute((ino,)).await?;
-        }
+                commit(txn).await?;
 
-        Ok(())
+                // Invalidate cache
+                self.dentry_cache.remove(parent_ino, name);
+
+                Ok(())
+            })
+            .await
     }
 
     async fn link(&self, ino: i64, newparent_ino: i64, newname: &str) -> Result<Stats> {
-        if newname.len() > MAX_NAME_LEN {
-            return Err(FsError::NameTooLong.into());
-        }
-        let conn = self.pool.get_connection().await?;
+        self.pool
+            .retry(|| async move {
+                if newname.len() > MAX_NAME_LEN {
+                    return Err(FsError::NameTooLong.into());
+                }
+                let conn = self.pool.get_connection().await?;
+                let txn = conn.begin_write().await?;
 
-        // Check if source inode exists and is not a directory
-        let mut stmt = conn
-            .prepare_cached("SELECT mode FROM fs_inode WHERE ino = ?")
-            .await?;
-        let mut rows = stmt.query((ino,)).await?;
+                // Check if source inode exists and is not a directory
+                let mut stmt = conn
+                    .prepare_cached("SELECT mode FROM fs_inode WHERE ino = ?")
+                    .await?;
+                let mut rows = stmt.query((ino,)).await?;
 
-        if let Some(row) = rows.next().await? {
-            let mode = row
-                .get_value(0)
-                .ok()
-                .and_then(|v| v.as_integer().copied())
-                .unwrap_or(0) as u32;
+                if let Some(row) = rows.next().await? {
+                    let mode = row
+                        .get_value(0)
+                        .ok()
+                        .and_then(|v| v.as_integer().copied())
+                        .unwrap_or(0) as u32;
 
-            if (mode & S_IFMT) == super::S_IFDIR {
-                return Err(FsError::IsADirectory.into());
-            }
-        } else {
-            return Err(FsError::NotFound.into());
-        }
+                    if (mode & S_IFMT) == super::S_IFDIR {
+                        return Err(FsError::IsADirectory.into());
+                    }
+                } else {
+                    return Err(FsError::NotFound.into());
+                }
 
-        // Check if destination already exists
-        if self
-            .lookup_child(&conn, newparent_ino, newname)
-            .await?
-            .is_some()
-        {
-            return Err(FsError::AlreadyExists.into());
-        }
+                // Check if destination already exists
+                if self
+                    .lookup_child(&conn, newparent_ino, newname)
+                    .await?
+                    .is_some()
+                {
+                    return Err(FsError::AlreadyExists.into());
+                }
 
-        // Create directory entry pointing to the same inode
-        conn.execute(
-            "INSERT INTO fs_dentry (name, parent_ino, ino) VALUES (?, ?, ?)",
-            (newname, newparent_ino, ino),
-        )
-        .await?;
+                // Create directory entry pointing to the same inode
+                conn.execute(
+                    "INSERT INTO fs_dentry (name, parent_ino, ino) VALUES (?, ?, ?)",
+                    (newname, newparent_ino, ino),
+                )
+                .await?;
 
-        // Increment link count and update ctime
-        let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
-        let now_secs = dur.as_secs() as i64;
-        let now_nsec = dur.subsec_nanos() as i64;
-        conn.execute(
-            "UPDATE fs_inode SET nlink = nlink + 1, ctime = ?, ctime_nsec = ? WHERE ino = ?",
-            (now_secs, now_nsec, ino),
-        )
-        .await?;
+                // Increment link count and update ctime
+                let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                let now_secs = dur.as_secs() as i64;
+                let now_nsec = dur.subsec_nanos() as i64;
+                conn.execute(
+                    "UPDATE fs_inode SET nlink = nlink + 1, ctime = ?, ctime_nsec = ? WHERE ino = ?",
+                    (now_secs, now_nsec, ino),
+                )
+                .await?;
 
-        // Update parent directory ctime and mtime
-        conn.execute(
-            "UPDATE fs_inode SET ctime = ?, mtime = ?, ctime_nsec = ?, mtime_nsec = ? WHERE ino = ?",
-            (now_secs, now_secs, now_nsec, now_nsec, newparent_ino),
-        )
-        .await?;
+                // Update parent directory ctime and mtime
+                conn.execute(
+                    "UPDATE fs_inode SET ctime = ?, mtime = ?, ctime_nsec = ?, mtime_nsec = ? WHERE ino = ?",
+                    (now_secs, now_secs, now_nsec, now_nsec, newparent_ino),
+                )
+                .await?;
 
-        // Populate dentry cache
-        self.dentry_cache.insert(newparent_ino, newname, ino);
+                // The source path is reported as the inode's oldest link
+                Change::new(JournalOp::Link)
+                    .ino(ino)
+                    .path(journal::inode_path(&conn, ino).await?.unwrap_or_default())
+                    .new_path(journal::child_path(&conn, newparent_ino, newname).await?)
+                    .record(&conn, self.tool_call())
+                    .await?;
 
-        // Return updated stats
-        self.getattr_with_conn(&conn, ino)
-            .await?
-            .ok_or(FsError::NotFound.into())
+                commit(txn).await?;
+
+                // Populate dentry cache
+                self.dentry_cache.insert(newparent_ino, newname, ino);
+
+                // Return updated stats
+                self.getattr_with_conn(&conn, ino)
+                    .await?
+                    .ok_or(FsError::NotFound.into())
+            })
+            .await
     }
 
     async fn rename(
@@ -3579,173 +4839,336 @@ impl FileSystem for AgentFS {
         newparent_ino: i64,
         newname: &str,
     ) -> Result<()> {
-        if newname.len() > MAX_NAME_LEN {
-            return Err(FsError::NameTooLong.into());
-        }
-        let conn = self.pool.get_connection().await?;
+        self.pool
+            .retry(|| async move {
+                if newname.len() > MAX_NAME_LEN {
+                    return Err(FsError::NameTooLong.into());
+                }
+                let conn = self.pool.get_connection().await?;
 
-        // Get source inode
-        let src_ino = self
-            .lookup_child(&conn, oldparent_ino, oldname)
-            .await?
-            .ok_or(FsError::NotFound)?;
+                // Get source inode
+                let src_ino = self
+                    .lookup_child(&conn, oldparent_ino, oldname)
+                    .await?
+                    .ok_or(FsError::NotFound)?;
 
-        if src_ino == ROOT_INO {
-            return Err(FsError::RootOperation.into());
-        }
+                if src_ino == ROOT_INO {
+                    return Err(FsError::RootOperation.into());
+                }
 
-        // Get source stats to check if it's a directory
-        let src_stats = self
-            .getattr_with_conn(&conn, src_ino)
-            .await?
-            .ok_or(FsError::NotFound)?;
+                // Get source stats to check if it's a directory
+                let src_stats = self
+                    .getattr_with_conn(&conn, src_ino)
+                    .await?
+                    .ok_or(FsError::NotFound)?;
 
-        let txn = Transaction::new_unchecked(&conn, TransactionBehavior::Immediate).await?;
+                let txn = conn.begin_write().await?;
 
-        let result: Result<()> = async {
-            // Check if destination exists
-            if let Some(dst_ino) = self.lookup_child(&conn, newparent_ino, newname).await? {
-                let dst_stats = self.getattr_with_conn(&conn, dst_ino).await?.ok_or(FsError::NotFound)?;
+                let result: Result<()> = async {
+                    let from_path = journal::child_path(&conn, oldparent_ino, oldname).await?;
+                    let to_path = journal::child_path(&conn, newparent_ino, newname).await?;
 
-                // Can't replace directory with non-directory
-                if dst_stats.is_directory() && !src_stats.is_directory() {
-                    return Err(FsError::IsADirectory.into());
-                }
+                    // Check if destination exists
+                    if let Some(dst_ino) = self.lookup_child(&conn, newparent_ino, newname).await? {
+                        let dst_stats = self.getattr_with_conn(&conn, dst_ino).await?.ok_or(FsError::NotFound)?;
 
-                // Can't replace non-directory with directory
-                if !dst_stats.is_directory() && src_stats.is_directory() {
-                    return Err(FsError::NotADirectory.into());
-                }
+                        // Can't replace directory with non-directory
+                        if dst_stats.is_directory() && !src_stats.is_directory() {
+                            return Err(FsError::IsADirectory.into());
+                        }
+
+                        // Can't replace non-directory with directory
+                        if !dst_stats.is_directory() && src_stats.is_directory() {
+                            return Err(FsError::NotADirectory.into());
+                        }
+
+                        // If destination is directory, it must be empty
+                        if dst_stats.is_directory() {
+                            let mut stmt = conn
+                                .prepare_cached("SELECT COUNT(*) FROM fs_dentry WHERE parent_ino = ?")
+                                .await?;
+                            let mut rows = stmt.query((dst_ino,)).await?;
+
+                            if let Some(row) = rows.next().await? {
+                                let count = row
+                                    .get_value(0)
+                                    .ok()
+                                    .and_then(|v| v.as_integer().copied())
+                                    .unwrap_or(0);
+                                if count > 0 {
+                                    return Err(FsError::NotEmpty.into());
+                                }
+                            }
+                        }
 
-                // If destination is directory, it must be empty
-                if dst_stats.is_directory() {
+                        // Remove destination entry
+                        let mut stmt = conn
+                            .prepare_cached("DELETE FROM fs_dentry WHERE parent_ino = ? AND name = ?")
+                            .await?;
+                        stmt.execute((newparent_ino, newname)).await?;
+
+                        // Decrement link count and update ctime on destination inode
+                        let dur_dec = SystemTime::now()
+                            .duration_since(UNIX_EPOCH)
+                            .unwrap_or_default();
+                        let now_dec = dur_dec.as_secs() as i64;
+                        let now_dec_nsec = dur_dec.subsec_nanos() as i64;
+                        let mut stmt = conn
+                            .prepare_cached("UPDATE fs_inode SET nlink = nlink - 1, ctime = ?, ctime_nsec = ? WHERE ino = ?")
+                            .await?;
+                        stmt.execute((now_dec, now_dec_nsec, dst_ino)).await?;
+
+                        // A replaced directory goes away with its entry, along with
+                        // its ".." link to the parent
+                        if dst_stats.is_directory() {
+                            let mut stmt = conn
+                                .prepare_cached("UPDATE fs_inode SET nlink = nlink - 1 WHERE ino = ?")
+                                .await?;
+                            stmt.execute((newparent_ino,)).await?;
+                        }
+
+                        // Clean up destination inode if no more links
+                        let link_count = self.get_link_count(&conn, dst_ino).await?;
+                        if link_count == 0 || dst_stats.is_directory() {
+                            Self::delete_chunks(&conn, dst_ino, 0, i64::MAX).await?;
+                            let mut stmt = conn
+                                .prepare_cached("DELETE FROM fs_symlink WHERE ino = ?")
+                                .await?;
+                            stmt.execute((dst_ino,)).await?;
+                            let mut stmt = conn
+                                .prepare_cached("DELETE FROM fs_xattr WHERE ino = ?")
+                                .await?;
+                            stmt.execute((dst_ino,)).await?;
+                            let mut stmt = conn
+                                .prepare_cached("DELETE FROM fs_inode WHERE ino = ?")
+                                .await?;
+                            stmt.execute((dst_ino,)).await?;
+                        }
+                    }
+
+                    // Update the dentry: change parent and/or name
                     let mut stmt = conn
-                        .prepare_cached("SELECT COUNT(*) FROM fs_dentry WHERE parent_ino = ?")
+                        .prepare_cached(
+                            "UPDATE fs_dentry SET parent_ino = ?, name = ? WHERE parent_ino = ? AND name = ?",
+                        )
+                        .await?;
+                    stmt.execute((newparent_ino, newname, oldparent_ino, oldname))
                         .await?;
-                    let mut rows = stmt.query((dst_ino,)).await?;
 
-                    if let Some(row) = rows.next().await? {
-                        let count = row
-                            .get_value(0)
-                            .ok()
-                            .and_then(|v| v.as_integer().copied())
-                            .unwrap_or(0);
-                        if count > 0 {
-                            return Err(FsError::NotEmpty.into());
-                        }
+                    // If renaming a directory across parents, adjust parent nlink counts
+                    // (the ".." link moves from old parent to new parent)
+                    if src_stats.is_directory() && oldparent_ino != newparent_ino {
+                        let mut stmt = conn
+                            .prepare_cached("UPDATE fs_inode SET nlink = nlink - 1 WHERE ino = ?")
+                            .await?;
+                        stmt.execute((oldparent_ino,)).await?;
+
+                        let mut stmt = conn
+                            .prepare_cached("UPDATE fs_inode SET nlink = nlink + 1 WHERE ino = ?")
+                            .await?;
+                        stmt.execute((newparent_ino,)).await?;
                     }
-                }
 
-                // Remove destination entry
-                let mut stmt = conn
-                    .prepare_cached("DELETE FROM fs_dentry WHERE parent_ino = ? AND name = ?")
-                    .await?;
-                stmt.execute((newparent_ino, newname)).await?;
-
-                // Decrement link count and update ctime on destination inode
-                let dur_dec = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap_or_default();
-                let now_dec = dur_dec.as_secs() as i64;
-                let now_dec_nsec = dur_dec.subsec_nanos() as i64;
-                let mut stmt = conn
-                    .prepare_cached("UPDATE fs_inode SET nlink = nlink - 1, ctime = ?, ctime_nsec = ? WHERE ino = ?")
-                    .await?;
-                stmt.execute((now_dec, now_dec_nsec, dst_ino)).await?;
+                    // Update ctime of the inode
+                    let dur = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default();
+                    let now_secs = dur.as_secs() as i64;
+                    let now_nsec = dur.subsec_nanos() as i64;
 
-                // Clean up destination inode if no more links
-                let link_count = self.get_link_count(&conn, dst_ino).await?;
-                if link_count == 0 {
                     let mut stmt = conn
-                        .prepare_cached("DELETE FROM fs_data WHERE ino = ?")
+                        .prepare_cached("UPDATE fs_inode SET ctime = ?, ctime_nsec = ? WHERE ino = ?")
                         .await?;
-                    stmt.execute((dst_ino,)).await?;
+                    stmt.execute((now_secs, now_nsec, src_ino)).await?;
+
+                    // Update source parent directory timestamps
                     let mut stmt = conn
-                        .prepare_cached("DELETE FROM fs_symlink WHERE ino = ?")
+                        .prepare_cached("UPDATE fs_inode SET mtime = ?, ctime = ?, mtime_nsec = ?, ctime_nsec = ? WHERE ino = ?")
                         .await?;
-                    stmt.execute((dst_ino,)).await?;
-                    let mut stmt = conn
-                        .prepare_cached("DELETE FROM fs_inode WHERE ino = ?")
+                    stmt.execute((now_secs, now_secs, now_nsec, now_nsec, oldparent_ino)).await?;
+
+                    // Update destination parent directory timestamps
+                    if newparent_ino != oldparent_ino {
+                        let mut stmt = conn
+                            .prepare_cached("UPDATE fs_inode SET mtime = ?, ctime = ?, mtime_nsec = ?, ctime_nsec = ? WHERE ino = ?")
+                            .await?;
+                        stmt.execute((now_secs, now_secs, now_nsec, now_nsec, newparent_ino)).await?;
+                    }
+
+                    Change::new(JournalOp::Rename)
+                        .ino(src_ino)
+                        .path(from_path)
+                        .new_path(to_path)
+                        .record(&conn, self.tool_call())
                         .await?;
-                    stmt.execute((dst_ino,)).await?;
+
+                    Ok(())
                 }
+                .await;
+
+                match result {
+                    Ok(()) => {
+                        commit(txn).await?;
+
+                        // Invalidate cache for source and destination
+                        self.dentry_cache.remove(oldparent_ino, oldname);
+                        self.dentry_cache.remove(newparent_ino, newname);
+
+                        // Add new entry to cache (source inode is now at destination)
+                        self.dentry_cache.insert(newparent_ino, newname, src_ino);
+
+                        Ok(())
+                    }
+                    Err(e) => {
+                        let _ = txn.rollback().await;
+                        Err(e)
+                    }
+                }
+            })
+            .await
+    }
+
+    async fn statfs(&self) -> Result<FilesystemStats> {
+        AgentFS::statfs(self).await
+    }
+
+    async fn getxattr(&self, ino: i64, name: &str) -> Result<Option<Vec<u8>>> {
+        Self::check_xattr_name(name)?;
+        let conn = self.pool.get_read_connection().await?;
+        self.ensure_inode_exists(&conn, ino).await?;
+
+        let mut stmt = conn
+            .prepare_cached("SELECT value FROM fs_xattr WHERE ino = ? AND name = ?")
+            .await?;
+        let mut rows = stmt.query((ino, name)).await?;
+
+        if let Some(row) = rows.next().await? {
+            match row.get_value(0) {
+                Ok(Value::Blob(value)) => Ok(Some(value)),
+                Ok(Value::Text(value)) => Ok(Some(value.into_bytes())),
+                _ => Ok(Some(Vec::new())),
             }
+        } else {
+            Ok(None)
+        }
+    }
 
-            // Update the dentry: change parent and/or name
-            let mut stmt = conn
-                .prepare_cached(
-                    "UPDATE fs_dentry SET parent_ino = ?, name = ? WHERE parent_ino = ? AND name = ?",
-                )
-                .await?;
-            stmt.execute((newparent_ino, newname, oldparent_ino, oldname))
-                .await?;
+    async fn setxattr(&self, ino: i64, name: &str, value: &[u8], flags: i32) -> Result<()> {
+        self.pool
+            .retry(|| async move {
+                Self::check_xattr_name(name)?;
+                let conn = self.pool.get_connection().await?;
+                let txn = conn.begin_write().await?;
+                self.ensure_inode_exists(&conn, ino).await?;
+
+                if flags & (XATTR_CREATE | XATTR_REPLACE) != 0 {
+                    let mut stmt = conn
+                        .prepare_cached("SELECT 1 FROM fs_xattr WHERE ino = ? AND name = ?")
+                        .await?;
+                    let mut rows = stmt.query((ino, name)).await?;
+                    let exists = rows.next().await?.is_some();
+
+                    if flags & XATTR_CREATE != 0 && exists {
+                        return Err(FsError::AlreadyExists.into());
+                    }
+                    if flags & XATTR_REPLACE != 0 && !exists {
+                        return Err(FsError::NoAttribute.into());
+                    }
+                }
 
-            // If renaming a directory across parents, adjust parent nlink counts
-            // (the ".." link moves from old parent to new parent)
-            if src_stats.is_directory() && oldparent_ino != newparent_ino {
                 let mut stmt = conn
-                    .prepare_cached("UPDATE fs_inode SET nlink = nlink - 1 WHERE ino = ?")
+                    .prepare_cached(
+                        "INSERT OR REPLACE INTO fs_xattr (ino, name, value) VALUES (?, ?, ?)",
+                    )
+                    .await?;
+                stmt.execute((ino, name, Value::Blob(value.to_vec())))
                     .await?;
-                stmt.execute((oldparent_ino,)).await?;
 
+                // Extended attribute changes update ctime
+                let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                let now_secs = dur.as_secs() as i64;
+                let now_nsec = dur.subsec_nanos() as i64;
                 let mut stmt = conn
-                    .prepare_cached("UPDATE fs_inode SET nlink = nlink + 1 WHERE ino = ?")
+                    .prepare_cached("UPDATE fs_inode SET ctime = ?, ctime_nsec = ? WHERE ino = ?")
                     .await?;
-                stmt.execute((newparent_ino,)).await?;
-            }
+                stmt.execute((now_secs, now_nsec, ino)).await?;
 
-            // Update ctime of the inode
-            let dur = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap_or_default();
-            let now_secs = dur.as_secs() as i64;
-            let now_nsec = dur.subsec_nanos() as i64;
+                Change::new(JournalOp::SetXattr)
+                    .ino(ino)
+                    .detail(name)
+                    .record(&conn, self.tool_call())
+                    .await?;
+
+                commit(txn).await?;
+
+                Ok(())
+            })
+            .await
+    }
+
+    async fn listxattr(&self, ino: i64) -> Result<Vec<String>> {
+        let conn = self.pool.get_read_connection().await?;
+        self.ensure_inode_exists(&conn, ino).await?;
+
+        let mut stmt = conn
+            .prepare_cached("SELECT name FROM fs_xattr WHERE ino = ? ORDER BY name")
+            .await?;
+        let mut rows = stmt.query((ino,)).await?;
 
-            let mut stmt = conn
-                .prepare_cached("UPDATE fs_inode SET ctime = ?, ctime_nsec = ? WHERE ino = ?")
-                .await?;
-            stmt.execute((now_secs, now_nsec, src_ino)).await?;
+        let mut names = Vec::new();
+        while let Some(row) = rows.next().await? {
+            if let Ok(Value::Text(name)) = row.get_value(0) {
+                names.push(name);
+            }
+        }
+        Ok(names)
+    }
 
-            // Update source parent directory timestamps
-            let mut stmt = conn
-                .prepare_cached("UPDATE fs_inode SET mtime = ?, ctime = ?, mtime_nsec = ?, ctime_nsec = ? WHERE ino = ?")
-                .await?;
-            stmt.execute((now_secs, now_secs, now_nsec, now_nsec, oldparent_ino)).await?;
+    async fn removexattr(&self, ino: i64, name: &str) -> Result<()> {
+        self.pool
+            .retry(|| async move {
+                Self::check_xattr_name(name)?;
+                let conn = self.pool.get_connection().await?;
+                let txn = conn.begin_write().await?;
+                self.ensure_inode_exists(&conn, ino).await?;
 
-            // Update destination parent directory timestamps
-            if newparent_ino != oldparent_ino {
                 let mut stmt = conn
-                    .prepare_cached("UPDATE fs_inode SET mtime = ?, ctime = ?, mtime_nsec = ?, ctime_nsec = ? WHERE ino = ?")
+                    .prepare_cached("SELECT 1 FROM fs_xattr WHERE ino = ? AND name = ?")
                     .await?;
-                stmt.execute((now_secs, now_secs, now_nsec, now_nsec, newparent_ino)).await?;
-            }
+                let mut rows = stmt.query((ino, name)).await?;
+                if rows.next().await?.is_none() {
+                    return Err(FsError::NoAttribute.into());
+                }
 
-            Ok(())
-        }
-        .await;
+                let mut stmt = conn
+                    .prepare_cached("DELETE FROM fs_xattr WHERE ino = ? AND name = ?")
+                    .await?;
+                stmt.execute((ino, name)).await?;
 
-        match result {
-            Ok(()) => {
-                txn.commit().await?;
+                let dur = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                let now_secs = dur.as_secs() as i64;
+                let now_nsec = dur.subsec_nanos() as i64;
+                let mut stmt = conn
+                    .prepare_cached("UPDATE fs_inode SET ctime = ?, ctime_nsec = ? WHERE ino = ?")
+                    .await?;
+                stmt.execute((now_secs, now_nsec, ino)).await?;
 
-                // Invalidate cache for source and destination
-                self.dentry_cache.remove(oldparent_ino, oldname);
-                self.dentry_cache.remove(newparent_ino, newname);
+                Change::new(JournalOp::RemoveXattr)
+                    .ino(ino)
+                    .detail(name)
+                    .record(&conn, self.tool_call())
+                    .await?;
 
-                // Add new entry to cache (source inode is now at destination)
-                self.dentry_cache.insert(newparent_ino, newname, src_ino);
+                commit(txn).await?;
 
                 Ok(())
-            }
-            Err(e) => {
-                let _ = txn.rollback().await;
-                Err(e)
-            }
-        }
+            })
+            .await
     }
 
-    async fn statfs(&self) -> Result<FilesystemStats> {
-        AgentFS::statfs(self).await
+    async fn inode_path(&self, ino: i64) -> Result<Option<String>> {
+        let conn = self.pool.get_read_connection().await?;
+        journal::inode_path(&conn, ino).await
     }
 }
 
@@ -3754,6 +5177,9 @@ mod tests {
     use super::*;
     use tempfile::tempdir;
 
+    /// Makes [`commit`] abort the process, as if it crashed mid-mutation
+    pub(super) static CRASH_BEFORE_COMMIT: AtomicBool = AtomicBool::new(false);
+
     async fn create_test_fs() -> Result<(AgentFS, tempfile::TempDir)> {
         let dir = tempdir()?;
         let db_path = dir.path().join("test.db");
@@ -3767,8 +5193,8 @@ mod tests {
     async fn test_file_smaller_than_chunk_size() -> Result<()> {
         let (fs, _dir) = create_test_fs().await?;
 
-        // Write a file smaller than chunk_size (100 bytes)
-        let data = vec![0u8; 100];
+        // Write a file smaller than chunk_size, too large to store inline
+        let data = vec![0u8; INLINE_DATA_MAX_SIZE as usize + 100];
         let (_, file) = fs
             .create_file("/small.txt", DEFAULT_FILE_MODE, 0, 0)
             .await?;
@@ -3776,7 +5202,7 @@ mod tests {
 
         // Read it back
         let read_data = fs.read_file("/small.txt").await?.unwrap();
-        assert_eq!(read_data.len(), 100);
+        assert_eq!(read_data.len(), data.len());
         assert_eq!(read_data, data);
 
         // Verify only 1 chunk was created
@@ -3979,23 +5405,25 @@ mod tests {
             .create_file("/overwrite.txt", DEFAULT_FILE_MODE, 0, 0)
             .await?;
         file.pwrite(0, &initial_data).await?;
+        file.flush().await?;
 
         let ino = fs.resolve_path("/overwrite.txt").await?.unwrap();
         let initial_chunk_count = fs.get_chunk_count(ino).await?;
         assert_eq!(initial_chunk_count, 3);
 
-        // Overwrite with smaller file (1 chunk)
+        // Overwrite with small file (stored in the inode)
         let new_data = vec![42u8; 100];
         fs.truncate("/overwrite.txt", 0).await?;
         let file = fs.open("/overwrite.txt").await?;
         file.pwrite(0, &new_data).await?;
+        file.flush().await?;
 
         // Verify old chunks are gone and new data is correct
         let read_data = fs.read_file("/overwrite.txt").await?.unwrap();
         assert_eq!(read_data, new_data);
 
         let new_chunk_count = fs.get_chunk_count(ino).await?;
-        assert_eq!(new_chunk_count, 1);
+        assert_eq!(new_chunk_count, 0);
 
         // Verify size is updated
         let stats = fs.stat("/overwrite.txt").await?.unwrap();
@@ -4010,19 +5438,21 @@ mod tests {
 
         let chunk_size = fs.chunk_size();
 
-        // Write initial small file (1 chunk)
+        // Write initial small file (stored in the inode)
         let initial_data = vec![1u8; 100];
         let (_, file) = fs.create_file("/grow.txt", DEFAULT_FILE_MODE, 0, 0).await?;
         file.pwrite(0, &initial_data).await?;
+        file.flush().await?;
 
         let ino = fs.resolve_path("/grow.txt").await?.unwrap();
-        assert_eq!(fs.get_chunk_count(ino).await?, 1);
+        assert_eq!(fs.get_chunk_count(ino).await?, 0);
 
         // Overwrite with larger file (3 chunks)
         let new_data: Vec<u8> = (0..chunk_size * 3).map(|i| (i % 256) as u8).collect();
         fs.truncate("/grow.txt", 0).await?;
         let file = fs.open("/grow.txt").await?;
         file.pwrite(0, &new_data).await?;
+        file.flush().await?;
 
         // Verify data is correct
         let read_data = fs.read_file("/grow.txt").await?.unwrap();
@@ -4058,6 +5488,20 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_dentry_cache_drops_stale_reads() {
+        let cache = DentryCache::new(16);
+        let generation = cache.generation();
+        cache.insert_since(generation, 1, "a", 2);
+        assert_eq!(cache.get(1, "a"), Some(2));
+
+        // A reader that started before a removal doesn't cache what it read
+        let generation = cache.generation();
+        cache.remove(1, "a");
+        cache.insert_since(generation, 1, "a", 2);
+        assert_eq!(cache.get(1, "a"), None);
+    }
+
     // ==================== Configuration Tests ====================
 
     #[tokio::test]
@@ -4081,6 +5525,7 @@ mod tests {
         let data = vec![0u8; chunk_size * 2 + 1];
         let (_, file) = fs.create_file("/test.bin", DEFAULT_FILE_MODE, 0, 0).await?;
         file.pwrite(0, &data).await?;
+        file.flush().await?;
 
         let ino = fs.resolve_path("/test.bin").await?.unwrap();
         let chunk_count = fs.get_chunk_count(ino).await?;
@@ -4127,6 +5572,7 @@ mod tests {
             .create_file("/unique.txt", DEFAULT_FILE_MODE, 0, 0)
             .await?;
         file.pwrite(0, &data).await?;
+        file.flush().await?;
 
         let ino = fs.resolve_path("/unique.txt").await?.unwrap();
 
@@ -4156,6 +5602,7 @@ mod tests {
             .create_file("/ordered.bin", DEFAULT_FILE_MODE, 0, 0)
             .await?;
         file.pwrite(0, &data).await?;
+        file.flush().await?;
 
         let ino = fs.resolve_path("/ordered.bin").await?.unwrap();
 
@@ -4183,6 +5630,121 @@ mod tests {
         Ok(())
     }
 
+    // ==================== Deduplication Tests ====================
+
+    async fn query_count(fs: &AgentFS, sql: &str) -> Result<i64> {
+        let conn = fs.pool.get_connection().await?;
+        let mut rows = conn.query(sql, ()).await?;
+        Ok(rows
+            .next()
+            .await?
+            .and_then(|r| r.get_value(0).ok().and_then(|v| v.as_integer().copied()))
+            .unwrap_or(-1))
+    }
+
+    #[tokio::test]
+    async fn test_dedup_shares_identical_chunks() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+        fs.enable_dedup().await?;
+        assert!(fs.dedup_enabled());
+
+        let chunk_size = fs.chunk_size();
+        // Two distinct chunks, written to two files
+        let mut data = vec![1u8; chunk_size];
+        data.extend(vec![2u8; chunk_size]);
+        for path in ["/a.bin", "/b.bin"] {
+            let (_, file) = fs.create_file(path, DEFAULT_FILE_MODE, 0, 0).await?;
+            file.pwrite(0, &data).await?;
+        }
+
+        assert_eq!(fs.read_file("/a.bin").await?.unwrap(), data);
+        assert_eq!(fs.pread("/b.bin", 10, 10).await?.unwrap(), vec![1u8; 10]);
+        assert_eq!(query_count(&fs, "SELECT COUNT(*) FROM fs_chunk").await?, 2);
+        assert_eq!(
+            query_count(&fs, "SELECT SUM(refcount) FROM fs_chunk").await?,
+            4
+        );
+
+        let stats = fs.statfs().await?;
+        assert_eq!(stats.bytes_used, (chunk_size * 4) as u64);
+        assert_eq!(stats.physical_bytes, (chunk_size * 2) as u64);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_dedup_releases_chunks() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+        fs.enable_dedup().await?;
+
+        let chunk_size = fs.chunk_size();
+        let data = vec![9u8; chunk_size * 2];
+        for path in ["/a.bin", "/b.bin"] {
+            let (_, file) = fs.create_file(path, DEFAULT_FILE_MODE, 0, 0).await?;
+            file.pwrite(0, &data).await?;
+            file.flush().await?;
+        }
+        // Both chunks of both files share one stored chunk
+        assert_eq!(query_count(&fs, "SELECT refcount FROM fs_chunk").await?, 4);
+
+        // Overwriting part of a chunk moves it to new contents
+        fs.pwrite("/a.bin", 0, b"hello").await?;
+        assert_eq!(query_count(&fs, "SELECT COUNT(*) FROM fs_chunk").await?, 2);
+
+        // Truncating to a small size moves the contents into the inode,
+        // dropping the references of both chunks
+        fs.truncate("/a.bin", 3).await?;
+        assert_eq!(fs.read_file("/a.bin").await?.unwrap(), b"hel");
+        assert_eq!(
+            query_count(&fs, "SELECT SUM(refcount) FROM fs_chunk").await?,
+            2
+        );
+
+        fs.remove("/a.bin").await?;
+        fs.remove("/b.bin").await?;
+        assert_eq!(query_count(&fs, "SELECT COUNT(*) FROM fs_chunk").await?, 0);
+        assert_eq!(fs.statfs().await?.physical_bytes, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_enable_dedup_migrates_existing_chunks() -> Result<()> {
+        let dir = tempdir()?;
+        let db_path = dir.path().join("test.db");
+        let chunk_size;
+        let data: Vec<u8> = (0..10000).map(|i| (i % 7) as u8).collect();
+        {
+            let fs = AgentFS::new(db_path.to_str().unwrap()).await?;
+            chunk_size = fs.chunk_size();
+            for path in ["/a.bin", "/b.bin"] {
+                let (_, file) = fs.create_file(path, DEFAULT_FILE_MODE, 0, 0).await?;
+                file.pwrite(0, &data).await?;
+                file.flush().await?;
+            }
+            assert!(!fs.dedup_enabled());
+            assert_eq!(query_count(&fs, "SELECT COUNT(*) FROM fs_chunk").await?, 0);
+
+            fs.enable_dedup().await?;
+        }
+
+        // The setting is persistent
+        let fs = AgentFS::new(db_path.to_str().unwrap()).await?;
+        assert!(fs.dedup_enabled());
+        assert_eq!(
+            query_count(&fs, "SELECT COUNT(*) FROM fs_data WHERE hash IS NULL").await?,
+            0
+        );
+        assert_eq!(fs.read_file("/a.bin").await?.unwrap(), data);
+        assert_eq!(fs.read_file("/b.bin").await?.unwrap(), data);
+
+        let stats = fs.statfs().await?;
+        assert_eq!(stats.bytes_used, 20000);
+        assert!(stats.physical_bytes <= (chunk_size * 3) as u64);
+
+        Ok(())
+    }
+
     // ==================== Cleanup Tests ====================
 
     #[tokio::test]
@@ -4196,6 +5758,7 @@ mod tests {
             .create_file("/deleteme.txt", DEFAULT_FILE_MODE, 0, 0)
             .await?;
         file.pwrite(0, &data).await?;
+        file.flush().await?;
 
         let ino = fs.resolve_path("/deleteme.txt").await?.unwrap();
         assert_eq!(fs.get_chunk_count(ino).await?, 4);
@@ -4249,7 +5812,11 @@ mod tests {
             let expected_data: Vec<u8> = (0..*size).map(|i| (i % 256) as u8).collect();
             assert_eq!(read_data, expected_data, "Data mismatch for {}", path);
 
-            let expected_chunks = size.div_ceil(chunk_size);
+            let expected_chunks = if stores_inline(*size as u64, chunk_size) {
+                0
+            } else {
+                size.div_ceil(chunk_size)
+            };
             let ino = fs.resolve_path(path).await?.unwrap();
             let actual_chunks = fs.get_chunk_count(ino).await? as usize;
             assert_eq!(
@@ -4907,4 +6474,546 @@ mod tests {
 
         Ok(())
     }
+
+    // ─────────────────────────────────────────────────────────────
+    // Extended Attribute Tests
+    // ─────────────────────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn test_xattr_set_get_list_remove() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+
+        let (stats, _) = fs.create_file("/file.txt", DEFAULT_FILE_MODE, 0, 0).await?;
+        assert!(fs.listxattr(stats.ino).await?.is_empty());
+        assert_eq!(fs.getxattr(stats.ino, "user.test").await?, None);
+
+        fs.setxattr(stats.ino, "user.test", b"value", 0).await?;
+        fs.setxattr(stats.ino, "user.empty", b"", 0).await?;
+        assert_eq!(
+            fs.getxattr(stats.ino, "user.test").await?,
+            Some(b"value".to_vec())
+        );
+        assert_eq!(
+            fs.getxattr(stats.ino, "user.empty").await?,
+            Some(Vec::new())
+        );
+        assert_eq!(
+            fs.listxattr(stats.ino).await?,
+            vec!["user.empty".to_string(), "user.test".to_string()]
+        );
+
+        // Overwrite without flags
+        fs.setxattr(stats.ino, "user.test", b"other", 0).await?;
+        assert_eq!(
+            fs.getxattr(stats.ino, "user.test").await?,
+            Some(b"other".to_vec())
+        );
+
+        fs.removexattr(stats.ino, "user.test").await?;
+        assert_eq!(fs.getxattr(stats.ino, "user.test").await?, None);
+        assert_eq!(
+            fs.listxattr(stats.ino).await?,
+            vec!["user.empty".to_string()]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_xattr_create_replace_flags() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+
+        let (stats, _) = fs.create_file("/file.txt", DEFAULT_FILE_MODE, 0, 0).await?;
+
+        // XATTR_REPLACE fails when the attribute does not exist
+        let err = fs
+            .setxattr(stats.ino, "user.a", b"1", XATTR_REPLACE)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Fs(FsError::NoAttribute)));
+
+        fs.setxattr(stats.ino, "user.a", b"1", XATTR_CREATE).await?;
+
+        // XATTR_CREATE fails when the attribute already exists
+        let err = fs
+            .setxattr(stats.ino, "user.a", b"2", XATTR_CREATE)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Fs(FsError::AlreadyExists)));
+
+        fs.setxattr(stats.ino, "user.a", b"2", XATTR_REPLACE)
+            .await?;
+        assert_eq!(fs.getxattr(stats.ino, "user.a").await?, Some(b"2".to_vec()));
+
+        // Removing a missing attribute fails
+        let err = fs.removexattr(stats.ino, "user.missing").await.unwrap_err();
+        assert!(matches!(err, Error::Fs(FsError::NoAttribute)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_xattr_removed_with_inode() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+
+        let (stats, _) = fs.create_file("/file.txt", DEFAULT_FILE_MODE, 0, 0).await?;
+        fs.setxattr(stats.ino, "user.test", b"value", 0).await?;
+        FileSystem::unlink(&fs, ROOT_INO, "file.txt").await?;
+
+        let conn = fs.get_connection().await?;
+        let mut rows = conn
+            .query("SELECT COUNT(*) FROM fs_xattr WHERE ino = ?", (stats.ino,))
+            .await?;
+        let count = rows
+            .next()
+            .await?
+            .and_then(|row| row.get_value(0).ok())
+            .and_then(|v| v.as_integer().copied())
+            .unwrap_or(-1);
+        assert_eq!(count, 0, "xattrs should be deleted with the inode");
+        drop(rows);
+        drop(conn);
+
+        // Operations on a missing inode fail with NotFound
+        let err = fs.listxattr(stats.ino).await.unwrap_err();
+        assert!(matches!(err, Error::Fs(FsError::NotFound)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_inode_path_follows_remaining_link() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+        fs.mkdir("/a", 0, 0).await?;
+        let (stats, _) = fs.create_file("/a/x.txt", DEFAULT_FILE_MODE, 0, 0).await?;
+        FileSystem::link(&fs, stats.ino, ROOT_INO, "y.txt").await?;
+        assert_eq!(fs.inode_path(stats.ino).await?.as_deref(), Some("/a/x.txt"));
+
+        let dir = fs.stat("/a").await?.unwrap();
+        FileSystem::unlink(&fs, dir.ino, "x.txt").await?;
+        assert_eq!(fs.inode_path(stats.ino).await?.as_deref(), Some("/y.txt"));
+        FileSystem::unlink(&fs, ROOT_INO, "y.txt").await?;
+        assert_eq!(fs.inode_path(stats.ino).await?, None);
+
+        Ok(())
+    }
+
+    // ==================== Write-back Tests ====================
+
+    #[tokio::test]
+    async fn test_write_back_read_your_writes_across_handles() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+        let (stats, writer) = fs.create_file("/wb.txt", DEFAULT_FILE_MODE, 0, 0).await?;
+        writer.pwrite(0, &vec![1u8; 5000]).await?;
+        writer.pwrite(8000, b"tail").await?;
+
+        // Nothing is stored yet, but every handle and stat sees the writes
+        let sql = format!("SELECT COUNT(*) FROM fs_data WHERE ino = {}", stats.ino);
+        assert_eq!(query_count(&fs, &sql).await?, 0);
+        let reader = FileSystem::open(&fs, stats.ino, libc::O_RDONLY).await?;
+        let data = reader.pread(4990, 4000).await?;
+        assert_eq!(data.len(), 3014);
+        assert_eq!(&data[..10], &[1u8; 10]);
+        assert!(data[10..3010].iter().all(|&b| b == 0));
+        assert_eq!(&data[3010..], b"tail");
+        assert_eq!(reader.fstat().await?.size, 8004);
+        assert_eq!(fs.getattr(stats.ino).await?.unwrap().size, 8004);
+        assert_eq!(fs.stat("/wb.txt").await?.unwrap().size, 8004);
+
+        writer.flush().await?;
+        assert_eq!(query_count(&fs, &sql).await?, 2);
+        assert_eq!(reader.pread(4990, 4000).await?, data);
+        assert_eq!(fs.read_file("/wb.txt").await?.unwrap().len(), 8004);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_write_back_coalesces_writes() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+        let (stats, file) = fs.create_file("/log.txt", DEFAULT_FILE_MODE, 0, 0).await?;
+        for i in 0..300u64 {
+            file.pwrite(i * 10, b"0123456789").await?;
+        }
+        file.pwrite(0, b"x").await?;
+        file.fsync().await?;
+
+        // One chunk and one journal entry per run of contiguous writes
+        let sql = format!("SELECT COUNT(*) FROM fs_data WHERE ino = {}", stats.ino);
+        assert_eq!(query_count(&fs, &sql).await?, 1);
+        let sql = "SELECT COUNT(*) FROM fs_journal WHERE op = 'write'";
+        assert_eq!(query_count(&fs, sql).await?, 2);
+        let data = fs.read_file("/log.txt").await?.unwrap();
+        assert_eq!(data.len(), 3000);
+        assert_eq!(&data[..11], b"x1234567890");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_write_back_truncate_keeps_order() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+        let (_, file) = fs.create_file("/t.bin", DEFAULT_FILE_MODE, 0, 0).await?;
+        file.pwrite(0, &vec![7u8; DEFAULT_CHUNK_SIZE * 3]).await?;
+        file.truncate(10).await?;
+        file.pwrite(20, b"end").await?;
+
+        let mut expected = vec![7u8; 10];
+        expected.extend_from_slice(&[0u8; 10]);
+        expected.extend_from_slice(b"end");
+        assert_eq!(file.pread(0, 100).await?, expected);
+        file.flush().await?;
+        assert_eq!(fs.read_file("/t.bin").await?.unwrap(), expected);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_write_back_flushes_after_delay() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+        let (stats, file) = fs.create_file("/late.txt", DEFAULT_FILE_MODE, 0, 0).await?;
+        file.pwrite(0, b"eventually").await?;
+        drop(file);
+
+        tokio::time::sleep(WRITE_BACK_DELAY * 2).await;
+        let conn = fs.pool.get_read_connection().await?;
+        let data = AgentFS::read_inline(&conn, stats.ino).await?;
+        assert_eq!(data.as_deref(), Some(&b"eventually"[..]));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_write_back_discards_writes_to_removed_file() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+        let (stats, file) = fs.create_file("/gone.txt", DEFAULT_FILE_MODE, 0, 0).await?;
+        file.pwrite(0, b"data").await?;
+        fs.remove("/gone.txt").await?;
+
+        file.flush().await?;
+        let sql = format!("SELECT COUNT(*) FROM fs_data WHERE ino = {}", stats.ino);
+        assert_eq!(query_count(&fs, &sql).await?, 0);
+        assert!(fs.getattr(stats.ino).await?.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_write_back_counts_stored_chunks() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+        let (_, file) = fs.create_file("/part.bin", DEFAULT_FILE_MODE, 0, 0).await?;
+        file.pwrite(0, &vec![1u8; 10 * 1024]).await?;
+        file.flush().await?;
+
+        // Partial overwrites load the rest of the stored chunk into the buffer
+        file.pwrite(10, b"x").await?;
+        assert_eq!(
+            fs.write_back.bytes.load(Ordering::SeqCst),
+            DEFAULT_CHUNK_SIZE
+        );
+        file.flush().await?;
+        assert_eq!(fs.write_back.bytes.load(Ordering::SeqCst), 0);
+
+        file.pwrite(20, b"y").await?;
+        file.pwrite(0, &vec![2u8; 64 * 1024]).await?;
+        file.flush().await?;
+        assert_eq!(fs.write_back.bytes.load(Ordering::SeqCst), 0);
+        let data = fs.read_file("/part.bin").await?.unwrap();
+        assert_eq!(data, vec![2u8; 64 * 1024]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_write_back_flushes_without_readers() -> Result<()> {
+        let dir = tempdir()?;
+        let db_path = dir.path().join("test.db");
+        let db = Builder::new_local(db_path.to_str().unwrap())
+            .build()
+            .await?;
+        let fs = AgentFS::from_pool(ConnectionPool::with_readers(db, 0)).await?;
+        let (_, file) = fs.create_file("/solo.txt", DEFAULT_FILE_MODE, 0, 0).await?;
+        file.pwrite(0, b"hello world").await?;
+
+        let read = tokio::time::timeout(Duration::from_secs(5), fs.read_file("/solo.txt"));
+        assert_eq!(read.await.unwrap()?.unwrap(), b"hello world");
+        file.pwrite(0, b"HELLO").await?;
+        let read = tokio::time::timeout(Duration::from_secs(5), fs.pread("/solo.txt", 0, 5));
+        assert_eq!(read.await.unwrap()?.unwrap(), b"HELLO");
+
+        Ok(())
+    }
+
+    // ==================== Inline Data Tests ====================
+
+    async fn stored_inline(fs: &AgentFS, ino: i64) -> Result<Option<Vec<u8>>> {
+        let conn = fs.pool.get_read_connection().await?;
+        AgentFS::read_inline(&conn, ino).await
+    }
+
+    #[tokio::test]
+    async fn test_small_file_promoted_and_inlined_again() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+        let (stats, file) = fs.create_file("/note.txt", DEFAULT_FILE_MODE, 0, 0).await?;
+        file.pwrite(0, b"hello world").await?;
+        file.flush().await?;
+
+        assert_eq!(
+            stored_inline(&fs, stats.ino).await?.unwrap(),
+            b"hello world"
+        );
+        assert_eq!(fs.get_chunk_count(stats.ino).await?, 0);
+        assert_eq!(file.pread(6, 100).await?, b"world");
+        assert_eq!(fs.pread("/note.txt", 0, 5).await?.unwrap(), b"hello");
+
+        // Growing past the threshold moves the contents to chunks
+        let tail = vec![5u8; INLINE_DATA_MAX_SIZE as usize];
+        file.pwrite(11, &tail).await?;
+        file.flush().await?;
+        assert!(stored_inline(&fs, stats.ino).await?.is_none());
+        assert_eq!(fs.get_chunk_count(stats.ino).await?, 1);
+        let data = file.pread(0, 4096).await?;
+        assert_eq!(&data[..11], b"hello world");
+        assert_eq!(&data[11..], &tail[..]);
+
+        // Shrinking below it moves them back into the inode
+        file.truncate(5).await?;
+        assert_eq!(stored_inline(&fs, stats.ino).await?.unwrap(), b"hello");
+        assert_eq!(fs.get_chunk_count(stats.ino).await?, 0);
+        file.truncate(8).await?;
+        assert_eq!(file.pread(0, 100).await?, b"hello\0\0\0");
+
+        file.truncate(0).await?;
+        assert!(stored_inline(&fs, stats.ino).await?.is_none());
+        assert_eq!(file.fstat().await?.size, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_small_file_inline_through_paths() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+        fs.pwrite("/config.json", 0, b"{}").await?;
+        fs.pwrite("/config.json", 1, b"\"a\": 1}").await?;
+        let ino = fs.resolve_path("/config.json").await?.unwrap();
+        assert_eq!(stored_inline(&fs, ino).await?.unwrap(), b"{\"a\": 1}");
+        assert_eq!(fs.read_file("/config.json").await?.unwrap(), b"{\"a\": 1}");
+
+        // Sizes come from the inode, like for any other file
+        let entries = fs.readdir_plus(ROOT_INO).await?.unwrap();
+        let entry = entries.iter().find(|e| e.name == "config.json").unwrap();
+        assert_eq!(entry.stats.size, 8);
+
+        fs.truncate("/config.json", 3 * DEFAULT_CHUNK_SIZE as u64)
+            .await?;
+        assert!(stored_inline(&fs, ino).await?.is_none());
+        let data = fs.read_file("/config.json").await?.unwrap();
+        assert_eq!(data.len(), 3 * DEFAULT_CHUNK_SIZE);
+        assert_eq!(&data[..8], b"{\"a\": 1}");
+
+        fs.truncate("/config.json", 4).await?;
+        assert_eq!(stored_inline(&fs, ino).await?.unwrap(), b"{\"a\"");
+        assert_eq!(fs.get_chunk_count(ino).await?, 0);
+        assert_eq!(fs.statfs().await?.physical_bytes, 4);
+
+        Ok(())
+    }
+
+    // ==================== Crash Atomicity Tests ====================
+
+    /// Mutations run by the crash tests against the tree from `crash_fixture`
+    const CRASH_MUTATIONS: &[&str] = &[
+        "mkdir",
+        "create_file",
+        "mknod",
+        "symlink",
+        "link",
+        "unlink",
+        "rmdir",
+        "rename",
+        "rename_overwrite",
+        "rename_directory",
+        "chmod",
+        "chown",
+        "utimens",
+        "setxattr",
+        "removexattr",
+        "pwrite",
+        "truncate",
+        "path_mkdir",
+        "path_mknod",
+        "path_symlink",
+        "path_link",
+        "path_remove",
+        "path_rename",
+    ];
+
+    /// Build a tree with a multi-chunk file that has an xattr and a symlink
+    /// to it, so that removing or overwriting it touches every table
+    async fn crash_fixture(fs: &AgentFS) -> Result<()> {
+        fs.mkdir("/dir", 0, 0).await?;
+        fs.mkdir("/dir/empty", 0, 0).await?;
+        fs.mkdir("/spare", 0, 0).await?;
+        let (stats, file) = fs.create_file("/dir/file", DEFAULT_FILE_MODE, 0, 0).await?;
+        file.pwrite(0, &vec![7u8; DEFAULT_CHUNK_SIZE * 3 + 100])
+            .await?;
+        fs.setxattr(stats.ino, "user.tag", b"value", 0).await?;
+        let (_, other) = fs.create_file("/other", DEFAULT_FILE_MODE, 0, 0).await?;
+        other.pwrite(0, b"other").await?;
+        fs.symlink("/dir/file", "/dir/link", 0, 0).await?;
+        Ok(())
+    }
+
+    async fn run_mutation(fs: &AgentFS, op: &str) -> Result<()> {
+        let dir = fs.resolve_path("/dir").await?.ok_or(FsError::NotFound)?;
+        let file = fs
+            .resolve_path("/dir/file")
+            .await?
+            .ok_or(FsError::NotFound)?;
+        match op {
+            "mkdir" => FileSystem::mkdir(fs, dir, "new", 0o755, 0, 0)
+                .await
+                .map(drop),
+            "create_file" => FileSystem::create_file(fs, dir, "new", 0o644, 0, 0)
+                .await
+                .map(drop),
+            "mknod" => {
+                FileSystem::mknod(fs, dir, "fifo", crate::filesystem::S_IFIFO | 0o644, 0, 0, 0)
+                    .await
+                    .map(drop)
+            }
+            "symlink" => FileSystem::symlink(fs, dir, "new", "file", 0, 0)
+                .await
+                .map(drop),
+            "link" => FileSystem::link(fs, file, ROOT_INO, "hard").await.map(drop),
+            "unlink" => FileSystem::unlink(fs, dir, "file").await,
+            "rmdir" => FileSystem::rmdir(fs, dir, "empty").await,
+            "rename" => FileSystem::rename(fs, dir, "file", ROOT_INO, "moved").await,
+            "rename_overwrite" => FileSystem::rename(fs, ROOT_INO, "other", dir, "file").await,
+            "rename_directory" => FileSystem::rename(fs, ROOT_INO, "spare", dir, "empty").await,
+            "chmod" => FileSystem::chmod(fs, file, 0o600).await,
+            "chown" => FileSystem::chown(fs, file, Some(1000), Some(1000)).await,
+            "utimens" => {
+                FileSystem::utimens(fs, file, TimeChange::Set(1, 0), TimeChange::Now).await
+            }
+            "setxattr" => fs.setxattr(file, "user.other", b"value", 0).await,
+            "removexattr" => fs.removexattr(file, "user.tag").await,
+            "pwrite" => {
+                let handle = FileSystem::open(fs, file, libc::O_RDWR).await?;
+                handle
+                    .pwrite(100, &vec![9u8; DEFAULT_CHUNK_SIZE * 5])
+                    .await?;
+                handle.flush().await
+            }
+            "truncate" => {
+                let handle = FileSystem::open(fs, file, libc::O_RDWR).await?;
+                handle.truncate(10).await
+            }
+            "path_mkdir" => fs.mkdir("/dir/new", 0, 0).await,
+            "path_mknod" => {
+                fs.mknod("/dir/fifo", crate::filesystem::S_IFIFO | 0o644, 0, 0, 0)
+                    .await
+            }
+            "path_symlink" => fs.symlink("file", "/dir/new", 0, 0).await,
+            "path_link" => fs.link("/dir/file", "/hard").await,
+            "path_remove" => fs.remove("/dir/file").await,
+            "path_rename" => fs.rename("/other", "/dir/file").await,
+            _ => unreachable!("unknown mutation {op}"),
+        }
+    }
+
+    /// Every row of the filesystem tables, sorted, to compare database states
+    async fn dump_tables(conn: &Connection) -> Result<Vec<String>> {
+        let mut dump = Vec::new();
+        for table in [
+            "fs_inode",
+            "fs_dentry",
+            "fs_data",
+            "fs_chunk",
+            "fs_symlink",
+            "fs_xattr",
+            "fs_journal",
+        ] {
+            let mut rows = conn.query(format!("SELECT * FROM {table}"), ()).await?;
+            while let Some(row) = rows.next().await? {
+                let values = (0..row.column_count())
+                    .map(|i| row.get_value(i))
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+                dump.push(format!("{table}: {values:?}"));
+            }
+        }
+        dump.sort();
+        Ok(dump)
+    }
+
+    /// Run mutation `AGENTFS_TEST_CRASH_OP` on the database at
+    /// `AGENTFS_TEST_CRASH_DB`, aborting the process before it commits.
+    ///
+    /// Run as a child process by `test_mutations_are_atomic_across_crashes`.
+    #[tokio::test]
+    #[ignore]
+    async fn crash_during_mutation() {
+        let (Ok(db_path), Ok(op)) = (
+            std::env::var("AGENTFS_TEST_CRASH_DB"),
+            std::env::var("AGENTFS_TEST_CRASH_OP"),
+        ) else {
+            return;
+        };
+        let fs = AgentFS::new(&db_path).await.unwrap();
+        CRASH_BEFORE_COMMIT.store(true, Ordering::SeqCst);
+        run_mutation(&fs, &op).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_mutations_are_atomic_across_crashes() -> Result<()> {
+        use std::process::{Command, Stdio};
+
+        for op in CRASH_MUTATIONS {
+            let dir = tempdir()?;
+            let db_path = dir.path().join("crash.db");
+            let db_path = db_path.to_str().unwrap();
+            let before = {
+                let fs = AgentFS::new(db_path).await?;
+                crash_fixture(&fs).await?;
+                let conn = fs.get_connection().await?;
+                dump_tables(&conn).await?
+            };
+
+            // The child runs every statement of the mutation, then dies
+            // before committing
+            let status = Command::new(std::env::current_exe()?)
+                .args([
+                    "--exact",
+                    "filesystem::agentfs::tests::crash_during_mutation",
+                    "--ignored",
+                ])
+                .env("AGENTFS_TEST_CRASH_DB", db_path)
+                .env("AGENTFS_TEST_CRASH_OP", op)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()?;
+            assert_eq!(status.code(), None, "{op} didn't crash before committing");
+
+            // None of its changes survive, and the filesystem is consistent
+            let fs = AgentFS::new(db_path).await?;
+            let conn = fs.get_connection().await?;
+            assert_eq!(
+                dump_tables(&conn).await?,
+                before,
+                "{op} left partial changes"
+            );
+            let report = crate::fsck::check(&conn, false).await?;
+            assert!(!report.has_errors(), "{op}: {:?}", report.issues);
+            drop(conn);
+
+            // Without the crash, all of them are applied
+            run_mutation(&fs, op).await?;
+            let conn = fs.get_connection().await?;
+            assert_ne!(dump_tables(&conn).await?, before, "{op} changed nothing");
+            let report = crate::fsck::check(&conn, false).await?;
+            assert!(!report.has_errors(), "{op}: {:?}", report.issues);
+        }
+
+        Ok(())
+    }
 }
@@ -4,7 +4,10 @@
 //! O_PATH file descriptors. macOS doesn't support O_PATH or AT_EMPTY_PATH,
 //! so we use a path-based approach similar to libfuse's passthrough.c example.
 
-use super::{BoxedFile, DirEntry, File, FileSystem, FilesystemStats, FsError, Stats, TimeChange};
+use super::{
+    BoxedFile, DirEntry, File, FileSystem, FilesystemStats, FsError, Stats, TimeChange,
+    XATTR_CREATE, XATTR_REPLACE,
+};
 use crate::error::{Error, Result};
 use async_trait::async_trait;
 use std::collections::HashMap;
@@ -869,15 +872,162 @@ impl FileSystem for HostFS {
                 return Err(std::io::Error::last_os_error().into());
             }
 
+            let bytes_used = (statfs.f_blocks - statfs.f_bfree) * statfs.f_bsize as u64;
             Ok(FilesystemStats {
                 inodes: statfs.f_files,
-                bytes_used: (statfs.f_blocks - statfs.f_bfree) * statfs.f_bsize as u64,
+                bytes_used,
+                physical_bytes: bytes_used,
             })
         })
         .await
         .map_err(|e| Error::Internal(e.to_string()))?
     }
 
+    async fn getxattr(&self, ino: i64, name: &str) -> Result<Option<Vec<u8>>> {
+        let path = self.get_inode_path(ino)?;
+        let c_path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| Error::Internal("invalid path".to_string()))?;
+        let c_name = CString::new(name).map_err(|_| FsError::InvalidPath)?;
+
+        loop {
+            let size = unsafe {
+                libc::getxattr(
+                    c_path.as_ptr(),
+                    c_name.as_ptr(),
+                    std::ptr::null_mut(),
+                    0,
+                    0,
+                    libc::XATTR_NOFOLLOW,
+                )
+            };
+            if size < 0 {
+                let err = std::io::Error::last_os_error();
+                if err.raw_os_error() == Some(libc::ENOATTR) {
+                    return Ok(None);
+                }
+                return Err(err.into());
+            }
+
+            let mut buf = vec![0u8; size as usize];
+            let n = unsafe {
+                libc::getxattr(
+                    c_path.as_ptr(),
+                    c_name.as_ptr(),
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                    0,
+                    libc::XATTR_NOFOLLOW,
+                )
+            };
+            if n < 0 {
+                let err = std::io::Error::last_os_error();
+                match err.raw_os_error() {
+                    // Value grew between the two calls, retry
+                    Some(libc::ERANGE) => continue,
+                    Some(libc::ENOATTR) => return Ok(None),
+                    _ => return Err(err.into()),
+                }
+            }
+            buf.truncate(n as usize);
+            return Ok(Some(buf));
+        }
+    }
+
+    async fn setxattr(&self, ino: i64, name: &str, value: &[u8], flags: i32) -> Result<()> {
+        let path = self.get_inode_path(ino)?;
+        let c_path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| Error::Internal("invalid path".to_string()))?;
+        let c_name = CString::new(name).map_err(|_| FsError::InvalidPath)?;
+
+        // Translate SDK (Linux) flag values to their macOS equivalents
+        let mut options = libc::XATTR_NOFOLLOW;
+        if flags & XATTR_CREATE != 0 {
+            options |= libc::XATTR_CREATE;
+        }
+        if flags & XATTR_REPLACE != 0 {
+            options |= libc::XATTR_REPLACE;
+        }
+
+        let result = unsafe {
+            libc::setxattr(
+                c_path.as_ptr(),
+                c_name.as_ptr(),
+                value.as_ptr() as *const libc::c_void,
+                value.len(),
+                0,
+                options,
+            )
+        };
+        if result < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+
+    async fn listxattr(&self, ino: i64) -> Result<Vec<String>> {
+        let path = self.get_inode_path(ino)?;
+        let c_path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| Error::Internal("invalid path".to_string()))?;
+
+        let buf = loop {
+            let size = unsafe {
+                libc::listxattr(
+                    c_path.as_ptr(),
+                    std::ptr::null_mut(),
+                    0,
+                    libc::XATTR_NOFOLLOW,
+                )
+            };
+            if size < 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+
+            let mut buf = vec![0u8; size as usize];
+            let n = unsafe {
+                libc::listxattr(
+                    c_path.as_ptr(),
+                    buf.as_mut_ptr() as *mut libc::c_char,
+                    buf.len(),
+                    libc::XATTR_NOFOLLOW,
+                )
+            };
+            if n < 0 {
+                let err = std::io::Error::last_os_error();
+                if err.raw_os_error() == Some(libc::ERANGE) {
+                    continue;
+                }
+                return Err(err.into());
+            }
+            buf.truncate(n as usize);
+            break buf;
+        };
+
+        // The kernel returns a sequence of NUL-terminated names
+        Ok(buf
+            .split(|&b| b == 0)
+            .filter(|name| !name.is_empty())
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .collect())
+    }
+
+    async fn removexattr(&self, ino: i64, name: &str) -> Result<()> {
+        let path = self.get_inode_path(ino)?;
+        let c_path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| Error::Internal("invalid path".to_string()))?;
+        let c_name = CString::new(name).map_err(|_| FsError::InvalidPath)?;
+
+        let result =
+            unsafe { libc::removexattr(c_path.as_ptr(), c_name.as_ptr(), libc::XATTR_NOFOLLOW) };
+        if result < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::ENOATTR) {
+                return Err(FsError::NoAttribute.into());
+            }
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
     async fn forget(&self, ino: i64, nlookup: u64) {
         // Never forget root inode
         if ino == ROOT_INO {
@@ -912,15 +912,140 @@ impl FileSystem for HostFS {
                 return Err(std::io::Error::last_os_error().into());
             }
 
+            let bytes_used = (statfs.f_blocks - statfs.f_bfree) * statfs.f_bsize as u64;
             Ok(FilesystemStats {
                 inodes: statfs.f_files,
-                bytes_used: (statfs.f_blocks - statfs.f_bfree) * statfs.f_bsize as u64,
+                bytes_used,
+                physical_bytes: bytes_used,
             })
         })
         .await
         .map_err(|e| Error::Internal(e.to_string()))?
     }
 
+    async fn getxattr(&self, ino: i64, name: &str) -> Result<Option<Vec<u8>>> {
+        let fd = self.get_inode_fd(ino)?;
+
+        // fgetxattr doesn't work on O_PATH fds, go through /proc/self/fd
+        let proc_path = CString::new(format!("/proc/self/fd/{}", fd))
+            .map_err(|_| Error::Internal("invalid path".to_string()))?;
+        let c_name = CString::new(name).map_err(|_| FsError::InvalidPath)?;
+
+        loop {
+            let size = unsafe {
+                libc::getxattr(proc_path.as_ptr(), c_name.as_ptr(), std::ptr::null_mut(), 0)
+            };
+            if size < 0 {
+                let err = std::io::Error::last_os_error();
+                if err.raw_os_error() == Some(libc::ENODATA) {
+                    return Ok(None);
+                }
+                return Err(err.into());
+            }
+
+            let mut buf = vec![0u8; size as usize];
+            let n = unsafe {
+                libc::getxattr(
+                    proc_path.as_ptr(),
+                    c_name.as_ptr(),
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                )
+            };
+            if n < 0 {
+                let err = std::io::Error::last_os_error();
+                match err.raw_os_error() {
+                    // Value grew between the two calls, retry
+                    Some(libc::ERANGE) => continue,
+                    Some(libc::ENODATA) => return Ok(None),
+                    _ => return Err(err.into()),
+                }
+            }
+            buf.truncate(n as usize);
+            return Ok(Some(buf));
+        }
+    }
+
+    async fn setxattr(&self, ino: i64, name: &str, value: &[u8], flags: i32) -> Result<()> {
+        let fd = self.get_inode_fd(ino)?;
+
+        let proc_path = CString::new(format!("/proc/self/fd/{}", fd))
+            .map_err(|_| Error::Internal("invalid path".to_string()))?;
+        let c_name = CString::new(name).map_err(|_| FsError::InvalidPath)?;
+
+        // SDK flag values match Linux, pass them through unchanged
+        let result = unsafe {
+            libc::setxattr(
+                proc_path.as_ptr(),
+                c_name.as_ptr(),
+                value.as_ptr() as *const libc::c_void,
+                value.len(),
+                flags,
+            )
+        };
+        if result < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+
+    async fn listxattr(&self, ino: i64) -> Result<Vec<String>> {
+        let fd = self.get_inode_fd(ino)?;
+
+        let proc_path = CString::new(format!("/proc/self/fd/{}", fd))
+            .map_err(|_| Error::Internal("invalid path".to_string()))?;
+
+        let buf = loop {
+            let size = unsafe { libc::listxattr(proc_path.as_ptr(), std::ptr::null_mut(), 0) };
+            if size < 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+
+            let mut buf = vec![0u8; size as usize];
+            let n = unsafe {
+                libc::listxattr(
+                    proc_path.as_ptr(),
+                    buf.as_mut_ptr() as *mut libc::c_char,
+                    buf.len(),
+                )
+            };
+            if n < 0 {
+                let err = std::io::Error::last_os_error();
+                if err.raw_os_error() == Some(libc::ERANGE) {
+                    continue;
+                }
+                return Err(err.into());
+            }
+            buf.truncate(n as usize);
+            break buf;
+        };
+
+        // The kernel returns a sequence of NUL-terminated names
+        Ok(buf
+            .split(|&b| b == 0)
+            .filter(|name| !name.is_empty())
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .collect())
+    }
+
+    async fn removexattr(&self, ino: i64, name: &str) -> Result<()> {
+        let fd = self.get_inode_fd(ino)?;
+
+        let proc_path = CString::new(format!("/proc/self/fd/{}", fd))
+            .map_err(|_| Error::Internal("invalid path".to_string()))?;
+        let c_name = CString::new(name).map_err(|_| FsError::InvalidPath)?;
+
+        let result = unsafe { libc::removexattr(proc_path.as_ptr(), c_name.as_ptr()) };
+        if result < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::ENODATA) {
+                return Err(FsError::NoAttribute.into());
+            }
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
     async fn forget(&self, ino: i64, nlookup: u64) {
         // Never forget root inode
         if ino == ROOT_INO {
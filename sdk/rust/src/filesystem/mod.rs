@@ -53,6 +53,12 @@ pub enum FsError {
 
     #[error("Filename too long")]
     NameTooLong,
+
+    #[error("No such extended attribute")]
+    NoAttribute,
+
+    #[error("Data checksum mismatch")]
+    Corrupted,
 }
 
 impl FsError {
@@ -70,6 +76,11 @@ impl FsError {
             FsError::SymlinkLoop => libc::ELOOP,
             FsError::InvalidRename => libc::EINVAL,
             FsError::NameTooLong => libc::ENAMETOOLONG,
+            #[cfg(target_os = "macos")]
+            FsError::NoAttribute => libc::ENOATTR,
+            #[cfg(not(target_os = "macos"))]
+            FsError::NoAttribute => libc::ENODATA,
+            FsError::Corrupted => libc::EIO,
         }
     }
 }
@@ -77,6 +88,13 @@ impl FsError {
 /// Maximum filename length in bytes.
 pub const MAX_NAME_LEN: usize = 255;
 
+/// Maximum extended attribute name length in bytes.
+pub const XATTR_NAME_MAX: usize = 255;
+
+// Flags for setxattr (Linux values; backends translate as needed)
+pub const XATTR_CREATE: i32 = 0x1; // Fail if the attribute already exists
+pub const XATTR_REPLACE: i32 = 0x2; // Fail if the attribute does not exist
+
 // File types for mode field
 pub const S_IFMT: u32 = 0o170000; // File type mask
 pub const S_IFREG: u32 = 0o100000; // Regular file
@@ -127,6 +145,9 @@ pub struct FilesystemStats {
     pub inodes: u64,
     /// Total bytes used by file contents
     pub bytes_used: u64,
+    /// Bytes actually stored for file contents. Smaller than `bytes_used`
+    /// when chunks are deduplicated or files are sparse.
+    pub physical_bytes: u64,
 }
 
 /// Directory entry with full statistics
@@ -173,6 +194,14 @@ pub trait File: Send + Sync {
 
     /// Get file statistics.
     async fn fstat(&self) -> Result<Stats>;
+
+    /// Called when a descriptor referring to this handle is closed.
+    ///
+    /// An error is reported to the process closing the descriptor. The
+    /// default implementation does nothing, for files that don't buffer writes.
+    async fn flush(&self) -> Result<()> {
+        Ok(())
+    }
 }
 
 /// A boxed File trait object for dynamic dispatch.
@@ -307,6 +336,35 @@ pub trait FileSystem: Send + Sync {
     /// Get filesystem statistics.
     async fn statfs(&self) -> Result<FilesystemStats>;
 
+    /// Get the value of an extended attribute.
+    ///
+    /// Returns `Ok(None)` if the attribute is not set on the inode.
+    async fn getxattr(&self, ino: i64, name: &str) -> Result<Option<Vec<u8>>>;
+
+    /// Set the value of an extended attribute.
+    ///
+    /// The `flags` parameter accepts `XATTR_CREATE` (fail with `AlreadyExists`
+    /// if the attribute exists) and `XATTR_REPLACE` (fail with `NoAttribute`
+    /// if it does not). With no flags the attribute is created or replaced.
+    async fn setxattr(&self, ino: i64, name: &str, value: &[u8], flags: i32) -> Result<()>;
+
+    /// List the names of all extended attributes set on an inode.
+    async fn listxattr(&self, ino: i64) -> Result<Vec<String>>;
+
+    /// Remove an extended attribute.
+    ///
+    /// Fails with `NoAttribute` if the attribute is not set.
+    async fn removexattr(&self, ino: i64, name: &str) -> Result<()>;
+
+    /// Get a path of an inode from the root of the filesystem.
+    ///
+    /// Returns `Ok(None)` if the inode is not linked into the tree, or if the
+    /// filesystem can't tell, which is the default implementation. For an
+    /// inode with several hard links, any of them may be returned.
+    async fn inode_path(&self, _ino: i64) -> Result<Option<String>> {
+        Ok(None)
+    }
+
     /// Forget about an inode (called when kernel drops inode from cache).
     ///
     /// The `nlookup` parameter indicates how many lookups the kernel is forgetting.
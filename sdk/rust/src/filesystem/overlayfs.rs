@@ -206,13 +206,19 @@ impl OverlayFS {
 
     /// Create a whiteout for a path
     async fn create_whiteout(&self, path: &str) -> Result<()> {
-        let conn = self.delta.get_connection().await?;
-        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
-        conn.execute(
-            "INSERT OR REPLACE INTO fs_whiteout (path, created_at) VALUES (?, ?)",
-            (path, now),
-        )
-        .await?;
+        self.delta
+            .get_pool()
+            .retry(|| async move {
+                let conn = self.delta.get_connection().await?;
+                let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+                conn.execute(
+                    "INSERT OR REPLACE INTO fs_whiteout (path, created_at) VALUES (?, ?)",
+                    (path, now),
+                )
+                .await?;
+                Ok(())
+            })
+            .await?;
         self.whiteouts.write().unwrap().insert(path.to_string());
         Ok(())
     }
@@ -222,8 +228,14 @@ impl OverlayFS {
         if !self.whiteouts.read().unwrap().contains(path) {
             return Ok(());
         }
-        let conn = self.delta.get_connection().await?;
-        conn.execute("DELETE FROM fs_whiteout WHERE path = ?", (path,))
+        self.delta
+            .get_pool()
+            .retry(|| async move {
+                let conn = self.delta.get_connection().await?;
+                conn.execute("DELETE FROM fs_whiteout WHERE path = ?", (path,))
+                    .await?;
+                Ok(())
+            })
             .await?;
         self.whiteouts.write().unwrap().remove(path);
         Ok(())
@@ -329,12 +341,18 @@ impl OverlayFS {
 
     /// Store origin mapping for copy-up
     async fn add_origin_mapping(&self, delta_ino: i64, base_ino: i64) -> Result<()> {
-        let conn = self.delta.get_connection().await?;
-        conn.execute(
-            "INSERT OR REPLACE INTO fs_origin (delta_ino, base_ino) VALUES (?, ?)",
-            (delta_ino, base_ino),
-        )
-        .await?;
+        self.delta
+            .get_pool()
+            .retry(|| async move {
+                let conn = self.delta.get_connection().await?;
+                conn.execute(
+                    "INSERT OR REPLACE INTO fs_origin (delta_ino, base_ino) VALUES (?, ?)",
+                    (delta_ino, base_ino),
+                )
+                .await?;
+                Ok(())
+            })
+            .await?;
         self.origin_map.write().unwrap().insert(delta_ino, base_ino);
         Ok(())
     }
@@ -498,15 +516,49 @@ impl OverlayFS {
             )
             .await?;
             delta_file.pwrite(0, &content).await?;
+            // Store the copy right away, in the inode if the file is small
+            delta_file.flush().await?;
             stats.ino
         };
 
+        // Carry extended attributes over to the copy
+        self.copy_up_xattrs(base_ino, delta_ino).await?;
+
         // Store origin mapping
         self.add_origin_mapping(delta_ino, base_ino).await?;
 
         Ok(delta_ino)
     }
 
+    /// Copy extended attributes from a base inode to its delta copy.
+    ///
+    /// The base layer may not support xattrs at all (or may refuse to read
+    /// some namespaces), so read failures are skipped rather than failing
+    /// the copy-up.
+    async fn copy_up_xattrs(&self, base_ino: i64, delta_ino: i64) -> Result<()> {
+        let names = match self.base.listxattr(base_ino).await {
+            Ok(names) => names,
+            Err(e) => {
+                trace!("OverlayFS::copy_up_xattrs: listxattr failed: {}", e);
+                return Ok(());
+            }
+        };
+
+        for name in names {
+            match self.base.getxattr(base_ino, &name).await {
+                Ok(Some(value)) => {
+                    FileSystem::setxattr(&self.delta, delta_ino, &name, &value, 0).await?;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    trace!("OverlayFS::copy_up_xattrs: getxattr {} failed: {}", name, e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     /// Copy-up a file and update the inode mapping so subsequent operations
     /// go to the delta layer. Returns the delta inode.
     async fn copy_up_and_update_mapping(&self, overlay_ino: i64, info: &InodeInfo) -> Result<i64> {
@@ -1269,6 +1321,82 @@ impl FileSystem for OverlayFS {
         FileSystem::statfs(&self.delta).await
     }
 
+    async fn getxattr(&self, ino: i64, name: &str) -> Result<Option<Vec<u8>>> {
+        trace!("OverlayFS::getxattr: ino={}, name={}", ino, name);
+
+        let info = self.get_inode_info(ino).ok_or(FsError::NotFound)?;
+
+        match info.layer {
+            Layer::Delta => self.delta.getxattr(info.underlying_ino, name).await,
+            Layer::Base => self.base.getxattr(info.underlying_ino, name).await,
+        }
+    }
+
+    async fn setxattr(&self, ino: i64, name: &str, value: &[u8], flags: i32) -> Result<()> {
+        trace!(
+            "OverlayFS::setxattr: ino={}, name={}, len={}, flags={:#x}",
+            ino,
+            name,
+            value.len(),
+            flags
+        );
+
+        let info = self.get_inode_info(ino).ok_or(FsError::NotFound)?;
+
+        let delta_ino = match info.layer {
+            Layer::Delta => info.underlying_ino,
+            Layer::Base => self.copy_up_and_update_mapping(ino, &info).await?,
+        };
+
+        self.delta.setxattr(delta_ino, name, value, flags).await
+    }
+
+    async fn listxattr(&self, ino: i64) -> Result<Vec<String>> {
+        trace!("OverlayFS::listxattr: ino={}", ino);
+
+        let info = self.get_inode_info(ino).ok_or(FsError::NotFound)?;
+
+        match info.layer {
+            Layer::Delta => self.delta.listxattr(info.underlying_ino).await,
+            Layer::Base => self.base.listxattr(info.underlying_ino).await,
+        }
+    }
+
+    async fn removexattr(&self, ino: i64, name: &str) -> Result<()> {
+        trace!("OverlayFS::removexattr: ino={}, name={}", ino, name);
+
+        let info = self.get_inode_info(ino).ok_or(FsError::NotFound)?;
+
+        let delta_ino = match info.layer {
+            Layer::Delta => info.underlying_ino,
+            Layer::Base => {
+                // Avoid a pointless copy-up when there is nothing to remove
+                if self
+                    .base
+                    .getxattr(info.underlying_ino, name)
+                    .await?
+                    .is_none()
+                {
+                    return Err(FsError::NoAttribute.into());
+                }
+                self.copy_up_and_update_mapping(ino, &info).await?
+            }
+        };
+
+        self.delta.removexattr(delta_ino, name).await
+    }
+
+    async fn inode_path(&self, ino: i64) -> Result<Option<String>> {
+        let Some(info) = self.get_inode_info(ino) else {
+            return Ok(None);
+        };
+        match info.layer {
+            // Paths recorded for delta inodes go stale when they are renamed
+            Layer::Delta => self.delta.inode_path(info.underlying_ino).await,
+            Layer::Base => Ok(Some(info.path)),
+        }
+    }
+
     async fn forget(&self, ino: i64, nlookup: u64) {
         // Look up the inode info to determine which layer it belongs to
         let info = match self.get_inode_info(ino) {
@@ -1451,6 +1579,36 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_overlay_copy_on_write_setxattr() -> Result<()> {
+        let (overlay, _base_dir, _delta_dir) = create_test_overlay().await?;
+
+        let stats = overlay.lookup(ROOT_INO, "base.txt").await?.unwrap();
+
+        // setxattr should trigger copy-up
+        overlay
+            .setxattr(stats.ino, "user.agent", b"build-bot", 0)
+            .await?;
+
+        assert_eq!(
+            overlay.getxattr(stats.ino, "user.agent").await?,
+            Some(b"build-bot".to_vec())
+        );
+        assert!(overlay
+            .listxattr(stats.ino)
+            .await?
+            .contains(&"user.agent".to_string()));
+
+        // Content must survive the copy-up
+        let file = overlay.open(stats.ino, libc::O_RDONLY).await?;
+        assert_eq!(file.pread(0, 100).await?, b"base content");
+
+        overlay.removexattr(stats.ino, "user.agent").await?;
+        assert_eq!(overlay.getxattr(stats.ino, "user.agent").await?, None);
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_overlay_copy_on_write_truncate() -> Result<()> {
         let (overlay, base_dir, _delta_dir) = create_test_overlay().await?;
@@ -1481,6 +1639,24 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_overlay_copy_up_stores_small_file_inline() -> Result<()> {
+        let (overlay, _base_dir, _delta_dir) = create_test_overlay().await?;
+
+        // Opening for writing copies the file up and stores the copy
+        let stats = overlay.lookup(ROOT_INO, "base.txt").await?.unwrap();
+        let _file = overlay.open(stats.ino, libc::O_RDWR).await?;
+
+        let delta_stats = FileSystem::lookup(&overlay.delta, ROOT_INO, "base.txt")
+            .await?
+            .unwrap();
+        let conn = overlay.delta.get_connection().await?;
+        let data = AgentFS::read_inline(&conn, delta_stats.ino).await?;
+        assert_eq!(data.as_deref(), Some(&b"base content"[..]));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_overlay_copy_on_write_rename() -> Result<()> {
         let (overlay, base_dir, _delta_dir) = create_test_overlay().await?;
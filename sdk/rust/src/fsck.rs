@@ -0,0 +1,733 @@
+//! Integrity checking and repair of an AgentFS database.
+//!
+//! [`check`] verifies the invariants listed in the specification that the
+//! write paths cannot enforce on their own after a crash, a bad sync or a
+//! partial restore:
+//!
+//! - every directory entry references existing inodes, and every inode other
+//!   than the root has at least one directory entry
+//! - `nlink` matches the directory entries (plus `.` and `..` for directories)
+//! - no chunk lies beyond the end of its file or belongs to a file stored in
+//!   its inode, and every chunk belongs to an existing inode
+//! - symlink targets, extended attributes and overlay origin mappings belong
+//!   to existing inodes
+//! - no whiteout hides an entry that exists in the delta layer
+//! - `fs_chunk` reference counts match the chunks referencing them
+//! - every chunk matches its checksum (scrub)
+//!
+//! With `repair`, every inconsistency except lost or corrupted chunk contents
+//! is fixed in the same transaction. Repairs never invent data: dangling rows
+//! are deleted and counters are recomputed from the directory entries.
+
+use crate::connection_pool::begin_immediate;
+use crate::error::Result;
+use crate::filesystem::agentfs::chunk_hash;
+use crate::filesystem::{AgentFS, S_IFDIR, S_IFMT};
+use crate::snapshot::Snapshots;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use turso::{Connection, Value};
+
+const ROOT_INO: i64 = 1;
+
+/// Kind of inconsistency found by [`check`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    /// Directory entry whose inode or parent inode does not exist
+    OrphanDentry,
+    /// Inode without any directory entry
+    UnreachableInode,
+    /// `nlink` does not match the directory entries
+    LinkCount,
+    /// Chunk beyond the end of its file, or of a file stored in its inode
+    ExcessChunk,
+    /// Chunk of an inode that does not exist
+    DanglingChunk,
+    /// Symlink target of an inode that does not exist
+    DanglingSymlink,
+    /// Extended attribute of an inode that does not exist
+    DanglingXattr,
+    /// Overlay origin mapping of an inode that does not exist
+    DanglingOrigin,
+    /// Whiteout hiding an entry that exists in the delta layer
+    ShadowingWhiteout,
+    /// `fs_chunk.refcount` does not match the chunks referencing it
+    ChunkRefcount,
+    /// Deduplicated chunk whose contents are missing from `fs_chunk`
+    MissingChunk,
+    /// Chunk contents do not match their checksum
+    ChecksumMismatch,
+}
+
+impl fmt::Display for IssueKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            IssueKind::OrphanDentry => "orphan-dentry",
+            IssueKind::UnreachableInode => "unreachable-inode",
+            IssueKind::LinkCount => "link-count",
+            IssueKind::ExcessChunk => "excess-chunk",
+            IssueKind::DanglingChunk => "dangling-chunk",
+            IssueKind::DanglingSymlink => "dangling-symlink",
+            IssueKind::DanglingXattr => "dangling-xattr",
+            IssueKind::DanglingOrigin => "dangling-origin",
+            IssueKind::ShadowingWhiteout => "shadowing-whiteout",
+            IssueKind::ChunkRefcount => "chunk-refcount",
+            IssueKind::MissingChunk => "missing-chunk",
+            IssueKind::ChecksumMismatch => "checksum-mismatch",
+        };
+        f.pad(name)
+    }
+}
+
+/// A single inconsistency
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Issue {
+    pub kind: IssueKind,
+    /// Inode the issue concerns, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ino: Option<i64>,
+    pub message: String,
+    /// Whether the issue was fixed
+    pub repaired: bool,
+}
+
+/// Result of a [`check`] run
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FsckReport {
+    /// Number of inodes checked
+    pub inodes: u64,
+    /// Number of chunks whose contents were verified
+    pub chunks_verified: u64,
+    /// Number of chunks without a checksum (written by older versions)
+    pub chunks_unverified: u64,
+    pub issues: Vec<Issue>,
+}
+
+impl FsckReport {
+    /// Whether any issue is left unrepaired
+    pub fn has_errors(&self) -> bool {
+        self.issues.iter().any(|issue| !issue.repaired)
+    }
+
+    fn push(&mut self, kind: IssueKind, ino: Option<i64>, message: String, repaired: bool) {
+        self.issues.push(Issue {
+            kind,
+            ino,
+            message,
+            repaired,
+        });
+    }
+}
+
+/// Check the filesystem tables for inconsistencies, fixing them if `repair`
+/// is set.
+///
+/// Runs in a single immediate transaction, so the database is not modified
+/// concurrently while it is checked. Under MVCC the transaction is exclusive
+/// and fails busy while concurrent write transactions are open. Callers
+/// holding a dentry cache must drop it after a repair.
+pub async fn check(conn: &Connection, repair: bool) -> Result<FsckReport> {
+    let txn = begin_immediate(conn).await?;
+
+    let result: Result<FsckReport> = async {
+        let mut report = FsckReport::default();
+
+        // Refcounts first: repairs below release chunks through them
+        check_refcounts(conn, repair, &mut report).await?;
+
+        // Deleting an unreachable directory orphans the entries below it, so
+        // repeat until nothing changes
+        loop {
+            let before = report.issues.len();
+            check_dentries(conn, repair, &mut report).await?;
+            check_unreachable_inodes(conn, repair, &mut report).await?;
+            if !repair || report.issues.len() == before {
+                break;
+            }
+        }
+
+        check_link_counts(conn, repair, &mut report).await?;
+        scrub_chunks(conn, repair, &mut report).await?;
+        check_dangling_rows(conn, repair, &mut report).await?;
+        check_whiteouts(conn, repair, &mut report).await?;
+
+        Ok(report)
+    }
+    .await;
+
+    match result {
+        Ok(report) => {
+            txn.commit().await?;
+            Ok(report)
+        }
+        Err(e) => {
+            let _ = txn.rollback().await;
+            Err(e)
+        }
+    }
+}
+
+fn integer(row: &turso::Row, idx: usize) -> i64 {
+    row.get_value(idx)
+        .ok()
+        .and_then(|v| v.as_integer().copied())
+        .unwrap_or(0)
+}
+
+fn text(row: &turso::Row, idx: usize) -> Option<String> {
+    match row.get_value(idx) {
+        Ok(Value::Text(s)) => Some(s),
+        _ => None,
+    }
+}
+
+/// Delete an inode together with everything stored for it
+async fn delete_inode(conn: &Connection, ino: i64) -> Result<()> {
+    AgentFS::delete_chunks(conn, ino, 0, i64::MAX).await?;
+    conn.execute("DELETE FROM fs_symlink WHERE ino = ?", (ino,))
+        .await?;
+    conn.execute("DELETE FROM fs_xattr WHERE ino = ?", (ino,))
+        .await?;
+    conn.execute("DELETE FROM fs_inode WHERE ino = ?", (ino,))
+        .await?;
+    Ok(())
+}
+
+async fn check_refcounts(conn: &Connection, repair: bool, report: &mut FsckReport) -> Result<()> {
+    let mut rows = conn
+        .query(
+            "SELECT c.hash, c.refcount,
+                (SELECT COUNT(*) FROM fs_data d WHERE d.hash = c.hash)
+            FROM fs_chunk c",
+            (),
+        )
+        .await?;
+    let mut wrong = Vec::new();
+    while let Some(row) = rows.next().await? {
+        let Some(hash) = text(&row, 0) else { continue };
+        let (refcount, actual) = (integer(&row, 1), integer(&row, 2));
+        if refcount != actual {
+            wrong.push((hash, refcount, actual));
+        }
+    }
+    drop(rows);
+
+    for (hash, refcount, actual) in wrong {
+        if repair {
+            if actual == 0 {
+                conn.execute("DELETE FROM fs_chunk WHERE hash = ?", (hash.as_str(),))
+                    .await?;
+            } else {
+                conn.execute(
+                    "UPDATE fs_chunk SET refcount = ? WHERE hash = ?",
+                    (actual, hash.as_str()),
+                )
+                .await?;
+            }
+        }
+        report.push(
+            IssueKind::ChunkRefcount,
+            None,
+            format!("chunk {hash} has refcount {refcount}, referenced {actual} times"),
+            repair,
+        );
+    }
+    Ok(())
+}
+
+async fn check_dentries(conn: &Connection, repair: bool, report: &mut FsckReport) -> Result<()> {
+    let mut rows = conn
+        .query(
+            "SELECT id, parent_ino, name, ino FROM fs_dentry
+            WHERE ino NOT IN (SELECT ino FROM fs_inode)
+            OR parent_ino NOT IN (SELECT ino FROM fs_inode)",
+            (),
+        )
+        .await?;
+    let mut orphans = Vec::new();
+    while let Some(row) = rows.next().await? {
+        let name = text(&row, 2).unwrap_or_default();
+        orphans.push((integer(&row, 0), integer(&row, 1), name, integer(&row, 3)));
+    }
+    drop(rows);
+
+    for (id, parent_ino, name, ino) in orphans {
+        if repair {
+            conn.execute("DELETE FROM fs_dentry WHERE id = ?", (id,))
+                .await?;
+        }
+        report.push(
+            IssueKind::OrphanDentry,
+            Some(ino),
+            format!("entry '{name}' in directory {parent_ino} references a missing inode"),
+            repair,
+        );
+    }
+    Ok(())
+}
+
+async fn check_unreachable_inodes(
+    conn: &Connection,
+    repair: bool,
+    report: &mut FsckReport,
+) -> Result<()> {
+    let mut rows = conn
+        .query(
+            "SELECT ino FROM fs_inode
+            WHERE ino != ? AND ino NOT IN (SELECT ino FROM fs_dentry)",
+            (ROOT_INO,),
+        )
+        .await?;
+    let mut unreachable = Vec::new();
+    while let Some(row) = rows.next().await? {
+        unreachable.push(integer(&row, 0));
+    }
+    drop(rows);
+
+    for ino in unreachable {
+        if repair {
+            delete_inode(conn, ino).await?;
+        }
+        report.push(
+            IssueKind::UnreachableInode,
+            Some(ino),
+            format!("inode {ino} has no directory entry"),
+            repair,
+        );
+    }
+    Ok(())
+}
+
+async fn check_link_counts(conn: &Connection, repair: bool, report: &mut FsckReport) -> Result<()> {
+    let mut inodes = Vec::new();
+    let mut rows = conn
+        .query("SELECT ino, mode, nlink FROM fs_inode", ())
+        .await?;
+    while let Some(row) = rows.next().await? {
+        inodes.push((integer(&row, 0), integer(&row, 1) as u32, integer(&row, 2)));
+    }
+    drop(rows);
+    report.inodes = inodes.len() as u64;
+
+    let is_dir: HashSet<i64> = inodes
+        .iter()
+        .filter(|(_, mode, _)| mode & S_IFMT == S_IFDIR)
+        .map(|(ino, _, _)| *ino)
+        .collect();
+
+    // Non-directories are linked once per entry. Directories are linked by
+    // their entry and `.`, plus `..` of every subdirectory.
+    let mut links: HashMap<i64, i64> = HashMap::new();
+    for ino in &is_dir {
+        links.insert(*ino, 2);
+    }
+    let mut rows = conn
+        .query("SELECT parent_ino, ino FROM fs_dentry", ())
+        .await?;
+    while let Some(row) = rows.next().await? {
+        let (parent_ino, ino) = (integer(&row, 0), integer(&row, 1));
+        if is_dir.contains(&ino) {
+            *links.entry(parent_ino).or_insert(0) += 1;
+        } else {
+            *links.entry(ino).or_insert(0) += 1;
+        }
+    }
+    drop(rows);
+
+    for (ino, _, nlink) in inodes {
+        let expected = links.get(&ino).copied().unwrap_or(0);
+        if nlink == expected {
+            continue;
+        }
+        if repair {
+            conn.execute(
+                "UPDATE fs_inode SET nlink = ? WHERE ino = ?",
+                (expected, ino),
+            )
+            .await?;
+        }
+        report.push(
+            IssueKind::LinkCount,
+            Some(ino),
+            format!("inode {ino} has nlink {nlink}, expected {expected}"),
+            repair,
+        );
+    }
+    Ok(())
+}
+
+/// Verify every chunk and check that it belongs within an existing file
+async fn scrub_chunks(conn: &Connection, repair: bool, report: &mut FsckReport) -> Result<()> {
+    let chunk_size = AgentFS::read_chunk_size(conn).await? as i64;
+
+    let mut rows = conn
+        .query(
+            "SELECT d.ino, d.chunk_index, i.size, COALESCE(c.data, d.data),
+                COALESCE(d.checksum, d.hash), d.hash, c.hash, i.ino,
+                i.inline_data IS NOT NULL
+            FROM fs_data d
+            LEFT JOIN fs_chunk c ON c.hash = d.hash
+            LEFT JOIN fs_inode i ON i.ino = d.ino
+            ORDER BY d.ino, d.chunk_index",
+            (),
+        )
+        .await?;
+    let mut excess = Vec::new();
+    while let Some(row) = rows.next().await? {
+        let (ino, chunk_index) = (integer(&row, 0), integer(&row, 1));
+
+        if row
+            .get_value(7)
+            .ok()
+            .and_then(|v| v.as_integer().copied())
+            .is_none()
+        {
+            report.push(
+                IssueKind::DanglingChunk,
+                Some(ino),
+                format!("chunk {chunk_index} belongs to missing inode {ino}"),
+                repair,
+            );
+            excess.push((ino, chunk_index));
+            continue;
+        }
+
+        let size = integer(&row, 2);
+        let chunks = (size + chunk_size - 1) / chunk_size;
+        if chunk_index >= chunks {
+            report.push(
+                IssueKind::ExcessChunk,
+                Some(ino),
+                format!("chunk {chunk_index} of inode {ino} lies beyond its size of {size} bytes"),
+                repair,
+            );
+            excess.push((ino, chunk_index));
+            continue;
+        }
+
+        if integer(&row, 8) != 0 {
+            report.push(
+                IssueKind::ExcessChunk,
+                Some(ino),
+                format!("chunk {chunk_index} of inode {ino} belongs to a file stored inline"),
+                repair,
+            );
+            excess.push((ino, chunk_index));
+            continue;
+        }
+
+        if let Some(hash) = text(&row, 5) {
+            if text(&row, 6).is_none() {
+                report.push(
+                    IssueKind::MissingChunk,
+                    Some(ino),
+                    format!(
+                        "chunk {chunk_index} of inode {ino} references missing contents {hash}"
+                    ),
+                    false,
+                );
+                continue;
+            }
+        }
+
+        match text(&row, 4) {
+            Some(checksum) => {
+                let data = match row.get_value(3) {
+                    Ok(Value::Blob(data)) => data,
+                    _ => Vec::new(),
+                };
+                report.chunks_verified += 1;
+                if chunk_hash(&data) != checksum {
+                    report.push(
+                        IssueKind::ChecksumMismatch,
+                        Some(ino),
+                        format!("chunk {chunk_index} of inode {ino} does not match its checksum"),
+                        false,
+                    );
+                }
+            }
+            None => report.chunks_unverified += 1,
+        }
+    }
+    drop(rows);
+
+    if repair {
+        for (ino, chunk_index) in excess {
+            AgentFS::delete_chunks(conn, ino, chunk_index, chunk_index).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Rows keyed by an inode that no longer exists: (table, column, kind)
+const INODE_TABLES: &[(&str, &str, IssueKind)] = &[
+    ("fs_symlink", "ino", IssueKind::DanglingSymlink),
+    ("fs_xattr", "ino", IssueKind::DanglingXattr),
+    ("fs_origin", "delta_ino", IssueKind::DanglingOrigin),
+];
+
+async fn check_dangling_rows(
+    conn: &Connection,
+    repair: bool,
+    report: &mut FsckReport,
+) -> Result<()> {
+    for (table, column, kind) in INODE_TABLES {
+        if !Snapshots::table_exists(conn, table).await? {
+            continue;
+        }
+        let mut rows = conn
+            .query(
+                &format!(
+                    "SELECT DISTINCT {column} FROM {table}
+                    WHERE {column} NOT IN (SELECT ino FROM fs_inode)"
+                ),
+                (),
+            )
+            .await?;
+        let mut dangling = Vec::new();
+        while let Some(row) = rows.next().await? {
+            dangling.push(integer(&row, 0));
+        }
+        drop(rows);
+
+        for ino in dangling {
+            if repair {
+                conn.execute(&format!("DELETE FROM {table} WHERE {column} = ?"), (ino,))
+                    .await?;
+            }
+            report.push(
+                *kind,
+                Some(ino),
+                format!("{table} row references missing inode {ino}"),
+                repair,
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn check_whiteouts(conn: &Connection, repair: bool, report: &mut FsckReport) -> Result<()> {
+    if !Snapshots::table_exists(conn, "fs_whiteout").await? {
+        return Ok(());
+    }
+
+    let mut rows = conn.query("SELECT path FROM fs_whiteout", ()).await?;
+    let mut paths = Vec::new();
+    while let Some(row) = rows.next().await? {
+        if let Some(path) = text(&row, 0) {
+            paths.push(path);
+        }
+    }
+    drop(rows);
+
+    for path in paths {
+        let Some(ino) = resolve_delta_path(conn, &path).await? else {
+            continue;
+        };
+        if repair {
+            conn.execute("DELETE FROM fs_whiteout WHERE path = ?", (path.as_str(),))
+                .await?;
+        }
+        report.push(
+            IssueKind::ShadowingWhiteout,
+            Some(ino),
+            format!("whiteout for {path} hides an entry in the delta layer"),
+            repair,
+        );
+    }
+    Ok(())
+}
+
+/// Resolve an absolute path through the directory entries, without following
+/// symlinks
+async fn resolve_delta_path(conn: &Connection, path: &str) -> Result<Option<i64>> {
+    let mut ino = ROOT_INO;
+    for name in path.split('/').filter(|s| !s.is_empty()) {
+        let mut rows = conn
+            .query(
+                "SELECT ino FROM fs_dentry WHERE parent_ino = ? AND name = ?",
+                (ino, name),
+            )
+            .await?;
+        match rows.next().await? {
+            Some(row) => ino = integer(&row, 0),
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(ino))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Result;
+    use crate::{AgentFS, AgentFSOptions, FsError, DEFAULT_FILE_MODE};
+
+    async fn kinds(agent: &AgentFS, repair: bool) -> Result<Vec<IssueKind>> {
+        let report = agent.fsck(repair).await?;
+        Ok(report.issues.iter().map(|issue| issue.kind).collect())
+    }
+
+    #[tokio::test]
+    async fn test_fsck_clean_filesystem() -> Result<()> {
+        let agent = AgentFS::open(AgentFSOptions::ephemeral()).await?;
+        agent.fs.mkdir("/dir", 0, 0).await?;
+        agent.fs.mkdir("/dir/sub", 0, 0).await?;
+        agent
+            .fs
+            .create_file("/dir/file", DEFAULT_FILE_MODE, 0, 0)
+            .await?;
+        agent.fs.pwrite("/dir/file", 0, &[1u8; 10000]).await?;
+        agent.fs.symlink("/dir/file", "/link", 0, 0).await?;
+        agent.fs.link("/dir/file", "/hardlink").await?;
+
+        let report = agent.fsck(false).await?;
+        assert!(report.issues.is_empty(), "{:?}", report.issues);
+        assert_eq!(report.chunks_verified, 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fsck_detects_and_repairs_metadata() -> Result<()> {
+        let agent = AgentFS::open(AgentFSOptions::ephemeral()).await?;
+        agent
+            .fs
+            .create_file("/file", DEFAULT_FILE_MODE, 0, 0)
+            .await?;
+        agent.fs.pwrite("/file", 0, b"hello").await?;
+        let ino = agent.fs.stat("/file").await?.unwrap().ino;
+
+        let conn = agent.get_connection().await?;
+        conn.execute("UPDATE fs_inode SET nlink = 5 WHERE ino = ?", (ino,))
+            .await?;
+        conn.execute(
+            "INSERT INTO fs_data (ino, chunk_index, data) VALUES (?, 7, X'00')",
+            (ino,),
+        )
+        .await?;
+        conn.execute(
+            "INSERT INTO fs_dentry (name, parent_ino, ino) VALUES ('ghost', 1, 999)",
+            (),
+        )
+        .await?;
+        conn.execute(
+            "INSERT INTO fs_symlink (ino, target) VALUES (998, '/x')",
+            (),
+        )
+        .await?;
+        drop(conn);
+
+        let found = kinds(&agent, false).await?;
+        for kind in [
+            IssueKind::OrphanDentry,
+            IssueKind::LinkCount,
+            IssueKind::ExcessChunk,
+            IssueKind::DanglingSymlink,
+        ] {
+            assert!(found.contains(&kind), "{kind} not in {found:?}");
+        }
+
+        let report = agent.fsck(true).await?;
+        assert!(!report.has_errors());
+        assert!(kinds(&agent, false).await?.is_empty());
+        assert_eq!(agent.fs.read_file("/file").await?.unwrap(), b"hello");
+        assert!(agent.fs.stat("/ghost").await?.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fsck_repairs_chunk_of_inline_file() -> Result<()> {
+        let agent = AgentFS::open(AgentFSOptions::ephemeral()).await?;
+        agent
+            .fs
+            .create_file("/file", DEFAULT_FILE_MODE, 0, 0)
+            .await?;
+        agent.fs.pwrite("/file", 0, b"inline").await?;
+        let ino = agent.fs.stat("/file").await?.unwrap().ino;
+
+        let conn = agent.get_connection().await?;
+        conn.execute(
+            "INSERT INTO fs_data (ino, chunk_index, data) VALUES (?, 0, X'00')",
+            (ino,),
+        )
+        .await?;
+        drop(conn);
+
+        assert_eq!(kinds(&agent, true).await?, vec![IssueKind::ExcessChunk]);
+        assert!(kinds(&agent, false).await?.is_empty());
+        assert_eq!(agent.fs.read_file("/file").await?.unwrap(), b"inline");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fsck_repairs_unreachable_inode() -> Result<()> {
+        let agent = AgentFS::open(AgentFSOptions::ephemeral()).await?;
+        agent.fs.mkdir("/dir", 0, 0).await?;
+        agent
+            .fs
+            .create_file("/dir/file", DEFAULT_FILE_MODE, 0, 0)
+            .await?;
+        agent.fs.pwrite("/dir/file", 0, b"data").await?;
+
+        let conn = agent.get_connection().await?;
+        conn.execute("DELETE FROM fs_dentry WHERE name = 'dir'", ())
+            .await?;
+        drop(conn);
+
+        let report = agent.fsck(true).await?;
+        assert!(!report.has_errors());
+        assert!(kinds(&agent, false).await?.is_empty());
+        let conn = agent.get_connection().await?;
+        let mut rows = conn.query("SELECT COUNT(*) FROM fs_data", ()).await?;
+        let row = rows.next().await?.unwrap();
+        assert_eq!(integer(&row, 0), 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fsck_detects_checksum_mismatch() -> Result<()> {
+        let agent = AgentFS::open(AgentFSOptions::ephemeral()).await?;
+        agent
+            .fs
+            .create_file("/file", DEFAULT_FILE_MODE, 0, 0)
+            .await?;
+        // Large enough to be stored in a chunk rather than in the inode
+        agent.fs.pwrite("/file", 0, &[7u8; 3000]).await?;
+
+        let conn = agent.get_connection().await?;
+        conn.execute("UPDATE fs_data SET data = X'68656C6C30'", ())
+            .await?;
+        drop(conn);
+
+        let err = agent.fs.read_file("/file").await.unwrap_err();
+        assert!(matches!(err, crate::error::Error::Fs(FsError::Corrupted)));
+
+        let report = agent.fsck(true).await?;
+        assert!(report.has_errors());
+        assert_eq!(report.issues[0].kind, IssueKind::ChecksumMismatch);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fsck_repairs_chunk_refcounts() -> Result<()> {
+        let agent = AgentFS::open(AgentFSOptions::ephemeral()).await?;
+        agent.fs.enable_dedup().await?;
+        let data = vec![3u8; 3000];
+        agent.fs.create_file("/a", DEFAULT_FILE_MODE, 0, 0).await?;
+        agent.fs.pwrite("/a", 0, &data).await?;
+        agent.fs.create_file("/b", DEFAULT_FILE_MODE, 0, 0).await?;
+        agent.fs.pwrite("/b", 0, &data).await?;
+
+        let conn = agent.get_connection().await?;
+        conn.execute("UPDATE fs_chunk SET refcount = 1", ()).await?;
+        drop(conn);
+
+        assert_eq!(kinds(&agent, true).await?, vec![IssueKind::ChunkRefcount]);
+        agent.fs.remove("/a").await?;
+        assert_eq!(agent.fs.read_file("/b").await?.unwrap(), data);
+        assert!(kinds(&agent, false).await?.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,412 @@
+//! Audit log of filesystem hook decisions.
+//!
+//! Hooks that sit in front of a filesystem (see the LevFS hooks in the CLI)
+//! allow, deny or rewrite operations before they happen and react to them
+//! afterwards. Every outcome is recorded in `hook_log` with the hook that
+//! produced it and how long the hook took, so the agent database stays the
+//! audit record of what happened to the filesystem and why.
+//!
+//! Like the tool call log and the journal, the hook log is not part of a
+//! snapshot and is never rewritten by a restore.
+
+use crate::connection_pool::ConnectionPool;
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use turso::Value;
+
+/// Decision recorded in the hook log
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HookLogDecision {
+    /// The operation was allowed, or an async hook ran successfully
+    Allow,
+    /// The operation was denied
+    Deny,
+    /// The operation was allowed with a message
+    Message,
+    /// The operation was allowed with rewritten data
+    Transform,
+    /// A hook in audit mode would have denied the operation
+    Audit,
+    /// The hook failed, timed out or was skipped after repeated failures
+    Error,
+}
+
+impl HookLogDecision {
+    fn as_str(&self) -> &'static str {
+        match self {
+            HookLogDecision::Allow => "allow",
+            HookLogDecision::Deny => "deny",
+            HookLogDecision::Message => "message",
+            HookLogDecision::Transform => "transform",
+            HookLogDecision::Audit => "audit",
+            HookLogDecision::Error => "error",
+        }
+    }
+
+    /// Parse a decision from its name in the log (e.g. `deny`)
+    pub fn parse(s: &str) -> Option<Self> {
+        Some(match s {
+            "allow" => HookLogDecision::Allow,
+            "deny" => HookLogDecision::Deny,
+            "message" => HookLogDecision::Message,
+            "transform" => HookLogDecision::Transform,
+            "audit" => HookLogDecision::Audit,
+            "error" => HookLogDecision::Error,
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for HookLogDecision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// A hook log record
+///
+/// `event_type` is the hook event (e.g. `file:write`), `path` the path the
+/// operation applied to, if known, and `hook` the hook that made the decision.
+/// `message` is the hook's message, the reason for a denial or the error of a
+/// failed hook. `latency_us` is how long the hook ran, in microseconds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HookLogEntry {
+    pub id: i64,
+    pub timestamp: i64,
+    pub event_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hook: Option<String>,
+    pub decision: HookLogDecision,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_us: Option<i64>,
+}
+
+/// A hook decision to append to the log with [`HookLog::record`]
+#[derive(Debug, Clone)]
+pub struct HookLogRecord {
+    pub event_type: String,
+    pub path: Option<String>,
+    pub hook: Option<String>,
+    pub decision: HookLogDecision,
+    pub message: Option<String>,
+    pub latency_us: Option<i64>,
+}
+
+/// Filter for [`HookLog::query`]
+///
+/// All conditions are combined; unset fields match everything.
+#[derive(Debug, Clone, Default)]
+pub struct HookLogQuery {
+    /// Only entries with an id greater than this
+    pub after: Option<i64>,
+    /// Only entries for this path or below it
+    pub path: Option<String>,
+    /// Only entries for this event type (e.g. `file:close`)
+    pub event_type: Option<String>,
+    /// Only entries of this hook
+    pub hook: Option<String>,
+    /// Only entries with this decision
+    pub decision: Option<HookLogDecision>,
+    /// Maximum number of entries to return (default: 100)
+    pub limit: Option<i64>,
+}
+
+/// Columns of a hook log entry, in the order `row_to_entry` reads them
+const ENTRY_COLUMNS: &str = "id, timestamp, event_type, path, hook, decision, message, latency_us";
+
+/// Hook decision log backed by SQLite
+#[derive(Clone)]
+pub struct HookLog {
+    pool: ConnectionPool,
+}
+
+impl HookLog {
+    /// Create a hook log from a connection pool
+    pub async fn from_pool(pool: ConnectionPool) -> Result<Self> {
+        let log = Self { pool };
+        log.initialize().await?;
+        Ok(log)
+    }
+
+    /// Initialize the database schema
+    async fn initialize(&self) -> Result<()> {
+        let conn = self.pool.get_connection().await?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS hook_log (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                timestamp INTEGER NOT NULL,
+                event_type TEXT NOT NULL,
+                path TEXT,
+                decision TEXT NOT NULL,
+                message TEXT
+            )",
+            (),
+        )
+        .await?;
+
+        // Hook that made the decision and how long it ran (backward
+        // compatible migrations)
+        conn.execute("ALTER TABLE hook_log ADD COLUMN hook TEXT", ())
+            .await
+            .ok();
+        conn.execute("ALTER TABLE hook_log ADD COLUMN latency_us INTEGER", ())
+            .await
+            .ok();
+
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_hook_log_path ON hook_log(path)",
+            (),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Record a hook decision, returning the id of the new entry
+    pub async fn record(&self, record: &HookLogRecord) -> Result<i64> {
+        self.pool
+            .retry(|| async move {
+                let conn = self.pool.get_connection().await?;
+                let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+                let mut stmt = conn
+                    .prepare(
+                        "INSERT INTO hook_log
+                        (timestamp, event_type, path, hook, decision, message, latency_us)
+                        VALUES (?, ?, ?, ?, ?, ?, ?) RETURNING id",
+                    )
+                    .await?;
+                let row = stmt
+                    .query_row((
+                        timestamp,
+                        record.event_type.as_str(),
+                        record.path.as_deref(),
+                        record.hook.as_deref(),
+                        record.decision.as_str(),
+                        record.message.as_deref(),
+                        record.latency_us,
+                    ))
+                    .await?;
+                let id = row
+                    .get_value(0)
+                    .ok()
+                    .and_then(|v| v.as_integer().copied())
+                    .ok_or_else(|| Error::Internal("failed to get hook log ID".to_string()))?;
+                Ok(id)
+            })
+            .await
+    }
+
+    /// Get the most recent entries, oldest first
+    pub async fn recent(&self, limit: i64) -> Result<Vec<HookLogEntry>> {
+        self.tail(&HookLogQuery {
+            limit: Some(limit),
+            ..Default::default()
+        })
+        .await
+    }
+
+    /// Get the oldest entries matching a filter, oldest first
+    pub async fn query(&self, query: &HookLogQuery) -> Result<Vec<HookLogEntry>> {
+        self.select(query, false).await
+    }
+
+    /// Get the most recent entries matching a filter, oldest first
+    pub async fn tail(&self, query: &HookLogQuery) -> Result<Vec<HookLogEntry>> {
+        self.select(query, true).await
+    }
+
+    async fn select(&self, query: &HookLogQuery, newest: bool) -> Result<Vec<HookLogEntry>> {
+        let mut conditions = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+
+        if let Some(after) = query.after {
+            conditions.push("id > ?");
+            values.push(Value::Integer(after));
+        }
+        if let Some(path) = &query.path {
+            let path = path.trim_end_matches('/');
+            if !path.is_empty() {
+                conditions.push("(path = ? OR path LIKE ? || '/%')");
+                for _ in 0..2 {
+                    values.push(Value::Text(path.to_string()));
+                }
+            }
+        }
+        if let Some(event_type) = &query.event_type {
+            conditions.push("event_type = ?");
+            values.push(Value::Text(event_type.clone()));
+        }
+        if let Some(hook) = &query.hook {
+            conditions.push("hook = ?");
+            values.push(Value::Text(hook.clone()));
+        }
+        if let Some(decision) = query.decision {
+            conditions.push("decision = ?");
+            values.push(Value::Text(decision.as_str().to_string()));
+        }
+        values.push(Value::Integer(query.limit.unwrap_or(100)));
+
+        let where_clause = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+        let sql = if newest {
+            format!(
+                "SELECT {} FROM (SELECT * FROM hook_log {} ORDER BY id DESC LIMIT ?) ORDER BY id",
+                ENTRY_COLUMNS, where_clause
+            )
+        } else {
+            format!(
+                "SELECT {} FROM hook_log {} ORDER BY id LIMIT ?",
+                ENTRY_COLUMNS, where_clause
+            )
+        };
+
+        let conn = self.pool.get_read_connection().await?;
+        let mut rows = conn.query(&sql, values).await?;
+        let mut entries = Vec::new();
+        while let Some(row) = rows.next().await? {
+            entries.extend(Self::row_to_entry(&row));
+        }
+        Ok(entries)
+    }
+
+    fn row_to_entry(row: &turso::Row) -> Option<HookLogEntry> {
+        let int = |idx: usize| {
+            row.get_value(idx)
+                .ok()
+                .and_then(|v| v.as_integer().copied())
+        };
+        let text = |idx: usize| match row.get_value(idx) {
+            Ok(Value::Text(s)) => Some(s),
+            _ => None,
+        };
+
+        Some(HookLogEntry {
+            id: int(0)?,
+            timestamp: int(1).unwrap_or(0),
+            event_type: text(2)?,
+            path: text(3),
+            hook: text(4),
+            decision: HookLogDecision::parse(&text(5)?)?,
+            message: text(6),
+            latency_us: int(7),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AgentFS, AgentFSOptions};
+
+    fn record(path: &str, hook: &str, decision: HookLogDecision) -> HookLogRecord {
+        HookLogRecord {
+            event_type: "file:write".to_string(),
+            path: Some(path.to_string()),
+            hook: Some(hook.to_string()),
+            decision,
+            message: None,
+            latency_us: Some(42),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hook_log_records_decisions() -> Result<()> {
+        let agent = AgentFS::open(AgentFSOptions::ephemeral()).await?;
+        let log = &agent.hook_log;
+        log.record(&HookLogRecord {
+            message: Some("large file".to_string()),
+            ..record("/a.md", "levfs-validator", HookLogDecision::Message)
+        })
+        .await?;
+        log.record(&HookLogRecord {
+            event_type: "file:close".to_string(),
+            path: None,
+            hook: None,
+            decision: HookLogDecision::Transform,
+            message: None,
+            latency_us: None,
+        })
+        .await?;
+        log.record(&HookLogRecord {
+            message: Some("missing frontmatter".to_string()),
+            ..record("/b.md", "levfs-validator", HookLogDecision::Audit)
+        })
+        .await?;
+
+        let entries = log.recent(10).await?;
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].event_type, "file:write");
+        assert_eq!(entries[0].path.as_deref(), Some("/a.md"));
+        assert_eq!(entries[0].decision, HookLogDecision::Message);
+        assert_eq!(entries[0].message.as_deref(), Some("large file"));
+        assert_eq!(entries[0].latency_us, Some(42));
+        assert_eq!(entries[1].decision, HookLogDecision::Transform);
+        assert_eq!(entries[1].path, None);
+        assert_eq!(entries[1].latency_us, None);
+        assert_eq!(entries[2].hook.as_deref(), Some("levfs-validator"));
+        assert_eq!(entries[2].decision, HookLogDecision::Audit);
+
+        assert_eq!(log.recent(1).await?[0].id, entries[2].id);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_hook_log_query_filters() -> Result<()> {
+        let agent = AgentFS::open(AgentFSOptions::ephemeral()).await?;
+        let log = &agent.hook_log;
+        log.record(&record("/docs/a.md", "validator", HookLogDecision::Allow))
+            .await?;
+        log.record(&record("/docs/b.md", "validator", HookLogDecision::Deny))
+            .await?;
+        log.record(&record("/docs2/c.md", "policy", HookLogDecision::Deny))
+            .await?;
+
+        let denied = log
+            .query(&HookLogQuery {
+                decision: Some(HookLogDecision::Deny),
+                ..Default::default()
+            })
+            .await?;
+        assert_eq!(denied.len(), 2);
+        let docs = log
+            .query(&HookLogQuery {
+                path: Some("/docs/".to_string()),
+                ..Default::default()
+            })
+            .await?;
+        assert_eq!(docs.len(), 2);
+        let policy = log
+            .query(&HookLogQuery {
+                hook: Some("policy".to_string()),
+                ..Default::default()
+            })
+            .await?;
+        assert_eq!(policy[0].path.as_deref(), Some("/docs2/c.md"));
+        let after = log
+            .query(&HookLogQuery {
+                after: Some(denied[0].id),
+                ..Default::default()
+            })
+            .await?;
+        assert_eq!(after.len(), 1);
+        let first = log
+            .query(&HookLogQuery {
+                limit: Some(1),
+                ..Default::default()
+            })
+            .await?;
+        assert_eq!(first[0].decision, HookLogDecision::Allow);
+        Ok(())
+    }
+}
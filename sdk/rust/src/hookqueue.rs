@@ -0,0 +1,156 @@
+//! Persistent queue of events waiting for async hooks.
+//!
+//! Async hooks run after the operation that triggered them, and some run
+//! much later: debounced, queued behind others or retried. Events are kept
+//! in `hook_queue` until their hook is done with them, so events still
+//! pending when a mount exits are run by the next mount of the same agent
+//! database.
+//!
+//! Like the hook log, the queue is not part of a snapshot and is never
+//! rewritten by a restore.
+
+use crate::connection_pool::ConnectionPool;
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+use turso::Value;
+
+/// An event waiting for a hook
+///
+/// `hook` is the hook the event is queued for and `payload` whatever the
+/// hook needs to run it, typically the hook context as JSON.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HookQueueEntry {
+    pub id: i64,
+    pub created_at: i64,
+    pub hook: String,
+    pub payload: String,
+}
+
+/// Queue of events waiting for async hooks, backed by SQLite
+#[derive(Clone)]
+pub struct HookQueue {
+    pool: ConnectionPool,
+}
+
+impl HookQueue {
+    /// Create a hook queue from a connection pool
+    pub async fn from_pool(pool: ConnectionPool) -> Result<Self> {
+        let queue = Self { pool };
+        queue.initialize().await?;
+        Ok(queue)
+    }
+
+    /// Initialize the database schema
+    async fn initialize(&self) -> Result<()> {
+        let conn = self.pool.get_connection().await?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS hook_queue (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                created_at INTEGER NOT NULL,
+                hook TEXT NOT NULL,
+                payload TEXT NOT NULL
+            )",
+            (),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Queue an event for `hook`, returning the id of the new entry
+    pub async fn push(&self, hook: &str, payload: &str) -> Result<i64> {
+        self.pool
+            .retry(|| async move {
+                let conn = self.pool.get_connection().await?;
+                let created_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+                let mut stmt = conn
+                    .prepare(
+                        "INSERT INTO hook_queue (created_at, hook, payload)
+                        VALUES (?, ?, ?) RETURNING id",
+                    )
+                    .await?;
+                let row = stmt.query_row((created_at, hook, payload)).await?;
+                let id = row
+                    .get_value(0)
+                    .ok()
+                    .and_then(|v| v.as_integer().copied())
+                    .ok_or_else(|| Error::Internal("failed to get hook queue ID".to_string()))?;
+                Ok(id)
+            })
+            .await
+    }
+
+    /// Remove an event once its hook is done with it
+    pub async fn remove(&self, id: i64) -> Result<()> {
+        self.pool
+            .retry(|| async move {
+                let conn = self.pool.get_connection().await?;
+                conn.execute("DELETE FROM hook_queue WHERE id = ?", (id,))
+                    .await?;
+                Ok(())
+            })
+            .await
+    }
+
+    /// Get the events queued for `hook`, oldest first
+    pub async fn pending(&self, hook: &str) -> Result<Vec<HookQueueEntry>> {
+        let conn = self.pool.get_read_connection().await?;
+        let mut rows = conn
+            .query(
+                "SELECT id, created_at, hook, payload FROM hook_queue
+                WHERE hook = ? ORDER BY id",
+                (hook,),
+            )
+            .await?;
+        let mut entries = Vec::new();
+        while let Some(row) = rows.next().await? {
+            entries.extend(Self::row_to_entry(&row));
+        }
+        Ok(entries)
+    }
+
+    fn row_to_entry(row: &turso::Row) -> Option<HookQueueEntry> {
+        let int = |idx: usize| {
+            row.get_value(idx)
+                .ok()
+                .and_then(|v| v.as_integer().copied())
+        };
+        let text = |idx: usize| match row.get_value(idx) {
+            Ok(Value::Text(s)) => Some(s),
+            _ => None,
+        };
+
+        Some(HookQueueEntry {
+            id: int(0)?,
+            created_at: int(1).unwrap_or(0),
+            hook: text(2)?,
+            payload: text(3)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Result;
+    use crate::{AgentFS, AgentFSOptions};
+
+    #[tokio::test]
+    async fn test_hook_queue_push_and_remove() -> Result<()> {
+        let agent = AgentFS::open(AgentFSOptions::ephemeral()).await?;
+        let queue = &agent.hook_queue;
+        let first = queue.push("levfs-workflow", r#"{"path":"/a.md"}"#).await?;
+        queue.push("other", "{}").await?;
+        let second = queue.push("levfs-workflow", r#"{"path":"/b.md"}"#).await?;
+
+        let pending = queue.pending("levfs-workflow").await?;
+        assert_eq!(pending.len(), 2);
+        assert_eq!(pending[0].id, first);
+        assert_eq!(pending[0].payload, r#"{"path":"/a.md"}"#);
+
+        queue.remove(first).await?;
+        let pending = queue.pending("levfs-workflow").await?;
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].id, second);
+        Ok(())
+    }
+}
@@ -0,0 +1,673 @@
+//! Append-only journal of filesystem changes.
+//!
+//! Every mutating operation of [`crate::filesystem::AgentFS`] appends a row to
+//! `fs_journal` (inside the operation's transaction, if it runs in one), so
+//! the journal is a change feed of what happened on disk, as opposed to the
+//! tool call log which records what an agent intended to do.
+//!
+//! Consecutive writes that extend each other on the same inode are coalesced
+//! into a single entry to keep sequential writes (e.g. `cp` through FUSE) from
+//! producing one row per page.
+//!
+//! Like the tool call log, the journal is not part of a snapshot and is never
+//! rewritten by a restore.
+
+use crate::connection_pool::ConnectionPool;
+use crate::error::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use turso::{Connection, Value};
+
+const ROOT_INO: i64 = 1;
+
+/// Columns selected for a [`JournalEntry`]
+const ENTRY_COLUMNS: &str =
+    "id, timestamp, op, ino, path, new_path, offset, length, size, detail, tool_call_id";
+
+/// Kind of change recorded in the journal
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JournalOp {
+    Create,
+    Mkdir,
+    Mknod,
+    Symlink,
+    Link,
+    Unlink,
+    Rmdir,
+    Rename,
+    Write,
+    Truncate,
+    Chmod,
+    Chown,
+    Utimens,
+    SetXattr,
+    RemoveXattr,
+}
+
+impl JournalOp {
+    fn as_str(&self) -> &'static str {
+        match self {
+            JournalOp::Create => "create",
+            JournalOp::Mkdir => "mkdir",
+            JournalOp::Mknod => "mknod",
+            JournalOp::Symlink => "symlink",
+            JournalOp::Link => "link",
+            JournalOp::Unlink => "unlink",
+            JournalOp::Rmdir => "rmdir",
+            JournalOp::Rename => "rename",
+            JournalOp::Write => "write",
+            JournalOp::Truncate => "truncate",
+            JournalOp::Chmod => "chmod",
+            JournalOp::Chown => "chown",
+            JournalOp::Utimens => "utimens",
+            JournalOp::SetXattr => "setxattr",
+            JournalOp::RemoveXattr => "removexattr",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        Some(match s {
+            "create" => JournalOp::Create,
+            "mkdir" => JournalOp::Mkdir,
+            "mknod" => JournalOp::Mknod,
+            "symlink" => JournalOp::Symlink,
+            "link" => JournalOp::Link,
+            "unlink" => JournalOp::Unlink,
+            "rmdir" => JournalOp::Rmdir,
+            "rename" => JournalOp::Rename,
+            "write" => JournalOp::Write,
+            "truncate" => JournalOp::Truncate,
+            "chmod" => JournalOp::Chmod,
+            "chown" => JournalOp::Chown,
+            "utimens" => JournalOp::Utimens,
+            "setxattr" => JournalOp::SetXattr,
+            "removexattr" => JournalOp::RemoveXattr,
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for JournalOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// A journal record
+///
+/// `path` is the path of the affected entry when the change was made.
+/// `new_path` is the destination of a rename or link. `offset` and `length`
+/// describe the byte range of a write, `size` is the file size after a write
+/// or truncate. `detail` carries operation-specific data: the target of a
+/// symlink, the mode of a chmod (octal), `uid:gid` of a chown, or the name of
+/// an extended attribute.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub id: i64,
+    pub timestamp: i64,
+    pub op: JournalOp,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ino: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub length: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<i64>,
+}
+
+/// Filter for [`Journal::query`]
+///
+/// All conditions are combined; unset fields match everything.
+#[derive(Debug, Clone, Default)]
+pub struct JournalQuery {
+    /// Only entries with an id greater than this
+    pub after: Option<i64>,
+    /// Only entries whose path or new path is this path or below it
+    pub path: Option<String>,
+    /// Only entries for this inode
+    pub ino: Option<i64>,
+    /// Only entries recorded on behalf of this tool call
+    pub tool_call_id: Option<i64>,
+    /// Maximum number of entries to return (default: 100)
+    pub limit: Option<i64>,
+}
+
+/// Read access to the filesystem change journal
+///
+/// Expects the filesystem schema to be initialized, as `AgentFS::open` does.
+#[derive(Clone)]
+pub struct Journal {
+    pool: ConnectionPool,
+}
+
+impl Journal {
+    /// Create a journal reader from a connection pool
+    pub fn from_pool(pool: ConnectionPool) -> Self {
+        Self { pool }
+    }
+
+    /// Get the oldest entries matching a filter, oldest first
+    pub async fn query(&self, query: &JournalQuery) -> Result<Vec<JournalEntry>> {
+        self.select(query, false).await
+    }
+
+    /// Get the most recent entries matching a filter, oldest first
+    pub async fn tail(&self, query: &JournalQuery) -> Result<Vec<JournalEntry>> {
+        self.select(query, true).await
+    }
+
+    /// Get entries recorded after the given id, oldest first
+    ///
+    /// Pass the id of the last entry seen to follow the journal as it grows.
+    /// A coalesced write keeps its id, so growth of an entry that has already
+    /// been returned is not reported again.
+    pub async fn since(&self, after: i64) -> Result<Vec<JournalEntry>> {
+        self.query(&JournalQuery {
+            after: Some(after),
+            limit: Some(i64::MAX),
+            ..Default::default()
+        })
+        .await
+    }
+
+    /// Get the paths changed on behalf of a tool call, in order of first change
+    ///
+    /// Both the source and destination of renames and links are included.
+    pub async fn files_for_tool_call(&self, tool_call_id: i64) -> Result<Vec<String>> {
+        let conn = self.pool.get_read_connection().await?;
+        let mut rows = conn
+            .query(
+                "SELECT path, new_path FROM fs_journal WHERE tool_call_id = ? ORDER BY id",
+                (tool_call_id,),
+            )
+            .await?;
+
+        let mut paths: Vec<String> = Vec::new();
+        while let Some(row) = rows.next().await? {
+            for idx in 0..2 {
+                if let Ok(Value::Text(path)) = row.get_value(idx) {
+                    if !paths.contains(&path) {
+                        paths.push(path);
+                    }
+                }
+            }
+        }
+        Ok(paths)
+    }
+
+    async fn select(&self, query: &JournalQuery, newest: bool) -> Result<Vec<JournalEntry>> {
+        let mut conditions = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+
+        if let Some(after) = query.after {
+            conditions.push("id > ?");
+            values.push(Value::Integer(after));
+        }
+        if let Some(path) = &query.path {
+            let path = path.trim_end_matches('/');
+            if !path.is_empty() {
+                conditions.push(
+                    "(path = ? OR path LIKE ? || '/%' OR new_path = ? OR new_path LIKE ? || '/%')",
+                );
+                for _ in 0..4 {
+                    values.push(Value::Text(path.to_string()));
+                }
+            }
+        }
+        if let Some(ino) = query.ino {
+            conditions.push("ino = ?");
+            values.push(Value::Integer(ino));
+        }
+        if let Some(tool_call_id) = query.tool_call_id {
+            conditions.push("tool_call_id = ?");
+            values.push(Value::Integer(tool_call_id));
+        }
+        values.push(Value::Integer(query.limit.unwrap_or(100)));
+
+        let where_clause = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+        let sql = if newest {
+            format!(
+                "SELECT {} FROM (SELECT * FROM fs_journal {} ORDER BY id DESC LIMIT ?) ORDER BY id",
+                ENTRY_COLUMNS, where_clause
+            )
+        } else {
+            format!(
+                "SELECT {} FROM fs_journal {} ORDER BY id LIMIT ?",
+                ENTRY_COLUMNS, where_clause
+            )
+        };
+
+        let conn = self.pool.get_read_connection().await?;
+        let mut rows = conn.query(&sql, values).await?;
+        let mut entries = Vec::new();
+        while let Some(row) = rows.next().await? {
+            entries.extend(Self::row_to_entry(&row));
+        }
+        Ok(entries)
+    }
+
+    fn row_to_entry(row: &turso::Row) -> Option<JournalEntry> {
+        let int = |idx: usize| {
+            row.get_value(idx)
+                .ok()
+                .and_then(|v| v.as_integer().copied())
+        };
+        let text = |idx: usize| match row.get_value(idx) {
+            Ok(Value::Text(s)) => Some(s),
+            _ => None,
+        };
+
+        Some(JournalEntry {
+            id: int(0)?,
+            timestamp: int(1).unwrap_or(0),
+            op: JournalOp::parse(&text(2)?)?,
+            ino: int(3),
+            path: text(4),
+            new_path: text(5),
+            offset: int(6),
+            length: int(7),
+            size: int(8),
+            detail: text(9),
+            tool_call_id: int(10),
+        })
+    }
+}
+
+/// A change about to be appended to the journal
+pub(crate) struct Change {
+    op: JournalOp,
+    ino: Option<i64>,
+    path: Option<String>,
+    new_path: Option<String>,
+    offset: Option<i64>,
+    length: Option<i64>,
+    size: Option<i64>,
+    detail: Option<String>,
+}
+
+impl Change {
+    pub(crate) fn new(op: JournalOp) -> Self {
+        Self {
+            op,
+            ino: None,
+            path: None,
+            new_path: None,
+            offset: None,
+            length: None,
+            size: None,
+            detail: None,
+        }
+    }
+
+    pub(crate) fn ino(mut self, ino: i64) -> Self {
+        self.ino = Some(ino);
+        self
+    }
+
+    pub(crate) fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    pub(crate) fn new_path(mut self, new_path: impl Into<String>) -> Self {
+        self.new_path = Some(new_path.into());
+        self
+    }
+
+    pub(crate) fn range(mut self, offset: u64, length: usize) -> Self {
+        self.offset = Some(offset as i64);
+        self.length = Some(length as i64);
+        self
+    }
+
+    pub(crate) fn size(mut self, size: u64) -> Self {
+        self.size = Some(size as i64);
+        self
+    }
+
+    pub(crate) fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// Record permission bits as the detail, in octal
+    pub(crate) fn mode(self, mode: u32) -> Self {
+        self.detail(format!("{:04o}", mode & 0o7777))
+    }
+
+    /// Record new ownership as the detail, `-` standing for unchanged
+    pub(crate) fn owner(self, uid: Option<u32>, gid: Option<u32>) -> Self {
+        let id = |v: Option<u32>| v.map_or_else(|| "-".to_string(), |v| v.to_string());
+        self.detail(format!("{}:{}", id(uid), id(gid)))
+    }
+
+    /// Append the change to the journal, made on behalf of `tool_call_id`
+    ///
+    /// A write that starts where the previous entry (a write to the same
+    /// inode for the same tool call) ended extends that entry instead. If no
+    /// path was given, it is looked up from the inode.
+    pub(crate) async fn record(
+        mut self,
+        conn: &Connection,
+        tool_call_id: Option<i64>,
+    ) -> Result<()> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+
+        if let (JournalOp::Write, Some(ino), Some(offset), Some(length)) =
+            (self.op, self.ino, self.offset, self.length)
+        {
+            let mut stmt = conn
+                .prepare_cached(
+                    "SELECT id, op = 'write' AND ino = ? AND offset + length = ?
+                        AND COALESCE(tool_call_id, 0) = ?
+                    FROM fs_journal ORDER BY id DESC LIMIT 1",
+                )
+                .await?;
+            let mut rows = stmt.query((ino, offset, tool_call_id.unwrap_or(0))).await?;
+            let last = match rows.next().await? {
+                Some(row) => {
+                    let int = |idx| {
+                        row.get_value(idx)
+                            .ok()
+                            .and_then(|v| v.as_integer().copied())
+                    };
+                    int(0).filter(|_| int(1).unwrap_or(0) != 0)
+                }
+                None => None,
+            };
+            if let Some(id) = last {
+                let mut stmt = conn
+                    .prepare_cached(
+                        "UPDATE fs_journal SET timestamp = ?, length = length + ?, size = ? WHERE id = ?",
+                    )
+                    .await?;
+                stmt.execute((timestamp, length, self.size, id)).await?;
+                return Ok(());
+            }
+        }
+
+        if self.path.is_none() {
+            if let Some(ino) = self.ino {
+                self.path = inode_path(conn, ino).await?;
+            }
+        }
+
+        let mut stmt = conn
+            .prepare_cached(
+                "INSERT INTO fs_journal (timestamp, op, ino, path, new_path, offset, length, size, detail, tool_call_id)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .await?;
+        stmt.execute((
+            timestamp,
+            self.op.as_str(),
+            self.ino,
+            self.path,
+            self.new_path,
+            self.offset,
+            self.length,
+            self.size,
+            self.detail,
+            tool_call_id,
+        ))
+        .await?;
+        Ok(())
+    }
+}
+
+/// Get a path of an inode by walking directory entries up to the root
+///
+/// Returns `None` if the inode is not linked into the tree. For an inode with
+/// several hard links, the oldest link is used.
+pub(crate) async fn inode_path(conn: &Connection, ino: i64) -> Result<Option<String>> {
+    if ino == ROOT_INO {
+        return Ok(Some("/".to_string()));
+    }
+
+    let mut components = Vec::new();
+    let mut current = ino;
+    while current != ROOT_INO {
+        let mut stmt = conn
+            .prepare_cached(
+                "SELECT parent_ino, name FROM fs_dentry WHERE ino = ? ORDER BY id LIMIT 1",
+            )
+            .await?;
+        let mut rows = stmt.query((current,)).await?;
+        let Some(row) = rows.next().await? else {
+            return Ok(None);
+        };
+        let parent = row
+            .get_value(0)
+            .ok()
+            .and_then(|v| v.as_integer().copied())
+            .unwrap_or(ROOT_INO);
+        let name = match row.get_value(1) {
+            Ok(Value::Text(name)) => name,
+            _ => return Ok(None),
+        };
+        components.push(name);
+        // Guard against cycles in a corrupted tree
+        if components.len() > 4096 {
+            return Ok(None);
+        }
+        current = parent;
+    }
+
+    components.reverse();
+    Ok(Some(format!("/{}", components.join("/"))))
+}
+
+/// Get the path of the entry `name` in directory `parent_ino`
+pub(crate) async fn child_path(conn: &Connection, parent_ino: i64, name: &str) -> Result<String> {
+    let parent = inode_path(conn, parent_ino).await?.unwrap_or_default();
+    Ok(format!("{}/{}", parent.trim_end_matches('/'), name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::FileSystem;
+    use crate::{AgentFS, AgentFSOptions, DEFAULT_FILE_MODE};
+
+    fn ops(entries: &[JournalEntry]) -> Vec<JournalOp> {
+        entries.iter().map(|e| e.op).collect()
+    }
+
+    #[tokio::test]
+    async fn test_journal_records_changes() -> Result<()> {
+        let agent = AgentFS::open(AgentFSOptions::ephemeral()).await?;
+        agent.fs.mkdir("/dir", 0, 0).await?;
+        let (_, file) = agent
+            .fs
+            .create_file("/dir/a.txt", DEFAULT_FILE_MODE, 0, 0)
+            .await?;
+        file.pwrite(0, b"hello").await?;
+        file.truncate(2).await?;
+        agent.fs.rename("/dir/a.txt", "/dir/b.txt").await?;
+        agent.fs.remove("/dir/b.txt").await?;
+
+        let entries = agent.journal.query(&JournalQuery::default()).await?;
+        assert_eq!(
+            ops(&entries),
+            vec![
+                JournalOp::Mkdir,
+                JournalOp::Create,
+                JournalOp::Write,
+                JournalOp::Truncate,
+                JournalOp::Rename,
+                JournalOp::Unlink,
+            ]
+        );
+        assert_eq!(entries[2].path.as_deref(), Some("/dir/a.txt"));
+        assert_eq!((entries[2].offset, entries[2].length), (Some(0), Some(5)));
+        assert_eq!(entries[3].size, Some(2));
+        assert_eq!(entries[4].new_path.as_deref(), Some("/dir/b.txt"));
+        assert_eq!(entries[5].path.as_deref(), Some("/dir/b.txt"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_journal_coalesces_sequential_writes() -> Result<()> {
+        let agent = AgentFS::open(AgentFSOptions::ephemeral()).await?;
+        let (_, file) = agent
+            .fs
+            .create_file("/log.txt", DEFAULT_FILE_MODE, 0, 0)
+            .await?;
+        file.pwrite(0, b"aaaa").await?;
+        file.pwrite(4, b"bbbb").await?;
+        file.pwrite(8, b"cccc").await?;
+        file.pwrite(0, b"dd").await?;
+        file.flush().await?;
+
+        let entries = agent
+            .journal
+            .tail(&JournalQuery {
+                limit: Some(10),
+                ..Default::default()
+            })
+            .await?;
+        assert_eq!(
+            ops(&entries),
+            vec![JournalOp::Create, JournalOp::Write, JournalOp::Write]
+        );
+        assert_eq!((entries[1].offset, entries[1].length), (Some(0), Some(12)));
+        assert_eq!(entries[1].size, Some(12));
+        assert_eq!((entries[2].offset, entries[2].length), (Some(0), Some(2)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_journal_tool_call_correlation() -> Result<()> {
+        let agent = AgentFS::open(AgentFSOptions::ephemeral()).await?;
+        let (_, file) = agent
+            .fs
+            .create_file("/out.txt", DEFAULT_FILE_MODE, 0, 0)
+            .await?;
+
+        let id = agent.tools.start("execute_code", None).await?;
+        agent.set_tool_call(Some(id));
+        file.pwrite(0, b"result").await?;
+        file.flush().await?;
+        agent.fs.rename("/out.txt", "/result.txt").await?;
+        agent.set_tool_call(None);
+        file.pwrite(6, b"\n").await?;
+        file.flush().await?;
+
+        let entries = agent
+            .journal
+            .query(&JournalQuery {
+                tool_call_id: Some(id),
+                ..Default::default()
+            })
+            .await?;
+        assert_eq!(ops(&entries), vec![JournalOp::Write, JournalOp::Rename]);
+        assert_eq!(
+            agent.journal.files_for_tool_call(id).await?,
+            vec!["/out.txt".to_string(), "/result.txt".to_string()]
+        );
+
+        // The write after the tool call is not coalesced into its entry
+        let last = agent.journal.since(entries[1].id).await?;
+        assert_eq!(ops(&last), vec![JournalOp::Write]);
+        assert_eq!(last[0].tool_call_id, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_journal_tool_call_control_file() -> Result<()> {
+        let agent = AgentFS::open(AgentFSOptions::ephemeral()).await?;
+        let control = tempfile::NamedTempFile::new()?;
+        agent
+            .fs
+            .set_tool_call_file(Some(control.path().to_path_buf()));
+        let (_, file) = agent
+            .fs
+            .create_file("/out.txt", DEFAULT_FILE_MODE, 0, 0)
+            .await?;
+
+        // Each change is attributed to the id in the file as it is made
+        let first = agent.tools.start("first", None).await?;
+        let second = agent.tools.start("second", None).await?;
+        std::fs::write(control.path(), first.to_string())?;
+        file.pwrite(0, b"one").await?;
+        std::fs::write(control.path(), second.to_string())?;
+        file.pwrite(3, b"two").await?;
+        file.flush().await?;
+        agent.fs.mkdir("/second", 0, 0).await?;
+        std::fs::write(control.path(), "")?;
+        agent.fs.mkdir("/none", 0, 0).await?;
+
+        let entries = agent.journal.tail(&JournalQuery::default()).await?;
+        let ids: Vec<_> = entries.iter().map(|e| e.tool_call_id).collect();
+        assert_eq!(
+            ops(&entries),
+            vec![
+                JournalOp::Create,
+                JournalOp::Write,
+                JournalOp::Write,
+                JournalOp::Mkdir,
+                JournalOp::Mkdir
+            ]
+        );
+        assert_eq!(
+            ids,
+            vec![None, Some(first), Some(second), Some(second), None]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_journal_query_filters() -> Result<()> {
+        let agent = AgentFS::open(AgentFSOptions::ephemeral()).await?;
+        agent.fs.mkdir("/src", 0, 0).await?;
+        agent.fs.mkdir("/srcdir", 0, 0).await?;
+        agent.fs.pwrite("/src/main.rs", 0, b"fn main() {}").await?;
+        let stats = agent.fs.stat("/src/main.rs").await?.unwrap();
+        agent.fs.chmod(stats.ino, 0o755).await?;
+
+        let entries = agent
+            .journal
+            .query(&JournalQuery {
+                path: Some("/src".to_string()),
+                ..Default::default()
+            })
+            .await?;
+        assert!(entries.iter().all(|e| e.path.as_deref() != Some("/srcdir")));
+        assert_eq!(entries.last().unwrap().op, JournalOp::Chmod);
+        assert_eq!(entries.last().unwrap().detail.as_deref(), Some("0755"));
+
+        let last = entries.last().unwrap().id;
+        assert!(agent.journal.since(last).await?.is_empty());
+        agent.fs.remove("/srcdir").await?;
+        let new = agent.journal.since(last).await?;
+        assert_eq!(ops(&new), vec![JournalOp::Rmdir]);
+
+        let by_ino = agent
+            .journal
+            .query(&JournalQuery {
+                ino: Some(stats.ino),
+                limit: Some(1),
+                ..Default::default()
+            })
+            .await?;
+        assert_eq!(ops(&by_ino), vec![JournalOp::Create]);
+        Ok(())
+    }
+}
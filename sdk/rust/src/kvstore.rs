@@ -52,23 +52,28 @@ impl KvStore {
 
     /// Set a key-value pair
     pub async fn set<V: Serialize>(&self, key: &str, value: &V) -> Result<()> {
-        let conn = self.pool.get_connection().await?;
         let serialized = serde_json::to_string(value)?;
-        conn.execute(
-            "INSERT INTO kv_store (key, value, updated_at)
-            VALUES (?, ?, unixepoch())
-            ON CONFLICT(key) DO UPDATE SET
-                value = excluded.value,
-                updated_at = unixepoch()",
-            (key, serialized.as_str()),
-        )
-        .await?;
-        Ok(())
+        let serialized = serialized.as_str();
+        self.pool
+            .retry(|| async move {
+                let conn = self.pool.get_connection().await?;
+                conn.execute(
+                    "INSERT INTO kv_store (key, value, updated_at)
+                    VALUES (?, ?, unixepoch())
+                    ON CONFLICT(key) DO UPDATE SET
+                        value = excluded.value,
+                        updated_at = unixepoch()",
+                    (key, serialized),
+                )
+                .await?;
+                Ok(())
+            })
+            .await
     }
 
     /// Get a value by key
     pub async fn get<V: for<'de> Deserialize<'de>>(&self, key: &str) -> Result<Option<V>> {
-        let conn = self.pool.get_connection().await?;
+        let conn = self.pool.get_read_connection().await?;
         let mut rows = conn
             .query("SELECT value FROM kv_store WHERE key = ?", (key,))
             .await?;
@@ -93,15 +98,19 @@ impl KvStore {
 
     /// Delete a key
     pub async fn delete(&self, key: &str) -> Result<()> {
-        let conn = self.pool.get_connection().await?;
-        conn.execute("DELETE FROM kv_store WHERE key = ?", (key,))
-            .await?;
-        Ok(())
+        self.pool
+            .retry(|| async move {
+                let conn = self.pool.get_connection().await?;
+                conn.execute("DELETE FROM kv_store WHERE key = ?", (key,))
+                    .await?;
+                Ok(())
+            })
+            .await
     }
 
     /// List all keys
     pub async fn keys(&self) -> Result<Vec<String>> {
-        let conn = self.pool.get_connection().await?;
+        let conn = self.pool.get_read_connection().await?;
         let mut rows = conn.query("SELECT key FROM kv_store", ()).await?;
         let mut keys = Vec::new();
         while let Some(row) = rows.next().await? {
@@ -1,15 +1,22 @@
 pub mod connection_pool;
 pub mod error;
 pub mod filesystem;
+pub mod fsck;
+pub mod hooklog;
+pub mod hookqueue;
+pub mod journal;
 pub mod kvstore;
+pub mod readlog;
+pub mod snapshot;
 pub mod toolcalls;
 
 use error::{Error, Result};
 use std::{
     collections::{HashSet, VecDeque},
     path::{Path, PathBuf},
+    time::{Duration, Instant},
 };
-use turso::{Builder, EncryptionOpts, Value};
+use turso::{Builder, Database, EncryptionOpts, Value};
 
 // Re-export turso sync types for CLI usage
 pub use turso::sync::{DatabaseSyncStats, PartialBootstrapStrategy, PartialSyncOpts};
@@ -20,9 +27,15 @@ pub use filesystem::HostFS;
 pub use filesystem::{
     BoxedFile, DirEntry, File, FileSystem, FilesystemStats, FsError, OverlayFS, Stats, TimeChange,
     DEFAULT_DIR_MODE, DEFAULT_FILE_MODE, S_IFBLK, S_IFCHR, S_IFDIR, S_IFIFO, S_IFLNK, S_IFMT,
-    S_IFREG, S_IFSOCK,
+    S_IFREG, S_IFSOCK, XATTR_CREATE, XATTR_REPLACE,
 };
+pub use fsck::FsckReport;
+pub use hooklog::{HookLog, HookLogDecision, HookLogEntry, HookLogQuery, HookLogRecord};
+pub use hookqueue::{HookQueue, HookQueueEntry};
+pub use journal::{Journal, JournalEntry, JournalOp, JournalQuery};
 pub use kvstore::KvStore;
+pub use readlog::{ReadLog, ReadLogEntry, ReadLogQuery, ReadLogRecord};
+pub use snapshot::{Snapshot, Snapshots};
 pub use toolcalls::{ToolCall, ToolCallStats, ToolCallStatus, ToolCalls};
 
 /// Directory containing agentfs databases
@@ -114,6 +127,15 @@ pub struct AgentFSOptions {
     pub sync: SyncOptions,
     /// Encryption configuration for database at rest
     pub encryption: Option<EncryptionConfig>,
+    /// Maximum number of read-only connections opened alongside the writer
+    /// (default: 4). Reads run concurrently on them; `Some(0)` serializes
+    /// reads with writes on one connection. Synced databases always do.
+    pub readers: Option<usize>,
+    /// Maximum number of writer connections (default: 1). With more than
+    /// one, the database is switched to turso's MVCC mode and write
+    /// transactions run concurrently, each retried when it conflicts with
+    /// another. Ignored for synced databases.
+    pub writers: Option<usize>,
 }
 
 impl AgentFSOptions {
@@ -155,6 +177,8 @@ impl AgentFSOptions {
             base: None,
             sync: SyncOptions::default(),
             encryption: None,
+            readers: None,
+            writers: None,
         }
     }
 
@@ -166,6 +190,8 @@ impl AgentFSOptions {
             base: None,
             sync: SyncOptions::default(),
             encryption: None,
+            readers: None,
+            writers: None,
         }
     }
 
@@ -177,6 +203,8 @@ impl AgentFSOptions {
             base: None,
             sync: SyncOptions::default(),
             encryption: None,
+            readers: None,
+            writers: None,
         }
     }
 
@@ -186,6 +214,18 @@ impl AgentFSOptions {
         self
     }
 
+    /// Set the maximum number of read-only connections
+    pub fn with_readers(mut self, readers: usize) -> Self {
+        self.readers = Some(readers);
+        self
+    }
+
+    /// Set the maximum number of writer connections
+    pub fn with_writers(mut self, writers: usize) -> Self {
+        self.writers = Some(writers);
+        self
+    }
+
     /// Set the base directory for overlay filesystem (copy-on-write)
     pub fn with_base(mut self, base: impl Into<PathBuf>) -> Self {
         self.base = Some(base.into());
@@ -255,21 +295,93 @@ impl AgentFSOptions {
     }
 }
 
+/// Convert an error opening the local database at `db_path`.
+///
+/// Turso locks the database file for the process that opens it and never
+/// shares it with another process, so a second process opening the same
+/// database fails with a locking error.
+fn open_error(db_path: &str, err: turso::Error) -> Error {
+    match err {
+        turso::Error::Error(msg) if msg.contains("locked by another process") => {
+            Error::DatabaseInUse(db_path.to_string())
+        }
+        err => err.into(),
+    }
+}
+
+/// Open the local database at `db_path`, waiting up to
+/// [`connection_pool::BUSY_TIMEOUT`] for another process to close it.
+async fn open_local(db_path: &str, encryption: Option<&EncryptionConfig>) -> Result<Database> {
+    let started = Instant::now();
+    let mut delay = Duration::from_millis(10);
+    loop {
+        let mut builder = Builder::new_local(db_path);
+        if let Some(enc_config) = encryption {
+            builder = builder
+                .experimental_encryption(true)
+                .with_encryption(EncryptionOpts {
+                    cipher: enc_config.cipher.clone(),
+                    hexkey: enc_config.hex_key.clone(),
+                });
+        }
+        match builder.build().await.map_err(|e| open_error(db_path, e)) {
+            Err(Error::DatabaseInUse(_)) if started.elapsed() < connection_pool::BUSY_TIMEOUT => {
+                tokio::time::sleep(delay).await;
+                delay = std::cmp::min(delay * 2, Duration::from_millis(200));
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Switch the journal mode of `db` to turso's MVCC mode, or back to WAL.
+///
+/// The mode is stored in the database, so it follows the options of the
+/// process that opened it last.
+async fn set_mvcc(db: &Database, mvcc: bool) -> Result<()> {
+    let conn = db.connect()?;
+    let mut rows = conn.query("PRAGMA journal_mode", ()).await?;
+    let mode = match rows.next().await? {
+        Some(row) => row.get::<String>(0)?,
+        None => String::new(),
+    };
+    drop(rows);
+    let target = if mvcc { "experimental_mvcc" } else { "wal" };
+    if mvcc != (mode == "experimental_mvcc") {
+        let mut rows = conn
+            .query(&format!("PRAGMA journal_mode = '{target}'"), ())
+            .await?;
+        while rows.next().await?.is_some() {}
+    }
+    Ok(())
+}
+
 /// The main AgentFS SDK struct
 ///
 /// This provides a unified interface to the filesystem, key-value store,
-/// and tool calls tracking backed by a SQLite database.
+/// tool calls tracking, the filesystem change journal, the hook decision
+/// log and the file read log backed by a SQLite database.
 pub struct AgentFS {
     pool: connection_pool::ConnectionPool,
     sync_db: Option<turso::sync::Database>,
     pub kv: KvStore,
     pub fs: filesystem::AgentFS,
     pub tools: ToolCalls,
+    pub journal: Journal,
+    pub hook_log: HookLog,
+    pub hook_queue: HookQueue,
+    pub read_log: ReadLog,
+    snapshots: Snapshots,
 }
 
 impl AgentFS {
     /// Open an AgentFS instance
     ///
+    /// A local database stays locked by the process that opened it until
+    /// it's dropped. Opening one held by another process waits up to
+    /// [`connection_pool::BUSY_TIMEOUT`] for it, then fails with
+    /// [`Error::DatabaseInUse`].
+    ///
     /// # Arguments
     /// * `options` - Configuration options (use Default::default() for ephemeral)
     ///
@@ -333,19 +445,11 @@ impl AgentFS {
             let pool = connection_pool::ConnectionPool::new_sync(db.clone());
             (Some(db), pool)
         } else {
-            let db = if let Some(ref enc_config) = options.encryption {
-                Builder::new_local(&db_path)
-                    .experimental_encryption(true)
-                    .with_encryption(EncryptionOpts {
-                        cipher: enc_config.cipher.clone(),
-                        hexkey: enc_config.hex_key.clone(),
-                    })
-                    .build()
-                    .await?
-            } else {
-                Builder::new_local(&db_path).build().await?
-            };
-            let pool = connection_pool::ConnectionPool::new(db);
+            let db = open_local(&db_path, options.encryption.as_ref()).await?;
+            let writers = options.writers.unwrap_or(connection_pool::DEFAULT_WRITERS);
+            set_mvcc(&db, writers > 1).await?;
+            let readers = options.readers.unwrap_or(connection_pool::DEFAULT_READERS);
+            let pool = connection_pool::ConnectionPool::with_writers(db, writers, readers);
             (None, pool)
         };
 
@@ -368,6 +472,11 @@ impl AgentFS {
         let kv = KvStore::from_pool(pool.clone()).await?;
         let fs = filesystem::AgentFS::from_pool(pool.clone()).await?;
         let tools = ToolCalls::from_pool(pool.clone()).await?;
+        let journal = Journal::from_pool(pool.clone());
+        let hook_log = HookLog::from_pool(pool.clone()).await?;
+        let hook_queue = HookQueue::from_pool(pool.clone()).await?;
+        let read_log = ReadLog::from_pool(pool.clone()).await?;
+        let snapshots = Snapshots::from_pool(pool.clone()).await?;
 
         Ok(Self {
             pool,
@@ -375,6 +484,11 @@ impl AgentFS {
             kv,
             fs,
             tools,
+            journal,
+            hook_log,
+            hook_queue,
+            read_log,
+            snapshots,
         })
     }
 
@@ -421,6 +535,7 @@ impl AgentFS {
     /// Pull changes from remote database
     pub async fn pull(&self) -> Result<()> {
         let db = self.sync_db.as_ref().ok_or(Error::SyncNotEnabled)?;
+        self.fs.flush_writes().await?;
         db.pull().await?;
         Ok(())
     }
@@ -428,6 +543,7 @@ impl AgentFS {
     /// Push local changes to remote database
     pub async fn push(&self) -> Result<()> {
         let db = self.sync_db.as_ref().ok_or(Error::SyncNotEnabled)?;
+        self.fs.flush_writes().await?;
         db.push().await?;
         Ok(())
     }
@@ -435,6 +551,7 @@ impl AgentFS {
     /// Checkpoint the local database
     pub async fn checkpoint(&self) -> Result<()> {
         let db = self.sync_db.as_ref().ok_or(Error::SyncNotEnabled)?;
+        self.fs.flush_writes().await?;
         db.checkpoint().await?;
         Ok(())
     }
@@ -446,13 +563,70 @@ impl AgentFS {
         Ok(stats)
     }
 
+    /// Attribute subsequent filesystem changes to a tool call
+    ///
+    /// Pass the id returned by [`ToolCalls::start`] before the tool runs and
+    /// `None` once it has finished. Changes are recorded in the
+    /// [`journal`] with this id.
+    pub fn set_tool_call(&self, id: Option<i64>) {
+        self.fs.set_tool_call(id);
+    }
+
+    /// Take a named snapshot of the filesystem and key-value store
+    ///
+    /// Snapshots are copy-on-write: file contents are only copied when they
+    /// are modified afterwards. See [`snapshot`] for details.
+    pub async fn snapshot(&self, name: &str) -> Result<Snapshot> {
+        self.fs.flush_writes().await?;
+        self.snapshots.create(name).await
+    }
+
+    /// List snapshots, oldest first
+    pub async fn list_snapshots(&self) -> Result<Vec<Snapshot>> {
+        self.snapshots.list().await
+    }
+
+    /// Restore the filesystem and key-value store to a named snapshot
+    ///
+    /// Snapshots taken after it are kept. The tool call log is not affected.
+    pub async fn restore_snapshot(&self, name: &str) -> Result<()> {
+        self.fs.flush_writes().await?;
+        self.snapshots.restore(name).await?;
+        self.fs.invalidate_cache();
+        Ok(())
+    }
+
+    /// Delete a named snapshot
+    pub async fn delete_snapshot(&self, name: &str) -> Result<()> {
+        self.snapshots.delete(name).await
+    }
+
+    /// Check the filesystem for inconsistencies and corrupted chunks
+    ///
+    /// With `repair`, fixes everything except lost or corrupted file
+    /// contents. See [`fsck`] for the checks performed.
+    pub async fn fsck(&self, repair: bool) -> Result<FsckReport> {
+        self.fs.flush_writes().await?;
+        let report = self
+            .pool
+            .retry(|| async move {
+                let conn = self.pool.get_connection().await?;
+                fsck::check(&conn, repair).await
+            })
+            .await?;
+        if repair {
+            self.fs.invalidate_cache();
+        }
+        Ok(report)
+    }
+
     /// Get all paths in the delta layer (files in fs_dentry)
     ///
     /// This returns all file and directory paths that exist in the overlay's
     /// delta layer, which represents files that have been added or modified.
     pub async fn get_delta_paths(&self) -> Result<HashSet<String>> {
         const ROOT_INO: i64 = 1;
-        let conn = self.pool.get_connection().await?;
+        let conn = self.pool.get_read_connection().await?;
 
         let mut paths = HashSet::new();
         let mut queue: VecDeque<(i64, String)> = VecDeque::new();
@@ -518,7 +692,7 @@ impl AgentFS {
     /// the path doesn't exist in the delta layer.
     pub async fn get_file_mode(&self, path: &str) -> Result<Option<u32>> {
         const ROOT_INO: i64 = 1;
-        let conn = self.pool.get_connection().await?;
+        let conn = self.pool.get_read_connection().await?;
 
         // Resolve path to inode
         let components: Vec<&str> = path
@@ -585,7 +759,7 @@ impl AgentFS {
     /// Whiteouts mark paths that existed in the base layer but have been
     /// deleted in the overlay.
     pub async fn get_whiteouts(&self) -> Result<HashSet<String>> {
-        let conn = self.pool.get_connection().await?;
+        let conn = self.pool.get_read_connection().await?;
         let mut whiteouts = HashSet::new();
 
         let result = conn.query("SELECT path FROM fs_whiteout", ()).await;
@@ -605,7 +779,7 @@ impl AgentFS {
     ///
     /// Returns the base path if overlay is enabled, None otherwise.
     pub async fn is_overlay_enabled(&self) -> Result<Option<String>> {
-        let conn = self.pool.get_connection().await?;
+        let conn = self.pool.get_read_connection().await?;
         // Check if fs_overlay_config table exists and has base_path
         let result = conn
             .query(
@@ -684,6 +858,170 @@ mod tests {
         assert_eq!(value, None);
     }
 
+    /// Hold the database at `AGENTFS_TEST_HOLD_DB` open until stdin closes.
+    ///
+    /// Run as a child process by `test_open_database_in_use_by_another_process`.
+    #[tokio::test]
+    #[ignore]
+    async fn hold_database_open() {
+        let Ok(db_path) = std::env::var("AGENTFS_TEST_HOLD_DB") else {
+            return;
+        };
+        let _agentfs = AgentFS::open(AgentFSOptions::with_path(db_path))
+            .await
+            .unwrap();
+        // Written to stderr directly, since the test harness sends printed
+        // output to stdout
+        std::io::Write::write_all(&mut std::io::stderr(), b"ready\n").unwrap();
+        std::io::stdin().read_line(&mut String::new()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_open_database_in_use_by_another_process() {
+        use std::io::BufRead;
+        use std::process::{Command, Stdio};
+
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("agent.db");
+        let mut child = Command::new(std::env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "tests::hold_database_open",
+                "--ignored",
+                "--nocapture",
+            ])
+            .env("AGENTFS_TEST_HOLD_DB", &db_path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stderr = std::io::BufReader::new(child.stderr.take().unwrap());
+        let ready = stderr
+            .lines()
+            .any(|line| line.is_ok_and(|line| line == "ready"));
+        assert!(ready, "child process didn't open the database");
+
+        let result = AgentFS::open(AgentFSOptions::with_path(db_path.to_str().unwrap())).await;
+        assert!(matches!(result, Err(Error::DatabaseInUse(_))));
+
+        drop(child.stdin.take());
+        assert!(child.wait().unwrap().success());
+
+        // The database can be opened once the other process exits
+        AgentFS::open(AgentFSOptions::with_path(db_path.to_str().unwrap()))
+            .await
+            .unwrap();
+    }
+
+    /// Write files and a key to the database at `AGENTFS_TEST_SHARED_DB` as
+    /// writer `AGENTFS_TEST_WRITER`.
+    ///
+    /// Run as a child process by `test_processes_write_to_one_database`.
+    #[tokio::test]
+    #[ignore]
+    async fn write_shared_database() {
+        let (Ok(db_path), Ok(writer)) = (
+            std::env::var("AGENTFS_TEST_SHARED_DB"),
+            std::env::var("AGENTFS_TEST_WRITER"),
+        ) else {
+            return;
+        };
+        let agentfs = AgentFS::open(AgentFSOptions::with_path(db_path))
+            .await
+            .unwrap();
+        for i in 0..20 {
+            let path = format!("/{writer}-{i}.txt");
+            agentfs.fs.pwrite(&path, 0, path.as_bytes()).await.unwrap();
+        }
+        agentfs
+            .kv
+            .set(&format!("writer:{writer}"), &"done")
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_processes_write_to_one_database() {
+        use std::process::{Command, Stdio};
+
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("agent.db");
+        AgentFS::open(AgentFSOptions::with_path(db_path.to_str().unwrap()))
+            .await
+            .unwrap();
+
+        // Each process waits for the others to close the database
+        let children: Vec<_> = (0..4)
+            .map(|writer| {
+                Command::new(std::env::current_exe().unwrap())
+                    .args(["--exact", "tests::write_shared_database", "--ignored"])
+                    .env("AGENTFS_TEST_SHARED_DB", &db_path)
+                    .env("AGENTFS_TEST_WRITER", writer.to_string())
+                    .stdout(Stdio::null())
+                    .spawn()
+                    .unwrap()
+            })
+            .collect();
+        for mut child in children {
+            assert!(child.wait().unwrap().success());
+        }
+
+        let agentfs = AgentFS::open(AgentFSOptions::with_path(db_path.to_str().unwrap()))
+            .await
+            .unwrap();
+        for writer in 0..4 {
+            let done: Option<String> = agentfs.kv.get(&format!("writer:{writer}")).await.unwrap();
+            assert_eq!(done.as_deref(), Some("done"));
+            for i in 0..20 {
+                let path = format!("/{writer}-{i}.txt");
+                let data = agentfs.fs.read_file(&path).await.unwrap().unwrap();
+                assert_eq!(data, path.as_bytes());
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_concurrent_writers() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("agent.db");
+        let agentfs =
+            AgentFS::open(AgentFSOptions::with_path(db_path.to_str().unwrap()).with_writers(4))
+                .await
+                .unwrap();
+
+        // Writers overlap and retry the transactions that conflict
+        let tasks: Vec<_> = (0..4)
+            .map(|writer| {
+                let fs = agentfs.fs.clone();
+                let kv = agentfs.kv.clone();
+                tokio::spawn(async move {
+                    fs.mkdir(&format!("/{writer}"), 0, 0).await.unwrap();
+                    for i in 0..20 {
+                        let path = format!("/{writer}/{i}.txt");
+                        fs.pwrite(&path, 0, path.as_bytes()).await.unwrap();
+                        kv.set(&format!("{writer}:{i}"), &i).await.unwrap();
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        for writer in 0..4 {
+            for i in 0..20 {
+                let path = format!("/{writer}/{i}.txt");
+                let data = agentfs.fs.read_file(&path).await.unwrap().unwrap();
+                assert_eq!(data, path.as_bytes());
+                let value: Option<i32> = agentfs.kv.get(&format!("{writer}:{i}")).await.unwrap();
+                assert_eq!(value, Some(i));
+            }
+        }
+        let report = agentfs.fsck(false).await.unwrap();
+        assert!(report.issues.is_empty(), "{:?}", report.issues);
+    }
+
     #[tokio::test]
     async fn test_filesystem_operations() {
         let agentfs = AgentFS::open(AgentFSOptions::ephemeral()).await.unwrap();
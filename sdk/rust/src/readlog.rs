@@ -0,0 +1,260 @@
+//! Audit log of file reads.
+//!
+//! Read hooks in front of a filesystem (see the LevFS hooks in the CLI) can
+//! record which files an agent opened and how much it read from them. Reads
+//! are aggregated per open handle, and handles of the same path closed close
+//! together are folded into one entry, so an agent reading a file in many
+//! small chunks or reopening it in a loop adds a single row to `read_log`.
+//!
+//! Like the hook log, the read log is not part of a snapshot and is never
+//! rewritten by a restore.
+
+use crate::connection_pool::ConnectionPool;
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use turso::Value;
+
+/// A read log entry
+///
+/// `opens` handles of `path` were opened from `opened_at` and closed by
+/// `closed_at` (Unix timestamps, seconds); `reads` reads through them
+/// returned `bytes` bytes in total.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReadLogEntry {
+    pub id: i64,
+    pub opened_at: i64,
+    pub closed_at: i64,
+    pub path: String,
+    pub opens: i64,
+    pub reads: i64,
+    pub bytes: i64,
+}
+
+/// Reads of a path to append to the log with [`ReadLog::record`]
+#[derive(Debug, Clone)]
+pub struct ReadLogRecord {
+    pub path: String,
+    pub opened_at: i64,
+    pub closed_at: i64,
+    pub opens: i64,
+    pub reads: i64,
+    pub bytes: i64,
+}
+
+/// Filter for [`ReadLog::query`]
+///
+/// All conditions are combined; unset fields match everything.
+#[derive(Debug, Clone, Default)]
+pub struct ReadLogQuery {
+    /// Only entries with an id greater than this
+    pub after: Option<i64>,
+    /// Only entries for this path or below it
+    pub path: Option<String>,
+    /// Maximum number of entries to return (default: 100)
+    pub limit: Option<i64>,
+}
+
+/// Columns of a read log entry, in the order `row_to_entry` reads them
+const ENTRY_COLUMNS: &str = "id, opened_at, closed_at, path, opens, reads, bytes";
+
+/// File read log backed by SQLite
+#[derive(Clone)]
+pub struct ReadLog {
+    pool: ConnectionPool,
+}
+
+impl ReadLog {
+    /// Create a read log from a connection pool
+    pub async fn from_pool(pool: ConnectionPool) -> Result<Self> {
+        let log = Self { pool };
+        log.initialize().await?;
+        Ok(log)
+    }
+
+    /// Initialize the database schema
+    async fn initialize(&self) -> Result<()> {
+        let conn = self.pool.get_connection().await?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS read_log (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                opened_at INTEGER NOT NULL,
+                closed_at INTEGER NOT NULL,
+                path TEXT NOT NULL,
+                opens INTEGER NOT NULL,
+                reads INTEGER NOT NULL,
+                bytes INTEGER NOT NULL
+            )",
+            (),
+        )
+        .await?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_read_log_path ON read_log(path)",
+            (),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Record the reads of a path, returning the id of the new entry
+    pub async fn record(&self, record: &ReadLogRecord) -> Result<i64> {
+        self.pool
+            .retry(|| async move {
+                let conn = self.pool.get_connection().await?;
+                let mut stmt = conn
+                    .prepare(
+                        "INSERT INTO read_log (opened_at, closed_at, path, opens, reads, bytes)
+                        VALUES (?, ?, ?, ?, ?, ?) RETURNING id",
+                    )
+                    .await?;
+                let row = stmt
+                    .query_row((
+                        record.opened_at,
+                        record.closed_at,
+                        record.path.as_str(),
+                        record.opens,
+                        record.reads,
+                        record.bytes,
+                    ))
+                    .await?;
+                let id = row
+                    .get_value(0)
+                    .ok()
+                    .and_then(|v| v.as_integer().copied())
+                    .ok_or_else(|| Error::Internal("failed to get read log ID".to_string()))?;
+                Ok(id)
+            })
+            .await
+    }
+
+    /// Get the oldest entries matching a filter, oldest first
+    pub async fn query(&self, query: &ReadLogQuery) -> Result<Vec<ReadLogEntry>> {
+        self.select(query, false).await
+    }
+
+    /// Get the most recent entries matching a filter, oldest first
+    pub async fn tail(&self, query: &ReadLogQuery) -> Result<Vec<ReadLogEntry>> {
+        self.select(query, true).await
+    }
+
+    async fn select(&self, query: &ReadLogQuery, newest: bool) -> Result<Vec<ReadLogEntry>> {
+        let mut conditions = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+
+        if let Some(after) = query.after {
+            conditions.push("id > ?");
+            values.push(Value::Integer(after));
+        }
+        if let Some(path) = &query.path {
+            let path = path.trim_end_matches('/');
+            if !path.is_empty() {
+                conditions.push("(path = ? OR path LIKE ? || '/%')");
+                for _ in 0..2 {
+                    values.push(Value::Text(path.to_string()));
+                }
+            }
+        }
+        values.push(Value::Integer(query.limit.unwrap_or(100)));
+
+        let where_clause = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+        let sql = if newest {
+            format!(
+                "SELECT {} FROM (SELECT * FROM read_log {} ORDER BY id DESC LIMIT ?) ORDER BY id",
+                ENTRY_COLUMNS, where_clause
+            )
+        } else {
+            format!(
+                "SELECT {} FROM read_log {} ORDER BY id LIMIT ?",
+                ENTRY_COLUMNS, where_clause
+            )
+        };
+
+        let conn = self.pool.get_read_connection().await?;
+        let mut rows = conn.query(&sql, values).await?;
+        let mut entries = Vec::new();
+        while let Some(row) = rows.next().await? {
+            entries.extend(Self::row_to_entry(&row));
+        }
+        Ok(entries)
+    }
+
+    fn row_to_entry(row: &turso::Row) -> Option<ReadLogEntry> {
+        let int = |idx: usize| {
+            row.get_value(idx)
+                .ok()
+                .and_then(|v| v.as_integer().copied())
+        };
+        let path = match row.get_value(3) {
+            Ok(Value::Text(s)) => s,
+            _ => return None,
+        };
+
+        Some(ReadLogEntry {
+            id: int(0)?,
+            opened_at: int(1).unwrap_or(0),
+            closed_at: int(2).unwrap_or(0),
+            path,
+            opens: int(4).unwrap_or(0),
+            reads: int(5).unwrap_or(0),
+            bytes: int(6).unwrap_or(0),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AgentFS, AgentFSOptions};
+
+    fn record(path: &str, bytes: i64) -> ReadLogRecord {
+        ReadLogRecord {
+            path: path.to_string(),
+            opened_at: 100,
+            closed_at: 101,
+            opens: 1,
+            reads: 2,
+            bytes,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_log_records_reads() -> Result<()> {
+        let agent = AgentFS::open(AgentFSOptions::ephemeral()).await?;
+        let log = &agent.read_log;
+        let first = log.record(&record("/secrets/key", 64)).await?;
+        log.record(&record("/secrets2", 8)).await?;
+        log.record(&record("/docs/a.md", 4096)).await?;
+
+        let entries = log
+            .query(&ReadLogQuery {
+                path: Some("/secrets/".to_string()),
+                ..Default::default()
+            })
+            .await?;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].id, first);
+        assert_eq!(entries[0].bytes, 64);
+        assert_eq!(entries[0].reads, 2);
+
+        let recent = log
+            .tail(&ReadLogQuery {
+                limit: Some(2),
+                ..Default::default()
+            })
+            .await?;
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[1].path, "/docs/a.md");
+
+        let after = log
+            .query(&ReadLogQuery {
+                after: Some(first),
+                ..Default::default()
+            })
+            .await?;
+        assert_eq!(after.len(), 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,638 @@
+//! Named point-in-time snapshots of an AgentFS database.
+//!
+//! A snapshot copies the (small) metadata tables in full: inodes, directory
+//! entries, symlinks, extended attributes, the key-value store and, in overlay
+//! mode, whiteouts and origin mappings. Small files stored in their inode are
+//! copied along with it. Other file contents are copy-on-write at chunk
+//! granularity: nothing is copied when the snapshot is taken, and the
+//! filesystem saves a chunk's previous contents into `fs_snapshot_data` for the
+//! most recent snapshot the first time the chunk is modified afterwards.
+//!
+//! The contents of chunk `c` at snapshot `S` are therefore found in the oldest
+//! snapshot `T >= S` that holds a row for `c`, or in `fs_data` if no such
+//! snapshot exists. A NULL row means the chunk did not exist.
+//!
+//! The tool call log is not part of a snapshot; it stays an append-only audit
+//! trail across restores.
+
+use crate::connection_pool::ConnectionPool;
+use crate::error::{Error, Result};
+use crate::filesystem::AgentFS;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+use turso::{Connection, Value};
+
+/// Tables captured in full by every snapshot: (table, snapshot table, columns)
+const SNAPSHOT_TABLES: &[(&str, &str, &str)] = &[
+    (
+        "fs_inode",
+        "fs_snapshot_inode",
+        "ino, mode, nlink, uid, gid, size, atime, mtime, ctime, rdev, atime_nsec, mtime_nsec, ctime_nsec, inline_data",
+    ),
+    ("fs_dentry", "fs_snapshot_dentry", "id, name, parent_ino, ino"),
+    ("fs_symlink", "fs_snapshot_symlink", "ino, target"),
+    ("fs_xattr", "fs_snapshot_xattr", "ino, name, value"),
+    ("kv_store", "kv_snapshot", "key, value, created_at, updated_at"),
+    ("fs_whiteout", "fs_snapshot_whiteout", "path, created_at"),
+    ("fs_origin", "fs_snapshot_origin", "delta_ino, base_ino"),
+];
+
+/// A named snapshot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub id: i64,
+    pub name: String,
+    pub created_at: i64,
+}
+
+/// Snapshot manager backed by SQLite
+///
+/// Expects the filesystem and key-value schemas to be initialized, as
+/// `AgentFS::open` does.
+#[derive(Clone)]
+pub struct Snapshots {
+    pool: ConnectionPool,
+}
+
+impl Snapshots {
+    /// Create a snapshot manager from a connection pool
+    pub async fn from_pool(pool: ConnectionPool) -> Result<Self> {
+        let snapshots = Self { pool };
+        snapshots.initialize().await?;
+        Ok(snapshots)
+    }
+
+    /// Initialize the database schema
+    ///
+    /// `fs_snapshot` and `fs_snapshot_data` are created with the filesystem
+    /// schema since the write path depends on them.
+    async fn initialize(&self) -> Result<()> {
+        let conn = self.pool.get_connection().await?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS fs_snapshot_inode (
+                snapshot_id INTEGER NOT NULL,
+                ino INTEGER NOT NULL,
+                mode INTEGER NOT NULL,
+                nlink INTEGER NOT NULL,
+                uid INTEGER NOT NULL,
+                gid INTEGER NOT NULL,
+                size INTEGER NOT NULL,
+                atime INTEGER NOT NULL,
+                mtime INTEGER NOT NULL,
+                ctime INTEGER NOT NULL,
+                rdev INTEGER NOT NULL,
+                atime_nsec INTEGER NOT NULL,
+                mtime_nsec INTEGER NOT NULL,
+                ctime_nsec INTEGER NOT NULL,
+                PRIMARY KEY (snapshot_id, ino)
+            )",
+            (),
+        )
+        .await?;
+
+        // Contents of small files stored in their inode are copied with it
+        // (backward compatible migration)
+        conn.execute(
+            "ALTER TABLE fs_snapshot_inode ADD COLUMN inline_data BLOB",
+            (),
+        )
+        .await
+        .ok();
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS fs_snapshot_dentry (
+                snapshot_id INTEGER NOT NULL,
+                id INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                parent_ino INTEGER NOT NULL,
+                ino INTEGER NOT NULL,
+                PRIMARY KEY (snapshot_id, id)
+            )",
+            (),
+        )
+        .await?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS fs_snapshot_symlink (
+                snapshot_id INTEGER NOT NULL,
+                ino INTEGER NOT NULL,
+                target TEXT NOT NULL,
+                PRIMARY KEY (snapshot_id, ino)
+            )",
+            (),
+        )
+        .await?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS fs_snapshot_xattr (
+                snapshot_id INTEGER NOT NULL,
+                ino INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                value BLOB NOT NULL,
+                PRIMARY KEY (snapshot_id, ino, name)
+            )",
+            (),
+        )
+        .await?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS kv_snapshot (
+                snapshot_id INTEGER NOT NULL,
+                key TEXT NOT NULL,
+                value TEXT NOT NULL,
+                created_at INTEGER,
+                updated_at INTEGER,
+                PRIMARY KEY (snapshot_id, key)
+            )",
+            (),
+        )
+        .await?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS fs_snapshot_whiteout (
+                snapshot_id INTEGER NOT NULL,
+                path TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                PRIMARY KEY (snapshot_id, path)
+            )",
+            (),
+        )
+        .await?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS fs_snapshot_origin (
+                snapshot_id INTEGER NOT NULL,
+                delta_ino INTEGER NOT NULL,
+                base_ino INTEGER NOT NULL,
+                PRIMARY KEY (snapshot_id, delta_ino)
+            )",
+            (),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Take a snapshot of the current state under a new name
+    pub async fn create(&self, name: &str) -> Result<Snapshot> {
+        self.pool
+            .retry(|| async move {
+                let conn = self.pool.get_connection().await?;
+                let txn = conn.begin_write().await?;
+
+                let result: Result<Snapshot> = async {
+                    if Self::find(&conn, name).await?.is_some() {
+                        return Err(Error::SnapshotExists(name.to_string()));
+                    }
+
+                    let created_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+                    let mut stmt = conn
+                        .prepare(
+                            "INSERT INTO fs_snapshot (name, created_at) VALUES (?, ?) RETURNING id",
+                        )
+                        .await?;
+                    let row = stmt.query_row((name, created_at)).await?;
+                    let id = row
+                        .get_value(0)
+                        .ok()
+                        .and_then(|v| v.as_integer().copied())
+                        .ok_or_else(|| Error::Internal("failed to get snapshot ID".to_string()))?;
+
+                    for (table, snapshot_table, columns) in SNAPSHOT_TABLES {
+                        if !Self::table_exists(&conn, table).await? {
+                            continue;
+                        }
+                        conn.execute(
+                            &format!(
+                                "INSERT INTO {snapshot_table} (snapshot_id, {columns})
+                                SELECT ?, {columns} FROM {table}"
+                            ),
+                            (id,),
+                        )
+                        .await?;
+                    }
+
+                    Ok(Snapshot {
+                        id,
+                        name: name.to_string(),
+                        created_at,
+                    })
+                }
+                .await;
+
+                match result {
+                    Ok(snapshot) => {
+                        txn.commit().await?;
+                        Ok(snapshot)
+                    }
+                    Err(e) => {
+                        let _ = txn.rollback().await;
+                        Err(e)
+                    }
+                }
+            })
+            .await
+    }
+
+    /// List all snapshots, oldest first
+    pub async fn list(&self) -> Result<Vec<Snapshot>> {
+        let conn = self.pool.get_read_connection().await?;
+        let mut rows = conn
+            .query(
+                "SELECT id, name, created_at FROM fs_snapshot ORDER BY id",
+                (),
+            )
+            .await?;
+
+        let mut snapshots = Vec::new();
+        while let Some(row) = rows.next().await? {
+            snapshots.push(Self::row_to_snapshot(&row));
+        }
+        Ok(snapshots)
+    }
+
+    /// Get a snapshot by name
+    pub async fn get(&self, name: &str) -> Result<Option<Snapshot>> {
+        let conn = self.pool.get_read_connection().await?;
+        Self::find(&conn, name).await
+    }
+
+    /// Restore the filesystem and key-value store to a snapshot.
+    ///
+    /// The snapshot and every snapshot taken after it are kept, so it is
+    /// possible to restore forward again. Mounted filesystems cache inodes and
+    /// directory entries, so restore while the database is not mounted.
+    pub async fn restore(&self, name: &str) -> Result<()> {
+        self.pool
+            .retry(|| async move {
+                let conn = self.pool.get_connection().await?;
+                let txn = conn.begin_write().await?;
+
+                let result: Result<()> = async {
+                    let snapshot = Self::find(&conn, name)
+                        .await?
+                        .ok_or_else(|| Error::SnapshotNotFound(name.to_string()))?;
+
+                    // Every chunk modified since the snapshot has a row in it or in a
+                    // later snapshot. Find the oldest such row for each chunk.
+                    let mut rows = conn
+                        .query(
+                            "SELECT ino, chunk_index, MIN(snapshot_id) FROM fs_snapshot_data
+                            WHERE snapshot_id >= ? GROUP BY ino, chunk_index",
+                            (snapshot.id,),
+                        )
+                        .await?;
+                    let mut changed = Vec::new();
+                    while let Some(row) = rows.next().await? {
+                        let get = |i| {
+                            row.get_value(i)
+                                .ok()
+                                .and_then(|v| v.as_integer().copied())
+                                .unwrap_or(0)
+                        };
+                        changed.push((get(0), get(1), get(2)));
+                    }
+                    drop(rows);
+
+                    // Restored chunks are stored the way new writes would be. Writing
+                    // through AgentFS also keeps the current contents reachable from
+                    // later snapshots.
+                    let dedup = AgentFS::read_dedup(&conn).await?;
+                    for (ino, chunk_index, snapshot_id) in changed {
+                        let mut rows = conn
+                            .query(
+                                "SELECT data FROM fs_snapshot_data
+                                WHERE snapshot_id = ? AND ino = ? AND chunk_index = ?",
+                                (snapshot_id, ino, chunk_index),
+                            )
+                            .await?;
+                        let data = match rows.next().await? {
+                            Some(row) => match row.get_value(0) {
+                                Ok(Value::Blob(data)) => Some(data),
+                                _ => None,
+                            },
+                            None => None,
+                        };
+                        drop(rows);
+
+                        match data {
+                            Some(data) => {
+                                AgentFS::write_chunk(&conn, ino, chunk_index, &data, dedup).await?;
+                            }
+                            None => {
+                                AgentFS::delete_chunks(&conn, ino, chunk_index, chunk_index)
+                                    .await?;
+                            }
+                        }
+                    }
+
+                    for (table, snapshot_table, columns) in SNAPSHOT_TABLES {
+                        if !Self::table_exists(&conn, table).await? {
+                            continue;
+                        }
+                        conn.execute(&format!("DELETE FROM {table}"), ()).await?;
+                        conn.execute(
+                            &format!(
+                                "INSERT INTO {table} ({columns})
+                                SELECT {columns} FROM {snapshot_table} WHERE snapshot_id = ?"
+                            ),
+                            (snapshot.id,),
+                        )
+                        .await?;
+                    }
+
+                    Ok(())
+                }
+                .await;
+
+                match result {
+                    Ok(()) => {
+                        txn.commit().await?;
+                        Ok(())
+                    }
+                    Err(e) => {
+                        let _ = txn.rollback().await;
+                        Err(e)
+                    }
+                }
+            })
+            .await
+    }
+
+    /// Delete a snapshot
+    ///
+    /// Chunks saved for the snapshot that the previous snapshot still needs
+    /// are handed over to it.
+    pub async fn delete(&self, name: &str) -> Result<()> {
+        self.pool
+            .retry(|| async move {
+                let conn = self.pool.get_connection().await?;
+                let txn = conn.begin_write().await?;
+
+                let result: Result<()> = async {
+                    let snapshot = Self::find(&conn, name)
+                        .await?
+                        .ok_or_else(|| Error::SnapshotNotFound(name.to_string()))?;
+
+                    conn.execute(
+                        "INSERT OR IGNORE INTO fs_snapshot_data (snapshot_id, ino, chunk_index, data)
+                        SELECT (SELECT MAX(id) FROM fs_snapshot WHERE id < ?1), ino, chunk_index, data
+                        FROM fs_snapshot_data
+                        WHERE snapshot_id = ?1 AND EXISTS (SELECT 1 FROM fs_snapshot WHERE id < ?1)",
+                        (snapshot.id,),
+                    )
+                    .await?;
+                    conn.execute(
+                        "DELETE FROM fs_snapshot_data WHERE snapshot_id = ?",
+                        (snapshot.id,),
+                    )
+                    .await?;
+
+                    for (_, snapshot_table, _) in SNAPSHOT_TABLES {
+                        conn.execute(
+                            &format!("DELETE FROM {snapshot_table} WHERE snapshot_id = ?"),
+                            (snapshot.id,),
+                        )
+                        .await?;
+                    }
+
+                    conn.execute("DELETE FROM fs_snapshot WHERE id = ?", (snapshot.id,))
+                        .await?;
+
+                    Ok(())
+                }
+                .await;
+
+                match result {
+                    Ok(()) => {
+                        txn.commit().await?;
+                        Ok(())
+                    }
+                    Err(e) => {
+                        let _ = txn.rollback().await;
+                        Err(e)
+                    }
+                }
+            })
+            .await
+    }
+
+    async fn find(conn: &Connection, name: &str) -> Result<Option<Snapshot>> {
+        let mut rows = conn
+            .query(
+                "SELECT id, name, created_at FROM fs_snapshot WHERE name = ?",
+                (name,),
+            )
+            .await?;
+
+        if let Some(row) = rows.next().await? {
+            Ok(Some(Self::row_to_snapshot(&row)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Overlay tables only exist in overlay mode
+    pub(crate) async fn table_exists(conn: &Connection, table: &str) -> Result<bool> {
+        let mut rows = conn
+            .query(
+                "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?",
+                (table,),
+            )
+            .await?;
+        Ok(rows.next().await?.is_some())
+    }
+
+    fn row_to_snapshot(row: &turso::Row) -> Snapshot {
+        let id = row
+            .get_value(0)
+            .ok()
+            .and_then(|v| v.as_integer().copied())
+            .unwrap_or(0);
+
+        let name = row
+            .get_value(1)
+            .ok()
+            .and_then(|v| {
+                if let Value::Text(s) = v {
+                    Some(s.clone())
+                } else {
+                    None
+                }
+            })
+            .unwrap_or_default();
+
+        let created_at = row
+            .get_value(2)
+            .ok()
+            .and_then(|v| v.as_integer().copied())
+            .unwrap_or(0);
+
+        Snapshot {
+            id,
+            name,
+            created_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Result;
+    use crate::filesystem::FileSystem;
+    use crate::{AgentFS, AgentFSOptions, DEFAULT_FILE_MODE};
+
+    async fn write(agent: &AgentFS, path: &str, data: &[u8]) -> Result<()> {
+        let file = agent.fs.open(path).await;
+        let file = match file {
+            Ok(file) => file,
+            Err(_) => {
+                let (_, file) = agent.fs.create_file(path, DEFAULT_FILE_MODE, 0, 0).await?;
+                file
+            }
+        };
+        file.truncate(0).await?;
+        file.pwrite(0, data).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_restore_file_contents() -> Result<()> {
+        let agent = AgentFS::open(AgentFSOptions::ephemeral()).await?;
+        let chunk_size = agent.fs.chunk_size();
+
+        let original: Vec<u8> = (0..chunk_size * 3).map(|i| (i % 251) as u8).collect();
+        write(&agent, "/data.bin", &original).await?;
+        agent.kv.set("step", &1).await?;
+        agent.snapshot("before").await?;
+
+        // Modify one chunk in place, shrink, and add a new file
+        let file = agent.fs.open("/data.bin").await?;
+        file.pwrite(chunk_size as u64, b"changed").await?;
+        file.truncate(chunk_size as u64 * 2).await?;
+        write(&agent, "/new.txt", b"new file").await?;
+        agent.kv.set("step", &2).await?;
+
+        agent.restore_snapshot("before").await?;
+
+        let data = agent.fs.read_file("/data.bin").await?.unwrap();
+        assert_eq!(data, original);
+        assert!(agent.fs.stat("/new.txt").await?.is_none());
+        let step: Option<i32> = agent.kv.get("step").await?;
+        assert_eq!(step, Some(1));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_restore_across_inline_storage() -> Result<()> {
+        let agent = AgentFS::open(AgentFSOptions::ephemeral()).await?;
+        let chunk_size = agent.fs.chunk_size();
+        let large = vec![4u8; chunk_size * 2];
+        write(&agent, "/small.txt", b"small").await?;
+        write(&agent, "/large.bin", &large).await?;
+        agent.snapshot("before").await?;
+
+        // Move the small file to chunks and the large one into its inode
+        write(&agent, "/small.txt", &large).await?;
+        write(&agent, "/large.bin", b"shrunk").await?;
+
+        agent.restore_snapshot("before").await?;
+        assert_eq!(agent.fs.read_file("/small.txt").await?.unwrap(), b"small");
+        assert_eq!(agent.fs.read_file("/large.bin").await?.unwrap(), large);
+        assert!(agent.fsck(false).await?.issues.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_restore_deleted_file() -> Result<()> {
+        let agent = AgentFS::open(AgentFSOptions::ephemeral()).await?;
+        agent.fs.mkdir("/dir", 0, 0).await?;
+        write(&agent, "/dir/file.txt", b"keep me").await?;
+        agent.snapshot("s1").await?;
+
+        agent.fs.remove("/dir/file.txt").await?;
+        agent.fs.remove("/dir").await?;
+        assert!(agent.fs.stat("/dir").await?.is_none());
+
+        agent.restore_snapshot("s1").await?;
+        let data = agent.fs.read_file("/dir/file.txt").await?.unwrap();
+        assert_eq!(data, b"keep me");
+        let dir = agent.fs.stat("/dir").await?.unwrap();
+        let entries = agent.fs.readdir(dir.ino).await?.unwrap();
+        assert_eq!(entries, vec!["file.txt"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_restore_between_snapshots() -> Result<()> {
+        let agent = AgentFS::open(AgentFSOptions::ephemeral()).await?;
+        write(&agent, "/file.txt", b"one").await?;
+        agent.snapshot("one").await?;
+        write(&agent, "/file.txt", b"two").await?;
+        agent.snapshot("two").await?;
+        write(&agent, "/file.txt", b"three").await?;
+
+        agent.restore_snapshot("one").await?;
+        assert_eq!(agent.fs.read_file("/file.txt").await?.unwrap(), b"one");
+
+        // Later snapshots survive a restore to an earlier one
+        agent.restore_snapshot("two").await?;
+        assert_eq!(agent.fs.read_file("/file.txt").await?.unwrap(), b"two");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_delete_snapshot_keeps_older_snapshots() -> Result<()> {
+        let agent = AgentFS::open(AgentFSOptions::ephemeral()).await?;
+        write(&agent, "/file.txt", b"one").await?;
+        agent.snapshot("one").await?;
+        agent.snapshot("two").await?;
+        write(&agent, "/file.txt", b"three").await?;
+
+        // "one" has no saved chunks of its own; they were recorded in "two"
+        agent.delete_snapshot("two").await?;
+        let names: Vec<String> = agent
+            .list_snapshots()
+            .await?
+            .into_iter()
+            .map(|s| s.name)
+            .collect();
+        assert_eq!(names, vec!["one"]);
+
+        agent.restore_snapshot("one").await?;
+        assert_eq!(agent.fs.read_file("/file.txt").await?.unwrap(), b"one");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_name_errors() -> Result<()> {
+        let agent = AgentFS::open(AgentFSOptions::ephemeral()).await?;
+        agent.snapshot("dup").await?;
+        assert!(agent.snapshot("dup").await.is_err());
+        assert!(agent.restore_snapshot("missing").await.is_err());
+        assert!(agent.delete_snapshot("missing").await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_xattrs_and_symlinks() -> Result<()> {
+        let agent = AgentFS::open(AgentFSOptions::ephemeral()).await?;
+        write(&agent, "/file.txt", b"data").await?;
+        agent.fs.symlink("/file.txt", "/link", 0, 0).await?;
+        let ino = agent.fs.stat("/file.txt").await?.unwrap().ino;
+        FileSystem::setxattr(&agent.fs, ino, "user.tag", b"v1", 0).await?;
+        agent.snapshot("s1").await?;
+
+        FileSystem::setxattr(&agent.fs, ino, "user.tag", b"v2", 0).await?;
+        agent.fs.remove("/link").await?;
+
+        agent.restore_snapshot("s1").await?;
+        let value = FileSystem::getxattr(&agent.fs, ino, "user.tag").await?;
+        assert_eq!(value, Some(b"v1".to_vec()));
+        assert_eq!(
+            agent.fs.readlink("/link").await?,
+            Some("/file.txt".to_string())
+        );
+        Ok(())
+    }
+}
@@ -127,64 +127,74 @@ impl ToolCalls {
     /// Start a new tool call and mark it as pending
     /// Returns the ID of the created tool call record
     pub async fn start(&self, name: &str, parameters: Option<serde_json::Value>) -> Result<i64> {
-        let conn = self.pool.get_connection().await?;
         let serialized_params = parameters.map(|p| serde_json::to_string(&p)).transpose()?;
-        let started_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
-
-        let mut stmt = conn
-            .prepare(
-                "INSERT INTO tool_calls (name, parameters, status, started_at)
-                VALUES (?, ?, 'pending', ?) RETURNING id",
-            )
-            .await?;
-        let row = stmt
-            .query_row((name, serialized_params.as_deref().unwrap_or(""), started_at))
-            .await?;
-
-        let id = row
-            .get_value(0)
-            .ok()
-            .and_then(|v| v.as_integer().copied())
-            .ok_or_else(|| Error::Internal("failed to get tool call ID".to_string()))?;
-        Ok(id)
+        let serialized_params = serialized_params.as_deref();
+        self.pool
+            .retry(|| async move {
+                let conn = self.pool.get_connection().await?;
+                let started_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+
+                let mut stmt = conn
+                    .prepare(
+                        "INSERT INTO tool_calls (name, parameters, status, started_at)
+                        VALUES (?, ?, 'pending', ?) RETURNING id",
+                    )
+                    .await?;
+                let row = stmt
+                    .query_row((name, serialized_params.unwrap_or(""), started_at))
+                    .await?;
+
+                let id = row
+                    .get_value(0)
+                    .ok()
+                    .and_then(|v| v.as_integer().copied())
+                    .ok_or_else(|| Error::Internal("failed to get tool call ID".to_string()))?;
+                Ok(id)
+            })
+            .await
     }
 
     /// Mark a tool call as successful
     pub async fn success(&self, id: i64, result: Option<serde_json::Value>) -> Result<()> {
-        let conn = self.pool.get_connection().await?;
         let serialized_result = result.map(|r| serde_json::to_string(&r)).transpose()?;
-        let completed_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
-
-        // Get the started_at time to calculate duration
-        let mut rows = conn
-            .query("SELECT started_at FROM tool_calls WHERE id = ?", (id,))
-            .await?;
-
-        let started_at = if let Some(row) = rows.next().await? {
-            row.get_value(0)
-                .ok()
-                .and_then(|v| v.as_integer().copied())
-                .ok_or_else(|| Error::Internal("invalid started_at value".to_string()))?
-        } else {
-            return Err(Error::ToolCallNotFound);
-        };
-
-        let duration_ms = (completed_at - started_at) * 1000;
-
-        conn.execute(
-            "UPDATE tool_calls
-            SET result = ?, status = 'success', completed_at = ?, duration_ms = ?
-            WHERE id = ?",
-            (
-                serialized_result.as_deref().unwrap_or(""),
-                completed_at,
-                duration_ms,
-                id,
-            ),
-        )
-        .await?;
-
-        Ok(())
+        let serialized_result = serialized_result.as_deref();
+        self.pool
+            .retry(|| async move {
+                let conn = self.pool.get_connection().await?;
+                let completed_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+
+                // Get the started_at time to calculate duration
+                let mut rows = conn
+                    .query("SELECT started_at FROM tool_calls WHERE id = ?", (id,))
+                    .await?;
+
+                let started_at = if let Some(row) = rows.next().await? {
+                    row.get_value(0)
+                        .ok()
+                        .and_then(|v| v.as_integer().copied())
+                        .ok_or_else(|| Error::Internal("invalid started_at value".to_string()))?
+                } else {
+                    return Err(Error::ToolCallNotFound);
+                };
+
+                let duration_ms = (completed_at - started_at) * 1000;
+
+                conn.execute(
+                    "UPDATE tool_calls
+                    SET result = ?, status = 'success', completed_at = ?, duration_ms = ?
+                    WHERE id = ?",
+                    (
+                        serialized_result.unwrap_or(""),
+                        completed_at,
+                        duration_ms,
+                        id,
+                    ),
+                )
+                .await?;
+
+                Ok(())
+            })
+            .await
     }
 
     /// Record a completed tool call (spec-compliant insert-only method)
@@ -199,74 +209,84 @@ impl ToolCalls {
         result: Option<serde_json::Value>,
         error: Option<&str>,
     ) -> Result<i64> {
-        let conn = self.pool.get_connection().await?;
         let serialized_params = parameters.map(|p| serde_json::to_string(&p)).transpose()?;
+        let serialized_params = serialized_params.as_deref();
         let serialized_result = result.map(|r| serde_json::to_string(&r)).transpose()?;
-        let duration_ms = (completed_at - started_at) * 1000;
-        let status = if error.is_some() { "error" } else { "success" };
-
-        let mut stmt = conn
-            .prepare(
-                "INSERT INTO tool_calls (name, parameters, result, error, status, started_at, completed_at, duration_ms)
-                VALUES (?, ?, ?, ?, ?, ?, ?, ?) RETURNING id"
-            )
-            .await?;
-
-        let row = stmt
-            .query_row((
-                name,
-                serialized_params.as_deref().unwrap_or(""),
-                serialized_result.as_deref().unwrap_or(""),
-                error.unwrap_or(""),
-                status,
-                started_at,
-                completed_at,
-                duration_ms,
-            ))
-            .await?;
-        let id = row
-            .get_value(0)
-            .ok()
-            .and_then(|v| v.as_integer().copied())
-            .ok_or_else(|| Error::Internal("failed to get tool call ID".to_string()))?;
-        Ok(id)
+        let serialized_result = serialized_result.as_deref();
+        self.pool
+            .retry(|| async move {
+                let conn = self.pool.get_connection().await?;
+                let duration_ms = (completed_at - started_at) * 1000;
+                let status = if error.is_some() { "error" } else { "success" };
+
+                let mut stmt = conn
+                    .prepare(
+                        "INSERT INTO tool_calls (name, parameters, result, error, status, started_at, completed_at, duration_ms)
+                        VALUES (?, ?, ?, ?, ?, ?, ?, ?) RETURNING id"
+                    )
+                    .await?;
+
+                let row = stmt
+                    .query_row((
+                        name,
+                        serialized_params.unwrap_or(""),
+                        serialized_result.unwrap_or(""),
+                        error.unwrap_or(""),
+                        status,
+                        started_at,
+                        completed_at,
+                        duration_ms,
+                    ))
+                    .await?;
+                let id = row
+                    .get_value(0)
+                    .ok()
+                    .and_then(|v| v.as_integer().copied())
+                    .ok_or_else(|| Error::Internal("failed to get tool call ID".to_string()))?;
+                Ok(id)
+            })
+            .await
     }
 
     /// Mark a tool call as failed
     pub async fn error(&self, id: i64, error: &str) -> Result<()> {
-        let conn = self.pool.get_connection().await?;
-        let completed_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
-
-        // Get the started_at time to calculate duration
-        let mut rows = conn
-            .query("SELECT started_at FROM tool_calls WHERE id = ?", (id,))
-            .await?;
-
-        let started_at = if let Some(row) = rows.next().await? {
-            row.get_value(0)
-                .ok()
-                .and_then(|v| v.as_integer().copied())
-                .ok_or_else(|| Error::Internal("invalid started_at value".to_string()))?
-        } else {
-            return Err(Error::ToolCallNotFound);
-        };
+        self.pool
+            .retry(|| async move {
+                let conn = self.pool.get_connection().await?;
+                let completed_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+
+                // Get the started_at time to calculate duration
+                let mut rows = conn
+                    .query("SELECT started_at FROM tool_calls WHERE id = ?", (id,))
+                    .await?;
+
+                let started_at = if let Some(row) = rows.next().await? {
+                    row.get_value(0)
+                        .ok()
+                        .and_then(|v| v.as_integer().copied())
+                        .ok_or_else(|| Error::Internal("invalid started_at value".to_string()))?
+                } else {
+                    return Err(Error::ToolCallNotFound);
+                };
 
-        let duration_ms = (completed_at - started_at) * 1000;
+                let duration_ms = (completed_at - started_at) * 1000;
 
-        conn.execute(
-            "UPDATE tool_calls
-            SET error = ?, status = 'error', completed_at = ?, duration_ms = ?
-            WHERE id = ?",
-            (error, completed_at, duration_ms, id),
-        )
-        .await?;
+                conn.execute(
+                    "UPDATE tool_calls
+                    SET error = ?, status = 'error', completed_at = ?, duration_ms = ?
+                    WHERE id = ?",
+                    (error, completed_at, duration_ms, id),
+                )
+                .await?;
 
-        Ok(())
+                Ok(())
+            })
+            .await
     }
 
     /// Get a tool call by ID
     pub async fn get(&self, id: i64) -> Result<Option<ToolCall>> {
-        let conn = self.pool.get_connection().await?;
+        let conn = self.pool.get_read_connection().await?;
         let mut rows = conn
             .query(
                 "SELECT id, name, parameters, result, error, status, started_at, completed_at, duration_ms
@@ -284,7 +304,7 @@ impl ToolCalls {
 
     /// Get recent tool calls with optional limit
     pub async fn recent(&self, limit: Option<i64>) -> Result<Vec<ToolCall>> {
-        let conn = self.pool.get_connection().await?;
+        let conn = self.pool.get_read_connection().await?;
         let limit = limit.unwrap_or(100);
         let mut rows = conn
             .query(
@@ -306,7 +326,7 @@ impl ToolCalls {
 
     /// Get statistics for a specific tool
     pub async fn stats_for(&self, name: &str) -> Result<Option<ToolCallStats>> {
-        let conn = self.pool.get_connection().await?;
+        let conn = self.pool.get_read_connection().await?;
         let mut rows = conn
             .query(
                 "SELECT
@@ -331,7 +351,7 @@ impl ToolCalls {
 
     /// Get statistics for all tools
     pub async fn stats(&self) -> Result<Vec<ToolCallStats>> {
-        let conn = self.pool.get_connection().await?;
+        let conn = self.pool.get_read_connection().await?;
         let mut rows = conn
             .query(
                 "SELECT